@@ -1,8 +1,13 @@
 use crate::gui::imgui_wrapper::ImGuiWrapper;
+use crate::rendering::blinker_render::render_blinkers;
 use crate::rendering::camera_handler::CameraHandler;
+use crate::rendering::congestion_render::render_congestion;
 use crate::rendering::instanced_render::InstancedRender;
+use crate::rendering::itinerary_render::render_itineraries;
+use crate::rendering::minimap_render::render_minimap;
 use crate::rendering::render_context::RenderContext;
 use crate::rendering::road_rendering::RoadRenderer;
+use crate::rendering::sensor_render::render_sensors;
 use crate::rendering::sorted_mesh_renderer::SortedMeshRenderer;
 use cgmath::Vector2;
 use ggez::graphics::{Color, DrawMode, DrawParam, Font};
@@ -10,21 +15,23 @@ use ggez::input::keyboard::{KeyCode, KeyMods};
 use ggez::input::mouse::MouseButton;
 use ggez::{filesystem, graphics, timer, Context, GameResult};
 use scale::engine_interaction;
-use scale::engine_interaction::{KeyboardInfo, MouseInfo, RenderStats, TimeInfo};
+use scale::engine_interaction::{
+    FixedTimeStep, KeyboardInfo, MouseInfo, RenderStats, TimeInfo, TIME_STEP,
+};
 use scale::geometry::intersections::intersection_point;
 use scale::gui::Gui;
 use scale::interaction::FollowEntity;
 use scale::map_model::{Map, MapUIState, TraverseKind};
 use scale::pedestrians::PedestrianComponent;
 use scale::physics::{CollisionWorld, Transform};
+use scale::simulation::Simulation;
 use scale::specs::Join;
-use scale::specs::{Dispatcher, RunNow, World, WorldExt};
+use scale::specs::{World, WorldExt};
 use std::collections::HashSet;
 use std::iter::FromIterator;
 
 pub struct EngineState<'a> {
-    pub world: World,
-    pub dispatch: Dispatcher<'a, 'a>,
+    pub sim: Simulation<'a>,
     pub cam: CameraHandler,
     pub render_enabled: bool,
     pub grid: bool,
@@ -33,15 +40,11 @@ pub struct EngineState<'a> {
     pub sorted_mesh_render: SortedMeshRenderer,
     pub road_render: RoadRenderer,
     pub instanced_render: InstancedRender,
-    pub time_sync: f64,
+    pub time_step: FixedTimeStep,
 }
 
 impl<'a> EngineState<'a> {
-    pub(crate) fn new(
-        world: World,
-        dispatch: Dispatcher<'a, 'a>,
-        mut ctx: &mut Context,
-    ) -> GameResult<EngineState<'a>> {
+    pub(crate) fn new(sim: Simulation<'a>, mut ctx: &mut Context) -> GameResult<EngineState<'a>> {
         println!("{}", filesystem::resources_dir(ctx).display());
 
         let font = graphics::Font::new(ctx, "/bmonofont-i18n.ttf").ok();
@@ -53,8 +56,7 @@ impl<'a> EngineState<'a> {
         let imgui_wrapper = ImGuiWrapper::new(&mut ctx);
         Ok(EngineState {
             font,
-            world,
-            dispatch,
+            sim,
             cam: CameraHandler::new(width, height),
             render_enabled: true,
             grid: true,
@@ -62,13 +64,14 @@ impl<'a> EngineState<'a> {
             sorted_mesh_render: SortedMeshRenderer::new(),
             road_render: RoadRenderer::new(),
             instanced_render: InstancedRender::new(ctx),
-            time_sync: 0.0,
+            time_step: FixedTimeStep::default(),
         })
     }
 }
 
 impl EngineState<'_> {
-    fn tick(&mut self, ctx: &mut Context) {
+    /// Runs the given number of simulation steps, the camera is updated once per frame either way
+    fn tick(&mut self, ctx: &mut Context, ticks: u32) {
         let start_update = std::time::Instant::now();
         let pressed: Vec<engine_interaction::MouseButton> =
             if !self.imgui_wrapper.last_mouse_captured {
@@ -81,7 +84,8 @@ impl EngineState<'_> {
                 vec![]
             };
         if self.imgui_wrapper.last_kb_captured {
-            self.world
+            self.sim
+                .world
                 .write_resource::<KeyboardInfo>()
                 .just_pressed
                 .clear();
@@ -93,19 +97,48 @@ impl EngineState<'_> {
             ggez::input::mouse::button_pressed(ctx, MouseButton::Middle),
         ));
 
-        // info from last frame to determine "just pressed"
-        let last_pressed = self.world.read_resource::<MouseInfo>().buttons.clone();
-
-        *self.world.write_resource::<MouseInfo>() = MouseInfo {
-            unprojected: self.cam.unproject_mouse_click(ctx),
-            buttons: HashSet::from_iter(pressed.clone()),
-            just_pressed: HashSet::from_iter(
-                pressed.into_iter().filter(|x| !last_pressed.contains(x)),
-            ),
+        // The mouse info is updated every frame, so its buttons are the ones of last frame
+        let clicked = {
+            let mut mouse = self.sim.world.write_resource::<MouseInfo>();
+            let just_pressed: Vec<_> = pressed
+                .iter()
+                .filter(|x| !mouse.buttons.contains(x))
+                .copied()
+                .collect();
+
+            mouse.unprojected = self.cam.unproject_mouse_click(ctx);
+            mouse.buttons = HashSet::from_iter(pressed);
+            // Kept until a step sees them, frames without steps don't drop presses
+            mouse.just_pressed.extend(just_pressed.iter().copied());
+            !just_pressed.is_empty()
         };
 
-        self.dispatch.run_now(&self.world);
-        self.world.maintain();
+        if ticks == 0 {
+            // Picking must keep working while paused or when no step runs this frame
+            self.sim.interact();
+            self.sim
+                .world
+                .write_resource::<MouseInfo>()
+                .just_pressed
+                .clear();
+        }
+
+        for i in 0..ticks {
+            self.sim.step(TIME_STEP as f32);
+            if i == 0 {
+                // a click or key press must only be seen by one step
+                self.sim
+                    .world
+                    .write_resource::<MouseInfo>()
+                    .just_pressed
+                    .clear();
+                self.sim
+                    .world
+                    .write_resource::<KeyboardInfo>()
+                    .just_pressed
+                    .clear();
+            }
+        }
 
         self.cam.easy_camera_movement(
             ctx,
@@ -114,61 +147,35 @@ impl EngineState<'_> {
             !self.imgui_wrapper.last_kb_captured,
         );
 
-        if !self
-            .world
-            .read_resource::<MouseInfo>()
-            .just_pressed
-            .is_empty()
-        {
-            self.world.write_resource::<FollowEntity>().0.take();
+        if clicked {
+            self.sim.world.write_resource::<FollowEntity>().0.take();
         }
 
-        if let Some(e) = self.world.read_resource::<FollowEntity>().0 {
-            if let Some(pos) = self
-                .world
-                .read_component::<Transform>()
-                .get(e)
-                .map(|x| x.position())
-            {
-                self.cam.camera.position = pos;
-            }
+        if let Some(pos) = self
+            .sim
+            .world
+            .read_resource::<FollowEntity>()
+            .target(&self.sim.world.read_component::<Transform>())
+        {
+            self.cam.camera.position = pos;
         }
         self.cam.update(ctx);
 
-        self.world
-            .write_resource::<KeyboardInfo>()
-            .just_pressed
-            .clear();
-        self.world.write_resource::<RenderStats>().update_time =
+        self.sim.world.write_resource::<RenderStats>().update_time =
             (std::time::Instant::now() - start_update).as_secs_f32();
     }
 }
 
-const TIME_STEP: f64 = 1.0 / 30.0;
-
 impl<'a> ggez::event::EventHandler for EngineState<'a> {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
         let delta = timer::delta(ctx).as_secs_f64();
 
-        let time = self.world.read_resource::<TimeInfo>();
-        self.time_sync += delta * time.time_speed;
-        let mut ticks_to_do = (((self.time_sync - time.time) / TIME_STEP) as u32).max(0);
-
-        if ticks_to_do > 3 {
-            ticks_to_do = 3;
-            self.time_sync = time.time + 3.0 * TIME_STEP;
-        }
-        drop(time);
-
-        for _ in 0..ticks_to_do {
-            let mut time = self.world.write_resource::<TimeInfo>();
-            time.delta = TIME_STEP as f32;
-            time.time += TIME_STEP;
-            time.time_seconds = time.time as u64;
-            drop(time);
+        let ticks_to_do = {
+            let time = self.sim.world.read_resource::<TimeInfo>();
+            self.time_step.ticks(delta, &time)
+        };
 
-            self.tick(ctx);
-        }
+        self.tick(ctx, ticks_to_do);
 
         Ok(())
     }
@@ -176,9 +183,10 @@ impl<'a> ggez::event::EventHandler for EngineState<'a> {
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         let start_draw = std::time::Instant::now();
 
-        let time: TimeInfo = *self.world.read_resource::<TimeInfo>();
+        let time: TimeInfo = *self.sim.world.read_resource::<TimeInfo>();
 
         let mut rc = RenderContext::new(&mut self.cam, ctx, self.font);
+        rc.set_circle_tolerance(self.sim.world.read_resource::<Gui>().circle_tolerance);
         rc.clear();
 
         // Render grid
@@ -194,33 +202,44 @@ impl<'a> ggez::event::EventHandler for EngineState<'a> {
 
         {
             if self.render_enabled {
-                if self.world.read_resource::<MapUIState>().map_render_dirty
+                if self
+                    .sim
+                    .world
+                    .read_resource::<MapUIState>()
+                    .map_render_dirty
                     || self.road_render.mesh.is_none()
                 {
                     self.road_render.build_mesh(
-                        &self.world.read_resource::<Map>(),
+                        &self.sim.world.read_resource::<Map>(),
                         time.time_seconds,
                         &mut rc,
                     );
                 }
                 if let Some(m) = &self.road_render.mesh {
-                    ggez::graphics::draw(rc.ctx, m, DrawParam::default())?;
+                    rc.draw_mesh(m, DrawParam::default())?;
                 }
 
-                self.sorted_mesh_render.render(&mut self.world, &mut rc);
-                self.instanced_render.render(&mut self.world, &mut rc);
+                self.sorted_mesh_render.render(&mut self.sim.world, &mut rc);
+                self.instanced_render.render(&mut self.sim.world, &mut rc);
+                render_blinkers(&self.sim.world, time.time, &mut rc);
+                render_congestion(&self.sim.world, &mut rc);
+                render_itineraries(&self.sim.world, &mut rc);
+                render_sensors(&self.sim.world, &mut rc);
+                render_minimap(&self.sim.world, &mut rc);
             }
         }
 
-        rc.finish()?;
+        let draw_calls = rc.finish()?;
 
-        let mut gui: Gui = (*self.world.read_resource::<Gui>()).clone();
+        let mut gui: Gui = (*self.sim.world.read_resource::<Gui>()).clone();
         self.imgui_wrapper
-            .render(ctx, &mut self.world, &mut gui, 1.0);
-        *self.world.write_resource::<Gui>() = gui;
+            .render(ctx, &mut self.sim.world, &mut gui, 1.0);
+        *self.sim.world.write_resource::<Gui>() = gui;
 
-        self.world.write_resource::<RenderStats>().render_time =
-            (std::time::Instant::now() - start_draw).as_secs_f32();
+        let mut stats = self.sim.world.write_resource::<RenderStats>();
+        stats.render_time = (std::time::Instant::now() - start_draw).as_secs_f32();
+        stats.draw_calls = draw_calls;
+        drop(stats);
 
         graphics::present(ctx)
     }
@@ -242,7 +261,8 @@ impl<'a> ggez::event::EventHandler for EngineState<'a> {
     }
 
     fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _: KeyMods, _: bool) {
-        self.world
+        self.sim
+            .world
             .write_resource::<KeyboardInfo>()
             .just_pressed
             .insert(scale_kc(keycode));
@@ -337,7 +357,7 @@ fn debug_coworld(rc: &mut RenderContext, world: &World) -> GameResult<()> {
         for y in &x.objs {
             rc.tess.draw_circle(y.pos, 10.0);
             rc.draw_text(
-                &format!("{}", lol.query_around(y.pos, 10.0).count()),
+                &format!("{}", lol.query_around(y.pos, 10.0, None).count()),
                 y.pos,
                 5.0,
                 Color::new(1.0, 1.0, 1.0, 1.0),
@@ -4,6 +4,8 @@ use cgmath::{ElementWise, EuclideanSpace, Point2, Vector2};
 use ggez::graphics::{
     Color, DrawMode, LineCap, LineJoin, MeshBuilder, StrokeOptions, Vertex, WHITE,
 };
+use scale::geometry::shapes::{circle_segments, triangulate};
+use scale::rendering::shape_batch::{ShapeBatch, ShapeInstance, ShapeKey};
 
 pub struct Tesselator {
     pub color: Color,
@@ -13,9 +15,12 @@ pub struct Tesselator {
     pub empty: bool,
     pub zoom: f32,
     pub cull: bool,
+    /// How far in pixels the sides of circles may be from the true circle, lower is smoother
+    pub circle_tolerance: f32,
 }
 
 const DEFAULT_THICKNESS: f32 = 0.2;
+pub const DEFAULT_CIRCLE_TOLERANCE: f32 = 0.3;
 impl Tesselator {
     pub fn new(screen_box: Rect, zoom: f32, cull: bool) -> Self {
         Tesselator {
@@ -26,6 +31,7 @@ impl Tesselator {
             empty: true,
             zoom,
             cull,
+            circle_tolerance: DEFAULT_CIRCLE_TOLERANCE,
         }
     }
 }
@@ -53,8 +59,20 @@ impl Tesselator {
         let pp = Point2::from_vec(p);
 
         if !self.cull || (r > 0.0 && self.screen_box.contains_within(p, r)) {
-            self.meshbuilder
-                .circle(self.mode, pp, r, 0.3 / self.zoom, self.color);
+            let n = circle_segments(r * self.zoom, self.circle_tolerance);
+            let points: Vec<Point2<f32>> = (0..n)
+                .map(|i| {
+                    let ang = i as f32 / n as f32 * 2.0 * std::f32::consts::PI;
+                    pp + Vector2::new(ang.cos(), ang.sin()) * r
+                })
+                .collect();
+            if self
+                .meshbuilder
+                .polygon(self.mode, &points, self.color)
+                .is_err()
+            {
+                return false;
+            }
 
             self.empty = false;
             true
@@ -177,6 +195,66 @@ impl Tesselator {
         self.draw_rect_cos_sin_uv(p, width, height, cos_sin, vec2(0.0, 0.0), vec2(0.0, 0.0))
     }
 
+    pub fn draw_polygon(&mut self, points: &[Vector2<f32>]) -> bool {
+        if points.len() < 3 {
+            return false;
+        }
+
+        if self.cull {
+            let (min, max) = points.iter().fold((points[0], points[0]), |(min, max), p| {
+                (
+                    vec2(min.x.min(p.x), min.y.min(p.y)),
+                    vec2(max.x.max(p.x), max.y.max(p.y)),
+                )
+            });
+            let bbox = Rect::new(min.x, min.y, max.x - min.x, max.y - min.y);
+            if !self.screen_box.overlaps(&bbox) {
+                return false;
+            }
+        }
+
+        match self.mode {
+            DrawMode::Fill(_) => {
+                let col = Color::new(
+                    from_srgb(self.color.r),
+                    from_srgb(self.color.g),
+                    from_srgb(self.color.b),
+                    self.color.a,
+                );
+
+                let verts: Vec<Vertex> = points
+                    .iter()
+                    .map(|p| Vertex {
+                        pos: [p.x, p.y],
+                        uv: [0.0, 0.0],
+                        color: [col.r, col.g, col.b, col.a],
+                    })
+                    .collect();
+
+                let indices: Vec<u32> = triangulate(points)
+                    .iter()
+                    .flat_map(|tri| tri.iter().map(|x| *x as u32))
+                    .collect();
+
+                if indices.is_empty() {
+                    return false;
+                }
+                self.meshbuilder.raw(&verts, &indices, None);
+            }
+            DrawMode::Stroke(_) => {
+                let points: Vec<Point2<f32>> =
+                    points.iter().map(|x| Point2::new(x.x, x.y)).collect();
+                // Degenerate polygons (zero length roads, collinear points) are skipped
+                if let Err(e) = self.meshbuilder.polygon(self.mode, &points, self.color) {
+                    eprintln!("skipping polygon: {}", e);
+                    return false;
+                }
+            }
+        }
+        self.empty = false;
+        true
+    }
+
     pub fn draw_stroke(&mut self, p1: Vector2<f32>, p2: Vector2<f32>, thickness: f32) -> bool {
         if self.cull
             && !self
@@ -264,4 +342,94 @@ impl Tesselator {
     pub fn draw_line(&mut self, p1: Vector2<f32>, p2: Vector2<f32>) -> bool {
         self.draw_stroke(p1, p2, 0.5 / self.zoom)
     }
+
+    /// Draws every instance of a batch. Filled shapes are tesselated once and all the visible
+    /// instances go into a single vertex buffer. Returns the number of instances drawn.
+    pub fn draw_batch(&mut self, batch: &ShapeBatch) -> usize {
+        let c = batch.key.color();
+        self.color = Color::new(c.r, c.g, c.b, c.a);
+        self.set_filled(batch.key.filled());
+
+        // Local outline of the shape, and how far it reaches from its center for culling
+        let (outline, reach, rotates): (Vec<Vector2<f32>>, f32, bool) = match batch.key {
+            ShapeKey::Circle { radius, .. } => {
+                let r = f32::from_bits(radius);
+                let n = circle_segments(r * self.zoom, self.circle_tolerance);
+                let outline = (0..n)
+                    .map(|i| {
+                        let ang = i as f32 / n as f32 * 2.0 * std::f32::consts::PI;
+                        vec2(ang.cos(), ang.sin()) * r
+                    })
+                    .collect();
+                (outline, r, false)
+            }
+            ShapeKey::Rect { width, height, .. } => {
+                let (w, h) = (f32::from_bits(width) / 2.0, f32::from_bits(height) / 2.0);
+                let outline = vec![vec2(w, h), vec2(w, -h), vec2(-w, -h), vec2(-w, h)];
+                (outline, w.max(h) * 2.0, true)
+            }
+        };
+        if reach <= 0.0 {
+            return 0;
+        }
+
+        let place = |inst: &ShapeInstance, p: Vector2<f32>| {
+            if rotates {
+                inst.pos
+                    + vec2(
+                        p.x * inst.dir.x - p.y * inst.dir.y,
+                        p.x * inst.dir.y + p.y * inst.dir.x,
+                    )
+            } else {
+                inst.pos + p
+            }
+        };
+        let visible: Vec<&ShapeInstance> = batch
+            .instances
+            .iter()
+            .filter(|inst| !self.cull || self.screen_box.contains_within(inst.pos, reach))
+            .collect();
+        if visible.is_empty() {
+            return 0;
+        }
+
+        if let DrawMode::Stroke(_) = self.mode {
+            let mut drawn = 0;
+            for inst in &visible {
+                let points: Vec<Point2<f32>> = outline
+                    .iter()
+                    .map(|p| Point2::from_vec(place(inst, *p)))
+                    .collect();
+                match self.meshbuilder.polygon(self.mode, &points, self.color) {
+                    Ok(_) => drawn += 1,
+                    Err(e) => eprintln!("skipping batch instance: {}", e),
+                }
+            }
+            self.empty &= drawn == 0;
+            return drawn;
+        }
+
+        let col = [from_srgb(c.r), from_srgb(c.g), from_srgb(c.b), c.a];
+        let n = outline.len() as u32;
+        let mut verts = Vec::with_capacity(visible.len() * outline.len());
+        let mut indices = Vec::with_capacity(visible.len() * (outline.len() - 2) * 3);
+        for inst in &visible {
+            let start = verts.len() as u32;
+            verts.extend(outline.iter().map(|p| {
+                let p = place(inst, *p);
+                Vertex {
+                    pos: [p.x, p.y],
+                    uv: [0.0, 0.0],
+                    color: col,
+                }
+            }));
+            // The outline is convex, so a fan from its first point covers it
+            for i in 1..n - 1 {
+                indices.extend_from_slice(&[start, start + i, start + i + 1]);
+            }
+        }
+        self.meshbuilder.raw(&verts, &indices, None);
+        self.empty = false;
+        visible.len()
+    }
 }
@@ -1,7 +1,8 @@
 use crate::game_loop::EngineState;
 use ggez::conf::NumSamples;
 use ggez::{conf, event, ContextBuilder};
-use scale::specs::{World, WorldExt};
+use scale::pedestrians::spawn_pedestrian;
+use scale::simulation::Simulation;
 use std::env;
 use std::path;
 
@@ -11,8 +12,14 @@ mod gui;
 mod rendering;
 
 fn main() {
-    let mut world = World::new();
-    let schedule = scale::setup(&mut world);
+    let (mut sim, load_errors) = Simulation::load();
+    for e in load_errors {
+        eprintln!("{}", e);
+    }
+
+    for _ in 0..5000 {
+        spawn_pedestrian(&mut sim.world);
+    }
 
     let mut c = conf::Conf::new();
     if cfg!(target_os = "windows") {
@@ -38,7 +45,7 @@ fn main() {
 
     let (ref mut ctx, ref mut event_loop) = cb.build().unwrap();
 
-    let mut state: EngineState = game_loop::EngineState::new(world, schedule, ctx).unwrap();
+    let mut state: EngineState = game_loop::EngineState::new(sim, ctx).unwrap();
 
     state.cam.camera.zoom = 10.0;
     state.cam.camera.position.x = 50.0;
@@ -0,0 +1,38 @@
+use crate::rendering::meshrenderable::scale_color;
+use crate::rendering::render_context::RenderContext;
+use cgmath::vec2;
+use scale::physics::Transform;
+use scale::rendering::Color;
+use scale::specs::{Join, World, WorldExt};
+use scale::vehicles::{BlinkerState, VehicleComponent};
+
+/// Blinks per second
+const BLINK_FREQ: f64 = 1.5;
+
+pub fn render_blinkers(world: &World, time: f64, rc: &mut RenderContext) {
+    if (time * BLINK_FREQ).fract() > 0.5 {
+        return;
+    }
+
+    let transforms = world.read_component::<Transform>();
+    let vehicles = world.read_component::<VehicleComponent>();
+
+    rc.tess.color = scale_color(Color::ORANGE);
+
+    for (trans, vehicle) in (&transforms, &vehicles).join() {
+        let sides: &[f32] = match vehicle.blinker {
+            BlinkerState::None => continue,
+            BlinkerState::Left => &[1.0],
+            BlinkerState::Right => &[-1.0],
+            BlinkerState::Hazard => &[1.0, -1.0],
+        };
+
+        let half_length = vehicle.kind.width() / 2.0 - 0.3;
+        for &side in sides {
+            for &along in &[half_length, -half_length] {
+                rc.tess
+                    .draw_circle(trans.project(vec2(along, side * 0.9)), 0.3);
+            }
+        }
+    }
+}
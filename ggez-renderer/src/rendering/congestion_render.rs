@@ -0,0 +1,33 @@
+use crate::rendering::meshrenderable::scale_color;
+use crate::rendering::render_context::RenderContext;
+use scale::analytics::Congestion;
+use scale::gui::Gui;
+use scale::map_model::Map;
+use scale::rendering::Color;
+use scale::specs::{World, WorldExt};
+
+pub fn render_congestion(world: &World, rc: &mut RenderContext) {
+    if !world.read_resource::<Gui>().show_congestion {
+        return;
+    }
+
+    let map = world.read_resource::<Map>();
+    let congestion = world.read_resource::<Congestion>();
+
+    for (id, level) in congestion.iter() {
+        let lane = match map.lanes().get(id) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // Green when traffic flows, red when it is jammed
+        rc.tess.color = scale_color(Color {
+            r: level,
+            g: 1.0 - level,
+            b: 0.0,
+            a: 0.7,
+        });
+        rc.tess
+            .draw_polyline(lane.points.as_slice(), lane.width * 0.5);
+    }
+}
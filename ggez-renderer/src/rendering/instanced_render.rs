@@ -60,6 +60,7 @@ impl InstancedRender {
         }
 
         for x in &mut self.texs {
+            rc.draw_calls += 1;
             x.draw(rc.ctx, DrawParam::default()).unwrap()
         }
     }
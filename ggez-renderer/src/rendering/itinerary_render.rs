@@ -0,0 +1,30 @@
+use crate::rendering::meshrenderable::scale_color;
+use crate::rendering::render_context::RenderContext;
+use scale::geometry::Vec2;
+use scale::gui::Gui;
+use scale::map_model::Map;
+use scale::physics::Transform;
+use scale::rendering::Color;
+use scale::specs::{Join, World, WorldExt};
+use scale::vehicles::VehicleComponent;
+
+pub fn render_itineraries(world: &World, rc: &mut RenderContext) {
+    if !world.read_resource::<Gui>().show_itineraries {
+        return;
+    }
+
+    let map = world.read_resource::<Map>();
+    let transforms = world.read_component::<Transform>();
+    let vehicles = world.read_component::<VehicleComponent>();
+
+    rc.tess.color = scale_color(Color::CYAN);
+
+    for (trans, vehicle) in (&transforms, &vehicles).join() {
+        let mut points: Vec<Vec2> = vec![trans.position()];
+        points.extend(vehicle.itinerary.remaining_geometry(&map).iter());
+
+        if points.len() >= 2 {
+            rc.tess.draw_polyline(&points, 0.3);
+        }
+    }
+}
@@ -1,59 +1,163 @@
 use crate::rendering::render_context::RenderContext;
+use cgmath::{vec2, Vector2};
 use ggez::graphics::Color;
+use scale::geometry::shapes::{dashes, rounded_rect};
 use scale::physics::Transform;
 use scale::rendering::meshrender_component::{
-    CircleRender, LineRender, LineToRender, MeshRenderEnum, RectRender,
+    CircleRender, LineRender, LineToRender, MeshRenderEnum, PolygonRender, RectRender,
 };
+use scale::rendering::shape_batch::{ShapeInstance, ShapeKey};
 use scale::specs::ReadStorage;
 
+/// Number of segments used to draw each rounded corner of a rect
+const CORNER_SEGMENTS: usize = 4;
+
+/// Circles and sharp rects are not tesselated right away but pushed to the RenderContext
+/// batches, with the depth of the order in its MeshRender so that batches keep the order
+/// of the orders.
 pub trait MeshRenderable: Send + Sync {
-    fn draw(&self, trans: &Transform, transforms: &ReadStorage<Transform>, rc: &mut RenderContext);
+    fn draw(
+        &self,
+        trans: &Transform,
+        transforms: &ReadStorage<Transform>,
+        depth: usize,
+        rc: &mut RenderContext,
+    );
 }
 
 impl MeshRenderable for MeshRenderEnum {
-    fn draw(&self, trans: &Transform, transforms: &ReadStorage<Transform>, rc: &mut RenderContext) {
+    fn draw(
+        &self,
+        trans: &Transform,
+        transforms: &ReadStorage<Transform>,
+        depth: usize,
+        rc: &mut RenderContext,
+    ) {
         match self {
-            MeshRenderEnum::Circle(x) => x.draw(trans, transforms, rc),
-            MeshRenderEnum::Rect(x) => x.draw(trans, transforms, rc),
-            MeshRenderEnum::LineTo(x) => x.draw(trans, transforms, rc),
-            MeshRenderEnum::Line(x) => x.draw(trans, transforms, rc),
+            MeshRenderEnum::Circle(x) => x.draw(trans, transforms, depth, rc),
+            MeshRenderEnum::Rect(x) => x.draw(trans, transforms, depth, rc),
+            MeshRenderEnum::LineTo(x) => x.draw(trans, transforms, depth, rc),
+            MeshRenderEnum::Line(x) => x.draw(trans, transforms, depth, rc),
+            MeshRenderEnum::Polygon(x) => x.draw(trans, transforms, depth, rc),
         }
     }
 }
 
 impl MeshRenderable for CircleRender {
-    fn draw(&self, pos: &Transform, _: &ReadStorage<Transform>, rc: &mut RenderContext) {
-        rc.tess.color = scale_color(self.color);
-        rc.tess.set_filled(self.filled);
-        rc.tess.draw_circle(pos.project(self.offset), self.radius);
+    fn draw(
+        &self,
+        pos: &Transform,
+        _: &ReadStorage<Transform>,
+        depth: usize,
+        rc: &mut RenderContext,
+    ) {
+        rc.batches.push(
+            depth,
+            ShapeKey::circle(self.radius * pos.scale(), self.color, self.filled),
+            ShapeInstance {
+                pos: pos.project(self.offset),
+                dir: pos.direction(),
+            },
+        );
     }
 }
 
 impl MeshRenderable for RectRender {
-    fn draw(&self, trans: &Transform, _: &ReadStorage<Transform>, rc: &mut RenderContext) {
+    fn draw(
+        &self,
+        trans: &Transform,
+        _: &ReadStorage<Transform>,
+        depth: usize,
+        rc: &mut RenderContext,
+    ) {
+        if self.corner_radius <= 0.0 {
+            let scale = trans.scale();
+            rc.batches.push(
+                depth,
+                ShapeKey::rect(
+                    self.width * scale,
+                    self.height * scale,
+                    self.color,
+                    self.filled,
+                ),
+                ShapeInstance {
+                    pos: trans.project(self.offset),
+                    dir: trans.direction(),
+                },
+            );
+            return;
+        }
+
         rc.tess.color = scale_color(self.color);
         rc.tess.set_filled(self.filled);
-        let rect_pos = trans.position() + trans.apply_rotation(self.offset);
-        rc.tess
-            .draw_rect_cos_sin(rect_pos, self.width, self.height, trans.direction());
+        // Built in local space, project() rotates and scales it
+        let local = rounded_rect(
+            self.offset,
+            self.width,
+            self.height,
+            vec2(1.0, 0.0),
+            self.corner_radius,
+            CORNER_SEGMENTS,
+        );
+        let mut points = Vec::with_capacity(local.len());
+        trans.project_many(&local, &mut points);
+        rc.tess.draw_polygon(&points);
     }
 }
 
 impl MeshRenderable for LineToRender {
-    fn draw(&self, trans: &Transform, transforms: &ReadStorage<Transform>, rc: &mut RenderContext) {
+    fn draw(
+        &self,
+        trans: &Transform,
+        transforms: &ReadStorage<Transform>,
+        _: usize,
+        rc: &mut RenderContext,
+    ) {
         let e = self.to;
         let pos2 = transforms.get(e).unwrap().position();
         rc.tess.color = scale_color(self.color);
-        rc.tess.draw_stroke(trans.position(), pos2, self.thickness);
+        rc.tess
+            .draw_stroke(trans.position(), pos2, self.thickness * trans.scale());
     }
 }
 
 impl MeshRenderable for LineRender {
-    fn draw(&self, trans: &Transform, _: &ReadStorage<Transform>, rc: &mut RenderContext) {
+    fn draw(
+        &self,
+        trans: &Transform,
+        _: &ReadStorage<Transform>,
+        _: usize,
+        rc: &mut RenderContext,
+    ) {
+        let scale = trans.scale();
         let start = trans.position();
-        let end = start + self.offset;
+        let end = start + self.offset * scale;
+        let thickness = self.thickness * scale;
         rc.tess.color = scale_color(self.color);
-        rc.tess.draw_stroke(start, end, self.thickness);
+        match self.dash {
+            Some((dash, gap)) => {
+                for (a, b) in dashes(start, end, dash * scale, gap * scale) {
+                    rc.tess.draw_stroke(a, b, thickness);
+                }
+            }
+            None => rc.tess.draw_stroke(start, end, thickness),
+        }
+    }
+}
+
+impl MeshRenderable for PolygonRender {
+    fn draw(
+        &self,
+        trans: &Transform,
+        _: &ReadStorage<Transform>,
+        _: usize,
+        rc: &mut RenderContext,
+    ) {
+        let mut points: Vec<Vector2<f32>> = Vec::with_capacity(self.points.len());
+        trans.project_many(&self.points, &mut points);
+        rc.tess.color = scale_color(self.color);
+        rc.tess.set_filled(self.filled);
+        rc.tess.draw_polygon(&points);
     }
 }
 
@@ -0,0 +1,75 @@
+use crate::rendering::meshrenderable::scale_color;
+use crate::rendering::render_context::RenderContext;
+use cgmath::vec2;
+use scale::geometry::rect::Rect;
+use scale::geometry::Vec2;
+use scale::gui::Gui;
+use scale::map_model::Map;
+use scale::physics::Transform;
+use scale::rendering::minimap::{map_bounds, MinimapProjection};
+use scale::rendering::Color;
+use scale::specs::{Join, World, WorldExt};
+use scale::vehicles::{VehicleComponent, VehicleKind};
+
+/// Size in pixels of the longest side of the minimap
+const MINIMAP_SIZE: f32 = 250.0;
+/// Distance in pixels between the minimap and the screen corner
+const MINIMAP_MARGIN: f32 = 10.0;
+
+pub fn render_minimap(world: &World, rc: &mut RenderContext) {
+    if !world.read_resource::<Gui>().show_minimap {
+        return;
+    }
+
+    let map = world.read_resource::<Map>();
+    let bounds = match map_bounds(&map) {
+        Some(x) if x.w > 0.0 && x.h > 0.0 => x,
+        _ => return,
+    };
+
+    // Everything is drawn in world space, so pixel sizes are divided by the zoom to stay
+    // the same on screen whatever the camera does
+    let px = 1.0 / rc.cam.camera.zoom;
+    let ratio = bounds.w / bounds.h;
+    let (w, h) = if ratio > 1.0 {
+        (MINIMAP_SIZE, MINIMAP_SIZE / ratio)
+    } else {
+        (MINIMAP_SIZE * ratio, MINIMAP_SIZE)
+    };
+
+    let view = rc.view_rect();
+    let target = Rect::new(
+        view.x + MINIMAP_MARGIN * px,
+        view.y + MINIMAP_MARGIN * px,
+        w * px,
+        h * px,
+    );
+    let proj = MinimapProjection::new(bounds, target);
+
+    rc.tess.color = scale_color(Color::gray(0.1));
+    rc.tess.draw_polygon(&[
+        vec2(target.x, target.y),
+        vec2(target.x + target.w, target.y),
+        vec2(target.x + target.w, target.y + target.h),
+        vec2(target.x, target.y + target.h),
+    ]);
+
+    rc.tess.color = scale_color(Color::gray(0.6));
+    for lane in map.lanes().values() {
+        let points: Vec<Vec2> = lane.points.iter().map(|&p| proj.project(p)).collect();
+        rc.tess.draw_polyline(&points, px);
+    }
+
+    let transforms = world.read_component::<Transform>();
+    let vehicles = world.read_component::<VehicleComponent>();
+
+    for (trans, vehicle) in (&transforms, &vehicles).join() {
+        rc.tess.color = scale_color(match vehicle.kind {
+            VehicleKind::Car => Color::CYAN,
+            VehicleKind::Bus => Color::ORANGE,
+            VehicleKind::Motorcycle => Color::RED,
+        });
+        rc.tess
+            .draw_circle(proj.project(trans.position()), 2.0 * px);
+    }
+}
@@ -1,7 +1,12 @@
+pub mod blinker_render;
 pub mod camera_handler;
+pub mod congestion_render;
 pub mod instanced_render;
+pub mod itinerary_render;
 pub mod meshrenderable;
+pub mod minimap_render;
 pub mod render_context;
 pub mod road_rendering;
+pub mod sensor_render;
 
 pub mod sorted_mesh_renderer;
@@ -4,12 +4,17 @@ use crate::rendering::camera_handler::CameraHandler;
 use cgmath::{EuclideanSpace, Point2, Vector2};
 use ggez::graphics::{Color, DrawParam, Font, Image, Mesh, Text};
 use ggez::{graphics, Context, GameResult};
+use scale::rendering::shape_batch::ShapeBatches;
 
 pub struct RenderContext<'a> {
     pub cam: &'a mut camera_handler::CameraHandler,
     pub tess: Tesselator,
+    /// Shapes waiting to be tesselated together with the identical ones
+    pub batches: ShapeBatches,
     font: Option<Font>,
     pub ctx: &'a mut Context,
+    /// Number of draw calls issued to the GPU this frame
+    pub draw_calls: u32,
 }
 
 impl<'a> RenderContext<'a> {
@@ -24,10 +29,24 @@ impl<'a> RenderContext<'a> {
             ctx,
             cam,
             tess,
+            batches: ShapeBatches::default(),
             font,
+            draw_calls: 0,
         }
     }
 
+    /// Sets how smooth circles are drawn, as the largest gap in pixels between their sides and
+    /// the true circle. Circles get more segments as they get bigger on screen.
+    pub fn set_circle_tolerance(&mut self, pixels: f32) {
+        self.tess.circle_tolerance = pixels;
+    }
+
+    /// The rectangle of the world currently seen by the camera
+    pub fn view_rect(&self) -> scale::geometry::rect::Rect {
+        let b = self.tess.screen_box;
+        scale::geometry::rect::Rect::new(b.x, b.y, b.w, b.h)
+    }
+
     pub fn clear(&mut self) {
         graphics::clear(self.ctx, graphics::Color::from_rgb(0, 0, 0));
     }
@@ -71,6 +90,7 @@ impl<'a> RenderContext<'a> {
             .color(color)
             .dest(Point2::from_vec(pos))
             .scale([0.02 * size, -0.02 * size]);
+        self.draw_calls += 1;
         graphics::draw(self.ctx, &text, trans)
     }
 
@@ -80,24 +100,39 @@ impl<'a> RenderContext<'a> {
         let trans = graphics::DrawParam::new()
             .dest(Point2::from_vec(pos))
             .scale([1.0, -1.0]);
+        self.draw_calls += 1;
         graphics::draw(self.ctx, image, trans)
     }
 
-    #[allow(dead_code)]
     pub fn draw_mesh(&mut self, mesh: &Mesh, dp: DrawParam) -> GameResult<()> {
+        self.draw_calls += 1;
         graphics::draw(self.ctx, mesh, dp)
     }
 
+    /// Tesselates the batched shapes, one vertex buffer per shape signature. Called at the
+    /// end of each layer so that batches don't end up over the next layers.
+    pub fn flush_batches(&mut self) {
+        for batch in self.batches.drain() {
+            self.tess.draw_batch(&batch);
+        }
+    }
+
+    /// Draws everything tesselated since the last flush as a single mesh, so that all the
+    /// MeshRender orders of a frame end up in one draw call
     pub fn flush(&mut self) -> GameResult<()> {
+        self.flush_batches();
         if !self.tess.empty {
             let mesh = self.tess.meshbuilder.build(self.ctx)?;
+            self.draw_calls += 1;
             graphics::draw(self.ctx, &mesh, DrawParam::new().dest([0.0, 0.0]))?;
             self.tess.reset();
         }
         Ok(())
     }
 
-    pub fn finish(mut self) -> GameResult<()> {
-        self.flush()
+    /// Flushes the remaining tesselated shapes and returns the number of draw calls of the frame
+    pub fn finish(mut self) -> GameResult<u32> {
+        self.flush()?;
+        Ok(self.draw_calls)
     }
 }
@@ -50,7 +50,13 @@ impl RoadRenderer {
             sr.draw_polyline(n.points.as_slice(), w);
         }
 
+        // Fill the gap between the roads' ends
         sr.color = MID_GRAY;
+        for inter in inters.values() {
+            let polygon = inter.compute_polygon(map.roads(), lanes);
+            sr.draw_polygon(polygon.as_slice());
+        }
+
         for n in lanes.values() {
             sr.color = match n.kind {
                 LaneKind::Walking => HIGH_GRAY,
@@ -147,6 +153,22 @@ impl RoadRenderer {
                 continue;
             }
 
+            if n.control.is_yield() {
+                let triangle = |size: f32| {
+                    [
+                        r_center - dir * size,
+                        r_center + dir * size * 0.5 + dir_nor * size,
+                        r_center + dir * size * 0.5 - dir_nor * size,
+                    ]
+                };
+
+                sr.color = scale_color(scale::rendering::Color::RED);
+                sr.draw_polygon(&triangle(1.2));
+                sr.color = scale_color(scale::rendering::Color::WHITE);
+                sr.draw_polygon(&triangle(0.7));
+                continue;
+            }
+
             sr.color = scale_color(scale::rendering::Color::gray(0.3));
             sr.draw_rect_cos_sin(r_center, 1.1, 3.1, dir);
 
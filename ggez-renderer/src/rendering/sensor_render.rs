@@ -0,0 +1,38 @@
+use crate::rendering::meshrenderable::scale_color;
+use crate::rendering::render_context::RenderContext;
+use scale::gui::Gui;
+use scale::physics::Transform;
+use scale::rendering::Color;
+use scale::specs::{Join, World, WorldExt};
+use scale::vehicles::systems::SENSOR_RANGE;
+use scale::vehicles::VehicleComponent;
+
+pub fn render_sensors(world: &World, rc: &mut RenderContext) {
+    if !world.read_resource::<Gui>().show_sensors {
+        return;
+    }
+
+    let transforms = world.read_component::<Transform>();
+    let vehicles = world.read_component::<VehicleComponent>();
+
+    for (trans, vehicle) in (&transforms, &vehicles).join() {
+        let pos = trans.position();
+        let dir = trans.direction();
+        let normal = trans.normal();
+        let (sin, cos) = vehicle.sensor_angle.sin_cos();
+
+        // Edges of the cone
+        rc.tess.color = scale_color(Color::MAGENTA);
+        for &side in &[1.0, -1.0] {
+            let edge = dir * cos + normal * (sin * side);
+            rc.tess.draw_stroke(pos, pos + edge * SENSOR_RANGE, 0.1);
+        }
+
+        // Lateral limits applied while on a lane
+        rc.tess.color = scale_color(Color::YELLOW);
+        for &side in &[1.0, -1.0] {
+            let from = pos + normal * (vehicle.sensor_width * side);
+            rc.tess.draw_stroke(from, from + dir * SENSOR_RANGE, 0.1);
+        }
+    }
+}
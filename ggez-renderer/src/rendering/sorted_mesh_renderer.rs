@@ -29,15 +29,18 @@ impl SortedMeshRenderer {
             self.layers[mr.layer() as usize].add(id);
         }
 
+        let view = rc.view_rect();
+
         for b in &self.layers {
             for (trans, mr, _) in (&transforms, &mesh_render, b).join() {
-                if mr.hide {
+                if mr.hide || !mr.is_visible(trans, &view) {
                     continue;
                 }
-                for order in &mr.orders {
-                    order.draw(trans, &transforms, rc);
+                for (depth, order) in mr.orders.iter().enumerate() {
+                    order.draw(trans, &transforms, depth, rc);
                 }
             }
+            rc.flush_batches();
         }
     }
 }
@@ -0,0 +1,386 @@
+use crate::engine_interaction::TimeInfo;
+use crate::geometry::Vec2;
+use crate::map_model::{LaneID, LaneLoad, TraverseKind};
+use crate::physics::Kinematics;
+use crate::vehicles::VehicleComponent;
+use cgmath::InnerSpace;
+use specs::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+/// Maximum number of events kept, the oldest ones are dropped first
+pub const MAX_SIM_EVENTS: usize = 10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimEventKind {
+    /// Two bodies overlapped and were pushed apart
+    Collision,
+    /// A moving vehicle had to stop for an obstacle in front of it
+    AvoidanceStop,
+    /// A vehicle kept being blocked until it lost patience
+    Honk,
+    /// An entity left the world bounds, or its position stopped being finite
+    OutOfBounds,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SimEvent {
+    pub kind: SimEventKind,
+    pub time: f64,
+    /// For a stop, the vehicle that stopped then the obstacle. For a honk, the vehicle twice
+    pub entities: (Entity, Entity),
+    pub pos: Vec2,
+}
+
+/// Record of the notable things that happened during the simulation, for analytics.
+/// It is a plain resource without locking: parallel systems gather their events in the join
+/// results and merge them with extend once it is done, instead of writing from each thread.
+#[derive(Default)]
+pub struct SimEvents {
+    events: VecDeque<SimEvent>,
+}
+
+impl SimEvents {
+    pub fn push(&mut self, event: SimEvent) {
+        if self.events.len() >= MAX_SIM_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Adds a batch of events in order, such as the ones gathered by a parallel join
+    pub fn extend(&mut self, events: impl IntoIterator<Item = SimEvent>) {
+        for event in events {
+            self.push(event);
+        }
+    }
+
+    pub fn count(&self, kind: SimEventKind) -> usize {
+        self.events.iter().filter(|x| x.kind == kind).count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SimEvent> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+/// Speed under which a vehicle counts as jammed
+pub const CONGESTION_SPEED: f32 = 1.0;
+
+/// Time constant in seconds of the exponential smoothing of the congestion
+pub const CONGESTION_DECAY: f32 = 10.0;
+
+/// Number of jammed vehicles on a lane for which its congestion is at its maximum
+pub const CONGESTION_SATURATION: f32 = 5.0;
+
+/// Smoothed number of jammed vehicles on each lane
+#[derive(Default)]
+pub struct Congestion {
+    levels: HashMap<LaneID, f32>,
+}
+
+impl Congestion {
+    /// Moves every lane's level towards its current number of jammed vehicles
+    pub fn update(&mut self, jammed: &HashMap<LaneID, usize>, delta: f32) {
+        let alpha = 1.0 - (-delta / CONGESTION_DECAY).exp();
+
+        for &lane in jammed.keys() {
+            self.levels.entry(lane).or_insert(0.0);
+        }
+        for (lane, level) in self.levels.iter_mut() {
+            let count = jammed.get(lane).copied().unwrap_or(0) as f32;
+            *level += (count - *level) * alpha;
+        }
+        self.levels.retain(|_, level| *level > 1e-3);
+    }
+
+    /// Congestion of the lane between 0 (free) and 1 (jammed)
+    pub fn get(&self, lane: LaneID) -> f32 {
+        self.levels
+            .get(&lane)
+            .map_or(0.0, |x| (x / CONGESTION_SATURATION).min(1.0))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LaneID, f32)> + '_ {
+        self.levels.keys().map(move |&lane| (lane, self.get(lane)))
+    }
+}
+
+impl LaneLoad for Congestion {
+    fn lane_load(&self, lane: LaneID) -> f32 {
+        self.get(lane)
+    }
+}
+
+pub struct CongestionSystem;
+impl<'a> System<'a> for CongestionSystem {
+    type SystemData = (
+        Read<'a, TimeInfo>,
+        Write<'a, Congestion>,
+        ReadStorage<'a, VehicleComponent>,
+        ReadStorage<'a, Kinematics>,
+    );
+
+    fn run(&mut self, (time, mut congestion, vehicles, kinematics): Self::SystemData) {
+        let mut jammed: HashMap<LaneID, usize> = HashMap::new();
+        for (vehicle, kin) in (&vehicles, &kinematics).join() {
+            if vehicle.wait_time <= 0.0 && kin.velocity.magnitude() >= CONGESTION_SPEED {
+                continue;
+            }
+            if let Some(TraverseKind::Lane(lane)) = vehicle.itinerary.get_travers().map(|x| x.kind)
+            {
+                *jammed.entry(lane).or_default() += 1;
+            }
+        }
+
+        congestion.update(&jammed, time.delta);
+    }
+}
+
+/// Number of vehicles currently on each lane, rebuilt every tick by the LaneOccupancySystem
+#[derive(Default)]
+pub struct LaneOccupancy {
+    counts: HashMap<LaneID, usize>,
+}
+
+impl LaneOccupancy {
+    pub fn get(&self, lane: LaneID) -> usize {
+        self.counts.get(&lane).copied().unwrap_or(0)
+    }
+
+    /// Lanes with at least one vehicle on them
+    pub fn iter(&self) -> impl Iterator<Item = (LaneID, usize)> + '_ {
+        self.counts.iter().map(|(&lane, &count)| (lane, count))
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    pub fn add(&mut self, lane: LaneID) {
+        *self.counts.entry(lane).or_default() += 1;
+    }
+}
+
+pub struct LaneOccupancySystem;
+impl<'a> System<'a> for LaneOccupancySystem {
+    type SystemData = (Write<'a, LaneOccupancy>, ReadStorage<'a, VehicleComponent>);
+
+    fn run(&mut self, (mut occupancy, vehicles): Self::SystemData) {
+        occupancy.clear();
+        for vehicle in vehicles.join() {
+            if let Some(TraverseKind::Lane(lane)) = vehicle.itinerary.get_travers().map(|x| x.kind)
+            {
+                occupancy.add(lane);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::{
+        Itinerary, LaneKind, LanePatternBuilder, Map, Traversable, TraverseDirection,
+    };
+    use crate::vehicles::VehicleKind;
+
+    #[test]
+    fn test_congestion_rises_and_decays() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().build());
+        let lane = *map.roads()[road]
+            .outgoing_lanes_from(a)
+            .iter()
+            .find(|x| map.lanes()[**x].kind == LaneKind::Driving)
+            .unwrap();
+
+        let mut it = Itinerary::default();
+        it.set_simple(
+            Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward),
+            &map,
+        );
+
+        let mut world = World::new();
+        world.register::<VehicleComponent>();
+        world.register::<Kinematics>();
+        world.insert(TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        });
+        world.insert(Congestion::default());
+
+        let stopped: Vec<Entity> = (0..5)
+            .map(|_| {
+                world
+                    .create_entity()
+                    .with(VehicleComponent::new(it.clone(), VehicleKind::Car))
+                    .with(Kinematics::from_mass(1000.0))
+                    .build()
+            })
+            .collect();
+
+        for _ in 0..1800 {
+            CongestionSystem.run_now(&world);
+        }
+        let jammed = world.read_resource::<Congestion>().get(lane);
+        assert!(jammed > 0.9);
+
+        // Traffic clears up: some drive away, the others leave the simulation
+        for (i, &e) in stopped.iter().enumerate() {
+            if i % 2 == 0 {
+                world
+                    .write_storage::<Kinematics>()
+                    .get_mut(e)
+                    .unwrap()
+                    .velocity = vec2!(10.0, 0.0);
+            } else {
+                world.delete_entity(e).unwrap();
+            }
+        }
+        world.maintain();
+
+        let mut last = jammed;
+        for _ in 0..60 {
+            for _ in 0..30 {
+                CongestionSystem.run_now(&world);
+            }
+            let level = world.read_resource::<Congestion>().get(lane);
+            assert!(level < last);
+            last = level;
+        }
+        assert!(last < 0.01);
+    }
+
+    #[test]
+    fn test_merge_events_from_threads() {
+        let mut world = World::new();
+        let a = world.create_entity().build();
+        let b = world.create_entity().build();
+
+        const N_THREADS: usize = 8;
+        const PER_THREAD: usize = 500;
+
+        let handles: Vec<_> = (0..N_THREADS)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    (0..PER_THREAD)
+                        .map(|i| SimEvent {
+                            kind: if i % 5 == 0 {
+                                SimEventKind::Collision
+                            } else {
+                                SimEventKind::AvoidanceStop
+                            },
+                            time: (t * PER_THREAD + i) as f64,
+                            entities: (a, b),
+                            pos: vec2!(t as f32, i as f32),
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut events = SimEvents::default();
+        for h in handles {
+            events.extend(h.join().unwrap());
+        }
+
+        assert_eq!(events.len(), N_THREADS * PER_THREAD);
+        assert_eq!(
+            events.count(SimEventKind::Collision),
+            N_THREADS * PER_THREAD / 5
+        );
+        assert_eq!(
+            events.count(SimEventKind::AvoidanceStop),
+            N_THREADS * PER_THREAD * 4 / 5
+        );
+
+        // Every event made it exactly once, in the order of the threads
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.time, i as f64);
+        }
+
+        // Still capped when merging
+        events.extend(events.iter().copied().collect::<Vec<_>>());
+        events.extend(events.iter().copied().collect::<Vec<_>>());
+        assert_eq!(events.len(), MAX_SIM_EVENTS);
+    }
+
+    #[test]
+    fn test_lane_occupancy() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().build());
+        let lane_at = |from| {
+            *map.roads()[road]
+                .outgoing_lanes_from(from)
+                .iter()
+                .find(|x| map.lanes()[**x].kind == LaneKind::Driving)
+                .unwrap()
+        };
+        let (forward, backward) = (lane_at(a), lane_at(b));
+        let empty = map.roads()[road]
+            .lanes_iter()
+            .copied()
+            .find(|&x| map.lanes()[x].kind == LaneKind::Walking)
+            .unwrap();
+
+        let mut world = World::new();
+        world.register::<VehicleComponent>();
+        world.insert(LaneOccupancy::default());
+
+        let mut spawn = |lane| {
+            let mut it = Itinerary::default();
+            it.set_simple(
+                Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward),
+                &map,
+            );
+            world
+                .create_entity()
+                .with(
+                    VehicleComponent::builder(VehicleKind::Car)
+                        .with_itinerary(it)
+                        .build(),
+                )
+                .build()
+        };
+        let first = spawn(forward);
+        spawn(forward);
+        spawn(forward);
+        spawn(backward);
+        // Vehicles without itinerary aren't on any lane
+        world
+            .create_entity()
+            .with(VehicleComponent::builder(VehicleKind::Car).build())
+            .build();
+
+        LaneOccupancySystem.run_now(&world);
+        {
+            let occupancy = world.read_resource::<LaneOccupancy>();
+            assert_eq!(occupancy.get(forward), 3);
+            assert_eq!(occupancy.get(backward), 1);
+            assert_eq!(occupancy.get(empty), 0);
+            assert_eq!(occupancy.iter().count(), 2);
+        }
+
+        // Recomputed from scratch each tick
+        world.delete_entity(first).unwrap();
+        world.maintain();
+        LaneOccupancySystem.run_now(&world);
+        assert_eq!(world.read_resource::<LaneOccupancy>().get(forward), 2);
+    }
+}
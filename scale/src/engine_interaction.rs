@@ -1,18 +1,24 @@
 use crate::geometry::Vec2;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 #[derive(Default)]
 pub struct RenderStats {
     pub update_time: f32,
     pub render_time: f32,
+    pub draw_calls: u32,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct TimeInfo {
     pub delta: f32,
     pub time: f64,
     pub time_seconds: u64,
-    pub time_speed: f64,
+    /// How many times faster than real time the simulation runs, by doing more or fewer
+    /// fixed steps per frame. The delta of each step doesn't change.
+    pub time_scale: f32,
+    /// No step is done at all while paused
+    pub paused: bool,
 }
 
 impl Default for TimeInfo {
@@ -21,11 +27,57 @@ impl Default for TimeInfo {
             delta: 0.0,
             time: 0.0,
             time_seconds: 0,
-            time_speed: 1.0,
+            time_scale: 1.0,
+            paused: false,
         }
     }
 }
 
+/// Duration of one simulation step, independent of the render rate
+pub const TIME_STEP: f64 = 1.0 / 30.0;
+
+/// Maximum number of simulation steps done in a single frame, so that a slow frame doesn't make
+/// the simulation spiral into doing more and more steps
+pub const MAX_TICKS_PER_FRAME: u32 = 3;
+
+impl TimeInfo {
+    /// Advances the time by one step of delta seconds
+    pub fn advance(&mut self, delta: f64) {
+        self.delta = delta as f32;
+        self.time += delta;
+        self.time_seconds = self.time as u64;
+    }
+}
+
+/// Accumulates real frame time and tells how many fixed steps the simulation must do,
+/// carrying the remainder to the next frame.
+#[derive(Default)]
+pub struct FixedTimeStep {
+    accumulator: f64,
+}
+
+impl FixedTimeStep {
+    /// The frame time is sped up by the time scale of the simulation, none is accumulated
+    /// while it is paused
+    pub fn ticks(&mut self, frame_delta: f64, time: &TimeInfo) -> u32 {
+        if time.paused {
+            return 0;
+        }
+        let scale = time.time_scale.max(0.0);
+        self.accumulator += frame_delta * scale as f64;
+
+        let max_ticks = (MAX_TICKS_PER_FRAME as f32 * scale.max(1.0)).ceil() as u32;
+        let ticks = (self.accumulator / TIME_STEP) as u32;
+        if ticks > max_ticks {
+            self.accumulator = 0.0;
+            return max_ticks;
+        }
+
+        self.accumulator -= ticks as f64 * TIME_STEP;
+        ticks
+    }
+}
+
 pub const MAX_LAYERS: u32 = 20;
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
@@ -260,3 +312,82 @@ pub enum KeyCode {
     Paste,
     Cut,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps_for(frames: &[f64], wall_time: f64) -> u32 {
+        let mut fixed = FixedTimeStep::default();
+        let mut time = TimeInfo::default();
+        let mut elapsed = 0.0;
+        let mut steps = 0;
+
+        for frame in frames.iter().cycle() {
+            if elapsed + frame > wall_time + 1e-9 {
+                break;
+            }
+            elapsed += frame;
+
+            for _ in 0..fixed.ticks(*frame, &time) {
+                time.advance(TIME_STEP);
+                assert_eq!(time.delta, TIME_STEP as f32);
+                steps += 1;
+            }
+        }
+
+        steps
+    }
+
+    #[test]
+    fn test_fixed_rate_regardless_of_frame_times() {
+        let wall_time = 10.0;
+        let expected = (wall_time / TIME_STEP) as i64;
+
+        let frame_patterns: [&[f64]; 5] = [
+            &[1.0 / 60.0],
+            &[1.0 / 144.0],
+            &[1.0 / 20.0],
+            &[0.01, 0.09],
+            &[0.005, 0.031, 0.047, 0.012, 0.08],
+        ];
+
+        for frames in &frame_patterns {
+            let steps = steps_for(frames, wall_time) as i64;
+            assert!((steps - expected).abs() <= 1, "{} steps", steps);
+        }
+    }
+
+    #[test]
+    fn test_slow_frame_is_capped() {
+        let time = TimeInfo::default();
+        let mut fixed = FixedTimeStep::default();
+        assert_eq!(fixed.ticks(1.0, &time), MAX_TICKS_PER_FRAME);
+        assert_eq!(fixed.ticks(0.0, &time), 0);
+    }
+
+    #[test]
+    fn test_time_scale_and_pause_change_ticks() {
+        let ticks_over = |time: TimeInfo, frames: usize| {
+            let mut fixed = FixedTimeStep::default();
+            (0..frames)
+                .map(|_| fixed.ticks(1.0 / 60.0, &time))
+                .sum::<u32>()
+        };
+        let scaled = |time_scale, paused| TimeInfo {
+            time_scale,
+            paused,
+            ..Default::default()
+        };
+
+        let normal = ticks_over(scaled(1.0, false), 600) as i64;
+        assert!((normal - 300).abs() <= 1);
+        let fast = ticks_over(scaled(2.0, false), 600) as i64;
+        assert!((fast - 600).abs() <= 1);
+        let faster = ticks_over(scaled(3.0, false), 600) as i64;
+        assert!((faster - 900).abs() <= 1);
+
+        assert_eq!(ticks_over(scaled(2.0, true), 600), 0);
+        assert_eq!(ticks_over(scaled(0.0, false), 600), 0);
+    }
+}
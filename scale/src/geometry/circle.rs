@@ -0,0 +1,80 @@
+use super::Vec2;
+use crate::geometry::rect::Rect;
+use cgmath::InnerSpace;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(center: Vec2, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Checks whether the `Circle` contains a point, its border included
+    pub fn contains(&self, p: Vec2) -> bool {
+        (p - self.center).magnitude2() <= self.radius * self.radius
+    }
+
+    /// Checks whether the `Circle` overlaps another `Circle`
+    pub fn intersects(&self, other: &Circle) -> bool {
+        let r = self.radius + other.radius;
+        (other.center - self.center).magnitude2() <= r * r
+    }
+
+    /// Checks whether the `Circle` overlaps a `Rect`, using the point of the rect closest to its center
+    pub fn intersects_rect(&self, rect: &Rect) -> bool {
+        let closest = vec2!(
+            self.center.x.max(rect.left()).min(rect.right()),
+            self.center.y.max(rect.top()).min(rect.bottom())
+        );
+        self.contains(closest)
+    }
+
+    /// The smallest `Rect` containing the `Circle`
+    pub fn bbox(&self) -> Rect {
+        Rect::new(
+            self.center.x - self.radius,
+            self.center.y - self.radius,
+            2.0 * self.radius,
+            2.0 * self.radius,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let c = Circle::new(vec2!(1.0, 2.0), 3.0);
+        assert!(c.contains(vec2!(1.0, 2.0)));
+        assert!(c.contains(vec2!(4.0, 2.0)));
+        assert!(c.contains(vec2!(3.0, 4.0)));
+        assert!(!c.contains(vec2!(3.5, 4.5)));
+        assert!(!c.contains(vec2!(1.0, -1.1)));
+    }
+
+    #[test]
+    fn test_intersects() {
+        let c = Circle::new(vec2!(0.0, 0.0), 2.0);
+        assert!(c.intersects(&Circle::new(vec2!(3.0, 0.0), 1.5)));
+        assert!(c.intersects(&Circle::new(vec2!(0.5, 0.0), 0.1)));
+        assert!(!c.intersects(&Circle::new(vec2!(3.0, 3.0), 1.5)));
+
+        // Close to the corner of the rect but outside of it
+        let rect = Rect::new(2.0, 2.0, 4.0, 4.0);
+        assert!(!c.intersects_rect(&rect));
+        assert!(Circle::new(vec2!(0.0, 0.0), 3.0).intersects_rect(&rect));
+        // Facing a side
+        assert!(Circle::new(vec2!(1.5, 4.0), 0.6).intersects_rect(&rect));
+        assert!(!Circle::new(vec2!(1.5, 4.0), 0.4).intersects_rect(&rect));
+        // Inside
+        assert!(Circle::new(vec2!(4.0, 4.0), 0.1).intersects_rect(&rect));
+
+        assert_eq!(c.bbox(), Rect::new(-2.0, -2.0, 4.0, 4.0));
+    }
+}
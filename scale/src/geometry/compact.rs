@@ -0,0 +1,150 @@
+//! Serialized forms of points that are lighter than cgmath's field by field structs, used by
+//! the `compact_map` feature so that maps saved without it keep their format. Points are
+//! written as [x, y] arrays, and polylines are quantized to the centimeter and stored as small
+//! offsets from one point to the next, which about halves the size of binary maps. Each point
+//! then comes back within MAX_ERROR of where it was.
+
+use super::polyline::PolyLine;
+use super::Vec2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Size of the grid points are snapped to in compact polylines, in meters
+pub const QUANTUM: f32 = 0.01;
+
+/// Largest distance on each axis between a point and its compact round trip
+pub const MAX_ERROR: f32 = QUANTUM / 2.0 + 1e-3;
+
+/// Serializes a list of points as [x, y] arrays, use with #[serde(with = "points_array")]
+pub mod points_array {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(points: &[Vec2], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(points.iter().map(|p| [p.x, p.y]))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Vec2>, D::Error> {
+        let points: Vec<[f32; 2]> = Deserialize::deserialize(d)?;
+        Ok(points.into_iter().map(|[x, y]| vec2!(x, y)).collect())
+    }
+}
+
+/// A polyline snapped to a centimeter grid. Each point is stored as its offset from the
+/// previous one, the few offsets that don't fit in an i16 (over 327m) are stored apart.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CompactPolyLine {
+    start: Option<[i32; 2]>,
+    deltas: Vec<[i16; 2]>,
+    /// Index in deltas and position of the points too far from the previous one
+    far: Vec<(u32, [i32; 2])>,
+}
+
+fn quantize(p: Vec2) -> [i32; 2] {
+    [
+        (p.x / QUANTUM).round() as i32,
+        (p.y / QUANTUM).round() as i32,
+    ]
+}
+
+fn unquantize(q: [i32; 2]) -> Vec2 {
+    vec2!(q[0] as f32 * QUANTUM, q[1] as f32 * QUANTUM)
+}
+
+impl From<PolyLine> for CompactPolyLine {
+    fn from(line: PolyLine) -> Self {
+        let mut compact = CompactPolyLine::default();
+        let mut points = line.iter().map(|&p| quantize(p));
+
+        let mut prev = match points.next() {
+            Some(x) => x,
+            None => return compact,
+        };
+        compact.start = Some(prev);
+
+        for q in points {
+            let (dx, dy) = (q[0] as i64 - prev[0] as i64, q[1] as i64 - prev[1] as i64);
+            let fits = |d: i64| d >= i16::min_value() as i64 && d <= i16::max_value() as i64;
+            if fits(dx) && fits(dy) {
+                compact.deltas.push([dx as i16, dy as i16]);
+            } else {
+                compact.far.push((compact.deltas.len() as u32, q));
+                compact.deltas.push([0, 0]);
+            }
+            prev = q;
+        }
+        compact
+    }
+}
+
+impl From<CompactPolyLine> for PolyLine {
+    fn from(compact: CompactPolyLine) -> Self {
+        let mut prev = match compact.start {
+            Some(x) => x,
+            None => return PolyLine::default(),
+        };
+
+        let mut points = Vec::with_capacity(compact.deltas.len() + 1);
+        points.push(unquantize(prev));
+
+        let mut far = compact.far.iter().peekable();
+        for (i, d) in compact.deltas.iter().enumerate() {
+            prev = match far.peek() {
+                Some(&&(idx, q)) if idx as usize == i => {
+                    far.next();
+                    q
+                }
+                _ => [prev[0] + d[0] as i32, prev[1] + d[1] as i32],
+            };
+            points.push(unquantize(prev));
+        }
+        PolyLine::new(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        let mut points = vec![vec2!(-1234.567, 89.0123)];
+        for i in 1..200 {
+            let last = *points.last().unwrap();
+            let step = if i % 50 == 0 { 1000.0 } else { 7.31 };
+            points.push(last + vec2!(step, (i as f32 * 0.37).sin() * 3.3));
+        }
+        let line = PolyLine::new(points.clone());
+
+        let plain = bincode::serialize(&points).unwrap();
+        let compact = bincode::serialize(&CompactPolyLine::from(line)).unwrap();
+        assert!(compact.len() * 10 < plain.len() * 6);
+
+        let back: PolyLine = bincode::deserialize::<CompactPolyLine>(&compact)
+            .unwrap()
+            .into();
+        assert_eq!(back.n_points(), points.len());
+        for (a, b) in back.iter().zip(&points) {
+            assert!((a.x - b.x).abs() <= MAX_ERROR, "{:?} {:?}", a, b);
+            assert!((a.y - b.y).abs() <= MAX_ERROR, "{:?} {:?}", a, b);
+        }
+
+        let empty: PolyLine = CompactPolyLine::from(PolyLine::default()).into();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_points_array() {
+        #[derive(Serialize, Deserialize)]
+        struct Points(#[serde(with = "points_array")] Vec<Vec2>);
+
+        let points = vec![vec2!(1.5, -2.0), vec2!(3.25, 4.0)];
+        let bytes = bincode::serialize(&Points(points.clone())).unwrap();
+        // Same layout as plain floats
+        assert_eq!(
+            bytes,
+            bincode::serialize(&[[1.5f32, -2.0], [3.25, 4.0]][..]).unwrap()
+        );
+
+        let back: Points = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.0, points);
+    }
+}
@@ -21,11 +21,13 @@ enum ObjectState {
 pub struct CellObject {
     pub id: GridStoreHandle,
     pub pos: Vec2,
+    /// Bitmask of the groups the object belongs to, used to filter queries
+    pub mask: u32,
 }
 
 impl CellObject {
-    pub fn new(id: GridStoreHandle, pos: Vec2) -> Self {
-        Self { id, pos }
+    pub fn new(id: GridStoreHandle, pos: Vec2, mask: u32) -> Self {
+        Self { id, pos, mask }
     }
 }
 
@@ -36,6 +38,7 @@ struct StoreObject<O: Copy> {
     obj: O,
     state: ObjectState,
     pos: Vec2,
+    mask: u32,
     cell_id: usize,
 }
 
@@ -77,20 +80,27 @@ impl<O: Copy> GridStore<O> {
         }
     }
 
-    /// Inserts a new object with a position and an associated object
+    /// Inserts a new object with a position and an associated object, belonging to all groups
     /// Returns the handle
     pub fn insert(&mut self, pos: Vec2, obj: O) -> GridStoreHandle {
+        self.insert_masked(pos, obj, std::u32::MAX)
+    }
+
+    /// Inserts a new object with a position, an associated object and the bitmask of groups it belongs to
+    /// Returns the handle
+    pub fn insert_masked(&mut self, pos: Vec2, obj: O, mask: u32) -> GridStoreHandle {
         self.check_resize(pos);
         let cell_id = self.get_cell_id(pos);
         let handle = self.objects.insert(StoreObject {
             obj,
             state: ObjectState::Unchanged,
             pos,
+            mask,
             cell_id,
         });
         self.get_cell_mut(cell_id)
             .objs
-            .push(CellObject::new(handle, pos));
+            .push(CellObject::new(handle, pos, mask));
         handle
     }
 
@@ -129,7 +139,7 @@ impl<O: Copy> GridStore<O> {
     pub fn maintain(&mut self) {
         let mut to_add = vec![];
 
-        for (id, cell) in self.cells.iter_mut().filter(|x| x.dirty).enumerate() {
+        for (id, cell) in self.cells.iter_mut().enumerate().filter(|(_, x)| x.dirty) {
             cell.dirty = false;
 
             for cellobj in cell.objs.iter_mut() {
@@ -159,6 +169,11 @@ impl<O: Copy> GridStore<O> {
         }
     }
 
+    /// Whether the handle still refers to an object in the store
+    pub fn contains(&self, id: GridStoreHandle) -> bool {
+        self.objects.contains_key(id)
+    }
+
     pub fn get_obj(&self, id: GridStoreHandle) -> &O {
         &self.objects[id].obj
     }
@@ -168,65 +183,36 @@ impl<O: Copy> GridStore<O> {
     }
 
     /// Queries for all objects around a position within a certain radius.
-    /// Note that if the radius is bigger than the cell size, query_around might omit some results
-    #[rustfmt::skip]
-    pub fn query_around(&self, pos: Vec2, radius: f32) -> impl Iterator<Item = &CellObject> {
-
-        let cell = self.get_cell_id(pos);
-        let mut objs: Vec<&GridStoreCell> = Vec::with_capacity(4);
-
-        objs.push(&self.cells[cell as usize]);
-
-        let cell = cell as i32;
-
-        let (w, h) = (self.width as i32, self.height as i32);
-        let (x, y) = (cell % w, cell / w);
-
-        let left:   bool = x > 0   && ((pos.x - radius) as i32) < self.start_x + x     * self.cell_size;
-        let bottom: bool = y > 0   && ((pos.y - radius) as i32) < self.start_y + y     * self.cell_size;
-
-        let right:  bool = x < w-1 && ((pos.x + radius) as i32) > self.start_x + (x+1) * self.cell_size;
-        let top:    bool = y < h-1 && ((pos.y + radius) as i32) > self.start_y + (y+1) * self.cell_size;
-
-        if right {
-            self.populate_objs(cell + 1, &mut objs);
-            if top {
-                self.populate_objs(cell + w + 1, &mut objs);
-            }
-            if bottom {
-                self.populate_objs(cell - w + 1, &mut objs);
-            }
-        }
-
-        if left {
-            self.populate_objs(cell - 1, &mut objs);
-            if top {
-                self.populate_objs(cell + w - 1, &mut objs);
-            }
-            if bottom {
-                self.populate_objs(cell - w - 1, &mut objs);
-            }
-        }
-
-        if top {
-            self.populate_objs(cell + w, &mut objs);
-        }
-
-        if bottom {
-            self.populate_objs(cell - w, &mut objs);
-        }
-
-        let radius2 = radius*radius;
-        objs.into_iter().map(move |x| {
-            x.objs.iter().filter(move |x| {
-                (x.pos - pos).magnitude2() < radius2
-            })
-        }).flatten()
+    /// If a mask is given, only objects sharing at least one group with it are returned.
+    /// Every cell overlapping the query's bounding box is visited, so the radius can be bigger than the cell size.
+    pub fn query_around(
+        &self,
+        pos: Vec2,
+        radius: f32,
+        mask: Option<u32>,
+    ) -> impl Iterator<Item = &CellObject> {
+        let (x_min, y_min) = self.clamped_cell_coords(pos - Vec2::new(radius, radius));
+        let (x_max, y_max) = self.clamped_cell_coords(pos + Vec2::new(radius, radius));
+
+        let width = self.width as usize;
+        let radius2 = radius * radius;
+        let mask = mask.unwrap_or(std::u32::MAX);
+
+        (y_min..=y_max)
+            .flat_map(move |y| (x_min..=x_max).map(move |x| self.get_cell(y * width + x)))
+            .flat_map(|cell| cell.objs.iter())
+            .filter(move |x| x.mask & mask != 0 && (x.pos - pos).magnitude2() < radius2)
     }
 
-    #[inline(always)]
-    fn populate_objs<'a>(&'a self, cell_id: i32, objs: &mut Vec<&'a GridStoreCell>) {
-        objs.push(&self.get_cell(cell_id as usize));
+    /// Coordinates of the cell containing the position, clamped to the grid.
+    /// Uses the same rounding as get_cell_id_raw so that range queries agree with where objects are stored.
+    fn clamped_cell_coords(&self, pos: Vec2) -> (usize, usize) {
+        let i_x = (pos.x as i32 - self.start_x) / self.cell_size;
+        let i_y = (pos.y as i32 - self.start_y) / self.cell_size;
+        (
+            i_x.max(0).min(self.width as i32 - 1) as usize,
+            i_y.max(0).min(self.height as i32 - 1) as usize,
+        )
     }
 
     fn check_resize(&mut self, pos: Vec2) {
@@ -292,7 +278,7 @@ impl<O: Copy> GridStore<O> {
                     .get_mut(cell_id)
                     .unwrap()
                     .objs
-                    .push(CellObject::new(id, obj.pos));
+                    .push(CellObject::new(id, obj.pos, obj.mask));
             }
         }
     }
@@ -326,3 +312,51 @@ impl<O: Copy> GridStore<O> {
         (i_y * width + i_x) as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::rand_det;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_query_around_matches_brute_force() {
+        let mut store: GridStore<()> = GridStore::new(50);
+
+        let rand_pos = || {
+            Vec2::new(
+                rand_det::<f32>() * 1000.0 - 500.0,
+                rand_det::<f32>() * 1000.0 - 500.0,
+            )
+        };
+
+        let handles: Vec<GridStoreHandle> =
+            (0..5000).map(|_| store.insert(rand_pos(), ())).collect();
+
+        // Move some objects around so that the query also sees maintained positions
+        for h in handles.iter().step_by(3) {
+            store.set_position(*h, rand_pos());
+        }
+        store.maintain();
+
+        for i in 0..200 {
+            let pos = rand_pos();
+            let radius = 1.0 + (i as f32 / 200.0) * 120.0;
+
+            let grid: HashSet<GridStoreHandle> = store
+                .query_around(pos, radius, None)
+                .map(|x| x.id)
+                .collect();
+
+            let brute: HashSet<GridStoreHandle> = store
+                .cells()
+                .iter()
+                .flat_map(|cell| cell.objs.iter())
+                .filter(|x| (x.pos - pos).magnitude2() < radius * radius)
+                .map(|x| x.id)
+                .collect();
+
+            assert_eq!(grid, brute);
+        }
+    }
+}
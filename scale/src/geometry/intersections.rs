@@ -32,6 +32,11 @@ pub fn both_dist_to_inter(r1: Ray, r2: Ray) -> Option<(f32, f32)> {
 
     let div = r1.dir.dot(-r2dir_nor);
 
+    // (Almost) parallel rays would give huge or NaN distances
+    if div.abs() < 1e-5 {
+        return None;
+    }
+
     let t = r2dir_nor.dot(p_diff);
     let s = r1dir_nor.dot(p_diff);
 
@@ -42,6 +47,74 @@ pub fn both_dist_to_inter(r1: Ray, r2: Ray) -> Option<(f32, f32)> {
     }
 }
 
+/// Distance along the ray (in units of ray.dir) to the first point of the segment [a, b] it hits.
+/// If the segment lies on the ray, gives the distance to its closest point.
+pub fn ray_segment(ray: Ray, a: Vec2, b: Vec2) -> Option<f32> {
+    let e = b - a;
+    let w = a - ray.from;
+    let div = ray.dir.perp_dot(e);
+
+    if div.abs() < 1e-8 {
+        // Parallel, only hit if aligned
+        if w.perp_dot(ray.dir).abs() > 1e-5 {
+            return None;
+        }
+        let d2 = ray.dir.magnitude2();
+        let ta = w.dot(ray.dir) / d2;
+        let tb = (b - ray.from).dot(ray.dir) / d2;
+        if ta.max(tb) < 0.0 {
+            return None;
+        }
+        return Some(ta.min(tb).max(0.0));
+    }
+
+    let t = w.perp_dot(e) / div;
+    let u = w.perp_dot(ray.dir) / div;
+
+    if t >= 0.0 && u >= 0.0 && u <= 1.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Intersection point of the segments [a1, a2] and [b1, b2], endpoints included.
+/// Overlapping collinear segments have no single intersection point and give None, unless
+/// they only touch at one of their ends, in which case that end is returned.
+pub fn segment_intersection(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> Option<Vec2> {
+    let da = a2 - a1;
+    let db = b2 - b1;
+    let w = b1 - a1;
+    let div = da.perp_dot(db);
+
+    if div.abs() < 1e-8 {
+        let len2 = da.magnitude2();
+        if len2 < 1e-10 || w.perp_dot(da).abs() > 1e-5 {
+            return None;
+        }
+        // Collinear, find the overlap of b on a as a range of a's parameter
+        let tb1 = w.dot(da) / len2;
+        let tb2 = (b2 - a1).dot(da) / len2;
+        let lo = tb1.min(tb2).max(0.0);
+        let hi = tb1.max(tb2).min(1.0);
+
+        return if (hi - lo).abs() < 1e-6 {
+            Some(a1 + da * lo)
+        } else {
+            None
+        };
+    }
+
+    let t = w.perp_dot(db) / div;
+    let u = w.perp_dot(da) / div;
+
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some(a1 + da * t)
+    } else {
+        None
+    }
+}
+
 pub fn time_to_hit(dist: f32, v0: f32, acc: f32) -> f32 {
     // acc * t² / 2.0 + t*v0 - dist = 0
     // delta = v0² + 2 * acc * dist
@@ -49,6 +122,29 @@ pub fn time_to_hit(dist: f32, v0: f32, acc: f32) -> f32 {
     (-v0 + (v0 * v0 + 2.0 * acc * dist).sqrt()) / acc
 }
 
+/// Time before two discs moving at constant velocity touch, given the position and velocity of the second relative to the first
+/// and the sum of their radiuses. Returns None if they never touch, and 0 if they already overlap.
+pub fn time_to_collision(rel_pos: Vec2, rel_vel: Vec2, radius: f32) -> Option<f32> {
+    // |rel_pos + rel_vel * t|² = radius²
+    let c = rel_pos.magnitude2() - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+
+    let a = rel_vel.magnitude2();
+    let b = rel_pos.dot(rel_vel);
+    if a == 0.0 || b >= 0.0 {
+        return None;
+    }
+
+    let delta = b * b - a * c;
+    if delta < 0.0 {
+        return None;
+    }
+
+    Some((-b - delta.sqrt()) / a)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,4 +169,91 @@ mod tests {
             assert_eq!(v.y, 2.0);
         }
     }
+
+    #[test]
+    pub fn test_parallel_rays() {
+        let x = Ray {
+            from: [0.0, 0.0].into(),
+            dir: [1.0, 0.0].into(),
+        };
+
+        let y = Ray {
+            from: [5.0, 3.0].into(),
+            dir: [1.0, 1e-7].into(),
+        };
+
+        assert!(both_dist_to_inter(x, y).is_none());
+        assert!(both_dist_to_inter(x, x).is_none());
+    }
+
+    #[test]
+    pub fn test_ray_segment() {
+        let ray = Ray {
+            from: [0.0, 0.0].into(),
+            dir: [1.0, 0.0].into(),
+        };
+
+        let middle = ray_segment(ray, [5.0, -1.0].into(), [5.0, 1.0].into());
+        assert_eq!(middle, Some(5.0));
+
+        let past_endpoint = ray_segment(ray, [5.0, 1.0].into(), [5.0, 3.0].into());
+        assert_eq!(past_endpoint, None);
+
+        let behind = ray_segment(ray, [-5.0, -1.0].into(), [-5.0, 1.0].into());
+        assert_eq!(behind, None);
+
+        let parallel = ray_segment(ray, [2.0, 1.0].into(), [6.0, 1.0].into());
+        assert_eq!(parallel, None);
+
+        let aligned = ray_segment(ray, [6.0, 0.0].into(), [3.0, 0.0].into());
+        assert_eq!(aligned, Some(3.0));
+    }
+
+    #[test]
+    pub fn test_segment_intersection() {
+        let v = |x: f32, y: f32| Vec2::new(x, y);
+
+        let crossing = segment_intersection(v(0.0, 0.0), v(4.0, 4.0), v(0.0, 4.0), v(4.0, 0.0));
+        assert_eq!(crossing, Some(v(2.0, 2.0)));
+
+        let too_short = segment_intersection(v(0.0, 0.0), v(1.0, 1.0), v(0.0, 4.0), v(4.0, 0.0));
+        assert_eq!(too_short, None);
+
+        // T shape, one end on the other segment
+        let touching = segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(2.0, 0.0), v(2.0, 3.0));
+        assert_eq!(touching, Some(v(2.0, 0.0)));
+
+        // Sharing an end
+        let corner = segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(4.0, 0.0), v(4.0, 3.0));
+        assert_eq!(corner, Some(v(4.0, 0.0)));
+
+        let parallel = segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(0.0, 1.0), v(4.0, 1.0));
+        assert_eq!(parallel, None);
+
+        let overlapping = segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(2.0, 0.0), v(6.0, 0.0));
+        assert_eq!(overlapping, None);
+
+        let collinear_apart =
+            segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(5.0, 0.0), v(6.0, 0.0));
+        assert_eq!(collinear_apart, None);
+
+        let collinear_end_to_end =
+            segment_intersection(v(0.0, 0.0), v(4.0, 0.0), v(6.0, 0.0), v(4.0, 0.0));
+        assert_eq!(collinear_end_to_end, Some(v(4.0, 0.0)));
+    }
+
+    #[test]
+    pub fn test_time_to_collision() {
+        let head_on = time_to_collision([10.0, 0.0].into(), [-2.0, 0.0].into(), 2.0);
+        assert_eq!(head_on, Some(4.0));
+
+        let parallel = time_to_collision([10.0, 0.0].into(), [0.0, 1.0].into(), 2.0);
+        assert_eq!(parallel, None);
+
+        let moving_away = time_to_collision([10.0, 0.0].into(), [1.0, 0.0].into(), 2.0);
+        assert_eq!(moving_away, None);
+
+        let overlapping = time_to_collision([1.0, 0.0].into(), [1.0, 0.0].into(), 2.0);
+        assert_eq!(overlapping, Some(0.0));
+    }
 }
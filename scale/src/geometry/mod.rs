@@ -1,10 +1,13 @@
 use cgmath::{InnerSpace, Vector2};
 
+pub mod circle;
+pub mod compact;
 pub mod gridstore;
 pub mod intersections;
 pub mod polyline;
 pub mod rect;
 pub mod segment;
+pub mod shapes;
 pub mod splines;
 
 pub type Vec2 = Vector2<f32>;
@@ -22,6 +25,14 @@ pub trait Vec2Impl {
     fn dir_dist(&self) -> Option<(Vec2, f32)>;
 
     fn cap_magnitude(&self, max: f32) -> Vec2;
+
+    /// Rotates counter-clockwise by angle radians
+    fn rotate(&self, angle: f32) -> Vec2;
+
+    /// Signed angle in radians in [-pi, pi] to go from self to other, counter-clockwise being positive
+    fn angle_between(&self, other: Vec2) -> f32;
+
+    fn lerp(&self, other: Vec2, t: f32) -> Vec2;
 }
 
 impl Vec2Impl for Vec2 {
@@ -42,6 +53,19 @@ impl Vec2Impl for Vec2 {
             *self
         }
     }
+
+    fn rotate(&self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+
+    fn angle_between(&self, other: Vec2) -> f32 {
+        f32::atan2(self.perp_dot(other), self.dot(other))
+    }
+
+    fn lerp(&self, other: Vec2, t: f32) -> Vec2 {
+        self + (other - self) * t
+    }
 }
 
 pub fn pseudo_angle(v: Vec2) -> f32 {
@@ -56,3 +80,59 @@ pub fn pseudo_angle(v: Vec2) -> f32 {
         1.0 - p
     }
 }
+
+/// Brings an angle in radians back to [-pi, pi], odd multiples of pi keeping their sign
+pub fn wrap_angle(a: f32) -> f32 {
+    const TAU: f32 = 2.0 * std::f32::consts::PI;
+    let r = a % TAU;
+    if r > std::f32::consts::PI {
+        r - TAU
+    } else if r < -std::f32::consts::PI {
+        r + TAU
+    } else {
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        let v = Vec2::new(1.0, 2.0).rotate(std::f32::consts::FRAC_PI_2);
+        assert!((v - Vec2::new(-2.0, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn test_angle_between() {
+        let x = Vec2::new(2.0, 0.0);
+        let y = Vec2::new(0.0, 0.5);
+        assert!((x.angle_between(y) - std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+        assert!((y.angle_between(x) + std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_lerp() {
+        let a = Vec2::new(0.0, 2.0);
+        let b = Vec2::new(4.0, -2.0);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 0.5), Vec2::new(2.0, 0.0));
+        assert_eq!(a.lerp(b, 1.0), b);
+    }
+
+    #[test]
+    fn test_wrap_angle() {
+        use std::f32::consts::PI;
+
+        assert!((wrap_angle(3.0 * PI) - PI).abs() < 1e-5);
+        assert!((wrap_angle(-3.0 * PI) + PI).abs() < 1e-5);
+        assert!(wrap_angle(2.0 * PI).abs() < 1e-5);
+        assert!((wrap_angle(7.0) - (7.0 - 2.0 * PI)).abs() < 1e-5);
+        assert!((wrap_angle(-7.0) + (7.0 - 2.0 * PI)).abs() < 1e-5);
+
+        for &a in &[0.0, 1.0, -1.0, PI, -PI] {
+            assert_eq!(wrap_angle(a), a);
+        }
+    }
+}
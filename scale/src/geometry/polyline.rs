@@ -1,4 +1,6 @@
 use super::Vec2;
+#[cfg(feature = "compact_map")]
+use crate::geometry::compact::{points_array, CompactPolyLine};
 use crate::geometry::segment::Segment;
 use cgmath::{vec2, InnerSpace};
 use serde::{Deserialize, Serialize};
@@ -7,7 +9,11 @@ use std::ops::Index;
 use std::slice::{Iter, IterMut};
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct PolyLine(Vec<Vec2>);
+#[cfg_attr(
+    feature = "compact_map",
+    serde(from = "CompactPolyLine", into = "CompactPolyLine")
+)]
+pub struct PolyLine(#[cfg_attr(feature = "compact_map", serde(with = "points_array"))] Vec<Vec2>);
 
 impl From<Vec<Vec2>> for PolyLine {
     fn from(x: Vec<Vec2>) -> Self {
@@ -84,6 +90,131 @@ impl PolyLine {
         Some(min_proj)
     }
 
+    /// Cuts the polyline at the given distance along it, inserting the interpolated point at
+    /// the end of the first part and at the start of the second one
+    pub fn split_at(&self, dist: f32) -> (PolyLine, PolyLine) {
+        if dist <= 0.0 {
+            return (PolyLine::default(), self.clone());
+        }
+
+        let mut walked = 0.0;
+        for (i, w) in self.0.windows(2).enumerate() {
+            let (a, b) = (w[0], w[1]);
+            let seg_length = (b - a).magnitude();
+
+            if walked + seg_length > dist {
+                let cut = a + (b - a) * ((dist - walked) / seg_length);
+
+                let mut first = PolyLine::new(self.0[..=i].to_vec());
+                if a != cut {
+                    first.push(cut);
+                }
+
+                let mut second = PolyLine::with_capacity(self.n_points() - i);
+                second.push(cut);
+                second.extend(&self.0[i + 1..]);
+
+                return (first, second);
+            }
+            walked += seg_length;
+        }
+
+        (self.clone(), PolyLine::default())
+    }
+
+    /// The part of the polyline between the start and end distances along it
+    pub fn sub(&self, start: f32, end: f32) -> PolyLine {
+        self.split_at(start).1.split_at(end - start.max(0.0)).0
+    }
+
+    /// The point at the given distance along the polyline and the direction of its segment there,
+    /// None if the distance is out of the polyline
+    pub fn point_along(&self, dist: f32) -> Option<(Vec2, Vec2)> {
+        if dist < 0.0 {
+            return None;
+        }
+
+        let mut walked = 0.0;
+        for w in self.0.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            let seg_length = (b - a).magnitude();
+
+            if walked + seg_length >= dist && seg_length > 0.0 {
+                let dir = (b - a) / seg_length;
+                return Some((a + dir * (dist - walked), dir));
+            }
+            walked += seg_length;
+        }
+        None
+    }
+
+    /// Rounds the corners using Chaikin's corner cutting, the first and last points are kept
+    pub fn smoothed(&self, iterations: usize) -> PolyLine {
+        let mut cur = self.clone();
+        for _ in 0..iterations {
+            let n = cur.n_points();
+            if n <= 2 {
+                break;
+            }
+
+            let mut next = PolyLine::with_capacity(2 * n);
+            next.push(cur.0[0]);
+            for (i, w) in cur.0.windows(2).enumerate() {
+                let (a, b) = (w[0], w[1]);
+                if i > 0 {
+                    next.push(a * 0.75 + b * 0.25);
+                }
+                if i < n - 2 {
+                    next.push(a * 0.25 + b * 0.75);
+                }
+            }
+            next.push(cur.0[n - 1]);
+            cur = next;
+        }
+        cur
+    }
+
+    /// Points along the polyline at equal distances from each other, as close to spacing as
+    /// the length allows. The first and last points are kept, corners in between may be cut.
+    pub fn resample(&self, spacing: f32) -> PolyLine {
+        let length = self.length();
+        if self.n_points() < 2 || spacing <= 0.0 || length <= 0.0 {
+            return self.clone();
+        }
+
+        let n = (length / spacing).round().max(1.0) as usize;
+        let step = length / n as f32;
+
+        let mut out = PolyLine::with_capacity(n + 1);
+        out.push(self.0[0]);
+
+        let mut walked = 0.0;
+        let mut target = step;
+        for w in self.0.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            let seg_length = (b - a).magnitude();
+
+            while out.n_points() < n && walked + seg_length >= target {
+                out.push(a + (b - a) * ((target - walked) / seg_length));
+                target += step;
+            }
+            walked += seg_length;
+        }
+
+        out.push(self.0[self.n_points() - 1]);
+        out
+    }
+
+    /// Whether both polylines have the same number of points, each within epsilon of the other's
+    pub fn approx_eq(&self, other: &PolyLine, epsilon: f32) -> bool {
+        self.n_points() == other.n_points()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(&a, &b)| (a - b).magnitude2() <= epsilon * epsilon)
+    }
+
     pub fn pop_first(&mut self) -> Option<Vec2> {
         if self.0.is_empty() {
             None
@@ -132,3 +263,158 @@ impl Index<usize> for PolyLine {
         &self.0[index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn l_shape() -> PolyLine {
+        PolyLine::new(vec![vec2(0.0, 0.0), vec2(10.0, 0.0), vec2(10.0, 10.0)])
+    }
+
+    #[test]
+    fn test_split_at() {
+        let line = l_shape();
+
+        let (first, second) = line.split_at(4.0);
+        assert!((first.length() + second.length() - line.length()).abs() < 1e-5);
+        assert_eq!(first.as_slice(), &[vec2(0.0, 0.0), vec2(4.0, 0.0)]);
+        assert_eq!(
+            second.as_slice(),
+            &[vec2(4.0, 0.0), vec2(10.0, 0.0), vec2(10.0, 10.0)]
+        );
+
+        let (first, second) = line.split_at(15.0);
+        assert!((first.length() - 15.0).abs() < 1e-5);
+        assert!((second.length() - 5.0).abs() < 1e-5);
+        assert_eq!(first.last(), Some(vec2(10.0, 5.0)));
+        assert_eq!(second.first(), Some(vec2(10.0, 5.0)));
+
+        // On a vertex
+        let (first, second) = line.split_at(10.0);
+        assert_eq!(first.as_slice(), &[vec2(0.0, 0.0), vec2(10.0, 0.0)]);
+        assert_eq!(second.as_slice(), &[vec2(10.0, 0.0), vec2(10.0, 10.0)]);
+
+        let (first, second) = line.split_at(0.0);
+        assert!(first.is_empty());
+        assert_eq!(second.as_slice(), line.as_slice());
+
+        let (first, second) = line.split_at(100.0);
+        assert_eq!(first.as_slice(), line.as_slice());
+        assert!(second.is_empty());
+    }
+
+    #[test]
+    fn test_sub() {
+        let line = l_shape();
+
+        let sub = line.sub(5.0, 15.0);
+        assert_eq!(
+            sub.as_slice(),
+            &[vec2(5.0, 0.0), vec2(10.0, 0.0), vec2(10.0, 5.0)]
+        );
+        assert!((sub.length() - 10.0).abs() < 1e-5);
+
+        assert_eq!(line.sub(-5.0, 50.0).as_slice(), line.as_slice());
+        assert!(line.sub(8.0, 3.0).is_empty());
+    }
+
+    #[test]
+    fn test_point_along() {
+        let line = l_shape();
+
+        assert_eq!(
+            line.point_along(0.0),
+            Some((vec2(0.0, 0.0), vec2(1.0, 0.0)))
+        );
+        assert_eq!(
+            line.point_along(4.0),
+            Some((vec2(4.0, 0.0), vec2(1.0, 0.0)))
+        );
+        assert_eq!(
+            line.point_along(13.0),
+            Some((vec2(10.0, 3.0), vec2(0.0, 1.0)))
+        );
+        assert_eq!(
+            line.point_along(20.0),
+            Some((vec2(10.0, 10.0), vec2(0.0, 1.0)))
+        );
+        assert_eq!(line.point_along(20.5), None);
+        assert_eq!(line.point_along(-1.0), None);
+    }
+
+    #[test]
+    fn test_smoothed() {
+        let line = l_shape();
+
+        let smooth = line.smoothed(1);
+        assert_eq!(
+            smooth.as_slice(),
+            &[
+                vec2(0.0, 0.0),
+                vec2(7.5, 0.0),
+                vec2(10.0, 2.5),
+                vec2(10.0, 10.0)
+            ]
+        );
+
+        let smoother = line.smoothed(3);
+        assert_eq!(smoother.first(), line.first());
+        assert_eq!(smoother.last(), line.last());
+        assert!(smoother.length() < smooth.length());
+
+        assert_eq!(line.smoothed(0).as_slice(), line.as_slice());
+    }
+
+    #[test]
+    fn test_resample() {
+        // Quarter of a circle of radius 20 with points crowded at its start
+        let curve = PolyLine::new(
+            (0..=40)
+                .map(|i| {
+                    let t = (i as f32 / 40.0).powi(2) * std::f32::consts::FRAC_PI_2;
+                    vec2(t.cos(), t.sin()) * 20.0
+                })
+                .collect(),
+        );
+
+        let resampled = curve.resample(2.0);
+        assert_eq!(resampled.first(), curve.first());
+        assert_eq!(resampled.last(), curve.last());
+        assert_eq!(resampled.n_points(), 17);
+        for w in resampled.as_slice().windows(2) {
+            assert!(((w[1] - w[0]).magnitude() - 2.0).abs() < 0.1);
+        }
+
+        let line = l_shape();
+        assert_eq!(line.resample(5.0).n_points(), 5);
+        assert_eq!(
+            line.resample(100.0).as_slice(),
+            &[vec2(0.0, 0.0), vec2(10.0, 10.0)]
+        );
+        assert_eq!(line.resample(0.0).as_slice(), line.as_slice());
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let line = l_shape();
+
+        let mut noisy = line.clone();
+        for p in noisy.iter_mut() {
+            *p += vec2(1e-4, -1e-4);
+        }
+        assert!(line.approx_eq(&noisy, 1e-3));
+        assert!(!line.approx_eq(&noisy, 1e-5));
+        assert!(line.approx_eq(&line, 0.0));
+
+        let mut shorter = line.clone();
+        shorter.pop();
+        assert!(!line.approx_eq(&shorter, 1.0));
+        assert!(!shorter.approx_eq(&line, 1.0));
+
+        let mut moved = line.clone();
+        moved.iter_mut().nth(1).unwrap().y += 0.1;
+        assert!(!line.approx_eq(&moved, 0.05));
+        assert!(line.approx_eq(&moved, 0.2));
+    }
+}
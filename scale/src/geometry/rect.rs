@@ -1,6 +1,6 @@
 use super::Vec2;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -26,6 +26,16 @@ impl Rect {
         }
     }
 
+    /// Create the smallest `Rect` containing both corners
+    pub fn from_corners(a: Vec2, b: Vec2) -> Self {
+        Rect {
+            x: a.x.min(b.x),
+            y: a.y.min(b.y),
+            w: (a.x - b.x).abs(),
+            h: (a.y - b.y).abs(),
+        }
+    }
+
     /// Create a new rect from `i32` coordinates.
     pub const fn new_i32(x: i32, y: i32, w: i32, h: i32) -> Self {
         Rect {
@@ -125,3 +135,34 @@ impl Rect {
         Rect { x, y, w, h }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let rect = Rect::from_corners(vec2!(3.0, -1.0), vec2!(-1.0, 2.0));
+        assert_eq!(rect, Rect::new(-1.0, -1.0, 4.0, 3.0));
+
+        assert!(rect.contains(vec2!(0.0, 0.0)));
+        assert!(rect.contains(vec2!(3.0, 2.0)));
+        assert!(!rect.contains(vec2!(3.1, 0.0)));
+        assert!(!rect.contains(vec2!(0.0, -1.1)));
+        assert!(rect.contains_within(vec2!(0.0, -1.1), 0.2));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let rect = Rect::new(0.0, 0.0, 4.0, 4.0);
+        assert!(rect.overlaps(&Rect::new(3.0, 3.0, 4.0, 4.0)));
+        assert!(rect.overlaps(&Rect::new(1.0, 1.0, 1.0, 1.0)));
+        assert!(rect.overlaps(&Rect::new(-1.0, 1.0, 6.0, 1.0)));
+        // Touching edges
+        assert!(rect.overlaps(&Rect::new(4.0, 0.0, 1.0, 1.0)));
+
+        assert!(!rect.overlaps(&Rect::new(5.0, 0.0, 1.0, 1.0)));
+        assert!(!rect.overlaps(&Rect::new(0.0, -3.0, 4.0, 2.0)));
+        assert!(!Rect::new(5.0, 5.0, 1.0, 1.0).overlaps(&rect));
+    }
+}
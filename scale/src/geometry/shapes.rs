@@ -0,0 +1,282 @@
+use super::Vec2;
+
+/// Outline of a rectangle centered on `center` and oriented along `dir`, whose corners are
+/// quarter circles of the given radius made of `corner_segments` segments each.
+/// The points are in counter-clockwise order, and a radius of 0 gives back the 4 sharp corners.
+pub fn rounded_rect(
+    center: Vec2,
+    width: f32,
+    height: f32,
+    dir: Vec2,
+    radius: f32,
+    corner_segments: usize,
+) -> Vec<Vec2> {
+    let nor = Vec2::new(-dir.y, dir.x);
+    let to_world = |x: f32, y: f32| center + dir * x + nor * y;
+
+    let hw = width / 2.0;
+    let hh = height / 2.0;
+    let radius = radius.min(hw).min(hh);
+
+    if radius <= 0.0 || corner_segments == 0 {
+        return vec![
+            to_world(hw, hh),
+            to_world(-hw, hh),
+            to_world(-hw, -hh),
+            to_world(hw, -hh),
+        ];
+    }
+
+    let corners = [
+        (hw - radius, hh - radius),
+        (-hw + radius, hh - radius),
+        (-hw + radius, -hh + radius),
+        (hw - radius, -hh + radius),
+    ];
+
+    let mut points = Vec::with_capacity(4 * (corner_segments + 1));
+    for (i, (cx, cy)) in corners.iter().enumerate() {
+        let start = i as f32 * std::f32::consts::FRAC_PI_2;
+        for j in 0..=corner_segments {
+            let ang = start + j as f32 / corner_segments as f32 * std::f32::consts::FRAC_PI_2;
+            points.push(to_world(cx + radius * ang.cos(), cy + radius * ang.sin()));
+        }
+    }
+    points
+}
+
+/// Fewest and most segments circles are drawn with
+pub const MIN_CIRCLE_SEGMENTS: usize = 8;
+pub const MAX_CIRCLE_SEGMENTS: usize = 128;
+
+/// Number of segments a circle of the given radius needs so that its sides stay within
+/// `tolerance` of the true circle. Both are in the same unit, usually pixels so that the
+/// quality follows the zoom.
+pub fn circle_segments(radius: f32, tolerance: f32) -> usize {
+    if !(radius > tolerance && tolerance > 0.0) {
+        return MIN_CIRCLE_SEGMENTS;
+    }
+    // A side spanning the angle a is at most r * (1 - cos(a / 2)) away from the circle
+    let max_angle = 2.0 * (1.0 - tolerance / radius).acos();
+    let n = (2.0 * std::f32::consts::PI / max_angle).ceil() as usize;
+    n.max(MIN_CIRCLE_SEGMENTS).min(MAX_CIRCLE_SEGMENTS)
+}
+
+/// Splits the segment from `start` to `end` into dashes of length `dash` separated by `gap`.
+/// The segment always begins with a dash, and the last one is cut short at `end`.
+/// A non-positive dash length gives back the whole segment.
+pub fn dashes(start: Vec2, end: Vec2, dash: f32, gap: f32) -> Vec<(Vec2, Vec2)> {
+    let diff = end - start;
+    let length = diff.x.hypot(diff.y);
+
+    if dash <= 0.0 || length <= dash {
+        return vec![(start, end)];
+    }
+
+    let dir = diff / length;
+    let period = dash + gap.max(0.0);
+
+    let mut segments = Vec::with_capacity((length / period) as usize + 1);
+    let mut d = 0.0;
+    while d < length {
+        let dash_end = (d + dash).min(length);
+        segments.push((start + dir * d, start + dir * dash_end));
+        d += period;
+    }
+    segments
+}
+
+/// Twice the signed area of the polygon, positive if it is counter-clockwise
+fn signed_area2(points: &[Vec2]) -> f32 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - a.y * b.x)
+        .sum()
+}
+
+fn cross(o: Vec2, a: Vec2, b: Vec2) -> f32 {
+    (a - o).perp_dot(b - o)
+}
+
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    cross(a, b, p) > 0.0 && cross(b, c, p) > 0.0 && cross(c, a, p) > 0.0
+}
+
+/// Triangulates a simple polygon (convex or not, in any winding order) by ear clipping.
+/// Returns triangles as indices into `points`, there are n-2 of them for a polygon of n points
+/// without degenerate vertices.
+pub fn triangulate(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return vec![];
+    }
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    if signed_area2(points) < 0.0 {
+        remaining.reverse();
+    }
+
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |i: usize| {
+            (
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            )
+        };
+
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = corner(i);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            cross(pa, pb, pc) > 1e-8
+                && !remaining
+                    .iter()
+                    .filter(|&&x| x != a && x != b && x != c)
+                    .any(|&x| in_triangle(points[x], pa, pb, pc))
+        });
+
+        match ear {
+            Some(i) => {
+                let (a, b, c) = corner(i);
+                triangles.push([a, b, c]);
+                remaining.remove(i);
+            }
+            None => {
+                // Only degenerate (flat) vertices are left to remove, they don't make any triangle
+                let flat = (0..m).find(|&i| {
+                    let (a, b, c) = corner(i);
+                    cross(points[a], points[b], points[c]).abs() <= 1e-8
+                });
+                match flat {
+                    Some(i) => {
+                        remaining.remove(i);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn test_rounded_rect_outline() {
+        let dir = Vec2::new(3.0, 4.0).normalize();
+        let nor = Vec2::new(-dir.y, dir.x);
+        let center = Vec2::new(10.0, -5.0);
+        let (width, height) = (4.5, 2.0);
+
+        let points = rounded_rect(center, width, height, dir, 0.5, 6);
+        assert_eq!(points.len(), 4 * 7);
+
+        for p in &points {
+            let local = p - center;
+            assert!(local.dot(dir).abs() <= width / 2.0 + 1e-4);
+            assert!(local.dot(nor).abs() <= height / 2.0 + 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_zero_radius_is_sharp() {
+        let dir = Vec2::new(1.0, 0.0);
+        let points = rounded_rect(Vec2::new(0.0, 0.0), 4.0, 2.0, dir, 0.0, 6);
+
+        assert_eq!(
+            points,
+            vec![
+                Vec2::new(2.0, 1.0),
+                Vec2::new(-2.0, 1.0),
+                Vec2::new(-2.0, -1.0),
+                Vec2::new(2.0, -1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_circle_segments() {
+        let small = circle_segments(4.0, 0.3);
+        let large = circle_segments(200.0, 0.3);
+        assert!(large > small);
+
+        // Lower tolerance is better quality
+        assert!(circle_segments(50.0, 0.1) > circle_segments(50.0, 1.0));
+
+        // Bounded either way
+        assert_eq!(circle_segments(0.1, 0.3), MIN_CIRCLE_SEGMENTS);
+        assert_eq!(circle_segments(0.0, 0.0), MIN_CIRCLE_SEGMENTS);
+        assert_eq!(circle_segments(1e6, 0.3), MAX_CIRCLE_SEGMENTS);
+    }
+
+    #[test]
+    fn test_dashes() {
+        let start = Vec2::new(0.0, 0.0);
+        let end = Vec2::new(10.0, 0.0);
+
+        // 0-2, 3-5, 6-8, 9-10
+        let segs = dashes(start, end, 2.0, 1.0);
+        assert_eq!(segs.len(), 4);
+        assert_eq!(segs[1], (Vec2::new(3.0, 0.0), Vec2::new(5.0, 0.0)));
+        assert_eq!(segs[3], (Vec2::new(9.0, 0.0), end));
+
+        // Shorter than a dash: still drawn
+        let segs = dashes(start, Vec2::new(0.5, 0.0), 2.0, 1.0);
+        assert_eq!(segs, vec![(start, Vec2::new(0.5, 0.0))]);
+
+        assert_eq!(dashes(start, end, 0.0, 1.0), vec![(start, end)]);
+    }
+
+    fn total_area(points: &[Vec2], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|[a, b, c]| cross(points[*a], points[*b], points[*c]).abs() / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn test_triangulate() {
+        let triangle = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+        ];
+        assert_eq!(triangulate(&triangle).len(), 1);
+
+        // Clockwise square
+        let square = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(2.0, 0.0),
+        ];
+        let tris = triangulate(&square);
+        assert_eq!(tris.len(), 2);
+        assert!((total_area(&square, &tris) - 4.0).abs() < 1e-5);
+
+        // Concave L shape
+        let l_shape = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(0.0, 2.0),
+        ];
+        let tris = triangulate(&l_shape);
+        assert_eq!(tris.len(), 4);
+        assert!((total_area(&l_shape, &tris) - 3.0).abs() < 1e-5);
+    }
+}
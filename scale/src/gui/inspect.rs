@@ -272,6 +272,92 @@ impl InspectRenderDefault<PolyLine> for PolyLine {
     }
 }
 
+/// Shown instead of a value when the selected objects don't agree on it
+pub const MULTIPLE_VALUES: &str = "Multiple values";
+
+/// Shown instead of a value when nothing is selected
+pub const NO_VALUE: &str = "None";
+
+/// What a value looks like across the selected objects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SharedValue<T> {
+    /// Nothing is selected
+    Empty,
+    /// All the selected objects have this value
+    Same(T),
+    /// The selected objects don't agree on the value
+    Multiple,
+}
+
+pub fn shared_value<T: PartialEq>(values: impl IntoIterator<Item = T>) -> SharedValue<T> {
+    let mut it = values.into_iter();
+    let first = match it.next() {
+        Some(x) => x,
+        None => return SharedValue::Empty,
+    };
+    if it.all(|x| x == first) {
+        SharedValue::Same(first)
+    } else {
+        SharedValue::Multiple
+    }
+}
+
+/// The name shared by the selected objects, or a placeholder telling why there is none
+pub fn shared_name<'a>(names: impl IntoIterator<Item = &'a str>) -> &'a str {
+    match shared_value(names) {
+        SharedValue::Empty => NO_VALUE,
+        SharedValue::Same(name) => name,
+        SharedValue::Multiple => MULTIPLE_VALUES,
+    }
+}
+
+pub fn set_all<T: Copy>(data: &mut [&mut T], value: T) {
+    for x in data {
+        **x = value;
+    }
+}
+
+/// Combo box editing an enum for all the selected objects at once. When they differ a
+/// placeholder is shown until one of the choices is picked, which is then set on all of them.
+pub fn enum_combo<T: Copy + PartialEq>(
+    ui: &Ui,
+    label: &str,
+    data: &mut [&mut T],
+    choices: &[(T, &str)],
+) -> bool {
+    let shared = shared_value(
+        data.iter()
+            .map(|x| choices.iter().position(|(c, _)| *c == **x)),
+    );
+    let current = match shared {
+        SharedValue::Same(x) => x,
+        _ => None,
+    };
+
+    let mut names: Vec<imgui::ImString> = choices.iter().map(|(_, n)| im_str!("{}", n)).collect();
+    if current.is_none() {
+        let placeholder = match shared {
+            SharedValue::Empty => NO_VALUE,
+            _ => MULTIPLE_VALUES,
+        };
+        names.push(im_str!("{}", placeholder));
+    }
+    let mut id = current.unwrap_or(choices.len());
+
+    let changed = imgui::ComboBox::new(&im_str!("{}", label)).build_simple_string(
+        ui,
+        &mut id,
+        &names.iter().collect::<Vec<_>>(),
+    );
+
+    if changed && id < choices.len() {
+        set_all(data, choices[id].0);
+        true
+    } else {
+        false
+    }
+}
+
 #[rustfmt::skip]
 macro_rules! empty_inspect_impl {
     ($x : ty) => {
@@ -292,30 +378,31 @@ macro_rules! empty_inspect_impl {
 macro_rules! enum_inspect_impl {
     ($t: ty; $($x: pat),+) => {
         impl imgui_inspect::InspectRenderDefault<$t> for $t {
-            fn render(_: &[&$t], _: &'static str, _: &mut specs::World, _: &imgui::Ui, _: &imgui_inspect::InspectArgsDefault,
+            fn render(data: &[&$t], label: &'static str, _: &mut specs::World, ui: &imgui::Ui, _: &imgui_inspect::InspectArgsDefault,
             ) {
-                unimplemented!()
+                let name = |d: &$t| {
+                    let mut aha = "No match";
+                    $(
+                        if let $x = d {
+                            aha = stringify!($x);
+                        }
+                    )+
+                    aha
+                };
+                let shown = $crate::gui::shared_name(data.iter().map(|d| name(*d)));
+
+                ui.text(imgui::im_str!("{} {}", shown, label));
             }
 
             fn render_mut(
                 data: &mut [&mut $t],
                 label: &'static str,
-                _: &mut specs::World,
+                w: &mut specs::World,
                 ui: &imgui::Ui,
-                _: &imgui_inspect::InspectArgsDefault,
+                args: &imgui_inspect::InspectArgsDefault,
             ) -> bool {
-                if data.len() != 1 {
-                    unimplemented!()
-                }
-                let d = &mut data[0];
-                let mut aha = "No match";
-                $(
-                    if let $x = d {
-                        aha = stringify!($x);
-                    }
-                )+
-
-                ui.text(imgui::im_str!("{} {}", &aha, label));
+                let data: Vec<&$t> = data.iter().map(|d| &**d).collect();
+                Self::render(&data, label, w, ui, args);
                 false
             }
         }
@@ -2,7 +2,11 @@ use crate::engine_interaction::{RenderStats, TimeInfo};
 use crate::interaction::SelectedEntity;
 use crate::map_model::{LanePatternBuilder, MapUIState};
 use crate::pedestrians::{spawn_pedestrian, PedestrianComponent};
-use crate::vehicles::{delete_vehicle_entity, spawn_new_vehicle, VehicleComponent};
+use crate::profiler::Profiler;
+use crate::vehicles::systems::DecisionConfig;
+use crate::vehicles::{
+    delete_vehicle_entity, spawn_new_vehicle, spawn_parked_vehicle, SpawnConfig, VehicleComponent,
+};
 use imgui::im_str;
 use imgui::Ui;
 use imgui_inspect::{InspectArgsDefault, InspectRenderDefault};
@@ -18,8 +22,21 @@ pub struct Gui {
     show_car_ui: bool,
     show_stats: bool,
     show_tips: bool,
+    show_profiler: bool,
+    /// Draw the path each vehicle plans to follow
+    pub show_itineraries: bool,
+    /// Draw an overview of the whole map in a corner of the screen
+    pub show_minimap: bool,
+    /// Draw the cone in which each vehicle looks for obstacles
+    pub show_sensors: bool,
+    /// Color the lanes by how jammed they are
+    pub show_congestion: bool,
+    /// Largest gap in pixels between the sides of a drawn circle and the true circle
+    pub circle_tolerance: f32,
     n_cars: i32,
     n_pedestrians: i32,
+    /// Why the last save failed, shown under the save button
+    save_error: Option<String>,
 }
 
 impl Default for Gui {
@@ -28,8 +45,15 @@ impl Default for Gui {
             show_car_ui: true,
             show_stats: true,
             show_tips: false,
+            show_profiler: false,
+            show_itineraries: false,
+            show_minimap: false,
+            show_sensors: false,
+            show_congestion: false,
+            circle_tolerance: 0.3,
             n_cars: 100,
             n_pedestrians: 100,
+            save_error: None,
         }
     }
 }
@@ -71,10 +95,51 @@ impl Gui {
                 if imgui::MenuItem::new(im_str!("Tips")).build(&ui) {
                     self.show_tips = true;
                 }
+                if imgui::MenuItem::new(im_str!("Profiler")).build(&ui) {
+                    self.show_profiler = true;
+                }
+                if imgui::MenuItem::new(im_str!("Itineraries"))
+                    .selected(self.show_itineraries)
+                    .build(&ui)
+                {
+                    self.show_itineraries = !self.show_itineraries;
+                }
+                if imgui::MenuItem::new(im_str!("Minimap"))
+                    .selected(self.show_minimap)
+                    .build(&ui)
+                {
+                    self.show_minimap = !self.show_minimap;
+                }
+                if imgui::MenuItem::new(im_str!("Sensors"))
+                    .selected(self.show_sensors)
+                    .build(&ui)
+                {
+                    self.show_sensors = !self.show_sensors;
+                }
+                if imgui::MenuItem::new(im_str!("Congestion"))
+                    .selected(self.show_congestion)
+                    .build(&ui)
+                {
+                    self.show_congestion = !self.show_congestion;
+                }
+                ui.set_next_item_width(70.0);
+                imgui::DragFloat::new(
+                    &ui,
+                    im_str!("circle tolerance (px)"),
+                    &mut self.circle_tolerance,
+                )
+                .min(0.05)
+                .max(5.0)
+                .build();
             });
             if ui.small_button(im_str!("Save")) {
-                crate::vehicles::save(world);
-                crate::map_model::save(world);
+                self.save_error = crate::vehicles::save(world)
+                    .and_then(|_| crate::map_model::save(world))
+                    .err()
+                    .map(|e| e.to_string());
+            }
+            if let Some(e) = &self.save_error {
+                ui.text(im_str!("Could not save: {}", e));
             }
         });
 
@@ -98,6 +163,32 @@ impl Gui {
                         }
                     }
 
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("park")) {
+                        for _ in 0..self.n_cars {
+                            spawn_parked_vehicle(world);
+                        }
+                    }
+
+                    let mut spawn_config = world.write_resource::<SpawnConfig>();
+                    ui.set_next_item_width(70.0);
+                    imgui::DragFloat::new(
+                        &ui,
+                        im_str!("cars per minute"),
+                        &mut spawn_config.vehicles_per_minute,
+                    )
+                    .min(0.0)
+                    .max(600.0)
+                    .build();
+                    drop(spawn_config);
+
+                    let mut decision_config = world.write_resource::<DecisionConfig>();
+                    ui.checkbox(
+                        im_str!("sequential decisions"),
+                        &mut decision_config.sequential,
+                    );
+                    drop(decision_config);
+
                     ui.set_next_item_width(70.0);
                     imgui::DragInt::new(&ui, im_str!("n_pedestrians"), &mut self.n_pedestrians)
                         .min(1)
@@ -158,6 +249,22 @@ impl Gui {
                 .build(&ui, || {
                     ui.text(im_str!("Update time: {:.1}ms", stats.update_time * 1000.0));
                     ui.text(im_str!("Render time: {:.1}ms", stats.render_time * 1000.0));
+                    ui.text(im_str!("Draw calls: {}", stats.draw_calls));
+                });
+        }
+
+        if self.show_profiler {
+            let profiler = world.read_resource::<Profiler>();
+            imgui::Window::new(im_str!("Profiler"))
+                .size([220.0, 300.0], imgui::Condition::FirstUseEver)
+                .position([300.0, 160.0], imgui::Condition::FirstUseEver)
+                .opened(&mut self.show_profiler)
+                .build(&ui, || {
+                    ui.text(im_str!("Systems: {:.2}ms", profiler.total()));
+                    ui.separator();
+                    for (name, ms) in profiler.times() {
+                        ui.text(im_str!("{}: {:.2}ms", name, ms));
+                    }
                 });
         }
 
@@ -182,15 +289,16 @@ impl Gui {
         let time_info = world.get_mut::<TimeInfo>().unwrap();
         let [w, h] = ui.io().display_size;
         imgui::Window::new(im_str!("Time controls"))
-            .size([200.0, 40.0], imgui::Condition::Always)
-            .position([w / 2.0 - 100.0, h - 30.0], imgui::Condition::Always)
+            .size([200.0, 55.0], imgui::Condition::Always)
+            .position([w / 2.0 - 100.0, h - 55.0], imgui::Condition::Always)
             .no_decoration()
             .collapsible(false)
             .resizable(false)
             .build(&ui, || {
                 imgui::Slider::new(im_str!("speed"), std::ops::RangeInclusive::new(0.0, 3.0))
                     .display_format(im_str!("%.1f"))
-                    .build(&ui, &mut time_info.time_speed);
+                    .build(&ui, &mut time_info.time_scale);
+                ui.checkbox(im_str!("pause"), &mut time_info.paused);
             });
     }
 }
@@ -1,4 +1,124 @@
-use specs::Entity;
+use crate::engine_interaction::{KeyCode, KeyboardInfo};
+use crate::geometry::Vec2;
+use crate::physics::Transform;
+use crate::vehicles::VehicleComponent;
+use specs::prelude::*;
 
 #[derive(Default, Clone, Copy)]
 pub struct FollowEntity(pub Option<Entity>);
+
+impl FollowEntity {
+    /// Follows the entity after the currently followed one, wrapping around.
+    /// Starts from the first one if nothing is followed
+    pub fn cycle(&mut self, candidates: impl IntoIterator<Item = Entity>) {
+        let mut candidates: Vec<Entity> = candidates.into_iter().collect();
+        candidates.sort_by_key(|e| e.id());
+
+        let next = match self.0 {
+            Some(cur) => candidates
+                .iter()
+                .find(|e| e.id() > cur.id())
+                .or_else(|| candidates.first()),
+            None => candidates.first(),
+        };
+        self.0 = next.copied();
+    }
+
+    /// Position the camera should be centered on, if the followed entity still has one
+    pub fn target(&self, transforms: &ReadStorage<Transform>) -> Option<Vec2> {
+        self.0.and_then(|e| transforms.get(e)).map(|x| x.position())
+    }
+}
+
+/// Cycles the camera through the vehicles when F is pressed, Escape goes back to free camera
+pub struct FollowSystem;
+impl<'a> System<'a> for FollowSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, KeyboardInfo>,
+        Write<'a, FollowEntity>,
+        ReadStorage<'a, VehicleComponent>,
+    );
+
+    fn run(&mut self, (entities, kbinfo, mut follow, vehicles): Self::SystemData) {
+        if let Some(e) = follow.0 {
+            if !entities.is_alive(e) {
+                follow.0 = None;
+            }
+        }
+
+        if kbinfo.just_pressed.contains(&KeyCode::F) {
+            follow.cycle((&entities, &vehicles).join().map(|(e, _)| e));
+        }
+        if kbinfo.just_pressed.contains(&KeyCode::Escape) {
+            follow.0 = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::Itinerary;
+    use crate::vehicles::VehicleKind;
+
+    #[test]
+    fn test_follow_cycles_and_tracks() {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<VehicleComponent>();
+        world.insert(KeyboardInfo::default());
+        world.insert(FollowEntity::default());
+
+        let cars: Vec<Entity> = (0..3)
+            .map(|i| {
+                world
+                    .create_entity()
+                    .with(Transform::new(vec2!(i as f32 * 10.0, 0.0)))
+                    .with(VehicleComponent::new(
+                        Itinerary::default(),
+                        VehicleKind::Car,
+                    ))
+                    .build()
+            })
+            .collect();
+        // Not a vehicle, never followed
+        world
+            .create_entity()
+            .with(Transform::new(vec2!(-10.0, 0.0)))
+            .build();
+
+        let press = |world: &mut World, key: KeyCode| {
+            world.write_resource::<KeyboardInfo>().just_pressed.clear();
+            world
+                .write_resource::<KeyboardInfo>()
+                .just_pressed
+                .insert(key);
+            FollowSystem.run_now(world);
+            world.write_resource::<KeyboardInfo>().just_pressed.clear();
+        };
+
+        for &car in cars.iter().chain(cars.first()) {
+            press(&mut world, KeyCode::F);
+            assert_eq!(world.read_resource::<FollowEntity>().0, Some(car));
+        }
+
+        for tick in 0..10 {
+            let pos = vec2!(tick as f32, 2.0 * tick as f32);
+            world
+                .write_component::<Transform>()
+                .get_mut(cars[0])
+                .unwrap()
+                .set_position(pos);
+            FollowSystem.run_now(&world);
+
+            let target = world
+                .read_resource::<FollowEntity>()
+                .target(&world.read_component::<Transform>());
+            assert_eq!(target, Some(pos));
+        }
+
+        press(&mut world, KeyCode::Escape);
+        assert!(world.read_resource::<FollowEntity>().0.is_none());
+    }
+}
@@ -74,10 +74,13 @@ impl<'a> System<'a> for MovableSystem {
             }
         } else if let Some(off) = self.offset.take() {
             if let Some(e) = data.selected.e {
-                if let Some(kin) = data.kinematics.get_mut(e) {
-                    let p = data.transforms.get(e).unwrap();
-                    kin.velocity =
-                        (data.mouse.unprojected - (p.position() - off)) / data.time.delta;
+                if let (Some(kin), Some(p)) = (data.kinematics.get_mut(e), data.transforms.get(e)) {
+                    // Thrown with the speed of the last mouse move, if any time passed since
+                    kin.velocity = if data.time.delta > 0.0 {
+                        (data.mouse.unprojected - (p.position() - off)) / data.time.delta
+                    } else {
+                        zero()
+                    };
                 }
             }
         }
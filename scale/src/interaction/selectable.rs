@@ -1,7 +1,10 @@
 use crate::engine_interaction::KeyCode;
 use crate::engine_interaction::{KeyboardInfo, MouseButton, MouseInfo};
-use crate::physics::Transform;
+use crate::geometry::Vec2;
+use crate::physics::systems::MAX_COLLIDER_RADIUS;
+use crate::physics::{CollisionWorld, Transform};
 use cgmath::InnerSpace;
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs::Component;
@@ -30,30 +33,62 @@ pub struct SelectedEntity {
     pub dirty: bool, // Modified by inspection
 }
 
+/// Distance from a collider's edge under which a click still picks it
+pub const PICK_RADIUS: f32 = 1.0;
+
+/// The entity owning the collider closest to the position, among the ones the position is
+/// inside of or at most PICK_RADIUS away from
+pub fn pick_collider(coworld: &CollisionWorld, pos: Vec2) -> Option<Entity> {
+    coworld
+        .query_around(pos, MAX_COLLIDER_RADIUS + PICK_RADIUS, None)
+        .filter_map(|obj| {
+            let po = coworld.get_obj(obj.id);
+            Some((po.owner?, (obj.pos - pos).magnitude() - po.radius))
+        })
+        .filter(|&(_, dist)| dist <= PICK_RADIUS)
+        .min_by_key(|&(_, dist)| OrderedFloat(dist))
+        .map(|(e, _)| e)
+}
+
 pub struct SelectableSystem;
+
+#[derive(SystemData)]
+pub struct SelectableData<'a> {
+    entities: Entities<'a>,
+    mouse: Read<'a, MouseInfo>,
+    kbinfo: Read<'a, KeyboardInfo>,
+    coworld: Read<'a, CollisionWorld, specs::shred::PanicHandler>,
+    selected: Write<'a, SelectedEntity>,
+    transforms: ReadStorage<'a, Transform>,
+    selectables: ReadStorage<'a, Selectable>,
+}
+
 impl<'a> System<'a> for SelectableSystem {
-    #[allow(clippy::type_complexity)]
-    type SystemData = (
-        Entities<'a>,
-        Read<'a, MouseInfo>,
-        Read<'a, KeyboardInfo>,
-        Write<'a, SelectedEntity>,
-        ReadStorage<'a, Transform>,
-        ReadStorage<'a, Selectable>,
-    );
-
-    fn run(
-        &mut self,
-        (entities, mouse, kbinfo, mut selected, transforms, selectables): Self::SystemData,
-    ) {
+    type SystemData = SelectableData<'a>;
+
+    fn run(&mut self, data: Self::SystemData) {
+        let SelectableData {
+            entities,
+            mouse,
+            kbinfo,
+            coworld,
+            mut selected,
+            transforms,
+            selectables,
+        } = data;
+
         if mouse.just_pressed.contains(&MouseButton::Left) {
-            let mut min_dist2 = f32::MAX;
-            let mut closest = None;
-            for (entity, trans, select) in (&entities, &transforms, &selectables).join() {
-                let dist2: f32 = (trans.position() - mouse.unprojected).magnitude2();
-                if dist2 <= min_dist2 && dist2 <= select.radius * select.radius {
-                    closest = Some(entity);
-                    min_dist2 = dist2;
+            // Moving objects have a collider, use the grid to find them quickly
+            let mut closest = pick_collider(&coworld, mouse.unprojected);
+
+            if closest.is_none() {
+                let mut min_dist2 = f32::MAX;
+                for (entity, trans, select) in (&entities, &transforms, &selectables).join() {
+                    let dist2: f32 = (trans.position() - mouse.unprojected).magnitude2();
+                    if dist2 <= min_dist2 && dist2 <= select.radius * select.radius {
+                        closest = Some(entity);
+                        min_dist2 = dist2;
+                    }
                 }
             }
             selected.e = closest;
@@ -69,3 +104,56 @@ impl<'a> System<'a> for SelectableSystem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::gridstore::GridStore;
+    use crate::physics::{Collider, PhysicsObject};
+
+    fn click(world: &World, pos: Vec2) -> Option<Entity> {
+        {
+            let mut mouse = world.write_resource::<MouseInfo>();
+            mouse.unprojected = pos;
+            mouse.just_pressed.insert(MouseButton::Left);
+        }
+        SelectableSystem.run_now(world);
+        world.write_resource::<MouseInfo>().just_pressed.clear();
+        world.read_resource::<SelectedEntity>().e
+    }
+
+    #[test]
+    fn test_click_picks_collider() {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<Selectable>();
+        world.register::<Collider>();
+        world.insert(MouseInfo::default());
+        world.insert(KeyboardInfo::default());
+        world.insert(SelectedEntity::default());
+
+        let mut coworld: CollisionWorld = GridStore::new(50);
+        let mut vehicles = vec![];
+        for &pos in &[vec2!(10.0, 10.0), vec2!(14.0, 10.0)] {
+            let h = coworld.insert(
+                pos,
+                PhysicsObject {
+                    radius: 1.5,
+                    ..Default::default()
+                },
+            );
+            let e = world
+                .create_entity()
+                .with(Transform::new(pos))
+                .with(Collider(h))
+                .build();
+            coworld.set_owner(h, e);
+            vehicles.push(e);
+        }
+        world.insert(coworld);
+
+        assert_eq!(click(&world, vec2!(10.0, 10.0)), Some(vehicles[0]));
+        assert_eq!(click(&world, vec2!(13.0, 10.5)), Some(vehicles[1]));
+        assert_eq!(click(&world, vec2!(100.0, -50.0)), None);
+    }
+}
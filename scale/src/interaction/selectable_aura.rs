@@ -1,3 +1,4 @@
+use crate::engine_interaction::TimeInfo;
 use crate::interaction::SelectedEntity;
 use crate::physics::Transform;
 use crate::rendering::meshrender_component::{CircleRender, MeshRender};
@@ -5,6 +6,12 @@ use crate::rendering::Color;
 use specs::prelude::*;
 use specs::shred::DynamicSystemData;
 
+/// How much the aura grows and shrinks around its size as it pulses
+const AURA_PULSE: f32 = 0.1;
+
+/// Pulses per second of the aura
+const AURA_PULSE_FREQ: f64 = 1.0;
+
 #[derive(Default)]
 pub struct SelectableAuraSystem {
     aura: Option<Entity>,
@@ -12,22 +19,23 @@ pub struct SelectableAuraSystem {
 
 impl<'a> System<'a> for SelectableAuraSystem {
     type SystemData = (
+        Read<'a, TimeInfo>,
         Read<'a, SelectedEntity>,
         WriteStorage<'a, Transform>,
         WriteStorage<'a, MeshRender>,
     );
 
-    fn run(&mut self, (selected, mut transforms, mut meshrenders): Self::SystemData) {
+    fn run(&mut self, (time, selected, mut transforms, mut meshrenders): Self::SystemData) {
         meshrenders.get_mut(self.aura.unwrap()).unwrap().hide = true;
 
         if let Some(pos) = selected
             .e
             .and_then(|sel| transforms.get(sel).map(|x| x.position()))
         {
-            transforms
-                .get_mut(self.aura.unwrap())
-                .unwrap()
-                .set_position(pos);
+            let trans = transforms.get_mut(self.aura.unwrap()).unwrap();
+            trans.set_position(pos);
+            let phase = (time.time * AURA_PULSE_FREQ * std::f64::consts::PI * 2.0).sin() as f32;
+            trans.set_scale(1.0 + AURA_PULSE * phase);
             meshrenders.get_mut(self.aura.unwrap()).unwrap().hide = false;
         }
     }
@@ -1,18 +1,25 @@
 #![windows_subsystem = "windows"]
 #![allow(clippy::unreadable_literal)]
 
+use crate::analytics::{
+    Congestion, CongestionSystem, LaneOccupancy, LaneOccupancySystem, SimEvents,
+};
 use crate::engine_interaction::{KeyboardInfo, RenderStats, TimeInfo};
-use crate::geometry::gridstore::GridStore;
 use crate::gui::Gui;
 use crate::interaction::{
-    FollowEntity, MovableSystem, MovedEvent, SelectableAuraSystem, SelectableSystem, SelectedEntity,
+    FollowEntity, FollowSystem, MovableSystem, MovedEvent, SelectableAuraSystem, SelectableSystem,
+    SelectedEntity,
 };
 use crate::map_model::{MapUIState, MapUISystem};
-use crate::physics::systems::KinematicsApply;
+use crate::physics::systems::{CollisionResponse, KinematicsApply, WorldBoundsSystem};
 use crate::physics::Collider;
-use crate::physics::CollisionWorld;
+use crate::physics::{Contacts, PhysicsConfig, WorldBounds};
+use crate::profiler::{Profiler, TimedDispatcherBuilder};
 use crate::rendering::meshrender_component::MeshRender;
-use crate::vehicles::systems::VehicleDecision;
+use crate::vehicles::systems::{
+    DecisionConfig, VehicleCleanupSystem, VehicleDecision, VehicleMergeSystem,
+};
+use crate::vehicles::{ODMatrix, SpawnConfig, VehicleMix, VehicleParamsTable, VehicleSpawnSystem};
 use specs::{Dispatcher, DispatcherBuilder, World, WorldExt};
 
 #[macro_use]
@@ -24,50 +31,81 @@ pub mod geometry;
 #[macro_use]
 pub mod gui;
 
+pub mod analytics;
 pub mod engine_interaction;
 pub mod graphs;
 pub mod interaction;
 pub mod map_model;
 pub mod pedestrians;
 pub mod physics;
+pub mod profiler;
 pub mod rendering;
+pub mod replay;
+pub mod saveload;
+pub mod simulation;
 pub mod vehicles;
 
-use crate::pedestrians::{spawn_pedestrian, PedestrianDecision};
+use crate::pedestrians::PedestrianDecision;
 use crate::rendering::assets::AssetRender;
-use crate::vehicles::spawn_new_vehicle;
 pub use specs;
 use specs::shrev::EventChannel;
 
-pub fn setup<'a>(world: &mut World) -> Dispatcher<'a, 'a> {
+/// Registers everything and loads the saves. The saves that couldn't be read are returned,
+/// they were set aside and the simulation starts without them.
+pub fn setup<'a>(world: &mut World) -> (Dispatcher<'a, 'a>, Vec<bincode::Error>) {
     let mut dispatch = DispatcherBuilder::new()
-        .with(VehicleDecision, "car decision", &[])
-        .with(PedestrianDecision, "pedestrian decision", &[])
-        .with(SelectableSystem, "selectable", &[])
-        .with(
+        .with_timed(VehicleMergeSystem::default(), "vehicle merge", &[])
+        .with_timed(
+            VehicleDecision::default(),
+            "car decision",
+            &["vehicle merge"],
+        )
+        .with_timed(VehicleSpawnSystem::default(), "vehicle spawn", &[])
+        .with_timed(VehicleCleanupSystem, "vehicle cleanup", &["car decision"])
+        .with_timed(CongestionSystem, "congestion", &["car decision"])
+        .with_timed(LaneOccupancySystem, "lane occupancy", &["car decision"])
+        .with_timed(PedestrianDecision, "pedestrian decision", &[])
+        .with_timed(SelectableSystem, "selectable", &[])
+        .with_timed(FollowSystem, "follow", &[])
+        .with_timed(
             MovableSystem::default(),
             "movable",
             &["car decision", "pedestrian decision", "selectable"],
         )
-        .with(MapUISystem, "rgs", &["movable"])
-        .with(KinematicsApply, "speed apply", &["movable"])
-        .with(
+        .with_timed(MapUISystem, "rgs", &["movable"])
+        .with_timed(CollisionResponse, "collision response", &["movable"])
+        .with_timed(KinematicsApply, "speed apply", &["collision response"])
+        .with_timed(WorldBoundsSystem, "world bounds", &["speed apply"])
+        .with_timed(
             SelectableAuraSystem::default(),
             "selectable aura",
             &["movable"],
         )
         .build();
 
-    let collision_world: CollisionWorld = GridStore::new(50);
+    let physics_config = PhysicsConfig::default();
+    let collision_world = physics_config.make_world();
 
     // Resources init
     world.insert(TimeInfo::default());
     world.insert(collision_world);
+    world.insert(physics_config);
+    world.insert(WorldBounds::default());
     world.insert(KeyboardInfo::default());
     world.insert(Gui::default());
     world.insert(SelectedEntity::default());
     world.insert(FollowEntity::default());
     world.insert(RenderStats::default());
+    world.insert(SpawnConfig::default());
+    world.insert(ODMatrix::default());
+    world.insert(VehicleMix::default());
+    world.insert(DecisionConfig::default());
+    world.insert(VehicleParamsTable::default());
+    world.insert(SimEvents::default());
+    world.insert(Congestion::default());
+    world.insert(LaneOccupancy::default());
+    world.insert(Contacts::default());
+    world.insert(Profiler::default());
 
     world.register::<Collider>();
     world.register::<MeshRender>();
@@ -82,14 +120,11 @@ pub fn setup<'a>(world: &mut World) -> Dispatcher<'a, 'a> {
 
     dispatch.setup(world);
 
-    map_model::setup(world);
-    vehicles::setup(world);
+    let load_errors = vec![map_model::setup(world), vehicles::setup(world)]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
     pedestrians::setup(world);
 
-    for _ in 0..5000 {
-        spawn_pedestrian(world);
-        //spawn_new_vehicle(world);
-    }
-
-    dispatch
+    (dispatch, load_errors)
 }
@@ -1,9 +1,12 @@
+use crate::geometry::polyline::PolyLine;
 use crate::geometry::pseudo_angle;
 use crate::geometry::Vec2;
 use crate::gui::InspectDragf;
 use crate::map_model::{
-    Intersections, LaneID, Lanes, LightPolicy, RoadID, Roads, Turn, TurnID, TurnPolicy,
+    GreenWave, Intersections, LaneID, Lanes, LightPolicy, RoadID, Roads, Turn, TurnID, TurnKind,
+    TurnPolicy,
 };
+use cgmath::InnerSpace;
 use imgui_inspect_derive::*;
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
@@ -22,6 +25,29 @@ impl IntersectionID {
     }
 }
 
+/// Radius of the ring of a roundabout relative to the interface radius of the intersection
+pub const ROUNDABOUT_RING_RATIO: f32 = 0.6;
+
+/// Largest angle between two roads and a straight line for them to be considered aligned
+pub const ALIGNED_ROADS_TOLERANCE: f32 = 20.0 * std::f32::consts::PI / 180.0;
+
+/// Shape of an intersection given by its roads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntersectionKind {
+    /// No road connected yet
+    Isolated,
+    DeadEnd,
+    /// Two roads, that may or may not be aligned
+    Bend,
+    /// Three roads, two of them aligned
+    T,
+    /// Three roads, none of them aligned
+    Y,
+    /// Four roads, aligned two by two
+    Cross,
+    Complex,
+}
+
 #[derive(Component, Clone, Serialize, Deserialize, Inspect)]
 #[storage(BTreeStorage)]
 pub struct IntersectionComponent {
@@ -46,6 +72,7 @@ pub struct Intersection {
     pub interface_radius: f32,
     pub turn_policy: TurnPolicy,
     pub light_policy: LightPolicy,
+    pub green_wave: Option<GreenWave>,
 }
 
 impl Intersection {
@@ -58,6 +85,7 @@ impl Intersection {
             interface_radius: 20.0,
             turn_policy: TurnPolicy::default(),
             light_policy: LightPolicy::default(),
+            green_wave: None,
         })
     }
 
@@ -88,8 +116,112 @@ impl Intersection {
                 .or_insert_with(|| Turn::new(turn_id, kind));
         }
 
+        let ring = self.roundabout_ring();
+        for turn in self.turns.values_mut() {
+            match ring {
+                Some((center, radius)) if turn.kind == TurnKind::Normal => {
+                    turn.make_ring_points(lanes, center, radius)
+                }
+                _ => turn.make_points(lanes),
+            }
+        }
+
+        self.gen_yields(lanes);
+    }
+
+    /// Unprotected left turns yield to the oncoming vehicles that go straight or turn right
+    fn gen_yields(&mut self, lanes: &Lanes) {
+        let roundabout = self.roundabout_ring().is_some();
+        let normal: Vec<TurnID> = self
+            .turns
+            .values()
+            .filter(|turn| turn.kind == TurnKind::Normal)
+            .map(|turn| turn.id)
+            .collect();
+
         for turn in self.turns.values_mut() {
-            turn.make_points(lanes);
+            turn.yield_to.clear();
+            if roundabout || turn.kind != TurnKind::Normal || !turn.id.is_left(lanes) {
+                continue;
+            }
+
+            let src_dir = lanes[turn.id.src].get_orientation_vec();
+            turn.yield_to = normal
+                .iter()
+                .filter(|id| {
+                    lanes[id.src].get_orientation_vec().dot(src_dir) < -0.7 && !id.is_left(lanes)
+                })
+                .copied()
+                .collect();
+        }
+    }
+
+    /// Center and radius of the ring vehicles drive around if the intersection is a roundabout
+    pub fn roundabout_ring(&self) -> Option<(Vec2, f32)> {
+        if self.light_policy == LightPolicy::Roundabout {
+            Some((self.pos, self.interface_radius * ROUNDABOUT_RING_RATIO))
+        } else {
+            None
+        }
+    }
+
+    /// Outline of the area where the roads meet, made of the outer edges of each road's lanes
+    /// where they end at the intersection, in angular order around it
+    pub fn compute_polygon(&self, roads: &Roads, lanes: &Lanes) -> PolyLine {
+        let mut points: Vec<Vec2> = vec![];
+
+        for &road_id in &self.roads {
+            let road = &roads[road_id];
+            let dir = road.dir_from(self.id, self.pos);
+            let normal = vec2!(-dir.y, dir.x);
+
+            let edges: Vec<Vec2> = road
+                .lanes_iter()
+                .flat_map(|&lane| {
+                    let lane = &lanes[lane];
+                    let node = lane.get_inter_node_pos(self.id);
+                    let half = normal * lane.width / 2.0;
+                    vec![node - half, node + half]
+                })
+                .collect();
+
+            let side = |p: &&Vec2| OrderedFloat((*p - self.pos).dot(normal));
+            if let (Some(&left), Some(&right)) =
+                (edges.iter().max_by_key(side), edges.iter().min_by_key(side))
+            {
+                points.push(left);
+                points.push(right);
+            }
+        }
+
+        points.retain(|&p| p != self.pos);
+        points.sort_by_key(|&p| OrderedFloat(pseudo_angle((p - self.pos).normalize())));
+        PolyLine::new(points)
+    }
+
+    pub fn classify(&self, roads: &Roads) -> IntersectionKind {
+        let dirs: Vec<Vec2> = self
+            .roads
+            .iter()
+            .map(|&x| roads[x].dir_from(self.id, self.pos))
+            .collect();
+        let aligned =
+            |a: Vec2, b: Vec2| a.angle(b).0.abs() > std::f32::consts::PI - ALIGNED_ROADS_TOLERANCE;
+
+        match *dirs.as_slice() {
+            [] => IntersectionKind::Isolated,
+            [_] => IntersectionKind::DeadEnd,
+            [_, _] => IntersectionKind::Bend,
+            [a, b, c] => {
+                if aligned(a, b) || aligned(b, c) || aligned(c, a) {
+                    IntersectionKind::T
+                } else {
+                    IntersectionKind::Y
+                }
+            }
+            // Roads are sorted by angle so the opposite ones are two apart
+            [a, b, c, d] if aligned(a, c) && aligned(b, d) => IntersectionKind::Cross,
+            _ => IntersectionKind::Complex,
         }
     }
 
@@ -101,6 +233,29 @@ impl Intersection {
             .collect()
     }
 
+    pub fn turns_to(&self, dst: LaneID) -> impl Iterator<Item = &Turn> {
+        self.turns
+            .iter()
+            .filter(move |(id, _)| id.dst == dst)
+            .map(|(_, x)| x)
+    }
+
+    /// The lane the given incoming lane merges into, if its only turn leads to a lane
+    /// that other lanes also turn into
+    pub fn merged_lane(&self, lane: LaneID) -> Option<LaneID> {
+        match *self.turns_from(lane).as_slice() {
+            [turn]
+                if turn.kind == TurnKind::Normal
+                    && self
+                        .turns_to(turn.id.dst)
+                        .any(|x| x.kind == TurnKind::Normal && x.id.src != lane) =>
+            {
+                Some(turn.id.dst)
+            }
+            _ => None,
+        }
+    }
+
     pub fn turns_adirectional(&self, lane: LaneID) -> Vec<&Turn> {
         self.turns
             .iter()
@@ -124,3 +279,216 @@ impl Intersection {
         self.light_policy.apply(self, lanes, roads);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::geometry::Vec2;
+    use crate::map_model::test_maps::crossroads;
+    use crate::map_model::{
+        IntersectionKind, LanePatternBuilder, LightPolicy, Map, RoadID, TurnID, TurnKind,
+    };
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn test_turns_to() {
+        let mut map = Map::empty();
+        let center = map.add_intersection(vec2!(0.0, 0.0));
+        let pattern = LanePatternBuilder::new().n_lanes(2).build();
+
+        let mut roads = vec![];
+        for &pos in &[vec2!(100.0, 0.0), vec2!(0.0, 100.0), vec2!(-100.0, 0.0)] {
+            let other = map.add_intersection(pos);
+            roads.push(map.connect(center, other, &pattern));
+        }
+
+        let inter = &map.intersections()[center];
+        for road in roads {
+            for &dst in map.roads()[road].outgoing_lanes_from(center) {
+                let mut to: Vec<TurnID> = inter.turns_to(dst).map(|x| x.id).collect();
+                to.sort();
+
+                let expected: Vec<TurnID> = inter
+                    .turns
+                    .keys()
+                    .filter(|id| id.dst == dst)
+                    .copied()
+                    .collect();
+
+                assert_eq!(to, expected);
+                assert!(to.iter().all(|id| id.dst == dst));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ring_turns_are_smooth() {
+        let (map, center, _) = crossroads(LightPolicy::Roundabout);
+
+        let lanes = map.lanes();
+        for turn in map.intersections()[center]
+            .turns
+            .values()
+            .filter(|t| t.kind == TurnKind::Normal)
+        {
+            let points = turn.points.as_slice();
+            assert_eq!(points[0], lanes[turn.id.src].get_inter_node_pos(center));
+            assert_eq!(
+                points[points.len() - 1],
+                lanes[turn.id.dst].get_inter_node_pos(center)
+            );
+
+            for w in points.windows(3) {
+                let (a, b) = ((w[1] - w[0]).normalize(), (w[2] - w[1]).normalize());
+                assert!(a.dot(b) > 0.5, "sharp corner in {:?}", turn.id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_way_turns() {
+        let mut map = Map::empty();
+        let center = map.add_intersection(vec2!(0.0, 0.0));
+        let two_way = LanePatternBuilder::new().build();
+        let one_way = LanePatternBuilder::new().one_way(true).build();
+
+        let west = map.add_intersection(vec2!(-100.0, 0.0));
+        let north = map.add_intersection(vec2!(0.0, 100.0));
+        let east = map.add_intersection(vec2!(100.0, 0.0));
+        map.connect(west, center, &two_way);
+        map.connect(north, center, &two_way);
+        // Only drives into the center
+        let entry = map.connect(east, center, &one_way);
+
+        let road = &map.roads()[entry];
+        assert!(road.is_one_way(map.lanes()));
+        assert!(!map.roads()[map.find_road(west, center).unwrap()].is_one_way(map.lanes()));
+
+        let inter = &map.intersections()[center];
+        assert!(!inter.turns.is_empty());
+        for id in inter.turns.keys() {
+            let dst = &map.lanes()[id.dst];
+            if dst.kind.vehicles() {
+                assert_ne!(dst.parent, entry);
+                assert_eq!(dst.src, center);
+            }
+        }
+        // Vehicles coming in from the one way road can still go somewhere
+        let incoming = road.incoming_lanes_to(center)[0];
+        assert!(inter.turns.keys().any(|id| id.src == incoming));
+
+        // The dead end has nothing to turn into
+        assert!(map.intersections()[east]
+            .turns
+            .keys()
+            .all(|id| !map.lanes()[id.src].kind.vehicles()));
+    }
+
+    #[test]
+    fn test_one_way_keeps_opposite_phases() {
+        let (mut map, center, mut roads) = crossroads(LightPolicy::Lights);
+
+        // The first one only leaves the center
+        let east = map.roads()[roads[0]].other_end(center);
+        map.remove_road(roads[0]);
+        let one_way = LanePatternBuilder::new().one_way(true).build();
+        roads[0] = map.connect(center, east, &one_way);
+
+        let inter = &map.intersections()[center];
+        let control = |road: RoadID| {
+            let lane = map.roads()[road]
+                .incoming_lanes_to(center)
+                .iter()
+                .find(|&&x| map.lanes()[x].kind.vehicles())
+                .copied()
+                .unwrap();
+            map.lanes()[lane].control
+        };
+
+        // Roads facing each other in inter.roads order share the same phase
+        let index = |road: RoadID| inter.roads.iter().position(|&x| x == road).unwrap();
+        let others: Vec<RoadID> = roads[1..].to_vec();
+        for &a in &others {
+            for &b in &others {
+                let same_phase = index(a) % 2 == index(b) % 2;
+                let agree =
+                    (0..28).all(|t| control(a).get_behavior(t) == control(b).get_behavior(t));
+                assert_eq!(same_phase, agree);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compute_polygon() {
+        let mut map = Map::empty();
+        let center = map.add_intersection(vec2!(3.0, -2.0));
+        let pattern = LanePatternBuilder::new().n_lanes(2).build();
+        for &pos in &[
+            vec2!(100.0, 0.0),
+            vec2!(0.0, 100.0),
+            vec2!(-100.0, 10.0),
+            vec2!(20.0, -100.0),
+        ] {
+            let other = map.add_intersection(pos);
+            map.connect(center, other, &pattern);
+        }
+
+        let inter = &map.intersections()[center];
+        let poly = inter.compute_polygon(map.roads(), map.lanes());
+        assert_eq!(poly.n_points(), 8);
+
+        // Ray casting towards +x
+        let p = inter.pos;
+        let pts = poly.as_slice();
+        let mut crossings = 0;
+        for i in 0..pts.len() {
+            let (a, b) = (pts[i], pts[(i + 1) % pts.len()]);
+            if (a.y > p.y) != (b.y > p.y) {
+                let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if x > p.x {
+                    crossings += 1;
+                }
+            }
+        }
+        assert_eq!(crossings % 2, 1);
+    }
+
+    #[test]
+    fn test_classify() {
+        fn classify(ends: &[Vec2]) -> IntersectionKind {
+            let mut map = Map::empty();
+            let center = map.add_intersection(vec2!(0.0, 0.0));
+            let pattern = LanePatternBuilder::new().build();
+            for &pos in ends {
+                let other = map.add_intersection(pos);
+                map.connect(center, other, &pattern);
+            }
+            map.intersections()[center].classify(map.roads())
+        }
+
+        assert_eq!(classify(&[]), IntersectionKind::Isolated);
+        assert_eq!(classify(&[vec2!(100.0, 0.0)]), IntersectionKind::DeadEnd);
+        assert_eq!(
+            classify(&[vec2!(100.0, 0.0), vec2!(-100.0, 0.0), vec2!(0.0, 100.0)]),
+            IntersectionKind::T
+        );
+        let (map, center, _) = crossroads(LightPolicy::default());
+        assert_eq!(
+            map.intersections()[center].classify(map.roads()),
+            IntersectionKind::Cross
+        );
+        assert_eq!(
+            classify(&[vec2!(0.0, 100.0), vec2!(87.0, -50.0), vec2!(-87.0, -50.0)]),
+            IntersectionKind::Y
+        );
+        assert_eq!(
+            classify(&[
+                vec2!(100.0, 0.0),
+                vec2!(0.0, 100.0),
+                vec2!(-100.0, 0.0),
+                vec2!(40.0, -100.0),
+                vec2!(-40.0, -100.0)
+            ]),
+            IntersectionKind::Complex
+        );
+    }
+}
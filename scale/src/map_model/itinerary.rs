@@ -1,7 +1,11 @@
 use crate::geometry::polyline::PolyLine;
 use crate::geometry::Vec2;
-use crate::map_model::{Map, Traversable};
+use crate::map_model::{
+    LaneID, LaneKind, Map, RouteCost, Traversable, TraverseDirection, TraverseKind, TurnID,
+};
+use cgmath::MetricSpace;
 use imgui_inspect_derive::*;
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Default, Inspect, Serialize, Deserialize)]
@@ -39,6 +43,17 @@ impl Itinerary {
         }
     }
 
+    /// Adds points to go through before resuming the current path, e.g. for a detour.
+    /// Has no effect on an empty itinerary.
+    pub fn prepend_waypoints(&mut self, pts: Vec<Vec2>) {
+        if self.is_none() {
+            return;
+        }
+        let mut path = PolyLine::new(pts);
+        path.extend(self.local_path.iter());
+        self.local_path = path;
+    }
+
     pub fn remaining_points(&self) -> usize {
         self.local_path.n_points()
     }
@@ -47,6 +62,22 @@ impl Itinerary {
         self.local_path.first()
     }
 
+    /// Points left to go through until the end of the itinerary, for debugging purposes
+    pub fn remaining_geometry(&self, map: &Map) -> PolyLine {
+        let mut geom = self.local_path.clone();
+        if let ItineraryKind::Route { cursor, path } = &self.kind {
+            for t in path.iter().skip(cursor + 1).filter(|t| t.is_valid(map)) {
+                for &p in t.points(map).iter() {
+                    // Consecutive traversables share their end points
+                    if geom.last().map_or(true, |last| last.distance2(p) > 1e-4) {
+                        geom.push(p);
+                    }
+                }
+            }
+        }
+        geom
+    }
+
     pub fn get_travers(&self) -> Option<&Traversable> {
         match &self.kind {
             ItineraryKind::None => None,
@@ -55,9 +86,20 @@ impl Itinerary {
         }
     }
 
+    /// The traversable advance will move to once the current one is done, if any
+    pub fn peek_next(&self, map: &Map) -> Option<Traversable> {
+        match &self.kind {
+            ItineraryKind::Route { cursor, path } => {
+                path.get(cursor + 1).copied().filter(|t| t.is_valid(map))
+            }
+            _ => None,
+        }
+    }
+
     pub fn advance(&mut self, map: &Map) -> Option<Vec2> {
         let v = self.local_path.pop_first();
         if self.local_path.is_empty() {
+            self.repair_next(map);
             if let ItineraryKind::Route { cursor, path } = &mut self.kind {
                 if *cursor < path.len() - 1 {
                     *cursor += 1;
@@ -68,6 +110,43 @@ impl Itinerary {
         v
     }
 
+    /// Makes sure the traversable after the current one can still be driven to. When a part
+    /// of the route was removed, e.g. while on a turn where reroute can't be used, the rest
+    /// of the route is replaced by a new way from the next lane, or dropped if there is none.
+    fn repair_next(&mut self, map: &Map) {
+        let (cursor, path) = match &mut self.kind {
+            ItineraryKind::Route { cursor, path } => (*cursor, path),
+            _ => return,
+        };
+        if cursor + 1 >= path.len() || connects(path[cursor], path[cursor + 1], map) {
+            return;
+        }
+
+        let current = path[cursor];
+        let dest = path[path.len() - 1];
+        path.truncate(cursor + 1);
+
+        let (from, skip) = match current.kind {
+            TraverseKind::Lane(id) => (id, 1),
+            TraverseKind::Turn(id) => (id.dst, 0),
+        };
+        let route = match dest.kind {
+            TraverseKind::Lane(to) => map.driving_route(from, to, RouteCost::Distance),
+            TraverseKind::Turn(_) => None,
+        };
+        if let Some(route) = route {
+            path.extend(route.into_iter().skip(skip));
+        }
+    }
+
+    /// Whether each traversable of the route starts where the previous one ends
+    pub fn is_continuous(&self, map: &Map) -> bool {
+        match &self.kind {
+            ItineraryKind::None | ItineraryKind::Simple(_) => true,
+            ItineraryKind::Route { path, .. } => path.windows(2).all(|w| connects(w[0], w[1], map)),
+        }
+    }
+
     pub fn check_validity(&mut self, map: &Map) {
         match &self.kind {
             ItineraryKind::None => {}
@@ -76,21 +155,166 @@ impl Itinerary {
                     self.set_none()
                 }
             }
-            ItineraryKind::Route { .. } => todo!(),
+            ItineraryKind::Route { path, .. } => {
+                if !path.iter().all(|t| t.is_valid(map)) {
+                    self.set_none()
+                }
+            }
+        }
+    }
+
+    /// Whether what is left of the route after the current traversable can't be taken anymore,
+    /// e.g. because a lane of it was closed
+    pub fn is_blocked_ahead(&self, map: &Map) -> bool {
+        match &self.kind {
+            ItineraryKind::Route { cursor, path } => {
+                path.iter().skip(cursor + 1).any(|t| !t.is_valid(map))
+            }
+            _ => false,
         }
     }
 
+    /// Replaces the rest of the route with a new way from the current lane to the same
+    /// destination, keeping the progress made on the current lane. Without any, only the
+    /// current lane is left to drive. Fails and leaves the itinerary as it was if it isn't a
+    /// route or if on a turn.
+    pub fn reroute(&mut self, map: &Map) -> bool {
+        let (current, dest) = match &self.kind {
+            ItineraryKind::Route { cursor, path } => (path[*cursor], path[path.len() - 1]),
+            _ => return false,
+        };
+        let from = match current.kind {
+            TraverseKind::Lane(id) if current.exists(map) => id,
+            _ => return false,
+        };
+
+        let path = match dest.kind {
+            TraverseKind::Lane(to) => map.driving_route(from, to, RouteCost::Distance),
+            TraverseKind::Turn(_) => None,
+        };
+        self.kind = match path {
+            Some(path) => ItineraryKind::Route { cursor: 0, path },
+            None => ItineraryKind::Simple(current),
+        };
+        true
+    }
+
     pub fn has_ended(&self) -> bool {
         match &self.kind {
             ItineraryKind::None => true,
             ItineraryKind::Simple(_) => self.local_path.is_empty(),
-            ItineraryKind::Route { cursor, path } => *cursor >= path.len(),
+            ItineraryKind::Route { cursor, path } => {
+                self.local_path.is_empty() && *cursor + 1 >= path.len()
+            }
         }
     }
 
     pub fn is_none(&self) -> bool {
         matches!(self.kind, ItineraryKind::None)
     }
+
+    /// Turns around on the current lane and goes back the way the route came, as far as it
+    /// can be driven back. Fails and leaves the itinerary as it was if the current lane has
+    /// no lane going the other way, or if on a turn.
+    pub fn reverse(&mut self, map: &Map) -> bool {
+        let (current, before) = match &self.kind {
+            ItineraryKind::None => return false,
+            ItineraryKind::Simple(t) => (*t, &[][..]),
+            ItineraryKind::Route { cursor, path } => (path[*cursor], &path[..*cursor]),
+        };
+        if !current.kind.is_lane() || !current.is_valid(map) {
+            return false;
+        }
+
+        let mut path = match reverse_travers(current, map) {
+            Some(x) => vec![x],
+            None => return false,
+        };
+        for t in before.iter().rev() {
+            match reverse_travers(*t, map) {
+                Some(x) if t.is_valid(map) => path.push(x),
+                _ => break,
+            }
+        }
+        // A turn can't end the route, stop on the lane before it
+        if !path.last().unwrap().kind.is_lane() {
+            path.pop();
+        }
+
+        // Lanes of a road share the same points, the ones facing what is left of the current
+        // lane are ahead of the vehicle.
+        let ahead = self.local_path.n_points();
+
+        self.set_route(path, map);
+        let skip = ahead.min(self.local_path.n_points().saturating_sub(1));
+        for _ in 0..skip {
+            self.local_path.pop_first();
+        }
+        true
+    }
+}
+
+/// Whether both exist and b starts where a ends
+fn connects(a: Traversable, b: Traversable, map: &Map) -> bool {
+    if !a.exists(map) || !b.exists(map) {
+        return false;
+    }
+    match (a.points(map).last(), b.points(map).first()) {
+        (Some(end), Some(start)) => end.distance2(start) < 1e-4,
+        _ => false,
+    }
+}
+
+/// The lane of the same road and kind going the other way, closest to the center
+fn opposite_lane(lane: LaneID, map: &Map) -> Option<LaneID> {
+    let lanes = map.lanes();
+    let l = &lanes[lane];
+    map.roads()[l.parent]
+        .outgoing_lanes_from(l.dst)
+        .iter()
+        .filter(|&&x| lanes[x].kind == l.kind)
+        .min_by_key(|&&x| OrderedFloat(lanes[x].dist_from_center.abs()))
+        .copied()
+}
+
+/// The traversable going through the same place the other way. Walking lanes and turns are
+/// just walked backward, vehicles need the opposite lanes and the turn between them.
+fn reverse_travers(t: Traversable, map: &Map) -> Option<Traversable> {
+    let flipped = match t.dir {
+        TraverseDirection::Forward => TraverseDirection::Backward,
+        TraverseDirection::Backward => TraverseDirection::Forward,
+    };
+
+    match t.kind {
+        TraverseKind::Lane(id) => {
+            if map.lanes()[id].kind == LaneKind::Walking {
+                return Some(Traversable::new(t.kind, flipped));
+            }
+            let opposite = opposite_lane(id, map)?;
+            Some(Traversable::new(
+                TraverseKind::Lane(opposite),
+                TraverseDirection::Forward,
+            ))
+        }
+        TraverseKind::Turn(id) => {
+            let inter = &map.intersections()[id.parent];
+            if inter.turns[&id].kind.is_walking() {
+                return Some(Traversable::new(t.kind, flipped));
+            }
+            let reversed = TurnID::new(
+                id.parent,
+                opposite_lane(id.dst, map)?,
+                opposite_lane(id.src, map)?,
+            );
+            if !inter.turns.contains_key(&reversed) {
+                return None;
+            }
+            Some(Traversable::new(
+                TraverseKind::Turn(reversed),
+                TraverseDirection::Forward,
+            ))
+        }
+    }
 }
 
 impl Default for ItineraryKind {
@@ -100,3 +324,248 @@ impl Default for ItineraryKind {
 }
 
 enum_inspect_impl!(ItineraryKind; ItineraryKind::None, ItineraryKind::Simple(_), ItineraryKind::Route { .. });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::LanePatternBuilder;
+
+    #[test]
+    fn test_is_continuous() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road = map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+
+        let lane1 = map.roads()[road].outgoing_lanes_from(a)[0];
+        let turn = map.intersections()[b].turns_from(lane1)[0].id;
+        let lane2 = turn.dst;
+
+        let forward = |kind| Traversable::new(kind, TraverseDirection::Forward);
+
+        let mut it = Itinerary::default();
+        it.set_route(
+            vec![
+                forward(TraverseKind::Lane(lane1)),
+                forward(TraverseKind::Turn(turn)),
+                forward(TraverseKind::Lane(lane2)),
+            ],
+            &map,
+        );
+        assert!(it.is_continuous(&map));
+
+        // Skips the turn
+        it.set_route(
+            vec![
+                forward(TraverseKind::Lane(lane1)),
+                forward(TraverseKind::Lane(lane2)),
+            ],
+            &map,
+        );
+        assert!(!it.is_continuous(&map));
+
+        // Wrong order
+        it.set_route(
+            vec![
+                forward(TraverseKind::Lane(lane2)),
+                forward(TraverseKind::Turn(turn)),
+            ],
+            &map,
+        );
+        assert!(!it.is_continuous(&map));
+    }
+
+    #[test]
+    fn test_remaining_geometry() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road = map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+
+        let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+        let turn = map.intersections()[b].turns_from(lane)[0].id;
+
+        let lane_t = Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward);
+        let turn_t = Traversable::new(TraverseKind::Turn(turn), TraverseDirection::Forward);
+
+        let mut it = Itinerary::default();
+        it.set_route(vec![lane_t, turn_t], &map);
+
+        let geom = it.remaining_geometry(&map);
+        assert_eq!(geom.first(), lane_t.points(&map).first());
+        assert_eq!(geom.last(), turn_t.points(&map).last());
+        assert_eq!(
+            geom.n_points(),
+            lane_t.points(&map).n_points() + turn_t.points(&map).n_points() - 1
+        );
+
+        // Only the turn is left once the lane is done
+        for _ in 0..lane_t.points(&map).n_points() {
+            it.advance(&map);
+        }
+        assert_eq!(
+            it.remaining_geometry(&map).first(),
+            turn_t.points(&map).first()
+        );
+    }
+
+    #[test]
+    fn test_peek_next() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road = map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+
+        let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+        let turn = map.intersections()[b].turns_from(lane)[0].id;
+
+        let lane_t = Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward);
+        let turn_t = Traversable::new(TraverseKind::Turn(turn), TraverseDirection::Forward);
+
+        let mut it = Itinerary::default();
+        it.set_route(vec![lane_t, turn_t], &map);
+        let n_points = it.remaining_points();
+
+        for _ in 0..2 {
+            let next = it.peek_next(&map).unwrap();
+            assert!(matches!(next.kind, TraverseKind::Turn(x) if x == turn));
+        }
+        assert!(matches!(it.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == lane));
+        assert_eq!(it.remaining_points(), n_points);
+
+        // Nothing after the last one
+        for _ in 0..n_points {
+            it.advance(&map);
+        }
+        assert!(matches!(it.get_travers().unwrap().kind, TraverseKind::Turn(x) if x == turn));
+        assert!(it.peek_next(&map).is_none());
+
+        it.set_simple(lane_t, &map);
+        assert!(it.peek_next(&map).is_none());
+    }
+
+    #[test]
+    fn test_lane_removed_ahead_while_on_turn() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let d = map.add_intersection(vec2!(300.0, 0.0));
+        let e = map.add_intersection(vec2!(200.0, 100.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road1 = map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+        let road3 = map.connect(c, d, &pattern);
+        map.connect(c, e, &pattern);
+        let road5 = map.connect(e, d, &pattern);
+
+        let lane1 = map.roads()[road1].outgoing_lanes_from(a)[0];
+        let dest = map.roads()[road3].outgoing_lanes_from(c)[0];
+        let mut it = Itinerary::default();
+        it.set_route(
+            map.driving_route(lane1, dest, RouteCost::Distance).unwrap(),
+            &map,
+        );
+        while it.get_travers().unwrap().kind.is_lane() {
+            it.advance(&map);
+        }
+
+        // The last lane can't be driven to anymore, the rest of the route is dropped
+        map.remove_road(road3);
+        while !it.has_ended() {
+            it.advance(&map);
+        }
+        assert!(it.is_continuous(&map));
+        assert!(it.get_travers().unwrap().exists(&map));
+
+        // A part of the route is gone but the destination is still reachable another way
+        let dest = map.roads()[road5].outgoing_lanes_from(e)[0];
+        it.set_route(
+            map.driving_route(lane1, dest, RouteCost::Distance).unwrap(),
+            &map,
+        );
+        while it.get_travers().unwrap().kind.is_lane() {
+            it.advance(&map);
+        }
+        let before = map.add_intersection(vec2!(150.0, 50.0));
+        let c_e = *map.intersections()[e]
+            .roads
+            .iter()
+            .find(|r| map.roads()[**r].other_end(e) == c)
+            .unwrap();
+        map.remove_road(c_e);
+        map.connect(c, before, &pattern);
+        map.connect(before, e, &pattern);
+
+        while !it.has_ended() {
+            it.advance(&map);
+        }
+        assert!(it.is_continuous(&map));
+        assert!(matches!(it.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == dest));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road1 = map.connect(a, b, &pattern);
+        let road2 = map.connect(b, c, &pattern);
+
+        let lane1 = map.roads()[road1].outgoing_lanes_from(a)[0];
+        let turn = TurnID::new(b, lane1, map.roads()[road2].outgoing_lanes_from(b)[0]);
+        assert!(map.connected(lane1, turn.dst));
+
+        let forward = |kind| Traversable::new(kind, TraverseDirection::Forward);
+
+        let mut it = Itinerary::default();
+        it.set_route(
+            vec![
+                forward(TraverseKind::Lane(lane1)),
+                forward(TraverseKind::Turn(turn)),
+                forward(TraverseKind::Lane(turn.dst)),
+            ],
+            &map,
+        );
+        // Drive up to the middle of the second lane
+        while !matches!(it.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == turn.dst) {
+            it.advance(&map);
+        }
+        it.advance(&map);
+
+        assert!(it.reverse(&map));
+        assert!(it.is_continuous(&map));
+
+        let current = *it.get_travers().unwrap();
+        assert!(matches!(current.kind, TraverseKind::Lane(x) if map.lanes()[x].parent == road2));
+        assert!(it.get_point().unwrap().x < 200.0);
+
+        // Goes back to a through b
+        let back = map.roads()[road1].outgoing_lanes_from(b)[0];
+        let geom = it.remaining_geometry(&map);
+        assert_eq!(geom.last(), map.lanes()[back].points.last());
+        assert!(geom.last().unwrap().distance(vec2!(0.0, 0.0)) < 20.0);
+
+        // Nothing to go back on a one way road
+        let d = map.add_intersection(vec2!(0.0, 100.0));
+        let one_way = map.connect(a, d, &LanePatternBuilder::new().one_way(true).build());
+        let lane = map.roads()[one_way].outgoing_lanes_from(a)[0];
+        it.set_simple(forward(TraverseKind::Lane(lane)), &map);
+        let n_points = it.remaining_points();
+
+        assert!(!it.reverse(&map));
+        assert!(matches!(it.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == lane));
+        assert_eq!(it.remaining_points(), n_points);
+    }
+}
@@ -18,6 +18,7 @@ pub enum LaneKind {
     Bus,
     Construction,
     Walking,
+    Parking,
 }
 
 impl LaneKind {
@@ -36,6 +37,9 @@ pub enum LaneDirection {
     Backward,
 }
 
+/// Speed limit of new lanes, in m/s
+pub const DEFAULT_SPEED_LIMIT: f32 = 15.0;
+
 #[derive(Serialize, Deserialize)]
 pub struct Lane {
     pub id: LaneID,
@@ -51,6 +55,17 @@ pub struct Lane {
     pub points: PolyLine,
     pub width: f32,
     pub dist_from_center: f32,
+
+    pub speed_limit: f32,
+
+    /// Buses stop at the end of the lane to let passengers in
+    pub bus_stop: bool,
+
+    /// Distances along the lane of the speed bumps vehicles slow down for, sorted
+    pub speed_bumps: Vec<f32>,
+
+    /// Closed by an incident or construction works: routes avoid it and vehicles on it leave it
+    pub closed: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -65,6 +80,7 @@ pub struct LanePatternBuilder {
     #[inspect(min_value = 1.0)]
     pub n_lanes: u32,
     pub sidewalks: bool,
+    pub parking: bool,
     pub one_way: bool,
 }
 
@@ -73,6 +89,7 @@ impl Default for LanePatternBuilder {
         LanePatternBuilder {
             n_lanes: 1,
             sidewalks: true,
+            parking: false,
             one_way: false,
         }
     }
@@ -94,6 +111,11 @@ impl LanePatternBuilder {
         self
     }
 
+    pub fn parking(&mut self, parking: bool) -> &mut Self {
+        self.parking = parking;
+        self
+    }
+
     pub fn one_way(&mut self, one_way: bool) -> &mut Self {
         self.one_way = one_way;
         self
@@ -108,6 +130,13 @@ impl LanePatternBuilder {
 
         let mut forward: Vec<_> = (0..self.n_lanes).map(|_| LaneKind::Driving).collect();
 
+        if self.parking {
+            if !self.one_way {
+                backward.push(LaneKind::Parking);
+            }
+            forward.push(LaneKind::Parking);
+        }
+
         if self.sidewalks {
             backward.push(LaneKind::Walking);
             forward.push(LaneKind::Walking);
@@ -119,6 +148,10 @@ impl LanePatternBuilder {
         if !self.sidewalks {
             name.push_str(&" no sidewalks");
         }
+
+        if self.parking {
+            name.push_str(&" parking");
+        }
         LanePattern {
             lanes_backward: backward,
             lanes_forward: forward,
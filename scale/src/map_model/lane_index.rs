@@ -0,0 +1,86 @@
+use crate::geometry::polyline::PolyLine;
+use crate::geometry::Vec2;
+use crate::map_model::LaneID;
+use std::collections::{HashMap, HashSet};
+
+/// Size in meters of the cells of the lane index
+pub const LANE_INDEX_CELL_SIZE: f32 = 50.0;
+
+/// Grid over the lanes' geometry: each lane is referenced by every cell touched by the
+/// bounding box of one of its segments, so that nearby lanes are found without scanning them all
+#[derive(Default, Clone)]
+pub struct LaneIndex {
+    cells: HashMap<(i32, i32), Vec<LaneID>>,
+    lane_cells: HashMap<LaneID, Vec<(i32, i32)>>,
+}
+
+fn cell(p: Vec2) -> (i32, i32) {
+    (
+        (p.x / LANE_INDEX_CELL_SIZE).floor() as i32,
+        (p.y / LANE_INDEX_CELL_SIZE).floor() as i32,
+    )
+}
+
+fn cells_between(a: Vec2, b: Vec2) -> impl Iterator<Item = (i32, i32)> {
+    let (min_x, min_y) = cell(vec2!(a.x.min(b.x), a.y.min(b.y)));
+    let (max_x, max_y) = cell(vec2!(a.x.max(b.x), a.y.max(b.y)));
+    (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+}
+
+impl LaneIndex {
+    /// Indexes the lane, replacing its previous geometry if it was already in it
+    pub fn insert(&mut self, id: LaneID, points: &PolyLine) {
+        self.remove(id);
+
+        let mut covered: Vec<(i32, i32)> = points
+            .as_slice()
+            .windows(2)
+            .flat_map(|w| cells_between(w[0], w[1]))
+            .chain(points.first().map(cell))
+            .collect();
+        covered.sort();
+        covered.dedup();
+
+        for &c in &covered {
+            self.cells.entry(c).or_default().push(id);
+        }
+        self.lane_cells.insert(id, covered);
+    }
+
+    pub fn remove(&mut self, id: LaneID) {
+        for c in self.lane_cells.remove(&id).unwrap_or_default() {
+            if let Some(lanes) = self.cells.get_mut(&c) {
+                lanes.retain(|&x| x != id);
+                if lanes.is_empty() {
+                    self.cells.remove(&c);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.lane_cells.clear();
+    }
+
+    /// The lanes that may come within radius of the point, each of them once.
+    /// Some of them can be further, the caller must check the exact distance.
+    pub fn candidates(&self, pos: Vec2, radius: f32) -> Vec<LaneID> {
+        let r = vec2!(radius, radius);
+        let mut seen = HashSet::new();
+        cells_between(pos - r, pos + r)
+            .filter_map(|c| self.cells.get(&c))
+            .flatten()
+            .filter(|&&id| seen.insert(id))
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lane_cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lane_cells.is_empty()
+    }
+}
@@ -1,17 +1,30 @@
-use crate::map_model::{Intersection, LaneID, Lanes, Roads, TrafficControl, TrafficLightSchedule};
+use crate::gui::{enum_combo, shared_name};
+use crate::map_model::{
+    Intersection, LaneID, Lanes, RoadID, Roads, TrafficControl, TrafficLightSchedule,
+};
 use cgmath::InnerSpace;
-use imgui::{im_str, Ui};
+use imgui::Ui;
 use imgui_inspect::{InspectArgsDefault, InspectRenderDefault};
 use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use specs::World;
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum LightPolicy {
     NoLights,
     StopSigns,
     Lights,
     Smart,
+    Roundabout,
+}
+
+/// Coordination of an intersection's lights with its neighbors along an arterial
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GreenWave {
+    /// The road whose incoming lanes get the coordinated green phase
+    pub road: RoadID,
+    /// Time in seconds into the cycle at which that green phase starts
+    pub delay: usize,
 }
 
 impl Default for LightPolicy {
@@ -22,7 +35,9 @@ impl Default for LightPolicy {
 
 impl LightPolicy {
     pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
-        let in_road_lanes: Vec<Vec<&LaneID>> = inter
+        // Roads leaving one-way only have no incoming lanes, keep the index of the others in
+        // inter.roads so that opposite roads still share their light phase
+        let (road_index, in_road_lanes): (Vec<usize>, Vec<Vec<&LaneID>>) = inter
             .roads
             .iter()
             .map(|&x| {
@@ -32,8 +47,9 @@ impl LightPolicy {
                     .filter(|&&x| lanes[x].kind.needs_light())
                     .collect::<Vec<_>>()
             })
-            .filter(|v| !v.is_empty())
-            .collect();
+            .enumerate()
+            .filter(|(_, v)| !v.is_empty())
+            .unzip();
 
         let two_lanes_or_less = in_road_lanes.len() <= 2;
 
@@ -45,6 +61,14 @@ impl LightPolicy {
 
         match (self, two_lanes_or_less) {
             (LightPolicy::NoLights, _) | (LightPolicy::Smart, true) => {}
+            (LightPolicy::Roundabout, _) => {
+                // Entering traffic yields to the ring
+                for incoming_lanes in in_road_lanes {
+                    for &lane in incoming_lanes {
+                        lanes[lane].control = TrafficControl::Yield;
+                    }
+                }
+            }
             (LightPolicy::StopSigns, _) => {
                 for incoming_lanes in in_road_lanes {
                     for &lane in incoming_lanes {
@@ -76,19 +100,34 @@ impl LightPolicy {
             (LightPolicy::Smart, false) | (LightPolicy::Lights, _) => {
                 let cycle_size = 10;
                 let orange_length = 4;
-                let offset = inter.id.as_ffi();
-                let offset: usize =
-                    rand::rngs::SmallRng::seed_from_u64(offset as u64).gen_range(0, cycle_size);
+                let period = 2 * (cycle_size + orange_length);
 
-                for (i, incoming_lanes) in in_road_lanes.into_iter().enumerate() {
+                // Roads with the same parity as main_parity share its phase
+                // A wave along a road that no longer reaches the intersection has nothing
+                // to coordinate, the lights go back to their own schedule
+                let wave = inter.green_wave.and_then(|wave| {
+                    let main = inter.roads.iter().position(|&x| x == wave.road)?;
+                    Some((wave.delay, main))
+                });
+                let (offset, main_parity) = match wave {
+                    Some((delay, main)) => (period - delay % period, main % 2),
+                    None => {
+                        let seed = inter.id.as_ffi();
+                        let offset: usize =
+                            rand::rngs::SmallRng::seed_from_u64(seed).gen_range(0, cycle_size);
+                        (offset, 1)
+                    }
+                };
+
+                for (i, incoming_lanes) in road_index.into_iter().zip(in_road_lanes) {
                     let light = TrafficControl::Light(TrafficLightSchedule::from_basic(
                         cycle_size,
                         orange_length,
                         cycle_size + orange_length,
-                        if i % 2 == 0 {
-                            cycle_size + orange_length + offset
-                        } else {
+                        if i % 2 == main_parity {
                             offset
+                        } else {
+                            cycle_size + orange_length + offset
                         },
                     ));
 
@@ -101,9 +140,42 @@ impl LightPolicy {
     }
 }
 
+impl LightPolicy {
+    pub const ALL: [LightPolicy; 5] = [
+        LightPolicy::NoLights,
+        LightPolicy::StopSigns,
+        LightPolicy::Lights,
+        LightPolicy::Smart,
+        LightPolicy::Roundabout,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LightPolicy::NoLights => "No lights",
+            LightPolicy::StopSigns => "Stop signs",
+            LightPolicy::Lights => "Lights",
+            LightPolicy::Smart => "Smart",
+            LightPolicy::Roundabout => "Roundabout",
+        }
+    }
+}
+
+/// Read-only text for the selected policies, a placeholder when they differ or when there
+/// are none
+fn policies_label(data: &[&LightPolicy], label: &str) -> String {
+    let name = shared_name(data.iter().map(|x| x.name()));
+    format!("{}: {}", label, name)
+}
+
 impl InspectRenderDefault<LightPolicy> for LightPolicy {
-    fn render(_: &[&LightPolicy], _: &'static str, _: &mut World, _: &Ui, _: &InspectArgsDefault) {
-        unimplemented!()
+    fn render(
+        data: &[&LightPolicy],
+        label: &'static str,
+        _: &mut World,
+        ui: &Ui,
+        _: &InspectArgsDefault,
+    ) {
+        ui.text(policies_label(data, label));
     }
 
     fn render_mut(
@@ -113,38 +185,48 @@ impl InspectRenderDefault<LightPolicy> for LightPolicy {
         ui: &Ui,
         _: &InspectArgsDefault,
     ) -> bool {
-        if data.len() != 1 {
-            unimplemented!()
-        }
-        let p = &mut data[0];
-        let mut id = match p {
-            LightPolicy::NoLights => 0,
-            LightPolicy::StopSigns => 1,
-            LightPolicy::Lights => 2,
-            LightPolicy::Smart => 3,
-        };
-
-        let changed = imgui::ComboBox::new(&im_str!("{}", label)).build_simple_string(
-            ui,
-            &mut id,
-            &[
-                &im_str!("No lights"),
-                &im_str!("Stop signs"),
-                &im_str!("Lights"),
-                &im_str!("Smart"),
-            ],
+        let choices: Vec<(LightPolicy, &str)> =
+            LightPolicy::ALL.iter().map(|&x| (x, x.name())).collect();
+        enum_combo(ui, label, data, &choices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::{set_all, shared_value, SharedValue};
+
+    #[test]
+    fn test_policies_label() {
+        assert_eq!(
+            policies_label(&[&LightPolicy::StopSigns], "policy"),
+            "policy: Stop signs"
+        );
+        assert_eq!(
+            policies_label(&[&LightPolicy::Smart, &LightPolicy::Smart], "policy"),
+            "policy: Smart"
         );
+        assert_eq!(
+            policies_label(&[&LightPolicy::Smart, &LightPolicy::Lights], "policy"),
+            "policy: Multiple values"
+        );
+        assert_eq!(policies_label(&[], "policy"), "policy: None");
+    }
 
-        if changed {
-            match id {
-                0 => **p = LightPolicy::NoLights,
-                1 => **p = LightPolicy::StopSigns,
-                2 => **p = LightPolicy::Lights,
-                3 => **p = LightPolicy::Smart,
-                _ => unreachable!(),
-            }
-        }
+    #[test]
+    fn test_multiple_selection() {
+        let mut a = LightPolicy::Smart;
+        let mut b = LightPolicy::Lights;
+
+        assert_eq!(shared_value(vec![a, b]), SharedValue::Multiple);
+        assert_eq!(
+            shared_value(vec![a, a]),
+            SharedValue::Same(LightPolicy::Smart)
+        );
+        assert_eq!(shared_value(Vec::<LightPolicy>::new()), SharedValue::Empty);
 
-        changed
+        // Picking a value in the combo sets it on every selected intersection
+        set_all(&mut [&mut a, &mut b], LightPolicy::Roundabout);
+        assert_eq!((a, b), (LightPolicy::Roundabout, LightPolicy::Roundabout));
     }
 }
@@ -1,11 +1,15 @@
+use crate::geometry::polyline::PolyLine;
 use crate::geometry::Vec2;
 use crate::map_model::{
-    Intersection, IntersectionID, Lane, LaneID, LaneKind, LanePattern, LightPolicy, Road, RoadID,
-    TurnPolicy,
+    GreenWave, Intersection, IntersectionID, Lane, LaneID, LaneIndex, LaneKind, LanePattern,
+    LightPolicy, Road, RoadID, TrafficControl, TurnID, TurnPolicy, LANE_INDEX_CELL_SIZE,
 };
-use crate::utils::rand_det;
+use crate::utils::{rand_det, FnvHasher};
 use serde::{Deserialize, Serialize};
 use slotmap::DenseSlotMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
 
 pub type Roads = DenseSlotMap<RoadID, Road>;
 pub type Lanes = DenseSlotMap<LaneID, Lane>;
@@ -16,8 +20,14 @@ pub struct Map {
     roads: Roads,
     lanes: Lanes,
     intersections: Intersections,
+    /// Derived from the lanes, rebuilt with rebuild_lane_index after loading
+    #[serde(skip)]
+    lane_index: LaneIndex,
 }
 
+/// Number of times closest_lane doubles its search radius before scanning all the lanes
+const CLOSEST_LANE_STEPS: usize = 8;
+
 impl Default for Map {
     fn default() -> Self {
         Self::empty()
@@ -30,6 +40,7 @@ impl Map {
             roads: Roads::with_key(),
             lanes: Lanes::with_key(),
             intersections: Intersections::with_key(),
+            lane_index: LaneIndex::default(),
         }
     }
 
@@ -37,6 +48,61 @@ impl Map {
         self.roads.is_empty() && self.lanes.is_empty() && self.intersections.is_empty()
     }
 
+    /// Hash of the topology, geometry and traffic rules of the map, in id order. Coordinates are rounded to
+    /// the centimeter so that floating point noise doesn't change it, and the hasher is
+    /// fixed so that it can be compared with hashes from other runs
+    pub fn content_hash(&self) -> u64 {
+        fn quantize(x: f32) -> i64 {
+            (x * 100.0).round() as i64
+        }
+        fn hash_points(points: &PolyLine, h: &mut FnvHasher) {
+            points.n_points().hash(h);
+            for p in points.iter() {
+                (quantize(p.x), quantize(p.y)).hash(h);
+            }
+        }
+
+        let mut h = FnvHasher::default();
+
+        for id in self.intersections_sorted() {
+            let inter = &self.intersections[id];
+            inter.id.hash(&mut h);
+            (quantize(inter.pos.x), quantize(inter.pos.y)).hash(&mut h);
+            quantize(inter.interface_radius).hash(&mut h);
+            inter.roads.hash(&mut h);
+            inter.light_policy.hash(&mut h);
+            // BTreeMap, already sorted
+            for turn in inter.turns.values() {
+                turn.id.hash(&mut h);
+                turn.kind.hash(&mut h);
+                hash_points(&turn.points, &mut h);
+            }
+        }
+
+        for id in self.roads_sorted() {
+            let road = &self.roads[id];
+            (road.id, road.src, road.dst).hash(&mut h);
+            road.lanes_iter().for_each(|x| x.hash(&mut h));
+            hash_points(&road.interpolation_points, &mut h);
+        }
+
+        for id in self.lanes_sorted() {
+            let lane = &self.lanes[id];
+            (lane.id, lane.parent, lane.kind, lane.src, lane.dst).hash(&mut h);
+            quantize(lane.width).hash(&mut h);
+            quantize(lane.speed_limit).hash(&mut h);
+            lane.bus_stop.hash(&mut h);
+            lane.closed.hash(&mut h);
+            lane.control.hash(&mut h);
+            lane.speed_bumps
+                .iter()
+                .for_each(|&x| quantize(x).hash(&mut h));
+            hash_points(&lane.points, &mut h);
+        }
+
+        h.finish()
+    }
+
     pub fn roads(&self) -> &Roads {
         &self.roads
     }
@@ -47,13 +113,45 @@ impl Map {
         &self.intersections
     }
 
+    /// Ids in ascending order, unlike the slot maps' iteration order it only depends on the
+    /// ids themselves
+    pub fn roads_sorted(&self) -> Vec<RoadID> {
+        let mut ids: Vec<RoadID> = self.roads.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn lanes_sorted(&self) -> Vec<LaneID> {
+        let mut ids: Vec<LaneID> = self.lanes.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn intersections_sorted(&self) -> Vec<IntersectionID> {
+        let mut ids: Vec<IntersectionID> = self.intersections.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    /// The turns of all the intersections, in ascending order
+    pub fn turns_sorted(&self) -> Vec<TurnID> {
+        let mut ids: Vec<TurnID> = self
+            .intersections
+            .values()
+            .flat_map(|inter| inter.turns.keys().copied())
+            .collect();
+        ids.sort();
+        ids
+    }
+
     pub fn set_intersection_radius(&mut self, id: IntersectionID, radius: f32) {
         if (self.intersections[id].interface_radius - radius).abs() < 0.001 {
             return;
         }
         self.intersections[id].interface_radius = radius;
-        for x in &self.intersections[id].roads {
-            self.roads[*x].gen_pos(&self.intersections, &mut self.lanes);
+        for x in self.intersections[id].roads.clone() {
+            self.roads[x].gen_pos(&self.intersections, &mut self.lanes);
+            self.index_road(x);
         }
         self.intersections[id].gen_turns(&self.lanes, &self.roads);
     }
@@ -74,6 +172,70 @@ impl Map {
 
         self.intersections[id].light_policy = policy;
         self.intersections[id].update_traffic_control(&mut self.lanes, &self.roads);
+        // Roundabouts have their own turn geometry
+        self.intersections[id].gen_turns(&self.lanes, &self.roads);
+    }
+
+    /// Offsets the lights along the route so that a vehicle driving at the speed limit
+    /// reaches each intersection as it turns green. Consecutive intersections must be connected
+    pub fn coordinate_green_wave(&mut self, route: &[IntersectionID]) {
+        let mut delay = 0;
+        for (i, &id) in route.iter().enumerate() {
+            let road = if i == 0 {
+                // Nothing comes before the first intersection, sync the lanes sharing the
+                // phase of the arterial
+                match route.get(1).and_then(|&next| self.find_road(id, next)) {
+                    Some(x) => x,
+                    None => return,
+                }
+            } else {
+                let prev = route[i - 1];
+                let road = match self.find_road(prev, id) {
+                    Some(x) => x,
+                    None => return,
+                };
+                let speed_limit = self.roads[road]
+                    .incoming_lanes_to(id)
+                    .iter()
+                    .filter(|&&x| self.lanes[x].kind.vehicles())
+                    .map(|&x| self.lanes[x].speed_limit)
+                    .fold(std::f32::INFINITY, f32::min);
+                if speed_limit.is_finite() && speed_limit > 0.0 {
+                    delay += (self.roads[road].length() / speed_limit).round() as usize;
+                }
+                road
+            };
+
+            self.intersections[id].green_wave = Some(GreenWave { road, delay });
+            self.intersections[id].update_traffic_control(&mut self.lanes, &self.roads);
+        }
+    }
+
+    pub fn set_lane_speed_limit(&mut self, id: LaneID, speed_limit: f32) {
+        self.lanes[id].speed_limit = speed_limit;
+    }
+
+    /// Overrides the control of the lane until the light policy of its intersection is applied again
+    pub fn set_lane_control(&mut self, id: LaneID, control: TrafficControl) {
+        self.lanes[id].control = control;
+    }
+
+    pub fn set_lane_bus_stop(&mut self, id: LaneID, bus_stop: bool) {
+        self.lanes[id].bus_stop = bus_stop;
+    }
+
+    /// Closes the lane to new traffic or opens it back. Itineraries going through it are no
+    /// longer valid, vehicles already on it drive to its end and leave it.
+    pub fn set_lane_closed(&mut self, id: LaneID, closed: bool) {
+        self.lanes[id].closed = closed;
+    }
+
+    /// Places speed bumps at the given distances along the lane, replacing the previous ones.
+    /// Distances that are not finite are ignored.
+    pub fn set_lane_speed_bumps(&mut self, id: LaneID, mut speed_bumps: Vec<f32>) {
+        speed_bumps.retain(|x| x.is_finite());
+        speed_bumps.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        self.lanes[id].speed_bumps = speed_bumps;
     }
 
     pub fn add_intersection(&mut self, pos: Vec2) -> IntersectionID {
@@ -85,6 +247,7 @@ impl Map {
 
         for x in self.intersections[id].roads.clone() {
             self.roads[x].gen_pos(&self.intersections, &mut self.lanes);
+            self.index_road(x);
 
             let other_end = &mut self.intersections[self.roads[x].other_end(id)];
             other_end.gen_turns(&self.lanes, &self.roads);
@@ -119,6 +282,7 @@ impl Map {
 
         self.intersections[src].add_road(road_id, &mut self.lanes, &self.roads);
         self.intersections[dst].add_road(road_id, &mut self.lanes, &self.roads);
+        self.index_road(road_id);
 
         road_id
     }
@@ -133,7 +297,7 @@ impl Map {
         let (_, road) = self.roads.iter().nth(r).unwrap();
         let lanes = road
             .lanes_iter()
-            .filter(|x| self.lanes[**x].kind == kind)
+            .filter(|x| self.lanes[**x].kind == kind && !self.lanes[**x].closed)
             .collect::<Vec<&LaneID>>();
 
         if lanes.is_empty() {
@@ -144,10 +308,14 @@ impl Map {
         Some(&self.lanes[*lanes[r]])
     }
 
-    pub(crate) fn remove_road(&mut self, road_id: RoadID) -> Road {
+    /// Removes the road and its lanes. Only the intersections at its ends get their turns
+    /// and traffic control recomputed, like connect does when adding one. Itineraries going
+    /// through it are no longer valid.
+    pub fn remove_road(&mut self, road_id: RoadID) -> Road {
         let road = self.roads.remove(road_id).unwrap();
         for lane_id in road.lanes_iter() {
             self.lanes.remove(*lane_id).unwrap();
+            self.lane_index.remove(*lane_id);
         }
 
         self.intersections[road.src].remove_road(road_id, &mut self.lanes, &self.roads);
@@ -167,20 +335,467 @@ impl Map {
     }
 
     pub fn closest_lane(&self, p: Vec2) -> Option<LaneID> {
-        let mut min_dist = std::f32::MAX;
-        let mut closest = None;
+        self.closest_lane_filter(p, |_| true)
+    }
 
-        for (id, lane) in &self.lanes {
-            let dist = lane.dist_to(p);
-            if dist < min_dist {
-                min_dist = dist;
-                closest = Some(id);
+    /// Looks in the lane index with a growing radius, only scanning all the lanes when
+    /// none passing the filter is close
+    pub fn closest_lane_filter(
+        &self,
+        p: Vec2,
+        filter: impl Fn(LaneKind) -> bool,
+    ) -> Option<LaneID> {
+        let mut radius = LANE_INDEX_CELL_SIZE;
+        for _ in 0..CLOSEST_LANE_STEPS {
+            let near = self.lanes_in_radius(p, radius);
+            if let Some(x) = closest_of(near.iter().map(|&x| &self.lanes[x]), p, &filter) {
+                return Some(x);
             }
+            radius *= 2.0;
+        }
+
+        closest_of(self.lanes.values(), p, &filter)
+    }
+
+    /// The lanes whose geometry comes within radius of the point
+    pub fn lanes_in_radius(&self, p: Vec2, radius: f32) -> Vec<LaneID> {
+        self.lane_index
+            .candidates(p, radius)
+            .into_iter()
+            .filter(|&id| {
+                self.lanes
+                    .get(id)
+                    .map_or(false, |lane| lane.dist_to(p) <= radius)
+            })
+            .collect()
+    }
+
+    /// Indexes all the lanes again, the index isn't saved with the map
+    pub fn rebuild_lane_index(&mut self) {
+        self.lane_index.clear();
+        for (id, lane) in &self.lanes {
+            self.lane_index.insert(id, &lane.points);
+        }
+    }
+
+    fn index_road(&mut self, road: RoadID) {
+        for &id in self.roads[road].lanes_iter() {
+            self.lane_index.insert(id, &self.lanes[id].points);
         }
-        closest
     }
 
     pub fn is_neigh(&self, src: IntersectionID, dst: IntersectionID) -> bool {
         self.find_road(src, dst).is_some()
     }
+
+    /// Whether a single turn at the end of lane a leads directly into lane b
+    pub fn connected(&self, a: LaneID, b: LaneID) -> bool {
+        let inter = match self
+            .lanes
+            .get(a)
+            .and_then(|l| self.intersections.get(l.dst))
+        {
+            Some(x) => x,
+            None => return false,
+        };
+        inter.turns.contains_key(&TurnID::new(inter.id, a, b))
+    }
+
+    /// GraphViz description of the network for debugging: lanes are nodes and turns are
+    /// edges labeled with their kind. Both are sorted by id so the output is stable.
+    pub fn to_dot(&self) -> String {
+        let lanes = self.lanes_sorted();
+        let node: HashMap<LaneID, usize> = lanes.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+
+        let mut out = String::from("digraph map {\n");
+        for (i, &id) in lanes.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    l{} [label=\"{:?} {:?}\"];",
+                i, id, self.lanes[id].kind
+            );
+        }
+        for id in self.turns_sorted() {
+            let _ = writeln!(
+                out,
+                "    l{} -> l{} [label=\"{:?}\"];",
+                node[&id.src], node[&id.dst], self.intersections[id.parent].turns[&id].kind
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn closest_of<'a>(
+    lanes: impl Iterator<Item = &'a Lane>,
+    p: Vec2,
+    filter: &impl Fn(LaneKind) -> bool,
+) -> Option<LaneID> {
+    let mut min_dist = std::f32::MAX;
+    let mut closest = None;
+
+    for lane in lanes.filter(|x| filter(x.kind)) {
+        let dist = lane.dist_to(p);
+        if dist < min_dist {
+            min_dist = dist;
+            closest = Some(lane.id);
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::test_maps::crossroads;
+    use crate::map_model::{
+        Itinerary, LanePatternBuilder, TrafficBehavior, TrafficControl, Traversable,
+        TraverseDirection, TraverseKind,
+    };
+
+    fn build_grid() -> Map {
+        let mut map = Map::empty();
+        let pattern = LanePatternBuilder::new().n_lanes(2).build();
+
+        let mut ids = vec![];
+        for y in 0..3 {
+            for x in 0..3 {
+                ids.push(map.add_intersection(vec2!(x as f32 * 123.4, y as f32 * 98.7)));
+            }
+        }
+        for y in 0..3 {
+            for x in 0..3 {
+                let id = ids[y * 3 + x];
+                if x < 2 {
+                    map.connect(id, ids[y * 3 + x + 1], &pattern);
+                }
+                if y < 2 {
+                    map.connect(id, ids[(y + 1) * 3 + x], &pattern);
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn test_sorted_ids() {
+        let map = build_grid();
+        let other = build_grid();
+
+        assert_eq!(map.lanes_sorted(), other.lanes_sorted());
+        assert_eq!(map.roads_sorted(), other.roads_sorted());
+        assert_eq!(map.intersections_sorted(), other.intersections_sorted());
+        assert_eq!(map.turns_sorted(), other.turns_sorted());
+
+        let lanes = map.lanes_sorted();
+        assert_eq!(lanes.len(), map.lanes().len());
+        assert!(lanes.windows(2).all(|w| w[0] < w[1]));
+
+        let turns = map.turns_sorted();
+        let n_turns: usize = map.intersections().values().map(|x| x.turns.len()).sum();
+        assert_eq!(turns.len(), n_turns);
+        assert!(turns.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let map = build_grid();
+        let mut other = build_grid();
+        assert_eq!(map.content_hash(), other.content_hash());
+
+        let lane = other.lanes().keys().next().unwrap();
+        other.set_lane_speed_limit(lane, 5.0);
+        assert_ne!(map.content_hash(), other.content_hash());
+
+        let mut moved = build_grid();
+        let inter = moved.intersections().keys().next().unwrap();
+        moved.move_intersection(inter, vec2!(10.0, 10.0));
+        assert_ne!(map.content_hash(), moved.content_hash());
+
+        let mut closed = build_grid();
+        closed.set_lane_closed(lane, true);
+        assert_ne!(map.content_hash(), closed.content_hash());
+
+        let mut controlled = build_grid();
+        controlled.set_lane_control(lane, TrafficControl::Yield);
+        assert_ne!(map.content_hash(), controlled.content_hash());
+
+        let mut policy = build_grid();
+        let inter = policy.intersections().keys().next().unwrap();
+        policy.set_intersection_light_policy(inter, LightPolicy::NoLights);
+        assert_ne!(map.content_hash(), policy.content_hash());
+    }
+
+    #[test]
+    fn test_speed_bumps_skip_non_finite() {
+        let mut map = build_grid();
+        let lane = map.lanes().keys().next().unwrap();
+
+        map.set_lane_speed_bumps(lane, vec![30.0, std::f32::NAN, 10.0, std::f32::INFINITY]);
+        assert_eq!(map.lanes()[lane].speed_bumps, vec![10.0, 30.0]);
+    }
+
+    #[test]
+    fn test_lanes_in_radius() {
+        let mut map = build_grid();
+
+        let check = |map: &Map| {
+            for i in 0..200 {
+                let p = vec2!((i * 37 % 300) as f32 - 30.0, (i * 53 % 260) as f32 - 30.0);
+                let r = (i % 7) as f32 * 8.0;
+
+                let mut found = map.lanes_in_radius(p, r);
+                found.sort();
+                let mut expected: Vec<LaneID> = map
+                    .lanes()
+                    .values()
+                    .filter(|x| x.dist_to(p) <= r)
+                    .map(|x| x.id)
+                    .collect();
+                expected.sort();
+                assert_eq!(found, expected);
+
+                let closest = map.closest_lane(p).unwrap();
+                let min_dist = map
+                    .lanes()
+                    .values()
+                    .map(|x| x.dist_to(p))
+                    .fold(std::f32::INFINITY, f32::min);
+                assert_eq!(map.lanes()[closest].dist_to(p), min_dist);
+            }
+        };
+
+        check(&map);
+
+        // Edits keep the index up to date
+        let inter = map.intersections().keys().next().unwrap();
+        map.move_intersection(inter, vec2!(-20.0, 15.0));
+        map.set_intersection_radius(inter, 12.0);
+        let road = map.roads().keys().nth(3).unwrap();
+        map.remove_road(road);
+        check(&map);
+
+        // The index isn't serialized
+        let mut loaded: Map = bincode::deserialize(&bincode::serialize(&map).unwrap()).unwrap();
+        assert!(loaded.lanes_in_radius(vec2!(0.0, 0.0), 1000.0).is_empty());
+        loaded.rebuild_lane_index();
+        check(&loaded);
+        assert!(map.closest_lane(vec2!(5000.0, 5000.0)).is_some());
+        assert!(Map::empty().closest_lane(vec2!(0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_green_wave() {
+        let mut map = Map::empty();
+        let pattern = LanePatternBuilder::new().n_lanes(1).build();
+
+        let route: Vec<IntersectionID> = (0..3)
+            .map(|i| map.add_intersection(vec2!(i as f32 * 300.0, 0.0)))
+            .collect();
+        for w in route.windows(2) {
+            map.connect(w[0], w[1], &pattern);
+        }
+        for &id in &route {
+            map.set_intersection_light_policy(id, LightPolicy::Lights);
+        }
+
+        map.coordinate_green_wave(&route);
+
+        let delay = |id: IntersectionID| map.intersections()[id].green_wave.unwrap().delay;
+        assert_eq!(delay(route[0]), 0);
+
+        for w in route.windows(2) {
+            let road = &map.roads()[map.find_road(w[0], w[1]).unwrap()];
+            let lane = road
+                .incoming_lanes_to(w[1])
+                .iter()
+                .map(|&x| &map.lanes()[x])
+                .find(|x| x.kind.vehicles())
+                .unwrap();
+            let travel_time = road.length() / lane.speed_limit;
+
+            assert_eq!(delay(w[1]), delay(w[0]) + travel_time.round() as usize);
+
+            // The light turns green when the vehicle arrives
+            let t = delay(w[1]) as u64;
+            assert!(matches!(
+                lane.control.get_behavior(t),
+                TrafficBehavior::GREEN
+            ));
+            assert!(!matches!(
+                lane.control.get_behavior(t + 27),
+                TrafficBehavior::GREEN
+            ));
+        }
+    }
+
+    #[test]
+    fn test_green_wave_on_removed_road() {
+        let (mut map, id, roads) = crossroads(LightPolicy::Lights);
+        map.intersections[id].green_wave = Some(GreenWave {
+            road: roads[0],
+            delay: 5,
+        });
+        map.remove_road(roads[0]);
+
+        let schedules = |map: &Map| -> Vec<Vec<TrafficBehavior>> {
+            map.lanes()
+                .values()
+                .map(|lane| (0..28).map(|t| lane.control.get_behavior(t)).collect())
+                .collect()
+        };
+        let with_wave = schedules(&map);
+
+        // Same lights as an intersection that was never part of a wave
+        map.intersections[id].green_wave = None;
+        map.intersections[id].update_traffic_control(&mut map.lanes, &map.roads);
+        assert_eq!(schedules(&map), with_wave);
+    }
+
+    #[test]
+    fn test_connected() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 0.0));
+        let d = map.add_intersection(vec2!(300.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let ab = map.connect(a, b, &pattern);
+        let bc = map.connect(b, c, &pattern);
+        let cd = map.connect(c, d, &pattern);
+
+        let driving = |map: &Map, road: RoadID, from: IntersectionID| {
+            *map.roads()[road]
+                .outgoing_lanes_from(from)
+                .iter()
+                .find(|&&x| map.lanes()[x].kind == LaneKind::Driving)
+                .unwrap()
+        };
+
+        let lane_ab = driving(&map, ab, a);
+        let lane_bc = driving(&map, bc, b);
+        let lane_cd = driving(&map, cd, c);
+        let lane_ba = driving(&map, ab, b);
+
+        assert!(map.connected(lane_ab, lane_bc));
+        assert!(map.connected(lane_bc, lane_cd));
+
+        // Not the other way around
+        assert!(!map.connected(lane_bc, lane_ab));
+
+        // Needs to go through bc first
+        assert!(!map.connected(lane_ab, lane_cd));
+
+        // Same road, there are no back turns by default
+        assert!(!map.connected(lane_ab, lane_ba));
+        assert!(!map.connected(lane_ab, lane_ab));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let c = map.add_intersection(vec2!(100.0, 100.0));
+        let pattern = LanePatternBuilder::new().build();
+        map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+
+        let dot = map.to_dot();
+        assert!(dot.starts_with("digraph map {"));
+        assert!(dot.trim_end().ends_with('}'));
+
+        let edges = dot.lines().filter(|l| l.contains("->")).count();
+        let nodes = dot
+            .lines()
+            .filter(|l| l.contains("[label") && !l.contains("->"))
+            .count();
+        let n_turns: usize = map.intersections().values().map(|x| x.turns.len()).sum();
+        assert_eq!(nodes, map.lanes().len());
+        assert_eq!(nodes, 8);
+        assert_eq!(edges, n_turns);
+        assert!(edges > 0);
+        assert!(dot.contains("\"Normal\""));
+        assert!(dot.contains("\"WalkingCorner\""));
+
+        assert_eq!(map.to_dot(), dot);
+    }
+
+    #[test]
+    fn test_remove_road_only_updates_its_ends() {
+        let mut map = Map::empty();
+        let ids: Vec<IntersectionID> = (0..4)
+            .map(|i| map.add_intersection(vec2!(i as f32 * 100.0, 0.0)))
+            .collect();
+        let pattern = LanePatternBuilder::new().build();
+        let roads: Vec<RoadID> = ids
+            .windows(2)
+            .map(|w| map.connect(w[0], w[1], &pattern))
+            .collect();
+        let (a, b, c, d) = (ids[0], ids[1], ids[2], ids[3]);
+        for &id in &ids {
+            map.set_intersection_light_policy(id, LightPolicy::Lights);
+        }
+
+        let removed_lanes: Vec<LaneID> = map.roads()[roads[1]].lanes_iter().copied().collect();
+        let mut it = Itinerary::default();
+        it.set_route(
+            vec![Traversable::new(
+                TraverseKind::Lane(removed_lanes[0]),
+                TraverseDirection::Forward,
+            )],
+            &map,
+        );
+
+        // Mark every turn and light, the recomputed ones get their points and lights back
+        for inter in map.intersections.values_mut() {
+            for turn in inter.turns.values_mut() {
+                turn.points.clear();
+            }
+        }
+        for lane in map.lanes.values_mut() {
+            lane.control = TrafficControl::Always;
+        }
+        let untouched = |map: &Map, id| {
+            let turns = &map.intersections()[id].turns;
+            !turns.is_empty() && turns.values().all(|t| t.points.is_empty())
+        };
+        let recomputed = |map: &Map, id| {
+            let turns = &map.intersections()[id].turns;
+            !turns.is_empty() && turns.values().all(|t| !t.points.is_empty())
+        };
+        let lit = |map: &Map, road: RoadID, id| {
+            map.roads()[road]
+                .incoming_lanes_to(id)
+                .iter()
+                .filter(|&&x| map.lanes()[x].kind.needs_light())
+                .all(|&x| map.lanes()[x].control.is_light())
+        };
+
+        map.remove_road(roads[1]);
+
+        assert!(untouched(&map, a));
+        assert!(untouched(&map, d));
+        assert!(recomputed(&map, b));
+        assert!(recomputed(&map, c));
+
+        assert!(lit(&map, roads[0], b));
+        assert!(lit(&map, roads[2], c));
+        assert!(!lit(&map, roads[0], a));
+        assert!(!lit(&map, roads[2], d));
+
+        for &id in &[b, c] {
+            let inter = &map.intersections()[id];
+            assert_eq!(inter.roads.len(), 1);
+            assert!(inter
+                .turns
+                .keys()
+                .all(|t| map.lanes().contains_key(t.src) && map.lanes().contains_key(t.dst)));
+        }
+
+        assert!(removed_lanes.iter().all(|&x| !map.lanes().contains_key(x)));
+        it.check_validity(&map);
+        assert!(it.is_none());
+    }
 }
@@ -4,11 +4,15 @@ use specs::World;
 mod intersection;
 mod itinerary;
 mod lane;
+mod lane_index;
 mod light_policy;
 mod map;
 mod map_ui;
+mod pathfinding;
 mod road;
 mod saveload;
+#[cfg(test)]
+pub mod test_maps;
 mod traffic_control;
 mod traversable;
 mod turn;
@@ -17,9 +21,11 @@ mod turn_policy;
 pub use intersection::*;
 pub use itinerary::*;
 pub use lane::*;
+pub use lane_index::*;
 pub use light_policy::*;
 pub use map::*;
 pub use map_ui::*;
+pub use pathfinding::*;
 pub use road::*;
 pub use saveload::*;
 pub use traffic_control::*;
@@ -27,6 +33,6 @@ pub use traversable::*;
 pub use turn::*;
 pub use turn_policy::*;
 
-pub fn setup(world: &mut World) {
-    load(world);
+pub fn setup(world: &mut World) -> bincode::Result<()> {
+    load(world)
 }
@@ -0,0 +1,498 @@
+use crate::geometry::Vec2;
+use crate::map_model::{
+    IntersectionID, LaneID, LaneKind, Map, Traversable, TraverseDirection, TraverseKind, TurnID,
+};
+use cgmath::MetricSpace;
+use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A pedestrian standing at the end of a sidewalk, next to an intersection
+type WalkNode = (LaneID, IntersectionID);
+
+/// How much longer a fully jammed lane looks to congestion aware routing, relative to its length
+pub const CONGESTION_ROUTE_PENALTY: f32 = 4.0;
+
+/// How jammed each lane is, between 0 (free) and 1 (jammed), for congestion aware routing
+pub trait LaneLoad {
+    fn lane_load(&self, lane: LaneID) -> f32;
+}
+
+impl<F: Fn(LaneID) -> f32> LaneLoad for F {
+    fn lane_load(&self, lane: LaneID) -> f32 {
+        self(lane)
+    }
+}
+
+/// What driving routes minimize
+#[derive(Clone, Copy)]
+pub enum RouteCost<'a> {
+    /// The length of the lanes and turns
+    Distance,
+    /// The length, with the lanes made longer the more loaded they are
+    Congestion(&'a dyn LaneLoad),
+}
+
+impl<'a> RouteCost<'a> {
+    /// Cost of driving the whole lane, never less than its length so that the A* heuristic
+    /// stays admissible
+    fn lane_cost(self, lane: LaneID, length: f32) -> f32 {
+        match self {
+            RouteCost::Distance => length,
+            RouteCost::Congestion(load) => {
+                let load = load.lane_load(lane).max(0.0).min(1.0);
+                length * (1.0 + CONGESTION_ROUTE_PENALTY * load)
+            }
+        }
+    }
+}
+
+impl Map {
+    /// Shortest way on foot from pos on the sidewalk from to the sidewalk to, going through
+    /// walking corners and crosswalks. Sidewalks and turns can be walked both ways.
+    /// The route ends by walking the whole destination sidewalk.
+    /// Gives an empty route if already on the destination, None if it cannot be reached.
+    pub fn walking_route(&self, from: LaneID, pos: Vec2, to: LaneID) -> Option<Vec<Traversable>> {
+        let lanes = self.lanes();
+        if !lanes.contains_key(from) || !lanes.contains_key(to) {
+            return None;
+        }
+        if lanes[from].kind != LaneKind::Walking || lanes[to].kind != LaneKind::Walking {
+            return None;
+        }
+        if from == to {
+            return Some(vec![]);
+        }
+
+        let mut dist: HashMap<WalkNode, f32> = HashMap::new();
+        let mut came_from: HashMap<WalkNode, (Option<WalkNode>, Traversable)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        let start = &lanes[from];
+        for &(end, dir) in &[
+            (start.dst, TraverseDirection::Forward),
+            (start.src, TraverseDirection::Backward),
+        ] {
+            let d = pos.distance(start.get_inter_node_pos(end));
+            let node = (from, end);
+            if d < *dist.get(&node).unwrap_or(&std::f32::INFINITY) {
+                dist.insert(node, d);
+                came_from.insert(
+                    node,
+                    (None, Traversable::new(TraverseKind::Lane(from), dir)),
+                );
+                queue.push(Reverse((OrderedFloat(d), node)));
+            }
+        }
+
+        while let Some(Reverse((OrderedFloat(d), node))) = queue.pop() {
+            if d > dist[&node] {
+                continue;
+            }
+            let (lane, inter) = node;
+
+            if lane == to {
+                return Some(self.unwind_walk(node, &came_from));
+            }
+
+            let mut neighs: Vec<(WalkNode, Traversable, f32)> = vec![];
+
+            let l = &lanes[lane];
+            let (other_end, dir) = if l.src == inter {
+                (l.dst, TraverseDirection::Forward)
+            } else {
+                (l.src, TraverseDirection::Backward)
+            };
+            neighs.push((
+                (lane, other_end),
+                Traversable::new(TraverseKind::Lane(lane), dir),
+                l.points.length(),
+            ));
+
+            for turn in self.intersections()[inter].turns_adirectional(lane) {
+                if turn.kind.is_walking() {
+                    let (next, dir) = walk_turn(turn.id, lane);
+                    neighs.push((
+                        (next, inter),
+                        Traversable::new(TraverseKind::Turn(turn.id), dir),
+                        turn.points.length(),
+                    ));
+                }
+            }
+
+            for (next, t, cost) in neighs {
+                let nd = d + cost;
+                if nd < *dist.get(&next).unwrap_or(&std::f32::INFINITY) {
+                    dist.insert(next, nd);
+                    came_from.insert(next, (Some(node), t));
+                    queue.push(Reverse((OrderedFloat(nd), next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn unwind_walk(
+        &self,
+        end: WalkNode,
+        came_from: &HashMap<WalkNode, (Option<WalkNode>, Traversable)>,
+    ) -> Vec<Traversable> {
+        let mut path = vec![];
+        let mut cur = Some(end);
+        while let Some(node) = cur {
+            let (prev, t) = came_from[&node];
+            path.push(t);
+            cur = prev;
+        }
+        path.reverse();
+
+        // Arrived at one end of the destination, walk it to the other end
+        let (lane, inter) = end;
+        let dir = if self.lanes()[lane].src == inter {
+            TraverseDirection::Forward
+        } else {
+            TraverseDirection::Backward
+        };
+        path.push(Traversable::new(TraverseKind::Lane(lane), dir));
+        path
+    }
+
+    /// Cheapest way for a vehicle from the start of lane from to the end of lane to, found with
+    /// A* over the lanes and the turns between them. Closed lanes are avoided, only from can be
+    /// closed so that vehicles on it find their way out. None if to cannot be reached.
+    pub fn driving_route(
+        &self,
+        from: LaneID,
+        to: LaneID,
+        cost: RouteCost,
+    ) -> Option<Vec<Traversable>> {
+        let lanes = self.lanes();
+        if !lanes.contains_key(from) || !lanes.contains_key(to) {
+            return None;
+        }
+        if !lanes[from].kind.vehicles() || !lanes[to].kind.vehicles() || lanes[to].closed {
+            return None;
+        }
+
+        let goal = lanes[to].get_inter_node_pos(lanes[to].src);
+        let heuristic = |lane: LaneID| {
+            let l = &lanes[lane];
+            l.get_inter_node_pos(l.dst).distance(goal)
+        };
+
+        // Costs are counted up to the end of each lane
+        let mut dist: HashMap<LaneID, f32> = HashMap::new();
+        let mut came_from: HashMap<LaneID, TurnID> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        let d = cost.lane_cost(from, lanes[from].points.length());
+        dist.insert(from, d);
+        queue.push(Reverse((OrderedFloat(d + heuristic(from)), from)));
+
+        while let Some(Reverse((_, lane))) = queue.pop() {
+            if lane == to {
+                let mut path = vec![Traversable::new(
+                    TraverseKind::Lane(lane),
+                    TraverseDirection::Forward,
+                )];
+                let mut cur = lane;
+                while let Some(&turn) = came_from.get(&cur) {
+                    path.push(Traversable::new(
+                        TraverseKind::Turn(turn),
+                        TraverseDirection::Forward,
+                    ));
+                    path.push(Traversable::new(
+                        TraverseKind::Lane(turn.src),
+                        TraverseDirection::Forward,
+                    ));
+                    cur = turn.src;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let d = dist[&lane];
+            for turn in self.intersections()[lanes[lane].dst].turns_from(lane) {
+                let next = turn.id.dst;
+                if lanes[next].closed {
+                    continue;
+                }
+                let nd =
+                    d + turn.points.length() + cost.lane_cost(next, lanes[next].points.length());
+                if nd < *dist.get(&next).unwrap_or(&std::f32::INFINITY) {
+                    dist.insert(next, nd);
+                    came_from.insert(next, turn.id);
+                    queue.push(Reverse((OrderedFloat(nd + heuristic(next)), next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Whether pedestrians can take the crosswalk now. The road it crosses must have a red
+    /// light on each of its lanes entering the intersection, crosswalks on roads without
+    /// lights are always open.
+    pub fn crosswalk_open(&self, turn: TurnID, time_seconds: u64) -> bool {
+        let lanes = self.lanes();
+        let road = &self.roads()[lanes[turn.src].parent];
+
+        road.incoming_lanes_to(turn.parent)
+            .iter()
+            .map(|&x| &lanes[x].control)
+            .filter(|control| control.is_light())
+            .all(|control| control.get_behavior(time_seconds).is_red())
+    }
+}
+
+/// The lane reached by walking the turn from lane, and the direction to walk it in
+fn walk_turn(turn: TurnID, lane: LaneID) -> (LaneID, TraverseDirection) {
+    if turn.src == lane {
+        (turn.dst, TraverseDirection::Forward)
+    } else {
+        (turn.src, TraverseDirection::Backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::test_maps::crossroads;
+    use crate::map_model::{Itinerary, LanePatternBuilder, LightPolicy, RoadID, TurnKind};
+
+    fn driving_lanes(map: &Map, road: RoadID, from: IntersectionID) -> Vec<LaneID> {
+        map.roads()[road]
+            .outgoing_lanes_from(from)
+            .iter()
+            .copied()
+            .filter(|&x| map.lanes()[x].kind.vehicles())
+            .collect()
+    }
+
+    /// A short road straight from a to b, and a longer detour through c. Returns the map, a
+    /// lane leading to a, a lane leaving b and the driving lanes of the short road from a to b.
+    fn detour_map() -> (Map, LaneID, LaneID, Vec<LaneID>) {
+        let mut map = Map::empty();
+        let pattern = LanePatternBuilder::new().build();
+        let start = map.add_intersection(vec2!(-100.0, 0.0));
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let c = map.add_intersection(vec2!(100.0, 150.0));
+        let end = map.add_intersection(vec2!(300.0, 0.0));
+        let first = map.connect(start, a, &pattern);
+        let direct = map.connect(a, b, &pattern);
+        map.connect(a, c, &pattern);
+        map.connect(c, b, &pattern);
+        let last = map.connect(b, end, &pattern);
+
+        let from = driving_lanes(&map, first, start)[0];
+        let to = driving_lanes(&map, last, b)[0];
+        let shortcut = driving_lanes(&map, direct, a);
+        (map, from, to, shortcut)
+    }
+
+    #[test]
+    fn test_walking_route_across_street() {
+        let (map, center, roads) = crossroads(LightPolicy::default());
+        let (to_center, from_center) = map.roads()[roads[0]].sidewalks(center, map.lanes());
+        let (start, destination) = (to_center.unwrap(), from_center.unwrap());
+        let pos = start.get_inter_node_pos(center) - start.get_orientation_vec() * 10.0;
+
+        let route = map.walking_route(start.id, pos, destination.id).unwrap();
+
+        let kinds: Vec<TurnKind> = route
+            .iter()
+            .filter_map(|t| match t.kind {
+                TraverseKind::Turn(id) => Some(map.intersections()[id.parent].turns[&id].kind),
+                TraverseKind::Lane(_) => None,
+            })
+            .collect();
+        assert_eq!(kinds, vec![TurnKind::Crosswalk]);
+        assert!(matches!(route.first().unwrap().kind, TraverseKind::Lane(x) if x == start.id));
+        assert!(matches!(route.last().unwrap().kind, TraverseKind::Lane(x) if x == destination.id));
+
+        let mut it = Itinerary::default();
+        it.set_route(route, &map);
+        assert!(it.is_continuous(&map));
+
+        assert!(map
+            .walking_route(start.id, pos, start.id)
+            .unwrap()
+            .is_empty());
+
+        // Only walking lanes are part of the walking graph
+        let driving = map.roads()[roads[0]].outgoing_lanes_from(center)[0];
+        assert!(map.walking_route(start.id, pos, driving).is_none());
+    }
+
+    #[test]
+    fn test_driving_route() {
+        let (map, center, roads) = crossroads(LightPolicy::default());
+        let driving = |road: RoadID, from| {
+            *map.roads()[road]
+                .outgoing_lanes_from(from)
+                .iter()
+                .find(|&&x| map.lanes()[x].kind.vehicles())
+                .unwrap()
+        };
+        let start = driving(roads[0], map.roads()[roads[0]].other_end(center));
+        let end = driving(roads[1], center);
+
+        let route = map.driving_route(start, end, RouteCost::Distance).unwrap();
+        assert_eq!(route.len(), 3);
+        assert!(matches!(route[0].kind, TraverseKind::Lane(x) if x == start));
+        assert!(matches!(route[1].kind, TraverseKind::Turn(x) if x.parent == center));
+        assert!(matches!(route[2].kind, TraverseKind::Lane(x) if x == end));
+
+        let mut it = Itinerary::default();
+        it.set_route(route, &map);
+        assert!(it.is_continuous(&map));
+
+        assert_eq!(
+            map.driving_route(start, start, RouteCost::Distance)
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Sidewalks aren't driven on
+        let (sidewalk, _) = map.roads()[roads[1]].sidewalks(center, map.lanes());
+        assert!(map
+            .driving_route(start, sidewalk.unwrap().id, RouteCost::Distance)
+            .is_none());
+    }
+
+    #[test]
+    fn test_route_avoids_congestion() {
+        let (map, from, to, jammed_lanes) = detour_map();
+        let direct = map.lanes()[jammed_lanes[0]].parent;
+        let uses_direct = |route: &[Traversable]| {
+            route.iter().any(|t| match t.kind {
+                TraverseKind::Lane(x) => map.lanes()[x].parent == direct,
+                TraverseKind::Turn(_) => false,
+            })
+        };
+
+        let free = |_| 0.0;
+        let route = map
+            .driving_route(from, to, RouteCost::Congestion(&free))
+            .unwrap();
+        assert!(uses_direct(&route));
+
+        let jammed = |lane| {
+            if jammed_lanes.contains(&lane) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+
+        let route = map
+            .driving_route(from, to, RouteCost::Congestion(&jammed))
+            .unwrap();
+        assert!(!uses_direct(&route));
+        let mut it = Itinerary::default();
+        it.set_route(route, &map);
+        assert!(it.is_continuous(&map));
+
+        // Distance only routing still takes the jammed shortcut
+        let route = map.driving_route(from, to, RouteCost::Distance).unwrap();
+        assert!(uses_direct(&route));
+    }
+
+    #[test]
+    fn test_route_avoids_closed_lane() {
+        let (mut map, from, to, shortcut_lanes) = detour_map();
+        let shortcut = shortcut_lanes[0];
+        let uses_shortcut = |route: &[Traversable]| {
+            route
+                .iter()
+                .any(|t| matches!(t.kind, TraverseKind::Lane(x) if x == shortcut))
+        };
+
+        let route = map.driving_route(from, to, RouteCost::Distance).unwrap();
+        assert!(uses_shortcut(&route));
+
+        // One vehicle about to take the shortcut, another one already on it
+        let mut before = Itinerary::default();
+        before.set_route(route.clone(), &map);
+        before.advance(&map);
+        let on_it = route
+            .iter()
+            .position(|t| matches!(t.kind, TraverseKind::Lane(x) if x == shortcut))
+            .unwrap();
+        let mut inside = Itinerary::default();
+        inside.set_route(route[on_it..].to_vec(), &map);
+        inside.advance(&map);
+
+        map.set_lane_closed(shortcut, true);
+
+        let route = map.driving_route(from, to, RouteCost::Distance).unwrap();
+        assert!(!uses_shortcut(&route));
+        assert!(map
+            .driving_route(from, shortcut, RouteCost::Distance)
+            .is_none());
+
+        // The first one goes around, the second one drives out of the closed lane
+        assert!(before.is_blocked_ahead(&map));
+        let remaining = before.remaining_points();
+        assert!(before.reroute(&map));
+        assert!(!before.is_blocked_ahead(&map));
+        assert_eq!(before.remaining_points(), remaining);
+        assert!(matches!(before.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == from));
+        assert!(before.is_continuous(&map));
+
+        assert!(!inside.get_travers().unwrap().is_valid(&map));
+        assert!(inside.get_travers().unwrap().exists(&map));
+        assert!(inside.reroute(&map));
+        assert!(!inside.is_blocked_ahead(&map));
+        assert!(
+            matches!(inside.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == shortcut)
+        );
+        assert!(inside.is_continuous(&map));
+        while !inside.has_ended() {
+            inside.advance(&map);
+        }
+        assert!(matches!(inside.get_travers().unwrap().kind, TraverseKind::Lane(x) if x == to));
+
+        map.set_lane_closed(shortcut, false);
+        assert!(uses_shortcut(
+            &map.driving_route(from, to, RouteCost::Distance).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_crosswalk_open() {
+        let (mut map, center, roads) = crossroads(LightPolicy::default());
+
+        let crosswalk = |map: &Map| {
+            map.intersections()[center]
+                .turns
+                .values()
+                .find(|t| t.kind.is_crosswalk() && map.lanes()[t.id.src].parent == roads[0])
+                .unwrap()
+                .id
+        };
+
+        // No lights, never wait
+        map.set_intersection_light_policy(center, LightPolicy::NoLights);
+        let turn = crosswalk(&map);
+        assert!((0..100).all(|t| map.crosswalk_open(turn, t)));
+
+        map.set_intersection_light_policy(center, LightPolicy::Lights);
+        let turn = crosswalk(&map);
+        let crossed = map.roads()[roads[0]].incoming_lanes_to(center);
+
+        let mut open = 0;
+        for t in 0..100 {
+            let all_red = crossed
+                .iter()
+                .filter(|&&x| map.lanes()[x].kind.needs_light())
+                .all(|&x| map.lanes()[x].control.get_behavior(t).is_red());
+            assert_eq!(map.crosswalk_open(turn, t), all_red);
+            open += all_red as usize;
+        }
+        assert!(open > 0 && open < 100);
+    }
+}
@@ -2,9 +2,10 @@ use crate::geometry::polyline::PolyLine;
 use crate::geometry::Vec2;
 use crate::map_model::{
     IntersectionID, Intersections, Lane, LaneDirection, LaneID, LaneKind, LanePattern, Lanes,
-    Roads, TrafficControl,
+    Roads, TrafficControl, DEFAULT_SPEED_LIMIT,
 };
 use cgmath::InnerSpace;
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
 
@@ -57,8 +58,10 @@ impl Road {
         id
     }
 
-    pub fn is_one_way(&self) -> bool {
-        self.lanes_forward.is_empty() || self.lanes_backward.is_empty()
+    /// Whether vehicles can only drive in one direction, sidewalks go both ways anyway
+    pub fn is_one_way(&self, lanes: &Lanes) -> bool {
+        let has_vehicles = |v: &[LaneID]| v.iter().any(|&x| lanes[x].kind.vehicles());
+        !has_vehicles(&self.lanes_forward) || !has_vehicles(&self.lanes_backward)
     }
 
     pub fn n_lanes(&self) -> usize {
@@ -108,6 +111,10 @@ impl Road {
             points: Default::default(),
             width: if lane_type.vehicles() { 8.0 } else { 4.0 },
             dist_from_center,
+            speed_limit: DEFAULT_SPEED_LIMIT,
+            bus_stop: false,
+            speed_bumps: vec![],
+            closed: false,
         });
         road_lanes.push(id);
         id
@@ -168,6 +175,37 @@ impl Road {
         );
     }
 
+    /// Signed distance from the road's centerline to the middle of the lane, positive to the
+    /// right when going from src to dst
+    pub fn lateral_offset(&self, lane: LaneID, lanes: &Lanes) -> f32 {
+        let l = &lanes[lane];
+        let offset = l.dist_from_center + l.width / 2.0;
+        if self.lanes_forward.contains(&lane) {
+            offset
+        } else {
+            -offset
+        }
+    }
+
+    /// All the lanes of the road, from left to right as seen when driving in the given direction
+    pub fn ordered_lanes(&self, dir: LaneDirection, lanes: &Lanes) -> Vec<LaneID> {
+        let mut ordered: Vec<LaneID> = self.lanes_iter().copied().collect();
+        ordered.sort_by_key(|&x| OrderedFloat(self.lateral_offset(x, lanes)));
+        if dir == LaneDirection::Backward {
+            ordered.reverse();
+        }
+        ordered
+    }
+
+    /// The lane covering the given signed offset from the centerline, using the same convention
+    /// as lateral_offset
+    pub fn lane_at_offset(&self, offset: f32, lanes: &Lanes) -> Option<LaneID> {
+        self.lanes_iter().copied().find(|&x| {
+            let half_width = lanes[x].width / 2.0;
+            (self.lateral_offset(x, lanes) - offset).abs() <= half_width
+        })
+    }
+
     pub fn distance_from_center(&self, lane: LaneID, lanes: &Lanes) -> f32 {
         let mut dist = 0.0;
         for x in &self.lanes_backward {
@@ -187,3 +225,70 @@ impl Road {
         0.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map_model::{LaneDirection, LanePatternBuilder, Map};
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn test_ordered_lanes() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(0.0, 100.0));
+        let pattern = LanePatternBuilder::new()
+            .n_lanes(3)
+            .one_way(true)
+            .sidewalks(false)
+            .build();
+        let road_id = map.connect(a, b, &pattern);
+
+        let road = &map.roads()[road_id];
+        let lanes = map.lanes();
+
+        // Going north, right is +x
+        let right = vec2!(1.0, 0.0);
+        let ordered = road.ordered_lanes(LaneDirection::Forward, lanes);
+        assert_eq!(ordered.len(), 3);
+
+        let xs: Vec<f32> = ordered
+            .iter()
+            .map(|&x| lanes[x].points[0].dot(right))
+            .collect();
+        assert!(xs.windows(2).all(|w| w[0] < w[1]));
+
+        for (&lane, &x) in ordered.iter().zip(&xs) {
+            assert!((road.lateral_offset(lane, lanes) - x).abs() < 1e-3);
+            assert_eq!(road.lane_at_offset(x, lanes), Some(lane));
+        }
+        assert_eq!(road.lane_at_offset(-1.0, lanes), None);
+
+        let mut backward = road.ordered_lanes(LaneDirection::Backward, lanes);
+        backward.reverse();
+        assert_eq!(backward, ordered);
+    }
+
+    #[test]
+    fn test_two_way_lateral_offset() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(100.0, 0.0));
+        let pattern = LanePatternBuilder::new().n_lanes(2).build();
+        let road_id = map.connect(a, b, &pattern);
+
+        let road = &map.roads()[road_id];
+        let lanes = map.lanes();
+
+        // Going east, right is -y
+        for &lane in road.lanes_iter() {
+            let y = lanes[lane].points[0].y;
+            assert!((road.lateral_offset(lane, lanes) + y).abs() < 1e-3);
+        }
+
+        let ordered = road.ordered_lanes(LaneDirection::Forward, lanes);
+        assert_eq!(ordered.len(), road.n_lanes());
+        assert!(ordered
+            .windows(2)
+            .all(|w| lanes[w[0]].points[0].y > lanes[w[1]].points[0].y));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::geometry::Vec2;
 use crate::map_model::{make_inter_entity, IntersectionID, LanePatternBuilder, Map};
+use crate::saveload;
 use cgmath::num_traits::FloatConst;
 use specs::{LazyUpdate, World, WorldExt};
 use std::fs::File;
@@ -8,25 +9,21 @@ use std::ops::Deref;
 
 const FILENAME: &str = "world/map.bc";
 
-pub fn save(world: &mut World) {
+pub fn save(world: &mut World) -> bincode::Result<()> {
     let _ = std::fs::create_dir("world");
 
     let map = world.read_resource::<Map>();
 
-    let file = File::create(FILENAME).unwrap();
-
-    bincode::serialize_into(file, map.deref()).unwrap();
+    saveload::save_file(FILENAME, map.deref())
 }
 
-fn load_from_file() -> Map {
-    let file = File::open(FILENAME);
-    if let Err(e) = file {
-        println!("error while trying to load map: {}", e);
-        return Map::empty();
-    }
-
-    let des = bincode::deserialize_from(file.unwrap());
-    des.unwrap_or_else(|_| Map::empty())
+fn load_from_file() -> bincode::Result<Map> {
+    let mut map: Map = match saveload::load_or_set_aside(FILENAME)? {
+        Some(map) => map,
+        None => return Ok(Map::empty()),
+    };
+    map.rebuild_lane_index();
+    Ok(map)
 }
 
 struct Scanner {
@@ -175,8 +172,13 @@ pub fn add_grid(pos: Vec2, m: &mut Map) {
     }
 }
 
-pub fn load(world: &mut World) {
-    let mut map = load_from_file();
+/// Loads the saved map, or generates a new one if there is none or it can't be read. The map
+/// is inserted either way, the error is returned so that it can be reported.
+pub fn load(world: &mut World) -> bincode::Result<()> {
+    let (mut map, loaded) = match load_from_file() {
+        Ok(map) => (map, Ok(())),
+        Err(e) => (Map::empty(), Err(e)),
+    };
 
     //map = load_parismap();
 
@@ -197,4 +199,5 @@ pub fn load(world: &mut World) {
             &world.entities(),
         );
     }
+    loaded
 }
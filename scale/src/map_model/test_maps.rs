@@ -0,0 +1,23 @@
+use crate::map_model::{IntersectionID, LanePatternBuilder, LightPolicy, Map, RoadID};
+
+/// Four roads of 100m meeting at a center intersection with the given light policy, going
+/// east, north, west and south of it in that order
+pub fn crossroads(policy: LightPolicy) -> (Map, IntersectionID, Vec<RoadID>) {
+    let mut map = Map::empty();
+    let center = map.add_intersection(vec2!(0.0, 0.0));
+    let pattern = LanePatternBuilder::new().build();
+
+    let mut roads = vec![];
+    for &pos in &[
+        vec2!(100.0, 0.0),
+        vec2!(0.0, 100.0),
+        vec2!(-100.0, 0.0),
+        vec2!(0.0, -100.0),
+    ] {
+        let other = map.add_intersection(pos);
+        roads.push(map.connect(center, other, &pattern));
+    }
+    map.set_intersection_light_policy(center, policy);
+
+    (map, center, roads)
+}
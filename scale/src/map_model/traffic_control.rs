@@ -1,12 +1,16 @@
 use crate::rendering::Color;
+use imgui::{im_str, Ui};
+use imgui_inspect::{InspectArgsDefault, InspectRenderDefault};
 use serde::{Deserialize, Serialize};
+use specs::World;
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrafficBehavior {
     RED,
     ORANGE,
     GREEN,
     STOP,
+    YIELD,
 }
 
 impl TrafficBehavior {
@@ -15,6 +19,7 @@ impl TrafficBehavior {
             TrafficBehavior::RED | TrafficBehavior::STOP => Color::RED,
             TrafficBehavior::ORANGE => Color::ORANGE,
             TrafficBehavior::GREEN => Color::GREEN,
+            TrafficBehavior::YIELD => Color::WHITE,
         }
     }
 
@@ -23,7 +28,7 @@ impl TrafficBehavior {
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Hash, Serialize, Deserialize)]
 pub struct TrafficLightSchedule {
     period: usize,
     green: usize,
@@ -42,13 +47,117 @@ impl TrafficLightSchedule {
             offset,
         }
     }
+
+    /// Splits the period into green, orange then red, green lasting green_fraction of the
+    /// period and red taking whatever is left once the orange is done
+    pub fn from_total(period: usize, green_fraction: f32, orange: usize, offset: usize) -> Self {
+        let orange = orange.min(period);
+        let green =
+            ((period as f32 * green_fraction.max(0.0)).round() as usize).min(period - orange);
+        Self::from_basic(green, orange, period - green - orange, offset)
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    pub fn green(&self) -> usize {
+        self.green
+    }
+
+    pub fn orange(&self) -> usize {
+        self.orange
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Changes the cycle, keeping the share of it the light is green for
+    pub fn reschedule(&mut self, period: usize, orange: usize, offset: usize) {
+        let green_fraction = if self.period == 0 {
+            0.0
+        } else {
+            self.green as f32 / self.period as f32
+        };
+        *self = Self::from_total(period.max(1), green_fraction, orange, offset);
+    }
+}
+
+/// Applies the values edited in the inspector to all the selected schedules
+fn reschedule_all(data: &mut [&mut TrafficLightSchedule], period: i32, orange: i32, offset: i32) {
+    for schedule in data {
+        schedule.reschedule(
+            period.max(1) as usize,
+            orange.max(0) as usize,
+            offset.max(0) as usize,
+        );
+    }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+impl InspectRenderDefault<TrafficLightSchedule> for TrafficLightSchedule {
+    fn render(
+        data: &[&TrafficLightSchedule],
+        label: &'static str,
+        _: &mut World,
+        ui: &Ui,
+        _: &InspectArgsDefault,
+    ) {
+        for schedule in data {
+            ui.text(im_str!(
+                "{}: cycle {}s, green {}s, orange {}s, offset {}s",
+                label,
+                schedule.period,
+                schedule.green,
+                schedule.orange,
+                schedule.offset
+            ));
+        }
+    }
+
+    /// With several schedules selected, the first one's values are shown and an edit
+    /// sets them on all of them
+    fn render_mut(
+        data: &mut [&mut TrafficLightSchedule],
+        label: &'static str,
+        _: &mut World,
+        ui: &Ui,
+        _: &InspectArgsDefault,
+    ) -> bool {
+        let first = match data.first() {
+            Some(x) => **x,
+            None => return false,
+        };
+        let mut period = first.period as i32;
+        let mut orange = first.orange as i32;
+        let mut offset = first.offset as i32;
+
+        let mut changed = imgui::DragInt::new(ui, &im_str!("{} cycle", label), &mut period)
+            .min(1)
+            .max(600)
+            .build();
+        changed |= imgui::DragInt::new(ui, &im_str!("{} orange", label), &mut orange)
+            .min(0)
+            .max(period)
+            .build();
+        changed |= imgui::DragInt::new(ui, &im_str!("{} offset", label), &mut offset)
+            .min(0)
+            .max(period)
+            .build();
+
+        if changed {
+            reschedule_all(data, period, orange, offset);
+        }
+        changed
+    }
+}
+
+#[derive(Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum TrafficControl {
     Always,
     Light(TrafficLightSchedule),
     StopSign,
+    Yield,
 }
 
 impl TrafficControl {
@@ -60,6 +169,10 @@ impl TrafficControl {
         matches!(self, TrafficControl::StopSign)
     }
 
+    pub fn is_yield(&self) -> bool {
+        matches!(self, TrafficControl::Yield)
+    }
+
     pub fn is_light(&self) -> bool {
         matches!(self, TrafficControl::Light(_))
     }
@@ -78,6 +191,69 @@ impl TrafficControl {
                 }
             }
             TrafficControl::StopSign => TrafficBehavior::STOP,
+            TrafficControl::Yield => TrafficBehavior::YIELD,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time_in(control: TrafficControl, period: u64, behavior: TrafficBehavior) -> usize {
+        (0..period)
+            .filter(|&t| control.get_behavior(t) == behavior)
+            .count()
+    }
+
+    #[test]
+    fn test_from_total_fractions() {
+        let control = TrafficControl::Light(TrafficLightSchedule::from_total(20, 0.4, 3, 7));
+
+        assert_eq!(time_in(control, 20, TrafficBehavior::GREEN), 8);
+        assert_eq!(time_in(control, 20, TrafficBehavior::ORANGE), 3);
+        assert_eq!(time_in(control, 20, TrafficBehavior::RED), 9);
+
+        // Offset only shifts the cycle
+        let shifted = TrafficControl::Light(TrafficLightSchedule::from_total(20, 0.4, 3, 0));
+        for t in 0..40 {
+            assert_eq!(control.get_behavior(t), shifted.get_behavior(t + 7));
+        }
+    }
+
+    #[test]
+    fn test_from_total_bounds() {
+        let always_green = TrafficControl::Light(TrafficLightSchedule::from_total(10, 1.0, 2, 0));
+        assert_eq!(time_in(always_green, 10, TrafficBehavior::GREEN), 8);
+        assert_eq!(time_in(always_green, 10, TrafficBehavior::ORANGE), 2);
+        assert_eq!(time_in(always_green, 10, TrafficBehavior::RED), 0);
+
+        let never_green = TrafficControl::Light(TrafficLightSchedule::from_total(10, 0.0, 2, 0));
+        assert_eq!(time_in(never_green, 10, TrafficBehavior::GREEN), 0);
+        assert_eq!(time_in(never_green, 10, TrafficBehavior::RED), 8);
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let mut a = TrafficLightSchedule::from_total(20, 0.5, 3, 7);
+        let mut b = TrafficLightSchedule::from_total(10, 0.2, 1, 0);
+
+        reschedule_all(&mut [&mut a], 40, 4, 5);
+        assert_eq!(
+            (a.period(), a.green(), a.orange(), a.offset()),
+            (40, 20, 4, 5)
+        );
+        let control = TrafficControl::Light(a);
+        assert_eq!(time_in(control, 40, TrafficBehavior::RED), 16);
+
+        // Several selected schedules all get the edit, nothing is selected is a no-op
+        reschedule_all(&mut [&mut a, &mut b], 30, 2, 0);
+        assert_eq!((a.period(), a.green()), (30, 15));
+        assert_eq!((b.period(), b.green(), b.orange()), (30, 6, 2));
+        reschedule_all(&mut [], 30, 2, 0);
+
+        // Out of range values from the drag widgets are clamped
+        reschedule_all(&mut [&mut a], 0, -3, -1);
+        assert_eq!((a.period(), a.orange(), a.offset()), (1, 0, 0));
+    }
+}
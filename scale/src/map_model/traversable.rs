@@ -57,7 +57,16 @@ impl Traversable {
         }
     }
 
+    /// Whether it can still be driven or walked: it exists and isn't a closed lane
     pub fn is_valid(&self, m: &Map) -> bool {
+        match self.kind {
+            TraverseKind::Lane(id) => m.lanes().get(id).map_or(false, |l| !l.closed),
+            TraverseKind::Turn(_) => self.exists(m),
+        }
+    }
+
+    /// Whether it is still part of the map, closed lanes included
+    pub fn exists(&self, m: &Map) -> bool {
         match self.kind {
             TraverseKind::Lane(id) => m.lanes().contains_key(id),
             TraverseKind::Turn(id) => {
@@ -1,10 +1,20 @@
 use crate::geometry::polyline::PolyLine;
 use crate::geometry::splines::Spline;
+use crate::geometry::Vec2;
 use crate::map_model::{IntersectionID, LaneID, Lanes};
 use cgmath::{Array, InnerSpace};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Serialize, PartialOrd, Ord, Deserialize, PartialEq, Eq)]
+/// Bend above which a turn goes left, and below the opposite of which it goes right
+pub const TURN_SIDE_THRESHOLD: f32 = 0.3;
+
+/// Distance between the points of turns, in meters
+pub const TURN_POINT_SPACING: f32 = 2.0;
+
+/// Chaikin iterations rounding the corners where ring turns enter and leave the ring
+pub const RING_SMOOTHING: usize = 2;
+
+#[derive(Clone, Copy, Debug, Serialize, PartialOrd, Ord, Deserialize, PartialEq, Eq, Hash)]
 pub struct TurnID {
     pub parent: IntersectionID,
     pub src: LaneID,
@@ -15,9 +25,20 @@ impl TurnID {
     pub fn new(parent: IntersectionID, src: LaneID, dst: LaneID) -> Self {
         Self { parent, src, dst }
     }
+
+    /// How much the turn bends, positive to the left and negative to the right
+    pub fn bend(self, lanes: &Lanes) -> f32 {
+        lanes[self.src]
+            .get_orientation_vec()
+            .perp_dot(lanes[self.dst].get_orientation_vec())
+    }
+
+    pub fn is_left(self, lanes: &Lanes) -> bool {
+        self.bend(lanes) > TURN_SIDE_THRESHOLD
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialOrd, Ord, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialOrd, Ord, PartialEq, Hash, Serialize, Deserialize)]
 pub enum TurnKind {
     Crosswalk,
     WalkingCorner,
@@ -28,6 +49,10 @@ impl TurnKind {
     pub fn is_crosswalk(self) -> bool {
         matches!(self, TurnKind::Crosswalk)
     }
+
+    pub fn is_walking(self) -> bool {
+        matches!(self, TurnKind::Crosswalk | TurnKind::WalkingCorner)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,6 +60,8 @@ pub struct Turn {
     pub id: TurnID,
     pub points: PolyLine,
     pub kind: TurnKind,
+    /// Conflicting movements that have priority over this one, computed with the turns
+    pub yield_to: Vec<TurnID>,
 }
 
 impl Turn {
@@ -43,6 +70,7 @@ impl Turn {
             id,
             points: Default::default(),
             kind,
+            yield_to: vec![],
         }
     }
 
@@ -84,5 +112,34 @@ impl Turn {
             self.points.push(pos);
         }
         self.points.push(pos_dst);
+
+        // The spline points are spread by parameter, not by length
+        self.points = self.points.resample(TURN_POINT_SPACING);
+    }
+
+    /// Goes counter-clockwise around the ring of a roundabout, from the entry lane to the exit lane
+    pub fn make_ring_points(&mut self, lanes: &Lanes, center: Vec2, radius: f32) {
+        const RING_STEP: f32 = std::f32::consts::FRAC_PI_8;
+
+        self.points.clear();
+
+        let pos_src = lanes[self.id.src].get_inter_node_pos(self.id.parent);
+        let pos_dst = lanes[self.id.dst].get_inter_node_pos(self.id.parent);
+
+        let ang_src = f32::atan2(pos_src.y - center.y, pos_src.x - center.x);
+        let ang_dst = f32::atan2(pos_dst.y - center.y, pos_dst.x - center.x);
+        let sweep = (ang_dst - ang_src).rem_euclid(2.0 * std::f32::consts::PI);
+        let n = (sweep / RING_STEP).ceil().max(2.0) as usize;
+
+        self.points.push(pos_src);
+        for i in 1..n {
+            let ang = ang_src + sweep * i as f32 / n as f32;
+            self.points
+                .push(center + Vec2::new(ang.cos(), ang.sin()) * radius);
+        }
+        self.points.push(pos_dst);
+
+        // Going straight from the lanes to the ring makes sharp corners
+        self.points = self.points.smoothed(RING_SMOOTHING);
     }
 }
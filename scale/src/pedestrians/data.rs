@@ -1,5 +1,8 @@
+use crate::geometry::Vec2;
 use crate::interaction::{Movable, Selectable};
-use crate::map_model::{Itinerary, LaneKind, Map, Traversable, TraverseDirection, TraverseKind};
+use crate::map_model::{
+    Itinerary, LaneID, LaneKind, Map, Traversable, TraverseDirection, TraverseKind,
+};
 use crate::physics::{
     Collider, CollisionWorld, Kinematics, PhysicsGroup, PhysicsObject, Transform,
 };
@@ -8,7 +11,7 @@ use crate::rendering::Color;
 use crate::utils::rand_normal;
 use imgui_inspect_derive::*;
 use serde::{Deserialize, Serialize};
-use specs::{Builder, World, WorldExt};
+use specs::{Builder, Entity, World, WorldExt};
 use specs::{Component, DenseVecStorage};
 
 #[derive(Clone, Serialize, Deserialize, Component, Inspect)]
@@ -16,6 +19,37 @@ pub struct PedestrianComponent {
     pub itinerary: Itinerary,
     pub walking_speed: f32,
     pub walk_anim: f32,
+    /// Sidewalk the pedestrian is walking to, wanders around once there
+    #[inspect(skip = true)]
+    pub destination: Option<LaneID>,
+    /// Set while standing at the curb for the crosswalk to open
+    pub waiting: bool,
+}
+
+impl PedestrianComponent {
+    /// Routes the pedestrian on foot from pos to the destination sidewalk.
+    /// Returns false if it cannot be reached, the itinerary is then left as is.
+    pub fn go_to(&mut self, destination: LaneID, pos: Vec2, map: &Map) -> bool {
+        let current = match self.itinerary.get_travers().map(|t| t.kind) {
+            Some(TraverseKind::Lane(l)) if map.lanes()[l].kind == LaneKind::Walking => Some(l),
+            _ => map.closest_lane_filter(pos, |kind| kind == LaneKind::Walking),
+        };
+        let route = match current.and_then(|cur| map.walking_route(cur, pos, destination)) {
+            Some(x) => x,
+            None => return false,
+        };
+
+        if route.is_empty() {
+            self.destination = None;
+            return true;
+        }
+
+        self.itinerary.set_route(route, map);
+        // The first sidewalk is walked from the pedestrian's position, not from its start
+        self.itinerary.advance(map);
+        self.destination = Some(destination);
+        true
+    }
 }
 
 pub fn spawn_pedestrian(world: &mut World) {
@@ -29,31 +63,37 @@ pub fn spawn_pedestrian(world: &mut World) {
         return;
     };
 
-    let mut itinerary = Itinerary::default();
-    itinerary.set_simple(
+    let mut pedestrian = PedestrianComponent::default();
+    pedestrian.itinerary.set_simple(
         Traversable::new(TraverseKind::Lane(lane.id), TraverseDirection::Forward),
         &map,
     );
-    itinerary.advance(&map);
+    pedestrian.itinerary.advance(&map);
+
+    if let Some(destination) = map.get_random_lane(LaneKind::Walking) {
+        pedestrian.go_to(destination.id, pos, &map);
+    }
     drop(map);
 
-    let h = world.get_mut::<CollisionWorld>().unwrap().insert(
+    make_pedestrian(world, pos, pedestrian);
+}
+
+pub fn make_pedestrian(world: &mut World, pos: Vec2, pedestrian: PedestrianComponent) -> Entity {
+    let h = world.get_mut::<CollisionWorld>().unwrap().insert_masked(
         pos,
         PhysicsObject {
             radius: 0.3,
             group: PhysicsGroup::Pedestrians,
             ..Default::default()
         },
+        PhysicsGroup::Pedestrians.mask(),
     );
     let color = random_pedestrian_shirt_color();
 
-    world
+    let e = world
         .create_entity()
         .with(Transform::new(pos))
-        .with(PedestrianComponent {
-            itinerary,
-            ..Default::default()
-        })
+        .with(pedestrian)
         .with(Kinematics::from_mass(80.0))
         .with(Movable)
         .with({
@@ -100,6 +140,8 @@ pub fn spawn_pedestrian(world: &mut World) {
         .with(Collider(h))
         .with(Selectable::new(0.5))
         .build();
+    world.write_resource::<CollisionWorld>().set_owner(h, e);
+    e
 }
 
 impl Default for PedestrianComponent {
@@ -108,6 +150,8 @@ impl Default for PedestrianComponent {
             itinerary: Itinerary::default(),
             walking_speed: rand_normal(1.34f32, 0.26).max(0.5), // https://arxiv.org/pdf/cond-mat/9805244.pdf
             walk_anim: 0.0,
+            destination: None,
+            waiting: false,
         }
     }
 }
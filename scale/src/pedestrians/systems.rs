@@ -2,10 +2,12 @@ use crate::engine_interaction::TimeInfo;
 use crate::geometry::{Vec2, Vec2Impl};
 use crate::map_model::{Map, Traversable, TraverseDirection, TraverseKind};
 use crate::pedestrians::PedestrianComponent;
-use crate::physics::{Collider, CollisionWorld, Kinematics, PhysicsObject, Transform};
+use crate::physics::{
+    Collider, CollisionWorld, Kinematics, PhysicsGroup, PhysicsObject, Transform,
+};
 use crate::rendering::meshrender_component::MeshRender;
-use crate::utils::{Choose, Restrict};
-use cgmath::{Angle, InnerSpace, MetricSpace};
+use crate::utils::{Choose, Remap, Restrict};
+use cgmath::{InnerSpace, MetricSpace};
 use specs::prelude::*;
 use specs::shred::PanicHandler;
 use specs::ParJoin;
@@ -42,10 +44,14 @@ impl<'a> System<'a> for PedestrianDecision {
         )
             .join()
             .for_each(|(coll, trans, kin, pedestrian, mr)| {
-                objective_update(pedestrian, trans, map);
+                objective_update(pedestrian, time, trans, map);
 
                 let my_obj = cow.get_obj(coll.0);
-                let neighbors = cow.query_around(trans.position(), 10.0);
+                let neighbors = cow.query_around(
+                    trans.position(),
+                    10.0,
+                    Some(PhysicsGroup::Pedestrians.mask()),
+                );
 
                 let objs = neighbors.map(|obj| (obj.pos, cow.get_obj(obj.id)));
 
@@ -76,24 +82,20 @@ pub fn physics(
     let speed = kin.velocity.magnitude();
     pedestrian.walk_anim += 7.0 * speed * time.delta / pedestrian.walking_speed;
 
-    let offset = pedestrian.walk_anim.cos()
-        * 0.1
-        * (speed * 2.0 - pedestrian.walking_speed).restrict(0.0, 1.0);
+    let offset =
+        pedestrian.walk_anim.cos() * 0.1 * (speed * 2.0 - pedestrian.walking_speed).clamp01();
 
     if mr.orders[0].as_rect_mut().offset.y.abs() < 0.25 {
         mr.orders[0].as_rect_mut().offset.x = offset;
         mr.orders[1].as_rect_mut().offset.x = -offset;
     }
 
-    let delta_ang = trans.direction().angle(desired_dir);
-    let mut ang = vec2!(1.0, 0.0).angle(trans.direction());
+    let delta_ang = trans.direction().angle_between(desired_dir);
 
     const ANG_VEL: f32 = 1.0;
-    ang.0 += delta_ang
-        .0
-        .restrict(-ANG_VEL * time.delta, ANG_VEL * time.delta);
+    let turn = delta_ang.restrict(-ANG_VEL * time.delta, ANG_VEL * time.delta);
 
-    trans.set_direction(vec2!(ang.cos(), ang.sin()));
+    trans.set_direction(trans.direction().rotate(turn));
 }
 
 pub fn calc_decision<'a>(
@@ -113,12 +115,18 @@ pub fn calc_decision<'a>(
     let direction = trans.direction();
 
     let delta_pos: Vec2 = objective - position;
-    let (dir_to_pos, _) = match delta_pos.dir_dist() {
+    let (dir_to_pos, dist) = match delta_pos.dir_dist() {
         Some(x) => x,
         None => return (vec2!(0.0, 0.0), trans.direction()),
     };
 
-    let mut desired_v = dir_to_pos * pedestrian.walking_speed;
+    let mut speed = pedestrian.walking_speed;
+    if pedestrian.waiting {
+        // Stop at the curb instead of walking around it
+        speed *= (dist / 2.0).min(1.0);
+    }
+
+    let mut desired_v = dir_to_pos * speed;
 
     for (his_pos, his_obj) in neighs {
         if his_pos == position {
@@ -155,17 +163,48 @@ pub fn calc_decision<'a>(
     (desired_v, desired_dir)
 }
 
-pub fn objective_update(pedestrian: &mut PedestrianComponent, trans: &Transform, map: &Map) {
+/// Whether the pedestrian is about to take a crosswalk that is not open yet
+fn must_wait(pedestrian: &PedestrianComponent, time: &TimeInfo, map: &Map) -> bool {
+    let t = match pedestrian.itinerary.get_travers() {
+        Some(x) => x,
+        None => return false,
+    };
+    match t.kind {
+        TraverseKind::Turn(id)
+            if map.intersections()[id.parent].turns[&id]
+                .kind
+                .is_crosswalk() =>
+        {
+            // Once started, keep crossing whatever the lights do
+            pedestrian.itinerary.remaining_points() == t.raw_points(map).n_points()
+                && !map.crosswalk_open(id, time.time_seconds)
+        }
+        _ => false,
+    }
+}
+
+pub fn objective_update(
+    pedestrian: &mut PedestrianComponent,
+    time: &TimeInfo,
+    trans: &Transform,
+    map: &Map,
+) {
     pedestrian.itinerary.check_validity(map);
+    pedestrian.waiting = false;
 
     if let Some(x) = pedestrian.itinerary.get_point() {
         if x.distance(trans.position()) > 3.0 {
             return;
         }
+        if must_wait(pedestrian, time, map) {
+            pedestrian.waiting = true;
+            return;
+        }
         pedestrian.itinerary.advance(map);
     }
 
     if pedestrian.itinerary.is_none() {
+        pedestrian.destination = None;
         if let Some(closest) = map.closest_lane(trans.position()) {
             pedestrian.itinerary.set_simple(
                 Traversable::new(TraverseKind::Lane(closest), TraverseDirection::Forward),
@@ -176,6 +215,9 @@ pub fn objective_update(pedestrian: &mut PedestrianComponent, trans: &Transform,
     }
 
     if pedestrian.itinerary.has_ended() {
+        // Arrived, wander around from there
+        pedestrian.destination = None;
+
         let t = *unwrap_ret!(pedestrian.itinerary.get_travers());
 
         match t.kind {
@@ -184,7 +226,14 @@ pub fn objective_update(pedestrian: &mut PedestrianComponent, trans: &Transform,
 
                 let neighs = arrived.turns_adirectional(l);
 
-                let turn = unwrap_ret!(neighs.choose());
+                // Try the turns in a random order, preferring one that doesn't wait on a light
+                let options = neighs.shuffled();
+                let turn = **unwrap_ret!(options
+                    .iter()
+                    .find(|t| {
+                        !t.kind.is_crosswalk() || map.crosswalk_open(t.id, time.time_seconds)
+                    })
+                    .or_else(|| options.first()));
 
                 let direction = if turn.id.src == l {
                     TraverseDirection::Forward
@@ -239,3 +288,86 @@ pub fn objective_update(pedestrian: &mut PedestrianComponent, trans: &Transform,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interaction::{Movable, Selectable};
+    use crate::map_model::test_maps::crossroads;
+    use crate::map_model::LightPolicy;
+    use crate::pedestrians::make_pedestrian;
+    use crate::physics::systems::KinematicsApply;
+
+    #[test]
+    fn test_crosses_street_to_destination() {
+        let (map, center, roads) = crossroads(LightPolicy::Lights);
+
+        // Both sidewalks of the same road, on each side of the street
+        let (to_center, from_center) = map.roads()[roads[0]].sidewalks(center, map.lanes());
+        let (start, destination) = (to_center.unwrap().id, from_center.unwrap().id);
+        let pos = {
+            let lane = &map.lanes()[start];
+            let curb = lane.get_inter_node_pos(center);
+            curb - lane.get_orientation_vec() * 10.0
+        };
+
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<Kinematics>();
+        world.register::<Collider>();
+        world.register::<PedestrianComponent>();
+        world.register::<MeshRender>();
+        world.register::<Movable>();
+        world.register::<Selectable>();
+        world.insert(CollisionWorld::new(50));
+        world.insert(TimeInfo::default());
+
+        let mut pedestrian = PedestrianComponent::default();
+        assert!(pedestrian.go_to(destination, pos, &map));
+        world.insert(map);
+        let e = make_pedestrian(&mut world, pos, pedestrian);
+
+        let mut crossed = false;
+        for _ in 0..20000 {
+            world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+            PedestrianDecision.run_now(&world);
+            KinematicsApply.run_now(&world);
+
+            let map = world.read_resource::<Map>();
+            let pedestrians = world.read_component::<PedestrianComponent>();
+            let pedestrian = pedestrians.get(e).unwrap();
+
+            if let Some(TraverseKind::Turn(turn)) =
+                pedestrian.itinerary.get_travers().map(|t| t.kind)
+            {
+                let kind = map.intersections()[turn.parent].turns[&turn].kind;
+                crossed |= kind.is_crosswalk();
+
+                let time = world.read_resource::<TimeInfo>();
+                if pedestrian.waiting {
+                    assert!(!map.crosswalk_open(turn, time.time_seconds));
+                }
+            }
+
+            if pedestrian.destination.is_none() {
+                break;
+            }
+        }
+
+        let map = world.read_resource::<Map>();
+        let pedestrian = world
+            .read_component::<PedestrianComponent>()
+            .get(e)
+            .cloned()
+            .unwrap();
+        let pos = world
+            .read_component::<Transform>()
+            .get(e)
+            .unwrap()
+            .position();
+
+        assert!(crossed);
+        assert!(pedestrian.destination.is_none());
+        assert!(map.lanes()[destination].dist_to(pos) < 3.0);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::geometry::Vec2;
 use crate::gui::InspectVec2;
+use crate::physics::Transform;
 use cgmath::num_traits::zero;
+use cgmath::Zero;
 use imgui_inspect_derive::*;
 use serde::{Deserialize, Serialize};
 use specs::{Component, VecStorage};
@@ -13,6 +15,8 @@ pub struct Kinematics {
     #[inspect(proxy_type = "InspectVec2", skip = true)]
     pub acceleration: Vec2,
     pub mass: f32,
+    /// Passive deceleration, proportional to the velocity
+    pub drag: f32,
 }
 
 impl Kinematics {
@@ -21,6 +25,112 @@ impl Kinematics {
             velocity: zero(),
             acceleration: zero(),
             mass,
+            drag: 0.0,
         }
     }
+
+    /// Accumulates the acceleration given by the force for the next integration
+    pub fn apply_force(&mut self, f: Vec2) {
+        self.acceleration += f / self.mass;
+    }
+
+    pub fn momentum(&self) -> Vec2 {
+        self.velocity * self.mass
+    }
+
+    /// Integrates the acceleration and the drag over delta seconds, then moves the transform with
+    /// the new velocity (semi-implicit Euler, which stays stable at high speeds) and resets the acceleration
+    pub fn integrate(&mut self, trans: &mut Transform, delta: f32) {
+        self.velocity += self.acceleration * delta;
+        self.velocity *= (1.0 - self.drag * delta).max(0.0);
+        trans.translate(self.velocity * delta);
+        self.acceleration.set_zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn test_drag_slows_down_to_zero() {
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = vec2!(10.0, 5.0);
+        kin.drag = 0.5;
+        let mut trans = Transform::zero();
+
+        let mut last_speed = kin.velocity.magnitude();
+        for _ in 0..600 {
+            kin.integrate(&mut trans, 1.0 / 30.0);
+
+            let speed = kin.velocity.magnitude();
+            assert!(speed < last_speed);
+            last_speed = speed;
+        }
+
+        assert!(last_speed < 1e-3);
+    }
+
+    #[test]
+    fn test_no_drag_keeps_velocity() {
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = vec2!(10.0, 5.0);
+        let mut trans = Transform::zero();
+
+        for _ in 0..600 {
+            kin.integrate(&mut trans, 1.0 / 30.0);
+        }
+
+        assert_eq!(kin.velocity, vec2!(10.0, 5.0));
+    }
+
+    #[test]
+    fn test_force_inverse_to_mass() {
+        let force = vec2!(3000.0, -1200.0);
+
+        let mut car = Kinematics::from_mass(1000.0);
+        let mut truck = Kinematics::from_mass(4000.0);
+        car.apply_force(force);
+        truck.apply_force(force);
+
+        assert!((car.acceleration - truck.acceleration * 4.0).magnitude() < 1e-4);
+        assert!((car.acceleration * car.mass - force).magnitude() < 1e-3);
+
+        // Forces add up until integrated
+        car.apply_force(force);
+        assert!((car.acceleration - vec2!(6.0, -2.4)).magnitude() < 1e-4);
+
+        car.integrate(&mut Transform::zero(), 0.5);
+        assert!((car.velocity - vec2!(3.0, -1.2)).magnitude() < 1e-4);
+        assert!((car.momentum() - vec2!(3000.0, -1200.0)).magnitude() < 1e-2);
+        assert_eq!(car.acceleration, vec2!(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_integrate_semi_implicit() {
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = vec2!(2.0, 0.0);
+        let mut trans = Transform::new(vec2!(1.0, 1.0));
+
+        let acc = vec2!(4.0, -2.0);
+        let dt = 0.25;
+        let mut expected_pos = trans.position();
+        let mut expected_vel = kin.velocity;
+        for _ in 0..4 {
+            kin.acceleration = acc;
+            kin.integrate(&mut trans, dt);
+
+            // The position moves with the velocity at the end of the step
+            expected_vel += acc * dt;
+            expected_pos += expected_vel * dt;
+
+            assert!((kin.velocity - expected_vel).magnitude() < 1e-5);
+            assert!((trans.position() - expected_pos).magnitude() < 1e-5);
+            assert_eq!(kin.acceleration, vec2!(0.0, 0.0));
+        }
+
+        // (3 + 4 + 5 + 6) * 0.25, explicit Euler would have moved by (2 + 3 + 4 + 5) * 0.25
+        assert!((trans.position() - vec2!(5.5, -0.25)).magnitude() < 1e-4);
+    }
 }
@@ -1,6 +1,11 @@
 use crate::geometry::gridstore::{GridStore, GridStoreHandle};
+use crate::geometry::intersections::{time_to_collision, Ray};
+use crate::geometry::rect::Rect;
 use crate::geometry::Vec2;
-use specs::{Component, VecStorage};
+use crate::physics::systems::MAX_COLLIDER_RADIUS;
+use ordered_float::OrderedFloat;
+use specs::{Component, Entity, VecStorage, World, WorldExt};
+use std::collections::HashMap;
 
 mod kinematics;
 pub mod systems;
@@ -16,12 +21,32 @@ pub enum PhysicsGroup {
     Pedestrians,
 }
 
+impl PhysicsGroup {
+    /// Bitmask of the group in the collision world, used to only query relevant objects
+    pub fn mask(self) -> u32 {
+        1 << (self as u32)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct PhysicsObject {
     pub dir: Vec2,
     pub speed: f32,
     pub radius: f32,
     pub group: PhysicsGroup,
+    /// Entity the object is the collider of, set once the entity is built
+    pub owner: Option<Entity>,
+}
+
+impl PhysicsObject {
+    pub fn velocity(&self) -> Vec2 {
+        self.dir * self.speed
+    }
+
+    /// Where the object will be in t seconds if it keeps its current velocity
+    pub fn predicted_position(&self, pos: Vec2, t: f32) -> Vec2 {
+        pos + self.velocity() * t
+    }
 }
 
 impl Default for PhysicsObject {
@@ -31,12 +56,226 @@ impl Default for PhysicsObject {
             speed: 0.0,
             radius: 1.0,
             group: PhysicsGroup::Unknown,
+            owner: None,
         }
     }
 }
 
 pub type CollisionWorld = GridStore<PhysicsObject>;
 
+impl CollisionWorld {
+    /// Records the entity the object is the collider of, so that queries can map it back
+    pub fn set_owner(&mut self, handle: GridStoreHandle, e: Entity) {
+        self.get_obj_mut(handle).owner = Some(e);
+    }
+
+    /// The first object the ray hits within max_dist and its distance along the ray, 0 if the
+    /// ray starts inside of it. The direction of the ray must be normalized. If a mask is given,
+    /// only objects sharing at least one group with it are hit.
+    pub fn ray_cast(
+        &self,
+        ray: Ray,
+        max_dist: f32,
+        mask: Option<u32>,
+    ) -> Option<(GridStoreHandle, f32)> {
+        let half = max_dist / 2.0;
+        self.query_around(ray.from + ray.dir * half, half + MAX_COLLIDER_RADIUS, mask)
+            .filter_map(|x| {
+                let radius = self.get_obj(x.id).radius;
+                time_to_collision(x.pos - ray.from, -ray.dir, radius).map(|dist| (x.id, dist))
+            })
+            .filter(|&(_, dist)| dist <= max_dist)
+            .min_by_key(|&(_, dist)| OrderedFloat(dist))
+    }
+}
+
+/// Contact of a body with another one it overlaps
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub other: Entity,
+    /// Unit vector pointing from the other body towards this one
+    pub normal: Vec2,
+    /// The other body has no kinematics and never moves
+    pub fixed: bool,
+}
+
+/// Contacts of each body found during the last collision response
+#[derive(Default)]
+pub struct Contacts(HashMap<Entity, Vec<Contact>>);
+
+impl Contacts {
+    pub fn get(&self, e: Entity) -> &[Contact] {
+        self.0.get(&e).map_or(&[], |x| x.as_slice())
+    }
+
+    pub fn add(&mut self, e: Entity, contact: Contact) {
+        self.0.entry(e).or_default().push(contact);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// What happens to entities that leave the world bounds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoundsPolicy {
+    /// Brings the entity back to the closest point inside the bounds and stops it
+    Clamp,
+    /// Deletes the entity
+    Remove,
+}
+
+/// Area entities must stay in, so that runaway ones don't end up breaking the physics
+pub struct WorldBounds {
+    pub rect: Rect,
+    pub policy: BoundsPolicy,
+}
+
+impl WorldBounds {
+    /// The four edges of the bounds, as segments
+    pub fn walls(&self) -> [(Vec2, Vec2); 4] {
+        let r = &self.rect;
+        let (a, b) = (vec2!(r.left(), r.top()), vec2!(r.right(), r.top()));
+        let (c, d) = (vec2!(r.right(), r.bottom()), vec2!(r.left(), r.bottom()));
+        [(a, b), (b, c), (c, d), (d, a)]
+    }
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        Self {
+            rect: Rect::new(-100_000.0, -100_000.0, 200_000.0, 200_000.0),
+            policy: BoundsPolicy::Remove,
+        }
+    }
+}
+
+/// Settings of the collision world, larger values are more accurate in dense scenarios but slower
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsConfig {
+    /// Size of the cells of the collision world, only used when it is created
+    pub cell_size: i32,
+    /// Radius around a vehicle in which it looks for obstacles, on top of its braking distance
+    pub query_radius: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 50,
+            query_radius: 12.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    pub fn make_world(&self) -> CollisionWorld {
+        GridStore::new(self.cell_size)
+    }
+}
+
 #[derive(Component, Debug)]
 #[storage(VecStorage)]
 pub struct Collider(pub GridStoreHandle);
+
+/// Takes the Collider component off the entity and removes its object from the collision world,
+/// does nothing if it has none. Must be called before deleting entities that have one, as the
+/// collision world knows nothing about entities. Queries see the removal after the next maintain().
+pub fn remove_shape(world: &mut World, e: Entity) {
+    if let Some(Collider(handle)) = world.write_storage::<Collider>().remove(e) {
+        world.write_resource::<CollisionWorld>().remove(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ray_cast() {
+        let mut coworld: CollisionWorld = GridStore::new(50);
+        let car = |coworld: &mut CollisionWorld, pos: Vec2| {
+            coworld.insert_masked(
+                pos,
+                PhysicsObject {
+                    radius: 2.0,
+                    group: PhysicsGroup::Vehicles,
+                    ..Default::default()
+                },
+                PhysicsGroup::Vehicles.mask(),
+            )
+        };
+
+        let far = car(&mut coworld, vec2!(25.0, 0.0));
+        let near = car(&mut coworld, vec2!(10.0, 0.5));
+        // In the next lane
+        car(&mut coworld, vec2!(5.0, 8.0));
+
+        let ray = Ray {
+            from: vec2!(0.0, 0.0),
+            dir: vec2!(1.0, 0.0),
+        };
+        let vehicles = Some(PhysicsGroup::Vehicles.mask());
+
+        let (hit, dist) = coworld.ray_cast(ray, 50.0, vehicles).unwrap();
+        assert_eq!(hit, near);
+        assert!((dist - (10.0 - 3.75f32.sqrt())).abs() < 1e-4);
+
+        assert!(coworld.ray_cast(ray, 5.0, vehicles).is_none());
+        assert!(coworld
+            .ray_cast(ray, 50.0, Some(PhysicsGroup::Pedestrians.mask()))
+            .is_none());
+
+        // Looking past the near one
+        let past = Ray {
+            from: vec2!(13.0, 0.0),
+            dir: vec2!(1.0, 0.0),
+        };
+        assert_eq!(coworld.ray_cast(past, 50.0, vehicles).unwrap().0, far);
+
+        // Starting inside a car
+        let inside = Ray {
+            from: vec2!(10.0, 0.0),
+            dir: vec2!(-1.0, 0.0),
+        };
+        assert_eq!(coworld.ray_cast(inside, 50.0, None), Some((near, 0.0)));
+    }
+
+    #[test]
+    fn test_query_filters_groups() {
+        let mut coworld: CollisionWorld = GridStore::new(50);
+
+        let pedestrian = coworld.insert_masked(
+            vec2!(1.0, 0.0),
+            PhysicsObject {
+                group: PhysicsGroup::Pedestrians,
+                ..Default::default()
+            },
+            PhysicsGroup::Pedestrians.mask(),
+        );
+        let vehicle = coworld.insert_masked(
+            vec2!(-1.0, 0.0),
+            PhysicsObject {
+                group: PhysicsGroup::Vehicles,
+                ..Default::default()
+            },
+            PhysicsGroup::Vehicles.mask(),
+        );
+
+        let vehicles: Vec<GridStoreHandle> = coworld
+            .query_around(vec2!(0.0, 0.0), 5.0, Some(PhysicsGroup::Vehicles.mask()))
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(vehicles, vec![vehicle]);
+
+        let all = coworld.query_around(vec2!(0.0, 0.0), 5.0, None).count();
+        assert_eq!(all, 2);
+
+        let pedestrians: Vec<GridStoreHandle> = coworld
+            .query_around(vec2!(0.0, 0.0), 5.0, Some(PhysicsGroup::Pedestrians.mask()))
+            .map(|x| x.id)
+            .collect();
+        assert_eq!(pedestrians, vec![pedestrian]);
+    }
+}
@@ -1,9 +1,19 @@
+use crate::analytics::{SimEvent, SimEventKind, SimEvents};
 use crate::engine_interaction::TimeInfo;
-use crate::physics::{Collider, Kinematics, Transform};
+use crate::geometry::gridstore::GridStoreHandle;
+use crate::geometry::Vec2;
+use crate::physics::{
+    BoundsPolicy, Collider, Contact, Contacts, Kinematics, Transform, WorldBounds,
+};
+use crate::utils::Restrict;
 use crate::CollisionWorld;
-use cgmath::{InnerSpace, Zero};
+use cgmath::num_traits::zero;
+use cgmath::InnerSpace;
 use specs::prelude::ResourceId;
-use specs::{Join, Read, ReadStorage, System, SystemData, World, Write, WriteStorage};
+use specs::{
+    Entities, Entity, Join, Read, ReadStorage, System, SystemData, World, Write, WriteStorage,
+};
+use std::collections::HashMap;
 
 pub struct KinematicsApply;
 
@@ -29,9 +39,12 @@ impl<'a> System<'a> for KinematicsApply {
         )
             .join()
         {
-            kin.velocity += kin.acceleration * delta;
-            transform.translate(kin.velocity * delta);
-            kin.acceleration.set_zero();
+            kin.integrate(transform, delta);
+
+            // The collision world can't place it, WorldBoundsSystem takes care of it
+            if !transform.position().is_finite() {
+                continue;
+            }
 
             if let Some(Collider(handle)) = collider {
                 data.coworld.set_position(*handle, transform.position());
@@ -44,3 +57,469 @@ impl<'a> System<'a> for KinematicsApply {
         data.coworld.maintain();
     }
 }
+
+/// Restitution of collisions, 1 being perfectly elastic
+pub const RESTITUTION: f32 = 1.0;
+
+/// Radius of the biggest collider (buses), to find every object that can overlap another one
+pub const MAX_COLLIDER_RADIUS: f32 = 5.0;
+
+/// Applies an impulse split by mass to the dynamic bodies that overlap, so that they bounce
+/// off each other instead of going through. Bodies without kinematics are static and don't move.
+pub struct CollisionResponse;
+
+#[derive(SystemData)]
+pub struct CollisionResponseData<'a> {
+    entities: Entities<'a>,
+    time: Read<'a, TimeInfo>,
+    events: Write<'a, SimEvents>,
+    contacts: Write<'a, Contacts>,
+    coworld: Read<'a, CollisionWorld, specs::shred::PanicHandler>,
+    colliders: ReadStorage<'a, Collider>,
+    transforms: ReadStorage<'a, Transform>,
+    kinematics: WriteStorage<'a, Kinematics>,
+}
+
+impl<'a> System<'a> for CollisionResponse {
+    type SystemData = CollisionResponseData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let owners: HashMap<GridStoreHandle, Entity> = (&data.entities, &data.colliders)
+            .join()
+            .map(|(e, coll)| (coll.0, e))
+            .collect();
+
+        let mut impulses: Vec<(Entity, Vec2)> = vec![];
+        data.contacts.clear();
+
+        for (e, coll, trans, kin) in (
+            &data.entities,
+            &data.colliders,
+            &data.transforms,
+            &data.kinematics,
+        )
+            .join()
+        {
+            let me = data.coworld.get_obj(coll.0);
+            let pos = trans.position();
+
+            for nei in data
+                .coworld
+                .query_around(pos, me.radius + MAX_COLLIDER_RADIUS, None)
+            {
+                if nei.id == coll.0 {
+                    continue;
+                }
+                let other = match owners.get(&nei.id) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let other_kin = data.kinematics.get(*other);
+
+                // Dynamic pairs are seen from both sides, only handle them once
+                if other_kin.is_some() && other.id() < e.id() {
+                    continue;
+                }
+
+                let his = data.coworld.get_obj(nei.id);
+                let diff = nei.pos - pos;
+                let dist = diff.magnitude();
+                if dist >= me.radius + his.radius || dist < 1e-5 {
+                    continue;
+                }
+                let normal = diff / dist;
+
+                data.contacts.add(
+                    e,
+                    Contact {
+                        other: *other,
+                        normal: -normal,
+                        fixed: other_kin.is_none(),
+                    },
+                );
+                data.contacts.add(
+                    *other,
+                    Contact {
+                        other: e,
+                        normal,
+                        fixed: false,
+                    },
+                );
+
+                let my_inv_mass = 1.0 / kin.mass;
+                let (his_velocity, his_inv_mass) =
+                    other_kin.map_or((zero(), 0.0), |k| (k.velocity, 1.0 / k.mass));
+
+                let rel_speed = (his_velocity - kin.velocity).dot(normal);
+                if rel_speed >= 0.0 {
+                    // Already moving apart
+                    continue;
+                }
+
+                let j = -(1.0 + RESTITUTION) * rel_speed / (my_inv_mass + his_inv_mass);
+
+                data.events.push(SimEvent {
+                    kind: SimEventKind::Collision,
+                    time: data.time.time,
+                    entities: (e, *other),
+                    pos: pos + normal * me.radius,
+                });
+
+                impulses.push((e, -normal * j * my_inv_mass));
+                if other_kin.is_some() {
+                    impulses.push((*other, normal * j * his_inv_mass));
+                }
+            }
+        }
+
+        for (e, dv) in impulses {
+            if let Some(kin) = data.kinematics.get_mut(e) {
+                kin.velocity += dv;
+            }
+        }
+    }
+}
+
+/// Brings back or removes the entities that went out of the world bounds
+pub struct WorldBoundsSystem;
+
+#[derive(SystemData)]
+pub struct WorldBoundsData<'a> {
+    entities: Entities<'a>,
+    time: Read<'a, TimeInfo>,
+    bounds: Read<'a, WorldBounds>,
+    events: Write<'a, SimEvents>,
+    coworld: Write<'a, CollisionWorld, specs::shred::PanicHandler>,
+    colliders: ReadStorage<'a, Collider>,
+    transforms: WriteStorage<'a, Transform>,
+    kinematics: WriteStorage<'a, Kinematics>,
+}
+
+impl<'a> System<'a> for WorldBoundsSystem {
+    type SystemData = WorldBoundsData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let rect = &data.bounds.rect;
+
+        for (e, trans, collider) in (
+            &data.entities,
+            &mut data.transforms,
+            (&data.colliders).maybe(),
+        )
+            .join()
+        {
+            let pos = trans.position();
+            if rect.contains(pos) {
+                continue;
+            }
+
+            let clamped = if pos.is_finite() {
+                vec2!(
+                    pos.x.restrict(rect.left(), rect.right()),
+                    pos.y.restrict(rect.top(), rect.bottom())
+                )
+            } else {
+                vec2!(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0)
+            };
+
+            data.events.push(SimEvent {
+                kind: SimEventKind::OutOfBounds,
+                time: data.time.time,
+                entities: (e, e),
+                pos: clamped,
+            });
+
+            match data.bounds.policy {
+                BoundsPolicy::Clamp => {
+                    trans.set_position(clamped);
+                    if let Some(kin) = data.kinematics.get_mut(e) {
+                        kin.velocity = zero();
+                        kin.acceleration = zero();
+                    }
+                    if let Some(Collider(handle)) = collider {
+                        data.coworld.set_position(*handle, clamped);
+                    }
+                }
+                BoundsPolicy::Remove => {
+                    if let Some(Collider(handle)) = collider {
+                        data.coworld.remove(*handle);
+                    }
+                    let _ = data.entities.delete(e);
+                }
+            }
+        }
+
+        data.coworld.maintain();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_interaction::{FixedTimeStep, TIME_STEP};
+    use crate::geometry::gridstore::GridStore;
+    use crate::geometry::rect::Rect;
+    use crate::physics::{PhysicsGroup, PhysicsObject};
+    use specs::{Builder, RunNow, WorldExt};
+
+    fn add_body(world: &mut World, pos: Vec2, kin: Option<Kinematics>) -> Entity {
+        let h = world.write_resource::<CollisionWorld>().insert(
+            pos,
+            PhysicsObject {
+                radius: 2.25,
+                group: PhysicsGroup::Vehicles,
+                ..Default::default()
+            },
+        );
+
+        let builder = world
+            .create_entity()
+            .with(Transform::new(pos))
+            .with(Collider(h));
+        match kin {
+            Some(kin) => builder.with(kin).build(),
+            None => builder.build(),
+        }
+    }
+
+    fn moving(velocity: Vec2) -> Kinematics {
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = velocity;
+        kin
+    }
+
+    fn distance_per_step(time_scale: f32) -> f32 {
+        let mut world = setup_world();
+        let e = add_body(&mut world, vec2!(0.0, 0.0), Some(moving(vec2!(10.0, 0.0))));
+
+        let mut time = TimeInfo {
+            time_scale,
+            ..Default::default()
+        };
+        time.advance(1.0 / 30.0);
+        world.insert(time);
+
+        KinematicsApply.run_now(&world);
+
+        let pos = world.read_storage::<Transform>().get(e).unwrap().position();
+        pos.magnitude()
+    }
+
+    /// Distance covered during one real frame, doing the steps the fixed time step asks for
+    fn distance_per_frame(time_scale: f32, frame_delta: f64) -> f32 {
+        let mut world = setup_world();
+        let e = add_body(&mut world, vec2!(0.0, 0.0), Some(moving(vec2!(10.0, 0.0))));
+
+        let mut time = TimeInfo {
+            time_scale,
+            ..Default::default()
+        };
+        let ticks = FixedTimeStep::default().ticks(frame_delta, &time);
+        for _ in 0..ticks {
+            time.advance(TIME_STEP);
+            world.insert(time);
+            KinematicsApply.run_now(&world);
+        }
+
+        let pos = world.read_storage::<Transform>().get(e).unwrap().position();
+        pos.magnitude()
+    }
+
+    #[test]
+    fn test_time_scale_keeps_step_delta() {
+        // speeding up does more steps, each step must stay the same for determinism
+        let normal = distance_per_step(1.0);
+        let fast = distance_per_step(2.0);
+
+        assert!(normal > 0.0);
+        assert_eq!(fast, normal);
+
+        // so twice the speed covers twice the distance in the same real time
+        let frame = 2.25 * TIME_STEP;
+        let normal = distance_per_frame(1.0, frame);
+        let fast = distance_per_frame(2.0, frame);
+
+        assert!(normal > 0.0);
+        assert!((fast - 2.0 * normal).abs() < 1e-4);
+    }
+
+    fn setup_world() -> World {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<Kinematics>();
+        world.register::<Collider>();
+        let coworld: CollisionWorld = GridStore::new(50);
+        world.insert(coworld);
+        world.insert(TimeInfo::default());
+        world.insert(SimEvents::default());
+        world.insert(Contacts::default());
+        world
+    }
+
+    #[test]
+    fn test_head_on_cars_separate() {
+        let mut world = setup_world();
+        let a = add_body(&mut world, vec2!(-2.0, 0.0), Some(moving(vec2!(5.0, 0.0))));
+        let b = add_body(&mut world, vec2!(2.0, 0.0), Some(moving(vec2!(-5.0, 0.0))));
+
+        CollisionResponse.run_now(&world);
+
+        let kinematics = world.read_storage::<Kinematics>();
+        let va = kinematics.get(a).unwrap().velocity;
+        let vb = kinematics.get(b).unwrap().velocity;
+
+        assert!(va.x < 0.0);
+        assert!(vb.x > 0.0);
+        assert!((va - vec2!(-5.0, 0.0)).magnitude() < 1e-4);
+        assert!((vb - vec2!(5.0, 0.0)).magnitude() < 1e-4);
+
+        // Equal mass: opposite velocity changes
+        let dva = va - vec2!(5.0, 0.0);
+        let dvb = vb - vec2!(-5.0, 0.0);
+        assert!((dva + dvb).magnitude() < 1e-4);
+    }
+
+    fn run_bounds(policy: BoundsPolicy) -> (World, Entity, GridStoreHandle) {
+        let mut world = setup_world();
+        world.insert(WorldBounds {
+            rect: Rect::new(-100.0, -100.0, 200.0, 200.0),
+            policy,
+        });
+
+        add_body(&mut world, vec2!(0.0, 0.0), Some(moving(vec2!(5.0, 0.0))));
+        let e = add_body(
+            &mut world,
+            vec2!(1000.0, 50.0),
+            Some(moving(vec2!(5.0, 0.0))),
+        );
+        let handle = world.read_storage::<Collider>().get(e).unwrap().0;
+
+        WorldBoundsSystem.run_now(&world);
+        world.maintain();
+        (world, e, handle)
+    }
+
+    #[test]
+    fn test_bounds_clamp() {
+        let (world, e, handle) = run_bounds(BoundsPolicy::Clamp);
+
+        let pos = world.read_storage::<Transform>().get(e).unwrap().position();
+        assert_eq!(pos, vec2!(100.0, 50.0));
+        assert_eq!(
+            world.read_storage::<Kinematics>().get(e).unwrap().velocity,
+            vec2!(0.0, 0.0)
+        );
+
+        let coworld = world.read_resource::<CollisionWorld>();
+        assert!(coworld
+            .query_around(pos, 1.0, None)
+            .any(|obj| obj.id == handle));
+    }
+
+    #[test]
+    fn test_bounds_remove() {
+        let (world, e, handle) = run_bounds(BoundsPolicy::Remove);
+
+        assert!(!world.is_alive(e));
+        assert!(!world.read_resource::<CollisionWorld>().contains(handle));
+        // The entity inside the bounds is untouched
+        assert_eq!(world.read_storage::<Transform>().join().count(), 1);
+
+        let events = world.read_resource::<SimEvents>();
+        assert_eq!(events.count(SimEventKind::OutOfBounds), 1);
+        assert_eq!(events.iter().next().unwrap().entities, (e, e));
+    }
+
+    #[test]
+    fn test_non_finite_position_is_brought_back() {
+        let mut world = setup_world();
+        world.insert(WorldBounds {
+            rect: Rect::new(-100.0, -100.0, 200.0, 200.0),
+            policy: BoundsPolicy::Clamp,
+        });
+        let e = add_body(
+            &mut world,
+            vec2!(10.0, 0.0),
+            Some(moving(vec2!(std::f32::NAN, 0.0))),
+        );
+        let handle = world.read_storage::<Collider>().get(e).unwrap().0;
+        world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+
+        KinematicsApply.run_now(&world);
+        WorldBoundsSystem.run_now(&world);
+
+        let pos = world.read_storage::<Transform>().get(e).unwrap().position();
+        assert_eq!(pos, vec2!(0.0, 0.0));
+        assert!(world
+            .read_resource::<CollisionWorld>()
+            .query_around(pos, 1.0, None)
+            .any(|obj| obj.id == handle));
+        assert_eq!(
+            world
+                .read_resource::<SimEvents>()
+                .count(SimEventKind::OutOfBounds),
+            1
+        );
+    }
+
+    #[test]
+    fn test_contact_normals() {
+        let mut world = setup_world();
+        let a = add_body(&mut world, vec2!(0.0, 0.0), Some(moving(vec2!(1.0, 0.0))));
+        let b = add_body(&mut world, vec2!(1.8, 2.4), None);
+
+        CollisionResponse.run_now(&world);
+
+        let contacts = world.read_resource::<Contacts>();
+        let ca = contacts.get(a);
+        assert_eq!(ca.len(), 1);
+        assert_eq!(ca[0].other, b);
+        assert!((ca[0].normal - vec2!(-0.6, -0.8)).magnitude() < 1e-5);
+
+        let cb = contacts.get(b);
+        assert_eq!(cb.len(), 1);
+        assert_eq!(cb[0].other, a);
+        assert!((cb[0].normal - vec2!(0.6, 0.8)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn test_collision_is_recorded() {
+        let mut world = setup_world();
+        let a = add_body(&mut world, vec2!(-2.0, 0.0), Some(moving(vec2!(5.0, 0.0))));
+        let b = add_body(&mut world, vec2!(2.0, 0.0), None);
+
+        CollisionResponse.run_now(&world);
+
+        let events = world.read_resource::<SimEvents>();
+        assert_eq!(events.len(), 1);
+
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.kind, SimEventKind::Collision);
+        assert_eq!(event.entities, (a, b));
+    }
+
+    #[test]
+    fn test_static_body_stays_put() {
+        let mut world = setup_world();
+        let car = add_body(&mut world, vec2!(-2.0, 0.0), Some(moving(vec2!(5.0, 0.0))));
+        let wall = add_body(&mut world, vec2!(2.0, 0.0), None);
+
+        CollisionResponse.run_now(&world);
+
+        let v = world
+            .read_storage::<Kinematics>()
+            .get(car)
+            .unwrap()
+            .velocity;
+        assert!((v - vec2!(-5.0, 0.0)).magnitude() < 1e-4);
+        assert!(world.read_storage::<Kinematics>().get(wall).is_none());
+        assert_eq!(
+            world
+                .read_storage::<Transform>()
+                .get(wall)
+                .unwrap()
+                .position(),
+            vec2!(2.0, 0.0)
+        );
+    }
+}
@@ -8,6 +8,7 @@ use specs::{Component, VecStorage};
 pub struct Transform {
     m: Matrix3<f32>,
     rotated: bool,
+    scale: f32,
 }
 
 #[allow(dead_code)]
@@ -21,7 +22,11 @@ impl Transform {
         let mut m = Matrix3::identity();
         m.z.x = position.x;
         m.z.y = position.y;
-        Transform { m, rotated: false }
+        Transform {
+            m,
+            rotated: false,
+            scale: 1.0,
+        }
     }
 
     pub fn position(&self) -> Vec2 {
@@ -39,33 +44,49 @@ impl Transform {
     }
 
     pub fn set_angle(&mut self, angle: f32) {
-        let cos = angle.cos();
-        let sin = angle.sin();
-        self.m.x.x = cos;
-        self.m.x.y = sin;
-        self.m.y.x = -sin;
-        self.m.y.y = cos;
+        self.set_rotation(angle.cos(), angle.sin());
         self.rotated = angle != 0.0;
     }
 
     pub fn set_cos_sin(&mut self, cos: f32, sin: f32) {
-        self.m.x.x = cos;
-        self.m.x.y = sin;
-        self.m.y.x = -sin;
-        self.m.y.y = cos;
+        self.set_rotation(cos, sin);
         self.rotated = sin != 0.0;
     }
 
+    fn set_rotation(&mut self, cos: f32, sin: f32) {
+        self.m.x.x = cos * self.scale;
+        self.m.x.y = sin * self.scale;
+        self.m.y.x = -sin * self.scale;
+        self.m.y.y = cos * self.scale;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Uniform scale applied by project() and to the sizes of the meshes drawn with this
+    /// transform, it doesn't change the angle or the direction
+    pub fn set_scale(&mut self, scale: f32) {
+        debug_assert!(
+            scale > 0.0,
+            "transform scale must be positive, got {}",
+            scale
+        );
+        let (cos, sin) = (self.cos(), self.sin());
+        self.scale = scale;
+        self.set_rotation(cos, sin);
+    }
+
     pub fn set_direction(&mut self, dir: Vec2) {
         self.set_cos_sin(dir.x, dir.y);
     }
 
     pub fn cos(&self) -> f32 {
-        self.m.x.x
+        self.m.x.x / self.scale
     }
 
     pub fn sin(&self) -> f32 {
-        self.m.x.y
+        self.m.x.y / self.scale
     }
 
     pub fn angle(&self) -> f32 {
@@ -80,10 +101,19 @@ impl Transform {
         vec2!(-self.sin(), self.cos())
     }
 
-    pub fn apply_rotation(&self, vec: Vec2) -> Vec2 {
+    /// Rotates a vector from local space to world space, without translating it
+    pub fn rotate(&self, vec: Vec2) -> Vec2 {
+        vec2!(
+            vec.x * self.cos() - vec.y * self.sin(),
+            vec.x * self.sin() + vec.y * self.cos(),
+        )
+    }
+
+    /// Rotates a vector from world space back to local space, without translating it
+    pub fn inverse_rotate(&self, vec: Vec2) -> Vec2 {
         vec2!(
             vec.x * self.cos() + vec.y * self.sin(),
-            vec.x * self.sin() - vec.y * self.cos(),
+            -vec.x * self.sin() + vec.y * self.cos(),
         )
     }
 
@@ -95,4 +125,103 @@ impl Transform {
         let p = self.m * point.extend(1.0);
         vec2!(p.x, p.y)
     }
+
+    /// Projects all the points in one pass and appends them to out, same as calling project()
+    /// on each of them
+    pub fn project_many(&self, points: &[Vec2], out: &mut Vec<Vec2>) {
+        let (x, y, z) = (self.m.x, self.m.y, self.m.z);
+        out.reserve(points.len());
+        out.extend(
+            points
+                .iter()
+                .map(|p| vec2!(x.x * p.x + y.x * p.y + z.x, x.y * p.x + y.y * p.y + z.y)),
+        );
+    }
+
+    /// Converts a point in world space to the local space of this transform, undoing project()
+    pub fn inverse_project(&self, point: Vec2) -> Vec2 {
+        self.inverse_rotate(point - self.position()) / self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    #[test]
+    fn test_rotate() {
+        let mut trans = Transform::zero();
+        trans.set_angle(std::f32::consts::FRAC_PI_2);
+
+        assert!((trans.rotate(vec2!(1.0, 0.0)) - vec2!(0.0, 1.0)).magnitude() < 1e-5);
+        assert!((trans.rotate(vec2!(0.0, 1.0)) - vec2!(-1.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn test_inverse_project() {
+        let points = [
+            vec2!(0.0, 0.0),
+            vec2!(1.0, 0.0),
+            vec2!(-3.5, 2.0),
+            vec2!(10.0, -7.25),
+        ];
+
+        for &angle in &[0.0, 0.3, 1.0, 2.5, -1.7, std::f32::consts::PI] {
+            let mut trans = Transform::new(vec2!(12.0, -4.0));
+            trans.set_angle(angle);
+
+            for &p in &points {
+                let back = trans.inverse_project(trans.project(p));
+                assert!((back - p).magnitude() < 1e-4);
+
+                let back = trans.project(trans.inverse_project(p));
+                assert!((back - p).magnitude() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_project_many() {
+        let points = [
+            vec2!(0.0, 0.0),
+            vec2!(1.0, 0.0),
+            vec2!(-3.5, 2.0),
+            vec2!(10.0, -7.25),
+        ];
+
+        let mut trans = Transform::new(vec2!(12.0, -4.0));
+        trans.set_angle(0.7);
+        trans.set_scale(1.5);
+
+        let mut out = vec![vec2!(42.0, 42.0)];
+        trans.project_many(&points, &mut out);
+
+        assert_eq!(out.len(), points.len() + 1);
+        assert_eq!(out[0], vec2!(42.0, 42.0));
+        for (&p, &projected) in points.iter().zip(&out[1..]) {
+            assert!((trans.project(p) - projected).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_scaled_project() {
+        let mut trans = Transform::zero();
+        trans.set_scale(2.0);
+
+        assert_eq!(trans.project(vec2!(0.0, 0.0)), vec2!(0.0, 0.0));
+        assert_eq!(trans.project(vec2!(1.0, -3.0)), vec2!(2.0, -6.0));
+        assert!(trans.is_angle_zero());
+        assert_eq!(trans.direction(), vec2!(1.0, 0.0));
+
+        trans.set_angle(std::f32::consts::FRAC_PI_2);
+        trans.set_position(vec2!(5.0, 5.0));
+        assert!((trans.project(vec2!(1.0, 0.0)) - vec2!(5.0, 7.0)).magnitude() < 1e-5);
+        assert!((trans.direction() - vec2!(0.0, 1.0)).magnitude() < 1e-5);
+        assert!((trans.inverse_project(vec2!(5.0, 7.0)) - vec2!(1.0, 0.0)).magnitude() < 1e-5);
+
+        // Scaling again doesn't compound and keeps the rotation
+        trans.set_scale(0.5);
+        assert!((trans.project(vec2!(2.0, 0.0)) - vec2!(5.0, 6.0)).magnitude() < 1e-5);
+    }
 }
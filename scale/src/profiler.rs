@@ -0,0 +1,130 @@
+use specs::shred::RunningTime;
+use specs::{DispatcherBuilder, Read, System, SystemData, World};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Time spent in each system during the last step, in milliseconds.
+/// Systems run in parallel, so the times are behind a lock that is only taken once per run.
+#[derive(Default)]
+pub struct Profiler {
+    times: Mutex<BTreeMap<&'static str, f32>>,
+}
+
+impl Profiler {
+    pub fn record(&self, name: &'static str, ms: f32) {
+        self.times.lock().unwrap().insert(name, ms);
+    }
+
+    /// Copy of the last time of each system, sorted by name
+    pub fn times(&self) -> Vec<(&'static str, f32)> {
+        self.times
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&name, &ms)| (name, ms))
+            .collect()
+    }
+
+    pub fn total(&self) -> f32 {
+        self.times.lock().unwrap().values().sum()
+    }
+
+    pub fn clear(&self) {
+        self.times.lock().unwrap().clear();
+    }
+}
+
+/// Runs the inner system and records how long it took in the Profiler
+pub struct Timed<S> {
+    name: &'static str,
+    system: S,
+}
+
+impl<S> Timed<S> {
+    pub fn new(system: S, name: &'static str) -> Self {
+        Self { name, system }
+    }
+}
+
+impl<'a, S> System<'a> for Timed<S>
+where
+    S: System<'a>,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (S::SystemData, Read<'a, Profiler>);
+
+    fn run(&mut self, (data, profiler): Self::SystemData) {
+        let start = Instant::now();
+        self.system.run(data);
+        profiler.record(self.name, start.elapsed().as_secs_f32() * 1000.0);
+    }
+
+    fn running_time(&self) -> RunningTime {
+        self.system.running_time()
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        <Read<Profiler> as SystemData>::setup(world);
+        self.system.setup(world);
+    }
+}
+
+pub trait TimedDispatcherBuilder {
+    /// Same as DispatcherBuilder::with, but the system is timed under its name
+    fn with_timed<S>(self, system: S, name: &'static str, dep: &[&str]) -> Self
+    where
+        S: for<'a> System<'a> + Send + 'static,
+        for<'a> <S as System<'a>>::SystemData: SystemData<'a>;
+}
+
+impl<'a, 'b> TimedDispatcherBuilder for DispatcherBuilder<'a, 'b> {
+    fn with_timed<S>(self, system: S, name: &'static str, dep: &[&str]) -> Self
+    where
+        S: for<'c> System<'c> + Send + 'static,
+        for<'c> <S as System<'c>>::SystemData: SystemData<'c>,
+    {
+        self.with(Timed::new(system, name), name, dep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profiler::Profiler;
+    use crate::simulation::Simulation;
+    use crate::vehicles::spawn_new_vehicle;
+
+    #[test]
+    fn test_profiler_records_every_system() {
+        let mut sim = Simulation::new();
+        for _ in 0..10 {
+            spawn_new_vehicle(&mut sim.world);
+        }
+
+        assert!(sim.world.read_resource::<Profiler>().times().is_empty());
+        sim.step(1.0 / 30.0);
+
+        let profiler = sim.world.read_resource::<Profiler>();
+        let names: Vec<&str> = profiler.times().into_iter().map(|(name, _)| name).collect();
+        let mut expected = vec![
+            "vehicle merge",
+            "car decision",
+            "vehicle spawn",
+            "vehicle cleanup",
+            "congestion",
+            "lane occupancy",
+            "pedestrian decision",
+            "selectable",
+            "follow",
+            "movable",
+            "rgs",
+            "collision response",
+            "speed apply",
+            "world bounds",
+            "selectable aura",
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+        assert!(profiler.times().iter().all(|&(_, ms)| ms >= 0.0));
+    }
+}
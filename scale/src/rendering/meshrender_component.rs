@@ -1,8 +1,12 @@
 use crate::engine_interaction::MAX_LAYERS;
+use crate::geometry::circle::Circle;
+use crate::geometry::rect::Rect;
 use crate::geometry::Vec2;
 use crate::gui::{ImEntity, InspectDragf, InspectVec, InspectVec2};
+use crate::physics::Transform;
 use crate::rendering::colors::*;
 use cgmath::num_traits::zero;
+use cgmath::InnerSpace;
 use imgui::Ui;
 use imgui_inspect::InspectArgsDefault;
 use imgui_inspect::InspectRenderDefault;
@@ -17,6 +21,7 @@ pub enum MeshRenderEnum {
     #[serde(skip)]
     LineTo(LineToRender),
     Line(LineRender),
+    Polygon(PolygonRender),
 }
 
 impl MeshRenderEnum {
@@ -33,6 +38,65 @@ impl MeshRenderEnum {
             _ => panic!(),
         }
     }
+
+    /// Radius around the transform's position containing the whole shape before the
+    /// transform's scale is applied, used for culling
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            MeshRenderEnum::Circle(x) => x.offset.magnitude() + x.radius,
+            MeshRenderEnum::Rect(x) => {
+                x.offset.magnitude() + (x.width * x.width + x.height * x.height).sqrt() / 2.0
+            }
+            // Goes to another entity which can be anywhere
+            MeshRenderEnum::LineTo(_) => std::f32::INFINITY,
+            MeshRenderEnum::Line(x) => x.offset.magnitude() + x.thickness / 2.0,
+            MeshRenderEnum::Polygon(x) => {
+                x.points.iter().map(|p| p.magnitude()).fold(0.0, f32::max)
+            }
+        }
+    }
+
+    /// Min and max corners of the axis aligned box containing the shape drawn at trans.
+    /// Lines to other entities only cover their start, where the entity is.
+    pub fn bounds(&self, trans: &Transform) -> (Vec2, Vec2) {
+        let pos = trans.position();
+        let scale = trans.scale();
+        match self {
+            MeshRenderEnum::Circle(x) => {
+                let circle = x.circle(trans);
+                let r = vec2!(circle.radius, circle.radius);
+                (circle.center - r, circle.center + r)
+            }
+            MeshRenderEnum::Rect(x) => points_bounds(x.corners(trans).iter().copied()),
+            MeshRenderEnum::LineTo(x) => {
+                let r = vec2!(x.thickness, x.thickness) * scale / 2.0;
+                (pos - r, pos + r)
+            }
+            MeshRenderEnum::Line(x) => {
+                let (min, max) = points_bounds(vec![pos, pos + x.offset * scale]);
+                let r = vec2!(x.thickness, x.thickness) * scale / 2.0;
+                (min - r, max + r)
+            }
+            MeshRenderEnum::Polygon(x) => {
+                if x.points.is_empty() {
+                    return (pos, pos);
+                }
+                points_bounds(x.points.iter().map(|&p| trans.project(p)))
+            }
+        }
+    }
+}
+
+/// Min and max corners of the points, which mustn't be empty
+fn points_bounds(points: impl IntoIterator<Item = Vec2>) -> (Vec2, Vec2) {
+    let mut it = points.into_iter();
+    let first = it.next().expect("no points to bound");
+    it.fold((first, first), |(min, max), p| {
+        (
+            vec2!(min.x.min(p.x), min.y.min(p.y)),
+            vec2!(max.x.max(p.x), max.y.max(p.y)),
+        )
+    })
 }
 
 impl InspectRenderDefault<MeshRenderEnum> for MeshRenderEnum {
@@ -95,6 +159,15 @@ impl InspectRenderDefault<MeshRenderEnum> for MeshRenderEnum {
                     args,
                 )
             }
+            MeshRenderEnum::Polygon(x) => {
+                <PolygonRender as InspectRenderDefault<PolygonRender>>::render_mut(
+                    &mut [x],
+                    label,
+                    world,
+                    ui,
+                    args,
+                )
+            }
         }
     }
 }
@@ -123,6 +196,12 @@ impl From<LineRender> for MeshRenderEnum {
     }
 }
 
+impl From<PolygonRender> for MeshRenderEnum {
+    fn from(x: PolygonRender) -> Self {
+        MeshRenderEnum::Polygon(x)
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Component)]
 pub struct MeshRender {
     pub orders: Vec<MeshRenderEnum>,
@@ -147,6 +226,18 @@ impl MeshRender {
         self.layer
     }
 
+    pub fn bounding_radius(&self) -> f32 {
+        self.orders
+            .iter()
+            .map(MeshRenderEnum::bounding_radius)
+            .fold(0.0, f32::max)
+    }
+
+    /// Whether any part of the mesh can be seen in the view rectangle when drawn at trans
+    pub fn is_visible(&self, trans: &Transform, view: &Rect) -> bool {
+        view.contains_within(trans.position(), self.bounding_radius() * trans.scale())
+    }
+
     pub fn add<T: Into<MeshRenderEnum>>(&mut self, x: T) -> &mut Self {
         self.orders.push(x.into());
         self
@@ -211,6 +302,13 @@ pub struct CircleRender {
     pub filled: bool,
 }
 
+impl CircleRender {
+    /// The circle covered in world space
+    pub fn circle(&self, trans: &Transform) -> Circle {
+        Circle::new(trans.project(self.offset), self.radius * trans.scale())
+    }
+}
+
 impl Default for CircleRender {
     fn default() -> Self {
         CircleRender {
@@ -230,16 +328,40 @@ pub struct RectRender {
     pub width: f32,
     #[inspect(proxy_type = "InspectDragf")]
     pub height: f32,
+    /// Radius of the rounded corners, 0 for a sharp rectangle
+    #[inspect(proxy_type = "InspectDragf")]
+    pub corner_radius: f32,
     pub color: Color,
     pub filled: bool,
 }
 
+impl RectRender {
+    /// Corners in world space, rotated and scaled with the transform
+    pub fn corners(&self, trans: &Transform) -> [Vec2; 4] {
+        let center = trans.project(self.offset);
+        let half = vec2!(self.width, self.height) * trans.scale() / 2.0;
+        [
+            center + trans.rotate(vec2!(half.x, half.y)),
+            center + trans.rotate(vec2!(-half.x, half.y)),
+            center + trans.rotate(vec2!(-half.x, -half.y)),
+            center + trans.rotate(vec2!(half.x, -half.y)),
+        ]
+    }
+
+    /// Axis aligned bounding box in world space
+    pub fn bbox(&self, trans: &Transform) -> Rect {
+        let (min, max) = points_bounds(self.corners(trans).iter().copied());
+        Rect::from_corners(min, max)
+    }
+}
+
 impl Default for RectRender {
     fn default() -> Self {
         RectRender {
             offset: [0.0, 0.0].into(),
             width: 0.0,
             height: 0.0,
+            corner_radius: 0.0,
             color: Color::WHITE,
             filled: true,
         }
@@ -262,4 +384,172 @@ pub struct LineRender {
     pub color: Color,
     #[inspect(proxy_type = "InspectDragf")]
     pub thickness: f32,
+    /// Length of the dashes and of the gaps between them, None for a solid line
+    #[inspect(skip = true)]
+    pub dash: Option<(f32, f32)>,
+}
+
+/// An arbitrary simple polygon, its points are relative to the entity's transform
+#[derive(Debug, Inspect, Clone, Serialize, Deserialize)]
+pub struct PolygonRender {
+    #[inspect(skip = true)]
+    pub points: Vec<Vec2>,
+    pub color: Color,
+    pub filled: bool,
+}
+
+impl Default for PolygonRender {
+    fn default() -> Self {
+        PolygonRender {
+            points: vec![],
+            color: Color::WHITE,
+            filled: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_culling() {
+        let view = Rect::new(0.0, 0.0, 100.0, 50.0);
+
+        let mut mr = MeshRender::empty(0);
+        mr.add(RectRender {
+            width: 4.0,
+            height: 2.0,
+            offset: vec2!(1.0, 0.0),
+            ..Default::default()
+        })
+        .add(CircleRender {
+            radius: 0.5,
+            ..Default::default()
+        });
+
+        let at = |x, y| Transform::new(vec2!(x, y));
+        assert!(mr.is_visible(&at(50.0, 25.0), &view));
+        // Out of the view, but the rect sticks into it
+        assert!(mr.is_visible(&at(-2.0, 25.0), &view));
+        assert!(!mr.is_visible(&at(1000.0, 25.0), &view));
+        assert!(!mr.is_visible(&at(50.0, -1000.0), &view));
+
+        // Only a scaled up rect reaches that far into the view
+        let mut trans = at(-8.0, 25.0);
+        assert!(!mr.is_visible(&trans, &view));
+        trans.set_scale(4.0);
+        assert!(mr.is_visible(&trans, &view));
+    }
+
+    #[test]
+    fn test_scaled_bounds() {
+        let mut trans = Transform::new(vec2!(10.0, 20.0));
+        trans.set_scale(2.0);
+
+        let rect: MeshRenderEnum = RectRender {
+            width: 4.0,
+            height: 2.0,
+            offset: vec2!(1.0, 0.0),
+            ..Default::default()
+        }
+        .into();
+        assert_close(rect.bounds(&trans), (vec2!(8.0, 18.0), vec2!(16.0, 22.0)));
+
+        let circle = CircleRender {
+            radius: 2.0,
+            offset: vec2!(3.0, 0.0),
+            ..Default::default()
+        };
+        let c = circle.circle(&trans);
+        assert!((c.center - vec2!(16.0, 20.0)).magnitude() < 1e-4);
+        assert!((c.radius - 4.0).abs() < 1e-5);
+
+        let circle: MeshRenderEnum = circle.into();
+        assert_close(
+            circle.bounds(&trans),
+            (vec2!(12.0, 16.0), vec2!(20.0, 24.0)),
+        );
+        assert!(circle.bounding_radius() * trans.scale() >= 8.0 - 1e-4);
+    }
+
+    fn assert_close(a: (Vec2, Vec2), b: (Vec2, Vec2)) {
+        assert!(
+            (a.0 - b.0).magnitude() < 1e-4 && (a.1 - b.1).magnitude() < 1e-4,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_rotated_rect_bounds() {
+        let rect: MeshRenderEnum = RectRender {
+            width: 4.0,
+            height: 2.0,
+            offset: vec2!(1.0, 0.0),
+            ..Default::default()
+        }
+        .into();
+
+        let mut trans = Transform::new(vec2!(10.0, 20.0));
+        assert_close(rect.bounds(&trans), (vec2!(9.0, 19.0), vec2!(13.0, 21.0)));
+
+        // A quarter turn swaps width and height and moves the offset up
+        trans.set_angle(std::f32::consts::FRAC_PI_2);
+        assert_close(rect.bounds(&trans), (vec2!(9.0, 19.0), vec2!(11.0, 23.0)));
+
+        // Diagonal, the corners of a 4x2 rect are sqrt(2) * 3 / 2 away on each axis
+        trans.set_angle(std::f32::consts::FRAC_PI_4);
+        trans.set_position(vec2!(0.0, 0.0));
+        let c = std::f32::consts::FRAC_1_SQRT_2;
+        let ext = 3.0 * c;
+        assert_close(
+            rect.bounds(&trans),
+            (vec2!(c - ext, c - ext), vec2!(c + ext, c + ext)),
+        );
+    }
+
+    #[test]
+    fn test_circle_bounds() {
+        let circle: MeshRenderEnum = CircleRender {
+            radius: 2.0,
+            offset: vec2!(3.0, 0.0),
+            ..Default::default()
+        }
+        .into();
+
+        let mut trans = Transform::new(vec2!(-5.0, 5.0));
+        assert_close(circle.bounds(&trans), (vec2!(-4.0, 3.0), vec2!(0.0, 7.0)));
+
+        trans.set_angle(std::f32::consts::PI);
+        assert_close(circle.bounds(&trans), (vec2!(-10.0, 3.0), vec2!(-6.0, 7.0)));
+    }
+
+    #[test]
+    fn test_to_primitives() {
+        let rect = RectRender {
+            width: 4.0,
+            height: 2.0,
+            offset: vec2!(1.0, 0.0),
+            ..Default::default()
+        };
+        let circle = CircleRender {
+            radius: 2.0,
+            offset: vec2!(3.0, 0.0),
+            ..Default::default()
+        };
+
+        let mut trans = Transform::new(vec2!(10.0, 20.0));
+        trans.set_angle(std::f32::consts::FRAC_PI_2);
+
+        let bbox = rect.bbox(&trans);
+        assert!(bbox.contains(vec2!(10.0, 22.5)));
+        assert!(!bbox.contains(vec2!(12.0, 20.0)));
+
+        let c = circle.circle(&trans);
+        assert!((c.center - vec2!(10.0, 23.0)).magnitude() < 1e-4);
+        assert!(c.intersects_rect(&bbox));
+        assert!(!c.contains(vec2!(10.0, 20.0)));
+    }
 }
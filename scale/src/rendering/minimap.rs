@@ -0,0 +1,90 @@
+use crate::geometry::rect::Rect;
+use crate::geometry::Vec2;
+use crate::map_model::Map;
+
+/// Maps world positions linearly from the world rectangle onto the minimap rectangle
+#[derive(Clone)]
+pub struct MinimapProjection {
+    pub world: Rect,
+    pub target: Rect,
+}
+
+impl MinimapProjection {
+    pub fn new(world: Rect, target: Rect) -> Self {
+        Self { world, target }
+    }
+
+    pub fn project(&self, p: Vec2) -> Vec2 {
+        // A map made of a single straight lane has a flat bounding box
+        let u = (p.x - self.world.x) / self.world.w.max(1.0);
+        let v = (p.y - self.world.y) / self.world.h.max(1.0);
+        vec2!(
+            self.target.x + u * self.target.w,
+            self.target.y + v * self.target.h
+        )
+    }
+}
+
+/// Bounding box of all the lanes of the map, None if the map has no lanes
+pub fn map_bounds(map: &Map) -> Option<Rect> {
+    let mut points = map.lanes().values().flat_map(|lane| lane.points.iter());
+    let first = *points.next()?;
+
+    let (min, max) = points.fold((first, first), |(min, max), p| {
+        (
+            vec2!(min.x.min(p.x), min.y.min(p.y)),
+            vec2!(max.x.max(p.x), max.y.max(p.y)),
+        )
+    });
+    Some(Rect::new(min.x, min.y, max.x - min.x, max.y - min.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_model::LanePatternBuilder;
+
+    #[test]
+    fn test_projection_corners() {
+        let proj = MinimapProjection::new(
+            Rect::new(-500.0, -200.0, 1000.0, 400.0),
+            Rect::new(10.0, 20.0, 250.0, 100.0),
+        );
+
+        assert_eq!(proj.project(vec2!(-500.0, -200.0)), vec2!(10.0, 20.0));
+        assert_eq!(proj.project(vec2!(500.0, -200.0)), vec2!(260.0, 20.0));
+        assert_eq!(proj.project(vec2!(-500.0, 200.0)), vec2!(10.0, 120.0));
+        assert_eq!(proj.project(vec2!(500.0, 200.0)), vec2!(260.0, 120.0));
+        assert_eq!(proj.project(vec2!(0.0, 0.0)), vec2!(135.0, 70.0));
+    }
+
+    #[test]
+    fn test_projection_flat_world() {
+        let proj = MinimapProjection::new(
+            Rect::new(0.0, 50.0, 200.0, 0.0),
+            Rect::new(10.0, 20.0, 250.0, 100.0),
+        );
+
+        let p = proj.project(vec2!(100.0, 50.0));
+        assert!(p.x.is_finite() && p.y.is_finite());
+        assert_eq!(p, vec2!(135.0, 20.0));
+    }
+
+    #[test]
+    fn test_map_bounds() {
+        let mut map = Map::empty();
+        assert!(map_bounds(&map).is_none());
+
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(300.0, 100.0));
+        map.connect(a, b, &LanePatternBuilder::new().build());
+
+        let bounds = map_bounds(&map).unwrap();
+        for lane in map.lanes().values() {
+            for &p in lane.points.iter() {
+                assert!(bounds.contains(p));
+            }
+        }
+        assert!(bounds.w > 200.0 && bounds.h > 50.0);
+    }
+}
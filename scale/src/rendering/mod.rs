@@ -1,4 +1,6 @@
 pub mod assets;
 pub mod colors;
 pub mod meshrender_component;
+pub mod minimap;
+pub mod shape_batch;
 pub use colors::*;
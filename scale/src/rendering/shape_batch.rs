@@ -0,0 +1,171 @@
+use crate::geometry::Vec2;
+use crate::rendering::Color;
+use std::collections::HashMap;
+
+/// Signature of a shape: two shapes with the same key only differ by where they are drawn,
+/// so they can share one tesselation and one vertex buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShapeKey {
+    Circle {
+        radius: u32,
+        color: [u32; 4],
+        filled: bool,
+    },
+    Rect {
+        width: u32,
+        height: u32,
+        color: [u32; 4],
+        filled: bool,
+    },
+}
+
+fn color_bits(c: Color) -> [u32; 4] {
+    [c.r.to_bits(), c.g.to_bits(), c.b.to_bits(), c.a.to_bits()]
+}
+
+fn bits_color(c: [u32; 4]) -> Color {
+    Color {
+        r: f32::from_bits(c[0]),
+        g: f32::from_bits(c[1]),
+        b: f32::from_bits(c[2]),
+        a: f32::from_bits(c[3]),
+    }
+}
+
+impl ShapeKey {
+    pub fn circle(radius: f32, color: Color, filled: bool) -> Self {
+        ShapeKey::Circle {
+            radius: radius.to_bits(),
+            color: color_bits(color),
+            filled,
+        }
+    }
+
+    pub fn rect(width: f32, height: f32, color: Color, filled: bool) -> Self {
+        ShapeKey::Rect {
+            width: width.to_bits(),
+            height: height.to_bits(),
+            color: color_bits(color),
+            filled,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match *self {
+            ShapeKey::Circle { color, .. } | ShapeKey::Rect { color, .. } => bits_color(color),
+        }
+    }
+
+    pub fn filled(&self) -> bool {
+        match *self {
+            ShapeKey::Circle { filled, .. } | ShapeKey::Rect { filled, .. } => filled,
+        }
+    }
+}
+
+/// Where one shape of a batch is drawn
+#[derive(Debug, Clone, Copy)]
+pub struct ShapeInstance {
+    pub pos: Vec2,
+    /// Unit vector the shape is rotated to, ignored by circles
+    pub dir: Vec2,
+}
+
+#[derive(Debug)]
+pub struct ShapeBatch {
+    /// Index of the shape in its MeshRender: shapes drawn later in a MeshRender stay on top
+    pub depth: usize,
+    pub key: ShapeKey,
+    pub instances: Vec<ShapeInstance>,
+}
+
+/// Groups the shapes pushed during a frame by signature, so that identical meshes are
+/// flushed together instead of one at a time.
+#[derive(Default)]
+pub struct ShapeBatches {
+    index: HashMap<(usize, ShapeKey), usize>,
+    batches: Vec<ShapeBatch>,
+}
+
+impl ShapeBatches {
+    pub fn push(&mut self, depth: usize, key: ShapeKey, instance: ShapeInstance) {
+        let batches = &mut self.batches;
+        let i = *self.index.entry((depth, key)).or_insert_with(|| {
+            batches.push(ShapeBatch {
+                depth,
+                key,
+                instances: vec![],
+            });
+            batches.len() - 1
+        });
+        self.batches[i].instances.push(instance);
+    }
+
+    /// Number of batches, each one is a single shape tesselation
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.batches.iter().map(|b| b.instances.len()).sum()
+    }
+
+    /// Takes all the batches in the order they must be drawn: by depth, then in the order
+    /// they were first pushed
+    pub fn drain(&mut self) -> Vec<ShapeBatch> {
+        self.index.clear();
+        let mut batches = std::mem::take(&mut self.batches);
+        batches.sort_by_key(|b| b.depth);
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn car(batches: &mut ShapeBatches, pos: Vec2, body: Color) {
+        let instance = ShapeInstance {
+            pos,
+            dir: vec2!(1.0, 0.0),
+        };
+        batches.push(0, ShapeKey::rect(4.5, 2.0, body, true), instance);
+        batches.push(1, ShapeKey::rect(0.4, 1.8, Color::BLACK, true), instance);
+        batches.push(2, ShapeKey::rect(1.0, 1.6, Color::BLACK, true), instance);
+    }
+
+    #[test]
+    fn test_identical_cars_share_batches() {
+        let mut batches = ShapeBatches::default();
+        for i in 0..1000 {
+            car(&mut batches, vec2!(i as f32 * 5.0, 0.0), Color::RED);
+        }
+
+        // Drawn one at a time, each shape would be its own tesselation
+        assert_eq!(batches.instance_count(), 3000);
+        assert_eq!(batches.len(), 3);
+
+        let drained = batches.drain();
+        assert!(drained.iter().all(|b| b.instances.len() == 1000));
+        assert!(batches.is_empty());
+    }
+
+    #[test]
+    fn test_batches_keep_depth_order() {
+        let mut batches = ShapeBatches::default();
+        car(&mut batches, vec2!(0.0, 0.0), Color::RED);
+        car(&mut batches, vec2!(5.0, 0.0), Color::BLUE);
+
+        let drained = batches.drain();
+        assert_eq!(drained.len(), 4);
+
+        // Both bodies are drawn before any window, whatever the order they were pushed in
+        assert_eq!(drained[0].key.color(), Color::RED);
+        assert_eq!(drained[1].key.color(), Color::BLUE);
+        assert!(drained.windows(2).all(|w| w[0].depth <= w[1].depth));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::map_model::Map;
+use crate::physics::Transform;
+use crate::saveload;
+use crate::simulation::Simulation;
+use crate::vehicles::{make_random_vehicle, make_vehicle_entity, VehicleComponent, VehicleKind};
+use serde::{Deserialize, Serialize};
+use specs::{Entity, WorldExt};
+use std::path::Path;
+
+/// Something done to the simulation from the outside. Spawns keep the spawned vehicle itself
+/// rather than a request for a random one, so that playing them back doesn't depend on the
+/// global random generator.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ReplayInput {
+    Step(f32),
+    SpawnVehicle(Transform, VehicleComponent),
+}
+
+/// Inputs of a run in the order they were applied, starting from a fresh simulation
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub inputs: Vec<ReplayInput>,
+}
+
+impl Replay {
+    pub fn save(&self, path: impl AsRef<Path>) -> bincode::Result<()> {
+        saveload::save_file(path, self)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> bincode::Result<Self> {
+        saveload::load_file(path)
+    }
+}
+
+/// Applies inputs to a simulation while keeping them in a replay.
+/// Vehicles must only be spawned through it, the spawn system isn't recorded.
+#[derive(Default)]
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn step(&mut self, sim: &mut Simulation, delta: f32) {
+        self.replay.inputs.push(ReplayInput::Step(delta));
+        sim.step(delta);
+    }
+
+    pub fn spawn_vehicle(
+        &mut self,
+        sim: &mut Simulation,
+        trans: Transform,
+        vehicle: VehicleComponent,
+    ) -> Entity {
+        self.replay
+            .inputs
+            .push(ReplayInput::SpawnVehicle(trans.clone(), vehicle.clone()));
+        make_vehicle_entity(&mut sim.world, trans, vehicle)
+    }
+
+    /// Same as spawn_new_vehicle, but recorded
+    pub fn spawn_random_vehicle(&mut self, sim: &mut Simulation) -> Option<Entity> {
+        let (trans, vehicle) =
+            make_random_vehicle(&sim.world.read_resource::<Map>(), VehicleKind::Car)?;
+        Some(self.spawn_vehicle(sim, trans, vehicle))
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+/// Plays a replay back against a simulation, which should be as fresh as the recorded one was
+pub struct Player {
+    replay: Replay,
+    cursor: usize,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, cursor: 0 }
+    }
+
+    /// Applies the next input, returns false once every input was played
+    pub fn play_next(&mut self, sim: &mut Simulation) -> bool {
+        let input = match self.replay.inputs.get(self.cursor) {
+            Some(x) => x.clone(),
+            None => return false,
+        };
+        self.cursor += 1;
+
+        match input {
+            ReplayInput::Step(delta) => sim.step(delta),
+            ReplayInput::SpawnVehicle(trans, vehicle) => {
+                make_vehicle_entity(&mut sim.world, trans, vehicle);
+            }
+        }
+        true
+    }
+
+    pub fn play_all(&mut self, sim: &mut Simulation) {
+        while self.play_next(sim) {}
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.cursor >= self.replay.inputs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_interaction::TIME_STEP;
+    use crate::geometry::Vec2;
+    use specs::Join;
+
+    fn trajectories(sim: &Simulation) -> Vec<(Entity, Vec2, Vec2)> {
+        (
+            &sim.world.entities(),
+            &sim.transforms(),
+            &sim.world.read_storage::<VehicleComponent>(),
+        )
+            .join()
+            .map(|(e, trans, _)| (e, trans.position(), trans.direction()))
+            .collect()
+    }
+
+    #[test]
+    fn test_replay_reproduces_run() {
+        let mut sim = Simulation::new();
+        let mut recorder = Recorder::new();
+
+        for _ in 0..20 {
+            recorder.spawn_random_vehicle(&mut sim);
+        }
+        for i in 0..500 {
+            if i % 50 == 0 {
+                recorder.spawn_random_vehicle(&mut sim);
+            }
+            recorder.step(&mut sim, TIME_STEP as f32);
+        }
+        let replay = recorder.finish();
+
+        let path = std::env::temp_dir().join("scale_test_replay.bc");
+        replay.save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let mut played = Simulation::new();
+        let mut player = Player::new(replay);
+        player.play_all(&mut played);
+        assert!(player.is_done());
+
+        assert_eq!(played.time().time, sim.time().time);
+
+        let expected = trajectories(&sim);
+        assert!(!expected.is_empty());
+        assert_eq!(trajectories(&played), expected);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of everything saved to disk. bincode is not self describing, a field added or
+/// removed anywhere makes older saves unreadable, so this must be bumped with any change to
+/// a saved type.
+pub const SAVE_VERSION: u32 = 1;
+
+const MAGIC: [u8; 4] = *b"SCAL";
+
+fn format_error(msg: String) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(msg))
+}
+
+/// Writes the header then the value
+pub fn write<T: Serialize>(mut w: impl Write, value: &T) -> bincode::Result<()> {
+    bincode::serialize_into(&mut w, &(MAGIC, SAVE_VERSION))?;
+    bincode::serialize_into(w, value)
+}
+
+/// Reads a value written by write, failing on saves from another version
+pub fn read<T: DeserializeOwned>(mut r: impl Read) -> bincode::Result<T> {
+    let (magic, version): ([u8; 4], u32) = bincode::deserialize_from(&mut r)
+        .map_err(|e| format_error(format!("not a save file: {}", e)))?;
+    if magic != MAGIC {
+        return Err(format_error("not a save file".to_string()));
+    }
+    if version != SAVE_VERSION {
+        return Err(format_error(format!(
+            "save from version {}, expected version {}",
+            version, SAVE_VERSION
+        )));
+    }
+    bincode::deserialize_from(r)
+}
+
+pub fn save_file<T: Serialize>(path: impl AsRef<Path>, value: &T) -> bincode::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write(&mut w, value)?;
+    w.flush()?;
+    Ok(())
+}
+
+pub fn load_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> bincode::Result<T> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Loads a save if there is one. A save that can't be read is moved next to itself with a
+/// .old extension, so that saving again doesn't overwrite it, and the error is returned.
+pub fn load_or_set_aside<T: DeserializeOwned>(
+    path: impl AsRef<Path>,
+) -> bincode::Result<Option<T>> {
+    let path = path.as_ref();
+    let err = match load_file(path) {
+        Ok(x) => return Ok(Some(x)),
+        Err(e) => e,
+    };
+
+    if let bincode::ErrorKind::Io(io) = &*err {
+        if io.kind() == ErrorKind::NotFound {
+            return Ok(None);
+        }
+    }
+
+    let mut old = PathBuf::from(path).into_os_string();
+    old.push(".old");
+    let moved = match std::fs::rename(path, &old) {
+        Ok(()) => format!("moved to {}", Path::new(&old).display()),
+        Err(e) => format!("could not be moved away: {}", e),
+    };
+    Err(format_error(format!(
+        "{} can't be read ({}), {}",
+        path.display(),
+        err,
+        moved
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut bytes = vec![];
+        write(&mut bytes, &(1u32, vec![2.0f32, 3.0])).unwrap();
+        let back: (u32, Vec<f32>) = read(&bytes[..]).unwrap();
+        assert_eq!(back, (1, vec![2.0, 3.0]));
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let mut bytes = vec![];
+        bincode::serialize_into(&mut bytes, &(MAGIC, SAVE_VERSION + 1)).unwrap();
+        bincode::serialize_into(&mut bytes, &1u32).unwrap();
+        assert!(read::<u32>(&bytes[..]).is_err());
+
+        // Saves from before the header
+        let bytes = bincode::serialize(&(1u32, 2u32)).unwrap();
+        assert!(read::<(u32, u32)>(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_unreadable_save_is_set_aside() {
+        let dir = std::env::temp_dir().join("scale_test_set_aside");
+        let _ = std::fs::create_dir_all(&dir);
+        let path = dir.join("map.bc");
+        let old = dir.join("map.bc.old");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&old);
+
+        assert!(load_or_set_aside::<u32>(&path).unwrap().is_none());
+
+        std::fs::write(&path, b"garbage").unwrap();
+        assert!(load_or_set_aside::<u32>(&path).is_err());
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&old).unwrap(), b"garbage");
+
+        save_file(&path, &7u32).unwrap();
+        assert_eq!(load_or_set_aside::<u32>(&path).unwrap(), Some(7));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::engine_interaction::TimeInfo;
+use crate::interaction::SelectableSystem;
+use crate::physics::{Collider, CollisionWorld, Kinematics, Transform};
+use crate::saveload;
+use crate::vehicles::{
+    delete_vehicle_entity, make_parked_vehicle_entity, make_vehicle_entity, ParkedVehicle,
+    VehicleComponent,
+};
+use serde::{Deserialize, Serialize};
+use specs::{Dispatcher, Entity, Join, ReadStorage, RunNow, World, WorldExt};
+use std::path::Path;
+
+/// Snapshot of a running simulation. Colliders are handles into the collision world that
+/// can't be serialized, so they are recreated on load.
+#[derive(Serialize, Deserialize)]
+struct SimulationState {
+    time: TimeInfo,
+    vehicles: Vec<(Transform, Kinematics, VehicleComponent)>,
+    parked: Vec<(Transform, ParkedVehicle)>,
+}
+
+/// Owns the world and the systems of the simulation, so that it can be run without any renderer.
+pub struct Simulation<'a> {
+    pub world: World,
+    pub dispatch: Dispatcher<'a, 'a>,
+}
+
+impl<'a> Default for Simulation<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Simulation<'a> {
+    /// Same as load, ignoring the saves that couldn't be read
+    pub fn new() -> Self {
+        Self::load().0
+    }
+
+    /// Sets up the simulation from the saves on disk, along with the errors of the saves that
+    /// couldn't be read. Those were set aside and replaced by a new map or no vehicles.
+    pub fn load() -> (Self, Vec<bincode::Error>) {
+        let mut world = World::new();
+        let (dispatch, load_errors) = crate::setup(&mut world);
+        (Self { world, dispatch }, load_errors)
+    }
+
+    /// Advances the simulation by delta seconds, running all the systems once. Does nothing
+    /// while paused.
+    pub fn step(&mut self, delta: f32) {
+        {
+            let mut time = self.world.write_resource::<TimeInfo>();
+            if time.paused {
+                return;
+            }
+            time.advance(delta as f64);
+        }
+
+        self.dispatch.run_now(&self.world);
+        self.world.maintain();
+    }
+
+    /// Runs the systems reacting to the mouse without advancing time, so that entities can
+    /// still be selected on frames where no step runs, e.g. while paused.
+    pub fn interact(&mut self) {
+        SelectableSystem.run_now(&self.world);
+    }
+
+    pub fn time(&self) -> TimeInfo {
+        *self.world.read_resource::<TimeInfo>()
+    }
+
+    pub fn transforms(&self) -> ReadStorage<Transform> {
+        self.world.read_storage::<Transform>()
+    }
+
+    pub fn vehicles(&self) -> ReadStorage<VehicleComponent> {
+        self.world.read_storage::<VehicleComponent>()
+    }
+
+    /// Saves the time and every vehicle, moving or parked. The map is saved separately.
+    pub fn save_state(&self, path: impl AsRef<Path>) -> bincode::Result<()> {
+        let transforms = self.transforms();
+
+        let state = SimulationState {
+            time: self.time(),
+            vehicles: (
+                &transforms,
+                &self.world.read_storage::<Kinematics>(),
+                &self.vehicles(),
+            )
+                .join()
+                .map(|(trans, kin, vehicle)| (trans.clone(), kin.clone(), vehicle.clone()))
+                .collect(),
+            parked: (&transforms, &self.world.read_storage::<ParkedVehicle>())
+                .join()
+                .map(|(trans, parked)| (trans.clone(), parked.clone()))
+                .collect(),
+        };
+
+        saveload::save_file(path, &state)
+    }
+
+    /// Replaces the time and all the vehicles by the ones saved with save_state
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> bincode::Result<()> {
+        let state: SimulationState = saveload::load_file(path)?;
+
+        let to_delete: Vec<Entity> = (&self.world.entities(), &self.vehicles())
+            .join()
+            .map(|(e, _)| e)
+            .chain(
+                (
+                    &self.world.entities(),
+                    &self.world.read_storage::<ParkedVehicle>(),
+                )
+                    .join()
+                    .map(|(e, _)| e),
+            )
+            .collect();
+
+        for e in to_delete {
+            delete_vehicle_entity(&mut self.world, e);
+        }
+        self.world.maintain();
+
+        *self.world.write_resource::<TimeInfo>() = state.time;
+
+        for (trans, kin, vehicle) in state.vehicles {
+            let e = make_vehicle_entity(&mut self.world, trans, vehicle);
+
+            let handle = self.world.read_storage::<Collider>().get(e).unwrap().0;
+            self.world
+                .write_resource::<CollisionWorld>()
+                .get_obj_mut(handle)
+                .speed = kin.velocity.magnitude();
+
+            self.world
+                .write_storage::<Kinematics>()
+                .insert(e, kin)
+                .unwrap();
+        }
+
+        for (trans, parked) in state.parked {
+            make_parked_vehicle_entity(&mut self.world, trans, parked);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine_interaction::TIME_STEP;
+    use crate::geometry::Vec2;
+    use crate::vehicles::spawn_new_vehicle;
+    use crate::vehicles::systems::DecisionConfig;
+    use cgmath::MetricSpace;
+
+    #[test]
+    fn test_headless_run_moves_vehicles() {
+        let mut sim = Simulation::new();
+
+        for _ in 0..20 {
+            spawn_new_vehicle(&mut sim.world);
+        }
+
+        let start: Vec<(Entity, Vec2)> =
+            (&sim.world.entities(), &sim.transforms(), &sim.vehicles())
+                .join()
+                .map(|(e, trans, _)| (e, trans.position()))
+                .collect();
+        assert!(!start.is_empty());
+
+        for _ in 0..1000 {
+            sim.step(TIME_STEP as f32);
+        }
+
+        assert!((sim.time().time - 1000.0 * TIME_STEP).abs() < 1e-3);
+
+        let transforms = sim.transforms();
+        let moved = start
+            .iter()
+            .filter(|(e, _)| sim.world.is_alive(*e))
+            .filter(|(e, pos)| transforms.get(*e).unwrap().position().distance(*pos) > 1.0)
+            .count();
+
+        assert!(moved > 0);
+    }
+
+    #[test]
+    fn test_pause_while_dragging() {
+        use crate::engine_interaction::{MouseButton, MouseInfo};
+        use crate::interaction::{Movable, SelectedEntity};
+        use crate::physics::{PhysicsGroup, PhysicsObject};
+        use specs::Builder;
+
+        let mut sim = Simulation::new();
+        let pos = vec2!(0.0, 0.0);
+        let h = sim.world.write_resource::<CollisionWorld>().insert_masked(
+            pos,
+            PhysicsObject {
+                radius: 0.3,
+                group: PhysicsGroup::Pedestrians,
+                ..Default::default()
+            },
+            PhysicsGroup::Pedestrians.mask(),
+        );
+        let e = sim
+            .world
+            .create_entity()
+            .with(Transform::new(pos))
+            .with(Kinematics::from_mass(80.0))
+            .with(Movable)
+            .with(Collider(h))
+            .build();
+        sim.world.write_resource::<SelectedEntity>().e = Some(e);
+
+        let set_mouse = |sim: &mut Simulation, unprojected: Vec2, held: bool| {
+            let mut mouse = sim.world.write_resource::<MouseInfo>();
+            mouse.unprojected = unprojected;
+            mouse.buttons.clear();
+            if held {
+                mouse.buttons.insert(MouseButton::Left);
+            }
+        };
+
+        set_mouse(&mut sim, pos, true);
+        sim.step(TIME_STEP as f32);
+        set_mouse(&mut sim, vec2!(1.0, 0.0), true);
+        sim.step(TIME_STEP as f32);
+
+        sim.world.write_resource::<TimeInfo>().paused = true;
+        let time = sim.time().time;
+        let dragged = sim.transforms().get(e).unwrap().position();
+
+        set_mouse(&mut sim, vec2!(5.0, 0.0), false);
+        sim.step(TIME_STEP as f32);
+        assert_eq!(sim.time().time, time);
+        assert_eq!(sim.transforms().get(e).unwrap().position(), dragged);
+
+        sim.world.write_resource::<TimeInfo>().paused = false;
+        for _ in 0..10 {
+            sim.step(TIME_STEP as f32);
+        }
+
+        let kin = sim.world.read_storage::<Kinematics>();
+        let velocity = kin.get(e).unwrap().velocity;
+        assert!(velocity.x.is_finite() && velocity.y.is_finite());
+        let pos = sim.transforms().get(e).unwrap().position();
+        assert!(pos.x.is_finite() && pos.y.is_finite());
+    }
+
+    #[test]
+    fn test_select_while_paused() {
+        use crate::engine_interaction::{MouseButton, MouseInfo};
+        use crate::interaction::{Selectable, SelectedEntity};
+        use specs::Builder;
+
+        let mut sim = Simulation::new();
+        let pos = vec2!(3.0, 4.0);
+        let e = sim
+            .world
+            .create_entity()
+            .with(Transform::new(pos))
+            .with(Selectable::default())
+            .build();
+
+        sim.world.write_resource::<TimeInfo>().paused = true;
+        {
+            let mut mouse = sim.world.write_resource::<MouseInfo>();
+            mouse.unprojected = pos;
+            mouse.just_pressed.insert(MouseButton::Left);
+        }
+        sim.step(TIME_STEP as f32);
+        assert_eq!(sim.world.read_resource::<SelectedEntity>().e, None);
+
+        sim.interact();
+        assert_eq!(sim.world.read_resource::<SelectedEntity>().e, Some(e));
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        fn positions(sim: &Simulation) -> Vec<Vec2> {
+            let mut pos: Vec<Vec2> = (&sim.transforms(), &sim.vehicles())
+                .join()
+                .map(|(trans, _)| trans.position())
+                .collect();
+            pos.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+            pos
+        }
+
+        let mut sim = Simulation::new();
+        sim.world.write_resource::<DecisionConfig>().sequential = true;
+
+        for _ in 0..20 {
+            spawn_new_vehicle(&mut sim.world);
+        }
+        for _ in 0..30 {
+            sim.step(TIME_STEP as f32);
+        }
+
+        let path = std::env::temp_dir().join("scale_test_simulation_state.bc");
+        sim.save_state(&path).unwrap();
+
+        let mut loaded = Simulation::new();
+        loaded.world.write_resource::<DecisionConfig>().sequential = true;
+        loaded.load_state(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.time().time, sim.time().time);
+        assert_eq!(positions(&loaded), positions(&sim));
+
+        sim.step(TIME_STEP as f32);
+        loaded.step(TIME_STEP as f32);
+
+        let expected = positions(&sim);
+        let got = positions(&loaded);
+        assert_eq!(got.len(), expected.len());
+        for (a, b) in got.iter().zip(expected.iter()) {
+            assert!(a.distance(*b) < 1e-3);
+        }
+    }
+}
@@ -36,11 +36,16 @@ where
 
 pub trait Choose<'a> {
     type Output;
+    type Item: 'a;
     fn choose(&'a self) -> Self::Output;
+
+    /// All the elements in a random order, driven by the deterministic RNG
+    fn shuffled(&'a self) -> Vec<&'a Self::Item>;
 }
 
 impl<'a, T: 'a> Choose<'a> for Vec<T> {
     type Output = Option<&'a T>;
+    type Item = T;
 
     fn choose(&'a self) -> Self::Output {
         if self.is_empty() {
@@ -51,6 +56,66 @@ impl<'a, T: 'a> Choose<'a> for Vec<T> {
             Some(&self[ix])
         }
     }
+
+    fn shuffled(&'a self) -> Vec<&'a T> {
+        let mut v: Vec<&T> = self.iter().collect();
+        shuffle(&mut v, &mut *RAND_STATE.lock().unwrap());
+        v
+    }
+}
+
+/// Fisher-Yates shuffle, the same rng state always gives the same permutation
+fn shuffle<T>(v: &mut [T], rng: &mut impl Rng) {
+    for i in (1..v.len()).rev() {
+        let j = rng.gen_range(0, i + 1);
+        v.swap(i, j);
+    }
+}
+
+/// An item picked at random in proportion to its weight, negative weights count as zero
+pub fn choose_weighted<T>(items: &[(T, f32)]) -> Option<&T> {
+    pick_weighted(items, rand_det())
+}
+
+/// The item for a number in [0, 1), each item covering a part of it proportional to its weight
+pub fn pick_weighted<T>(items: &[(T, f32)], r: f32) -> Option<&T> {
+    let total: f32 = items.iter().map(|x| x.1.max(0.0)).sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let target = r * total;
+    let mut acc = 0.0;
+    for (item, w) in items.iter().filter(|x| x.1 > 0.0) {
+        acc += w;
+        if target < acc {
+            return Some(item);
+        }
+    }
+    items.iter().rev().find(|x| x.1 > 0.0).map(|x| &x.0)
+}
+
+/// 64 bit FNV-1a. Unlike DefaultHasher, its algorithm and keys are fixed, so the hashes it
+/// gives stay the same between runs, platforms and versions of the standard library.
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::hash::Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
 }
 
 pub trait Restrict {
@@ -68,3 +133,97 @@ impl<T: PartialOrd> Restrict for T {
         }
     }
 }
+
+/// Helpers on top of restrict to express normalized values without dividing by hand
+pub trait Remap: Sized {
+    /// Restricts the value to [0, 1]
+    fn clamp01(self) -> Self;
+
+    /// Maps the value linearly from [in_min, in_max] to [out_min, out_max],
+    /// values outside of the input range are restricted to it first
+    fn remap(self, in_min: Self, in_max: Self, out_min: Self, out_max: Self) -> Self;
+}
+
+macro_rules! impl_remap {
+    ($t: ty) => {
+        impl Remap for $t {
+            fn clamp01(self) -> Self {
+                self.restrict(0.0, 1.0)
+            }
+
+            fn remap(self, in_min: Self, in_max: Self, out_min: Self, out_max: Self) -> Self {
+                let t = ((self - in_min) / (in_max - in_min)).clamp01();
+                out_min + t * (out_max - out_min)
+            }
+        }
+    };
+}
+
+impl_remap!(f32);
+impl_remap!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp01() {
+        assert_eq!((-0.5f32).clamp01(), 0.0);
+        assert_eq!(0.3f32.clamp01(), 0.3);
+        assert_eq!(2.0f64.clamp01(), 1.0);
+    }
+
+    #[test]
+    fn test_remap() {
+        assert_eq!(5.0f32.remap(0.0, 10.0, 100.0, 200.0), 150.0);
+        assert!((5.0f32.remap(1.0, 9.0, 1.0 / 9.0, 1.0) - 5.0 / 9.0).abs() < 1e-6);
+        assert_eq!((-3.0f32).remap(0.0, 10.0, 100.0, 200.0), 100.0);
+        assert_eq!(4.0f64.remap(0.0, 2.0, 1.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_shuffle_deterministic() {
+        let shuffle_seeded = |seed| {
+            let mut v: Vec<u32> = (0..20).collect();
+            shuffle(&mut v, &mut rand::rngs::SmallRng::seed_from_u64(seed));
+            v
+        };
+
+        let a = shuffle_seeded(42);
+        assert_eq!(a, shuffle_seeded(42));
+        assert_ne!(a, (0..20).collect::<Vec<u32>>());
+        assert_ne!(a, shuffle_seeded(43));
+
+        let v: Vec<u32> = (0..20).collect();
+        let mut s: Vec<u32> = v.shuffled().into_iter().copied().collect();
+        s.sort();
+        assert_eq!(s, v);
+        assert!(Vec::<u32>::new().shuffled().is_empty());
+    }
+
+    #[test]
+    fn test_fnv_is_stable() {
+        use std::hash::Hasher;
+
+        let hash = |bytes: &[u8]| {
+            let mut h = FnvHasher::default();
+            h.write(bytes);
+            h.finish()
+        };
+        // Reference values of FNV-1a 64
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_pick_weighted() {
+        let items = [('a', 1.0), ('b', 0.0), ('c', -2.0), ('d', 3.0)];
+        assert_eq!(pick_weighted(&items, 0.0), Some(&'a'));
+        assert_eq!(pick_weighted(&items, 0.24), Some(&'a'));
+        assert_eq!(pick_weighted(&items, 0.26), Some(&'d'));
+        assert_eq!(pick_weighted(&items, 0.9999), Some(&'d'));
+        assert_eq!(pick_weighted(&[('a', 0.0)], 0.5), None);
+        assert_eq!(pick_weighted::<char>(&[], 0.5), None);
+    }
+}
@@ -1,24 +1,46 @@
 use crate::geometry::Vec2;
 use crate::gui::{InspectDragf, InspectVec2};
 use crate::interaction::Selectable;
-use crate::map_model::{Itinerary, LaneKind, Map, Traversable, TraverseDirection, TraverseKind};
+use crate::map_model::{
+    Itinerary, LaneID, LaneKind, Map, Traversable, TraverseDirection, TraverseKind, TurnID,
+};
 use crate::physics::{
-    Collider, CollisionWorld, Kinematics, PhysicsGroup, PhysicsObject, Transform,
+    remove_shape, Collider, CollisionWorld, Kinematics, PhysicsGroup, PhysicsObject, Transform,
 };
 use crate::rendering::assets::{AssetID, AssetRender};
 use crate::rendering::meshrender_component::{MeshRender, RectRender};
 use crate::rendering::Color;
-use crate::utils::rand_det;
+use crate::utils::{pick_weighted, rand_det};
+use crate::vehicles::systems::FRUSTRATION_THRESHOLD;
 use cgmath::InnerSpace;
 use imgui_inspect_derive::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use specs::{Builder, Entity, World, WorldExt};
 use specs::{Component, DenseVecStorage};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum VehicleKind {
     Car,
     Bus,
+    Motorcycle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlinkerState {
+    Left,
+    Right,
+    None,
+    Hazard,
+}
+
+/// acos(0.7), obstacles less than about 45° off the vehicle's heading are in front of it
+pub const DEFAULT_SENSOR_ANGLE: f32 = 0.795_398_8;
+pub const DEFAULT_SENSOR_WIDTH: f32 = 4.0;
+
+fn default_vehicle_color() -> Color {
+    VehicleKind::Car.palette()[0].0
 }
 
 #[derive(Component, Debug, Inspect, Clone, Serialize, Deserialize)]
@@ -30,63 +52,323 @@ pub struct VehicleComponent {
     pub desired_dir: Vec2,
     #[inspect(proxy_type = "InspectDragf")]
     pub ang_velocity: f32,
+    /// Longitudinal acceleration, its changes are limited by the kind's max jerk
+    #[inspect(proxy_type = "InspectDragf")]
+    pub acceleration: f32,
     #[inspect(proxy_type = "InspectDragf")]
     pub wait_time: f32,
+    /// Time spent blocked without moving, used to detect stuck vehicles
+    #[inspect(proxy_type = "InspectDragf")]
+    pub stuck_time: f32,
+    /// Set when approaching a merge while another vehicle has priority
+    pub merge_yield: bool,
+    /// Riding between two lanes past slow traffic, only motorcycles do it
+    pub lane_splitting: bool,
+    /// Passing slow traffic in the oncoming lane of a two-way road
+    pub overtaking: bool,
+    /// Turn to take at the end of the current lane, chosen in advance to signal it
+    #[inspect(skip = true)]
+    pub next_turn: Option<TurnID>,
+    pub blinker: BlinkerState,
+    /// Last bus stop served, so that buses only stop once
+    #[inspect(skip = true)]
+    pub last_stop: Option<LaneID>,
+    /// Half angle in radians of the cone in front of the vehicle in which obstacles are followed
+    #[inspect(proxy_type = "InspectDragf")]
+    pub sensor_angle: f32,
+    /// Lateral distance beyond which obstacles in the cone are ignored while on a lane
+    #[inspect(proxy_type = "InspectDragf")]
+    pub sensor_width: f32,
+    pub color: Color,
+    /// State of the vehicle's own random generator, so that its choices don't depend on the
+    /// other vehicles nor on the order they are updated in
+    #[inspect(skip = true)]
+    pub rng: u64,
+    /// Variation in [0, 1) of the vehicle's cruising speed and following distance, drawn
+    /// once from its seed so that identical vehicles don't drive in lockstep
+    #[inspect(proxy_type = "InspectDragf")]
+    pub jitter: f32,
+    /// Number of times in a row the vehicle had to wait behind an obstacle, reset once it
+    /// drives freely again
+    #[inspect(skip = true)]
+    pub frustration: u32,
+
+    pub kind: VehicleKind,
+}
+
+/// Lets code outside of the simulation drive a vehicle, e.g. a learned controller. When the
+/// vehicle has it, the decision system follows its commands instead of the vehicle's own
+/// decisions, and reports back what the vehicle senses.
+#[derive(Component, Debug, Clone)]
+pub struct VehicleControl {
+    /// Speed to reach, in m/s, limited by the acceleration and deceleration of the vehicle
+    pub desired_speed: f32,
+    /// Direction to turn towards, the current one is kept if it is zero
+    pub desired_dir: Vec2,
+    speed: f32,
+    heading: Vec2,
+    nearest_dist: Option<f32>,
+}
+
+impl VehicleControl {
+    pub fn new(desired_speed: f32, desired_dir: Vec2) -> Self {
+        VehicleControl {
+            desired_speed,
+            desired_dir,
+            speed: 0.0,
+            heading: vec2!(1.0, 0.0),
+            nearest_dist: None,
+        }
+    }
+
+    /// Speed of the vehicle along its heading at the last decision, negative when reversing
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Direction the vehicle was facing at the last decision
+    pub fn heading(&self) -> Vec2 {
+        self.heading
+    }
+
+    /// Distance between the vehicle and the closest vehicle or pedestrian at the last
+    /// decision, None if there were none within the sensor range
+    pub fn nearest_neighbor_dist(&self) -> Option<f32> {
+        self.nearest_dist
+    }
+
+    pub(crate) fn record(&mut self, speed: f32, heading: Vec2, nearest_dist: Option<f32>) {
+        self.speed = speed;
+        self.heading = heading;
+        self.nearest_dist = nearest_dist;
+    }
+}
 
+/// A vehicle standing still on a parking lane. It has no kinematics nor decision making,
+/// only a collider so that moving vehicles avoid it.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct ParkedVehicle {
     pub kind: VehicleKind,
+    pub lane: LaneID,
+    pub color: Color,
+}
+
+/// Physical characteristics of a kind of vehicle, lengths in m and times in s
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VehicleParams {
+    pub width: f32,
+    pub height: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    /// Maximum rate of change of the acceleration, in m/s³
+    pub max_jerk: f32,
+    pub min_turning_radius: f32,
+    pub cruising_speed: f32,
+    pub ang_acc: f32,
+    /// Room kept in front of pedestrians when standing still
+    pub pedestrian_margin: f32,
+    /// Time of travel at the current speed added to pedestrian_margin
+    pub pedestrian_margin_time: f32,
+}
+
+/// A vehicle parameter that is not a finite positive number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidVehicleParam {
+    pub name: &'static str,
+    pub value: f32,
+}
+
+impl std::fmt::Display for InvalidVehicleParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "vehicle {} must be positive, got {}",
+            self.name, self.value
+        )
+    }
+}
+
+impl VehicleParams {
+    /// Every parameter must be strictly positive, the physics divides by most of them
+    pub fn validate(&self) -> Result<(), InvalidVehicleParam> {
+        for &(name, value) in &[
+            ("width", self.width),
+            ("height", self.height),
+            ("acceleration", self.acceleration),
+            ("deceleration", self.deceleration),
+            ("max_jerk", self.max_jerk),
+            ("min_turning_radius", self.min_turning_radius),
+            ("cruising_speed", self.cruising_speed),
+            ("ang_acc", self.ang_acc),
+            ("pedestrian_margin", self.pedestrian_margin),
+            ("pedestrian_margin_time", self.pedestrian_margin_time),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(InvalidVehicleParam { name, value });
+            }
+        }
+        Ok(())
+    }
+
+    /// Room kept in front of pedestrians at the given speed, faster vehicles keep further away
+    pub fn pedestrian_margin(&self, speed: f32) -> f32 {
+        self.pedestrian_margin + speed.max(0.0) * self.pedestrian_margin_time
+    }
+}
+
+/// Parameters the simulation drives each kind of vehicle with, the defaults unless
+/// overridden at runtime. The colliders and meshes of vehicles already spawned keep the
+/// size they were made with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VehicleParamsTable {
+    params: [VehicleParams; 3],
+}
+
+impl Default for VehicleParamsTable {
+    fn default() -> Self {
+        Self {
+            params: [
+                VehicleKind::Car.default_params(),
+                VehicleKind::Bus.default_params(),
+                VehicleKind::Motorcycle.default_params(),
+            ],
+        }
+    }
+}
+
+impl VehicleParamsTable {
+    pub fn get(&self, kind: VehicleKind) -> &VehicleParams {
+        &self.params[kind as usize]
+    }
+
+    /// Overrides the parameters of every vehicle of this kind. Invalid parameters are
+    /// rejected and the current ones are kept.
+    pub fn set(
+        &mut self,
+        kind: VehicleKind,
+        params: VehicleParams,
+    ) -> Result<(), InvalidVehicleParam> {
+        params.validate()?;
+        self.params[kind as usize] = params;
+        Ok(())
+    }
 }
 
 impl VehicleKind {
-    pub fn width(self) -> f32 {
+    pub fn default_params(self) -> VehicleParams {
         match self {
-            VehicleKind::Car => 4.5,
-            VehicleKind::Bus => 9.0,
+            VehicleKind::Car => VehicleParams {
+                width: 4.5,
+                height: 2.0,
+                acceleration: 3.0,
+                deceleration: 9.0,
+                max_jerk: 20.0,
+                min_turning_radius: 3.0,
+                cruising_speed: 15.0,
+                ang_acc: 1.0,
+                pedestrian_margin: 1.0,
+                pedestrian_margin_time: 0.3,
+            },
+            VehicleKind::Bus => VehicleParams {
+                width: 9.0,
+                height: 2.0,
+                acceleration: 2.0,
+                deceleration: 9.0,
+                max_jerk: 10.0,
+                min_turning_radius: 5.0,
+                cruising_speed: 10.0,
+                ang_acc: 0.8,
+                pedestrian_margin: 1.0,
+                pedestrian_margin_time: 0.5,
+            },
+            VehicleKind::Motorcycle => VehicleParams {
+                width: 2.2,
+                height: 0.8,
+                acceleration: 4.0,
+                deceleration: 9.0,
+                max_jerk: 25.0,
+                min_turning_radius: 2.0,
+                cruising_speed: 15.0,
+                ang_acc: 1.5,
+                pedestrian_margin: 1.0,
+                pedestrian_margin_time: 0.3,
+            },
         }
     }
 
+    pub fn width(self) -> f32 {
+        self.default_params().width
+    }
+
     pub fn height(self) -> f32 {
-        match self {
-            VehicleKind::Car => 2.0,
-            VehicleKind::Bus => 2.0,
-        }
+        self.default_params().height
     }
 
     pub fn acceleration(self) -> f32 {
-        match self {
-            VehicleKind::Car => 3.0,
-            VehicleKind::Bus => 2.0,
-        }
+        self.default_params().acceleration
     }
 
     pub fn deceleration(self) -> f32 {
-        match self {
-            VehicleKind::Car => 9.0,
-            VehicleKind::Bus => 9.0,
-        }
+        self.default_params().deceleration
+    }
+
+    /// Maximum rate of change of the acceleration, in m/s³
+    pub fn max_jerk(self) -> f32 {
+        self.default_params().max_jerk
     }
 
     pub fn min_turning_radius(self) -> f32 {
+        self.default_params().min_turning_radius
+    }
+
+    /// Colors vehicles of this kind are painted with, with how common each one is
+    pub fn palette(self) -> Vec<(Color, f32)> {
         match self {
-            VehicleKind::Car => 3.0,
-            VehicleKind::Bus => 5.0,
+            VehicleKind::Car => vec![
+                (Color::from_hex(0x22_22_22), 0.22),  // Black
+                (Color::from_hex(0xff_ff_ff), 0.19),  // White
+                (Color::from_hex(0x66_66_66), 0.17),  // Gray
+                (Color::from_hex(0xb8_b8_b8), 0.14),  // Silver
+                (Color::from_hex(0x1a_3c_70), 0.1),   // Blue
+                (Color::from_hex(0xd8_22_00), 0.1),   // Red
+                (Color::from_hex(0x7c_4b_24), 0.02),  // Brown
+                (Color::from_hex(0xd4_c6_78), 0.015), // Gold
+                (Color::from_hex(0x72_cb_19), 0.015), // Green
+            ],
+            VehicleKind::Bus => vec![
+                (Color::ORANGE, 0.5),
+                (Color::from_hex(0xf2_c9_1d), 0.3), // Yellow
+                (Color::from_hex(0xb5_1f_1f), 0.2), // Red
+            ],
+            VehicleKind::Motorcycle => vec![
+                (Color::from_hex(0x22_22_22), 0.4), // Black
+                (Color::from_hex(0xd8_22_00), 0.3), // Red
+                (Color::from_hex(0x1a_3c_70), 0.2), // Blue
+                (Color::from_hex(0xff_ff_ff), 0.1), // White
+            ],
         }
     }
 
+    /// Picks a color from the palette according to its frequencies, always the same for a seed
+    pub fn pick_color(self, seed: u64) -> Color {
+        let palette = self.palette();
+        *pick_weighted(&palette, SmallRng::seed_from_u64(seed).gen()).unwrap()
+    }
+
     pub fn cruising_speed(self) -> f32 {
-        match self {
-            VehicleKind::Car => 15.0,
-            VehicleKind::Bus => 10.0,
-        }
+        self.default_params().cruising_speed
     }
 
     pub fn ang_acc(self) -> f32 {
-        match self {
-            VehicleKind::Car => 1.0,
-            VehicleKind::Bus => 0.8,
-        }
+        self.default_params().ang_acc
+    }
+
+    /// Whether the kind is narrow enough to ride between two lanes of slow traffic
+    pub fn can_split_lanes(self) -> bool {
+        matches!(self, VehicleKind::Motorcycle)
     }
 
-    pub fn build_mr(self, mr: &mut MeshRender) {
+    pub fn build_mr(self, color: Color, mr: &mut MeshRender) {
         let width = self.width();
         let height = self.height();
 
@@ -95,7 +377,7 @@ impl VehicleKind {
                 mr.add(RectRender {
                     width,
                     height,
-                    color: get_random_car_color(),
+                    color,
                     ..Default::default()
                 })
                 .add(RectRender {
@@ -149,102 +431,236 @@ impl VehicleKind {
                     ..Default::default()
                 });
             }
+            VehicleKind::Motorcycle => {
+                mr.add(RectRender {
+                    width,
+                    height,
+                    color,
+                    ..Default::default()
+                })
+                .add(RectRender {
+                    width: 0.6,
+                    height: 0.6,
+                    offset: [-0.2, 0.0].into(),
+                    color: Color::BLACK,
+                    ..Default::default()
+                });
+            }
         }
     }
 }
 
 pub fn spawn_new_vehicle(world: &mut World) {
-    let map = world.read_resource::<Map>();
+    let v = make_random_vehicle(&world.read_resource::<Map>(), VehicleKind::Car);
+    if let Some((trans, vehicle)) = v {
+        make_vehicle_entity(world, trans, vehicle);
+    }
+}
 
-    if let Some(lane) = map.get_random_lane(LaneKind::Driving) {
-        if let [a, b, ..] = lane.points.as_slice() {
-            let diff = b - a;
+/// A new vehicle of the kind following the itinerary, with a random color and seed
+fn new_random_vehicle(kind: VehicleKind, it: Itinerary) -> VehicleComponent {
+    VehicleComponent::builder(kind)
+        .with_itinerary(it)
+        .with_color(kind.pick_color(rand_det()))
+        .with_seed(rand_det())
+        .build()
+}
 
-            let mut pos = Transform::new(*a + rand_det::<f32>() * diff);
-            pos.set_direction(diff.normalize());
+/// Places a new vehicle at a random position on a random driving lane
+pub fn make_random_vehicle(map: &Map, kind: VehicleKind) -> Option<(Transform, VehicleComponent)> {
+    let lane = map.get_random_lane(LaneKind::Driving)?;
 
-            let mut it = Itinerary::default();
-            it.set_simple(
-                Traversable::new(TraverseKind::Lane(lane.id), TraverseDirection::Forward),
-                &map,
-            );
-            it.advance(&map);
+    if let [a, b, ..] = lane.points.as_slice() {
+        let diff = b - a;
+
+        let mut pos = Transform::new(*a + rand_det::<f32>() * diff);
+        pos.set_direction(diff.normalize());
+
+        let mut it = Itinerary::default();
+        it.set_simple(
+            Traversable::new(TraverseKind::Lane(lane.id), TraverseDirection::Forward),
+            map,
+        );
+        it.advance(map);
+
+        Some((pos, new_random_vehicle(kind, it)))
+    } else {
+        None
+    }
+}
+
+/// Places a new car at the given distance along a driving lane, heading along it
+pub fn make_vehicle_on_lane(
+    map: &Map,
+    lane: LaneID,
+    dist: f32,
+) -> Option<(Transform, VehicleComponent)> {
+    let l = map.lanes().get(lane)?;
+    let (pos, dir) = l.points.point_along(dist)?;
 
-            drop(map);
-            make_vehicle_entity(world, pos, VehicleComponent::new(it, VehicleKind::Car));
+    let mut trans = Transform::new(pos);
+    trans.set_direction(dir);
+
+    let mut it = Itinerary::default();
+    it.set_simple(
+        Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward),
+        map,
+    );
+    // Skip the lane points already behind the car
+    it.advance(map);
+    while let Some(p) = it.get_point() {
+        if it.remaining_points() <= 1 || (p - pos).dot(dir) > 0.0 {
+            break;
         }
+        it.advance(map);
     }
+
+    Some((trans, new_random_vehicle(VehicleKind::Car, it)))
 }
 
-pub fn make_vehicle_entity(
+/// Places a new vehicle at the start of the route, to drive it to its end
+pub fn make_vehicle_on_route(
+    map: &Map,
+    route: Vec<Traversable>,
+    kind: VehicleKind,
+) -> Option<(Transform, VehicleComponent)> {
+    let (pos, dir) = route.first()?.points(map).point_along(0.0)?;
+
+    let mut trans = Transform::new(pos);
+    trans.set_direction(dir);
+
+    let mut it = Itinerary::default();
+    it.set_route(route, map);
+    it.advance(map);
+
+    Some((trans, new_random_vehicle(kind, it)))
+}
+
+pub fn spawn_parked_vehicle(world: &mut World) {
+    let map = world.read_resource::<Map>();
+
+    let lane = unwrap_ret!(map.get_random_lane(LaneKind::Parking));
+
+    if let [a, b, ..] = lane.points.as_slice() {
+        let diff = b - a;
+
+        let mut trans = Transform::new(*a + rand_det::<f32>() * diff);
+        trans.set_direction(diff.normalize());
+
+        let parked = ParkedVehicle {
+            kind: VehicleKind::Car,
+            lane: lane.id,
+            color: get_random_car_color(),
+        };
+
+        drop(map);
+        make_parked_vehicle_entity(world, trans, parked);
+    }
+}
+
+pub fn make_parked_vehicle_entity(
     world: &mut World,
     trans: Transform,
-    vehicle: VehicleComponent,
+    parked: ParkedVehicle,
 ) -> Entity {
-    let mut mr = MeshRender::empty(3);
-    vehicle.kind.build_mr(&mut mr);
-
     let coworld = world.get_mut::<CollisionWorld>().unwrap();
-    let h = coworld.insert(
+    let h = coworld.insert_masked(
         trans.position(),
         PhysicsObject {
             dir: trans.direction(),
             speed: 0.0,
-            radius: vehicle.kind.width() / 2.0,
+            radius: parked.kind.width() / 2.0,
             group: PhysicsGroup::Vehicles,
+            owner: None,
         },
+        PhysicsGroup::Vehicles.mask(),
     );
 
-    world
+    let e = world
         .create_entity()
+        .with(AssetRender {
+            id: AssetID::CAR,
+            hide: false,
+            scale: parked.kind.width(),
+            tint: parked.color,
+        })
+        .with(trans)
+        .with(parked)
+        .with(Collider(h))
+        .with(Selectable::default())
+        .build();
+    world.write_resource::<CollisionWorld>().set_owner(h, e);
+    e
+}
+
+pub fn make_vehicle_entity(
+    world: &mut World,
+    trans: Transform,
+    vehicle: VehicleComponent,
+) -> Entity {
+    let collider = insert_vehicle_collider(
+        &mut world.write_resource::<CollisionWorld>(),
+        &trans,
+        vehicle.kind,
+    );
+    let h = collider.0;
+    let e = build_vehicle_entity(world.create_entity(), trans, vehicle, collider);
+    world.write_resource::<CollisionWorld>().set_owner(h, e);
+    e
+}
+
+pub fn insert_vehicle_collider(
+    coworld: &mut CollisionWorld,
+    trans: &Transform,
+    kind: VehicleKind,
+) -> Collider {
+    Collider(coworld.insert_masked(
+        trans.position(),
+        PhysicsObject {
+            dir: trans.direction(),
+            speed: 0.0,
+            radius: kind.width() / 2.0,
+            group: PhysicsGroup::Vehicles,
+            owner: None,
+        },
+        PhysicsGroup::Vehicles.mask(),
+    ))
+}
+
+/// Adds the vehicle components to any entity builder, so that vehicles can be created
+/// either directly from the world or lazily from a system.
+pub fn build_vehicle_entity(
+    builder: impl Builder,
+    trans: Transform,
+    vehicle: VehicleComponent,
+    collider: Collider,
+) -> Entity {
+    let mut mr = MeshRender::empty(3);
+    vehicle.kind.build_mr(vehicle.color, &mut mr);
+
+    builder
         //.with(mr)
         .with(AssetRender {
             id: AssetID::CAR,
             hide: false,
-            scale: 4.5,
-            tint: get_random_car_color(),
+            scale: vehicle.kind.width(),
+            tint: vehicle.color,
         })
         .with(trans)
         .with(Kinematics::from_mass(1000.0))
         .with(vehicle)
-        .with(Collider(h))
+        .with(collider)
         .with(Selectable::default())
         .build()
 }
 
 pub fn delete_vehicle_entity(world: &mut World, e: Entity) {
-    {
-        let handle = world.read_component::<Collider>().get(e).unwrap().0;
-        let mut coworld = world.write_resource::<CollisionWorld>();
-        coworld.remove(handle);
-    }
+    remove_shape(world, e);
     world.delete_entity(e).unwrap();
 }
 
 pub fn get_random_car_color() -> Color {
-    let car_colors: [(Color, f32); 9] = [
-        (Color::from_hex(0x22_22_22), 0.22),  // Black
-        (Color::from_hex(0xff_ff_ff), 0.19),  // White
-        (Color::from_hex(0x66_66_66), 0.17),  // Gray
-        (Color::from_hex(0xb8_b8_b8), 0.14),  // Silver
-        (Color::from_hex(0x1a_3c_70), 0.1),   // Blue
-        (Color::from_hex(0xd8_22_00), 0.1),   // Red
-        (Color::from_hex(0x7c_4b_24), 0.02),  // Brown
-        (Color::from_hex(0xd4_c6_78), 0.015), // Gold
-        (Color::from_hex(0x72_cb_19), 0.015), // Green
-    ];
-
-    let total: f32 = car_colors.iter().map(|x| x.1).sum();
-
-    let r = rand_det::<f32>() * total;
-    let mut partial = 0.0;
-    for (col, freq) in &car_colors {
-        partial += freq;
-        if partial >= r {
-            return *col;
-        }
-    }
-    unreachable!();
+    VehicleKind::Car.pick_color(rand_det())
 }
 
 impl Default for VehicleComponent {
@@ -254,7 +670,21 @@ impl Default for VehicleComponent {
             desired_speed: 0.0,
             desired_dir: vec2!(1.0, 0.0),
             wait_time: 0.0,
+            stuck_time: 0.0,
+            merge_yield: false,
+            lane_splitting: false,
+            overtaking: false,
+            next_turn: None,
+            blinker: BlinkerState::None,
+            last_stop: None,
+            sensor_angle: DEFAULT_SENSOR_ANGLE,
+            sensor_width: DEFAULT_SENSOR_WIDTH,
+            color: default_vehicle_color(),
+            rng: 0,
+            jitter: 0.0,
+            frustration: 0,
             ang_velocity: 0.0,
+            acceleration: 0.0,
             kind: VehicleKind::Car,
         }
     }
@@ -262,12 +692,301 @@ impl Default for VehicleComponent {
 
 impl VehicleComponent {
     pub fn new(itinerary: Itinerary, kind: VehicleKind) -> VehicleComponent {
-        Self {
-            itinerary,
-            kind,
-            ..Default::default()
+        Self::builder(kind).with_itinerary(itinerary).build()
+    }
+
+    /// Whether the vehicle has been blocked long enough to drive more aggressively
+    pub fn is_frustrated(&self) -> bool {
+        self.frustration >= FRUSTRATION_THRESHOLD
+    }
+
+    /// Uniform random number in [0, 1) drawn from the vehicle's own generator
+    pub fn next_random(&mut self) -> f32 {
+        let mut rng = SmallRng::seed_from_u64(self.rng);
+        self.rng = rng.gen();
+        rng.gen()
+    }
+
+    pub fn builder(kind: VehicleKind) -> VehicleComponentBuilder {
+        VehicleComponentBuilder {
+            vehicle: VehicleComponent {
+                kind,
+                color: kind.palette()[0].0,
+                ..Default::default()
+            },
         }
     }
 }
 
-enum_inspect_impl!(VehicleKind; VehicleKind::Car, VehicleKind::Bus);
+pub struct VehicleComponentBuilder {
+    vehicle: VehicleComponent,
+}
+
+impl VehicleComponentBuilder {
+    pub fn with_itinerary(&mut self, itinerary: Itinerary) -> &mut Self {
+        self.vehicle.itinerary = itinerary;
+        self
+    }
+
+    pub fn with_speed(&mut self, desired_speed: f32) -> &mut Self {
+        self.vehicle.desired_speed = desired_speed;
+        self
+    }
+
+    pub fn with_color(&mut self, color: Color) -> &mut Self {
+        self.vehicle.color = color;
+        self
+    }
+
+    /// Seeds the vehicle's random generator and draws its jitter, the same seed always gives
+    /// the same vehicle
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        self.vehicle.jitter = rng.gen();
+        self.vehicle.rng = rng.gen();
+        self
+    }
+
+    pub fn build(&self) -> VehicleComponent {
+        self.vehicle.clone()
+    }
+}
+
+enum_inspect_impl!(VehicleKind; VehicleKind::Car, VehicleKind::Bus, VehicleKind::Motorcycle);
+enum_inspect_impl!(BlinkerState; BlinkerState::Left, BlinkerState::Right, BlinkerState::None, BlinkerState::Hazard);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::gridstore::GridStore;
+
+    /// A world with the components of moving and parked vehicles registered and an empty
+    /// collision world
+    fn vehicle_world() -> World {
+        let mut world = World::new();
+        world.register::<AssetRender>();
+        world.register::<Transform>();
+        world.register::<Kinematics>();
+        world.register::<VehicleComponent>();
+        world.register::<ParkedVehicle>();
+        world.register::<Collider>();
+        world.register::<Selectable>();
+        let coworld: CollisionWorld = GridStore::new(50);
+        world.insert(coworld);
+        world
+    }
+
+    #[test]
+    fn test_deleted_vehicle_leaves_collision_world() {
+        let mut world = vehicle_world();
+
+        let vehicle = VehicleComponent::builder(VehicleKind::Car).build();
+        let e = make_vehicle_entity(&mut world, Transform::new(vec2!(5.0, 5.0)), vehicle.clone());
+        let other = make_vehicle_entity(&mut world, Transform::new(vec2!(8.0, 5.0)), vehicle);
+        let handle = world.read_component::<Collider>().get(e).unwrap().0;
+
+        let around = |world: &World| {
+            world
+                .read_resource::<CollisionWorld>()
+                .query_around(vec2!(5.0, 5.0), 10.0, None)
+                .count()
+        };
+        assert_eq!(around(&world), 2);
+
+        delete_vehicle_entity(&mut world, e);
+        world.maintain();
+        world.write_resource::<CollisionWorld>().maintain();
+
+        assert_eq!(around(&world), 1);
+        assert!(!world.read_resource::<CollisionWorld>().contains(handle));
+        assert!(world.read_component::<Collider>().get(other).is_some());
+
+        // Deleting something without a collider doesn't touch the collision world
+        let plain = world.create_entity().build();
+        remove_shape(&mut world, plain);
+        assert_eq!(around(&world), 1);
+    }
+
+    #[test]
+    fn test_vehicle_rendered_with_its_size_and_color() {
+        let mut world = vehicle_world();
+
+        let color = VehicleKind::Motorcycle.palette()[1].0;
+        let vehicle = VehicleComponent::builder(VehicleKind::Motorcycle)
+            .with_color(color)
+            .build();
+        let e = make_vehicle_entity(&mut world, Transform::new(vec2!(5.0, 5.0)), vehicle);
+
+        let renders = world.read_component::<AssetRender>();
+        let render = renders.get(e).unwrap();
+        assert_eq!(render.scale, VehicleKind::Motorcycle.width());
+        assert_eq!(render.tint, color);
+    }
+
+    #[test]
+    fn test_parked_vehicles_on_parking_lane() {
+        use crate::map_model::LanePatternBuilder;
+        use specs::Join;
+
+        let mut world = vehicle_world();
+
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 50.0));
+        map.connect(a, b, &LanePatternBuilder::new().parking(true).build());
+        world.insert(map);
+
+        for _ in 0..20 {
+            spawn_parked_vehicle(&mut world);
+        }
+
+        let map = world.read_resource::<Map>();
+        let transforms = world.read_component::<Transform>();
+        let parked = world.read_component::<ParkedVehicle>();
+        let renders = world.read_component::<AssetRender>();
+        let mut n = 0;
+        for (trans, parked, render) in (&transforms, &parked, &renders).join() {
+            let lane = &map.lanes()[parked.lane];
+            assert_eq!(lane.kind, LaneKind::Parking);
+            assert!(lane.dist_to(trans.position()) < 1e-3);
+            assert!(trans.direction().dot(lane.get_orientation_vec()) > 0.999);
+            // Drawn with the color it is saved with
+            assert_eq!(render.tint, parked.color);
+            n += 1;
+        }
+        assert_eq!(n, 20);
+    }
+
+    #[test]
+    fn test_builder_matches_manual_construction() {
+        let manual = VehicleComponent {
+            itinerary: Itinerary::default(),
+            desired_speed: 5.0,
+            desired_dir: vec2!(1.0, 0.0),
+            ang_velocity: 0.0,
+            acceleration: 0.0,
+            wait_time: 0.0,
+            stuck_time: 0.0,
+            merge_yield: false,
+            lane_splitting: false,
+            overtaking: false,
+            next_turn: None,
+            blinker: BlinkerState::None,
+            last_stop: None,
+            sensor_angle: DEFAULT_SENSOR_ANGLE,
+            sensor_width: DEFAULT_SENSOR_WIDTH,
+            color: VehicleKind::Bus.palette()[0].0,
+            rng: 0,
+            jitter: 0.0,
+            frustration: 0,
+            kind: VehicleKind::Bus,
+        };
+
+        let built = VehicleComponent::builder(VehicleKind::Bus)
+            .with_itinerary(Itinerary::default())
+            .with_speed(5.0)
+            .build();
+
+        assert_eq!(format!("{:?}", built), format!("{:?}", manual));
+    }
+
+    #[test]
+    fn test_pick_color() {
+        for &kind in &[VehicleKind::Car, VehicleKind::Bus, VehicleKind::Motorcycle] {
+            let palette: Vec<Color> = kind.palette().into_iter().map(|x| x.0).collect();
+
+            for seed in 0..100 {
+                let color = kind.pick_color(seed);
+                assert!(palette.contains(&color));
+                assert_eq!(kind.pick_color(seed), color);
+            }
+        }
+
+        // Every car color shows up with enough vehicles
+        let palette = VehicleKind::Car.palette();
+        let picked: Vec<Color> = (0..5000).map(|s| VehicleKind::Car.pick_color(s)).collect();
+        assert!(palette.iter().all(|(c, _)| picked.contains(c)));
+
+        // Kinds have their own palette
+        let bus = VehicleKind::Bus.pick_color(7);
+        assert!(!palette.iter().any(|(c, _)| *c == bus));
+    }
+
+    #[test]
+    fn test_default_params() {
+        let car = VehicleKind::Car.default_params();
+        assert_eq!(car.width, 4.5);
+        assert_eq!(car.height, 2.0);
+        assert_eq!(car.acceleration, 3.0);
+        assert_eq!(car.deceleration, 9.0);
+        assert_eq!(car.max_jerk, 20.0);
+        assert_eq!(car.min_turning_radius, 3.0);
+        assert_eq!(car.cruising_speed, 15.0);
+        assert_eq!(car.ang_acc, 1.0);
+
+        let bus = VehicleKind::Bus.default_params();
+        assert_eq!(bus.width, 9.0);
+        assert_eq!(bus.height, 2.0);
+        assert_eq!(bus.acceleration, 2.0);
+        assert_eq!(bus.deceleration, 9.0);
+        assert_eq!(bus.max_jerk, 10.0);
+        assert_eq!(bus.min_turning_radius, 5.0);
+        assert_eq!(bus.cruising_speed, 10.0);
+        assert_eq!(bus.ang_acc, 0.8);
+
+        for &kind in &[VehicleKind::Car, VehicleKind::Bus, VehicleKind::Motorcycle] {
+            assert_eq!(kind.default_params().validate(), Ok(()));
+            assert_eq!(
+                *VehicleParamsTable::default().get(kind),
+                kind.default_params()
+            );
+            assert_eq!(kind.width(), kind.default_params().width);
+            assert_eq!(kind.cruising_speed(), kind.default_params().cruising_speed);
+        }
+    }
+
+    #[test]
+    fn test_invalid_params_rejected() {
+        let valid = VehicleKind::Car.default_params();
+
+        let no_turn = VehicleParams {
+            min_turning_radius: 0.0,
+            ..valid
+        };
+        assert_eq!(
+            no_turn.validate(),
+            Err(InvalidVehicleParam {
+                name: "min_turning_radius",
+                value: 0.0
+            })
+        );
+
+        let no_brakes = VehicleParams {
+            deceleration: -9.0,
+            ..valid
+        };
+        assert_eq!(no_brakes.validate().unwrap_err().name, "deceleration");
+
+        let nan = VehicleParams {
+            max_jerk: std::f32::NAN,
+            ..valid
+        };
+        assert_eq!(nan.validate().unwrap_err().name, "max_jerk");
+
+        // Rejected parameters are not applied
+        let mut table = VehicleParamsTable::default();
+        assert!(table.set(VehicleKind::Car, no_turn).is_err());
+        assert_eq!(*table.get(VehicleKind::Car), valid);
+
+        let slow = VehicleParams {
+            cruising_speed: 5.0,
+            ..valid
+        };
+        assert_eq!(table.set(VehicleKind::Car, slow), Ok(()));
+        assert_eq!(*table.get(VehicleKind::Car), slow);
+        assert_eq!(
+            *table.get(VehicleKind::Bus),
+            VehicleKind::Bus.default_params()
+        );
+    }
+}
@@ -1,12 +1,16 @@
-use specs::World;
+use specs::{World, WorldExt};
 
 mod data;
 mod saveload;
+mod spawn;
 pub mod systems;
 
 pub use data::*;
 pub use saveload::*;
+pub use spawn::*;
 
-pub fn setup(world: &mut World) {
-    load(world);
+pub fn setup(world: &mut World) -> bincode::Result<()> {
+    world.register::<ParkedVehicle>();
+    world.register::<VehicleControl>();
+    load(world)
 }
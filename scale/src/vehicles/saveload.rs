@@ -1,16 +1,15 @@
 use crate::physics::Transform;
+use crate::saveload;
 use crate::vehicles::make_vehicle_entity;
 use crate::vehicles::VehicleComponent;
 use specs::{Join, World, WorldExt};
-use std::fs::File;
 
 const VEHICLE_FILENAME: &str = "world/vehicle";
 
-pub fn save(world: &mut World) {
+pub fn save(world: &mut World) -> bincode::Result<()> {
     let _ = std::fs::create_dir("world");
 
     let path = VEHICLE_FILENAME.to_string() + ".bc";
-    let file = File::create(path).unwrap();
 
     let comps: Vec<(Transform, VehicleComponent)> = (
         &world.read_component::<Transform>(),
@@ -20,21 +19,16 @@ pub fn save(world: &mut World) {
         .map(|(trans, car)| (trans.clone(), car.clone()))
         .collect();
 
-    bincode::serialize_into(file, &comps).unwrap();
+    saveload::save_file(path, &comps)
 }
 
-pub fn load(world: &mut World) {
-    let file = File::open(VEHICLE_FILENAME.to_string() + ".bc");
-    if let Err(e) = file {
-        println!("error while trying to load entities: {}", e);
-        return;
-    }
-
-    let des = bincode::deserialize_from(file.unwrap());
-
-    let comps: Vec<(Transform, VehicleComponent)> = des.unwrap_or_default();
+/// Spawns the saved vehicles. An unreadable save is set aside and no vehicle is spawned.
+pub fn load(world: &mut World) -> bincode::Result<()> {
+    let comps: Vec<(Transform, VehicleComponent)> =
+        saveload::load_or_set_aside(VEHICLE_FILENAME.to_string() + ".bc")?.unwrap_or_default();
 
     for (trans, car) in comps {
         make_vehicle_entity(world, trans, car);
     }
+    Ok(())
 }
@@ -0,0 +1,392 @@
+use crate::analytics::Congestion;
+use crate::engine_interaction::TimeInfo;
+use crate::geometry::Vec2;
+use crate::map_model::{LaneID, LaneKind, Map, RouteCost};
+use crate::physics::{CollisionWorld, Transform};
+use crate::utils::{choose_weighted, pick_weighted, rand_det, Choose};
+use crate::vehicles::{
+    build_vehicle_entity, insert_vehicle_collider, make_random_vehicle, make_vehicle_entity,
+    make_vehicle_on_lane, make_vehicle_on_route, VehicleComponent, VehicleKind,
+};
+use cgmath::MetricSpace;
+use specs::prelude::*;
+use specs::shred::PanicHandler;
+
+pub struct SpawnConfig {
+    pub vehicles_per_minute: f32,
+    pub max_population: usize,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            vehicles_per_minute: 0.0,
+            max_population: 500,
+        }
+    }
+}
+
+/// Relative numbers of each kind of vehicle the spawner introduces
+pub struct VehicleMix {
+    pub weights: Vec<(VehicleKind, f32)>,
+}
+
+impl Default for VehicleMix {
+    fn default() -> Self {
+        Self {
+            weights: vec![(VehicleKind::Car, 1.0)],
+        }
+    }
+}
+
+impl VehicleMix {
+    /// A random kind according to the weights, cars if they are all zero
+    pub fn choose(&self) -> VehicleKind {
+        choose_weighted(&self.weights)
+            .copied()
+            .unwrap_or(VehicleKind::Car)
+    }
+}
+
+/// Where trips go, as relative numbers of trips between regions of the map, each region being
+/// a set of lanes. The spawner places vehicles at random when it is empty.
+#[derive(Default)]
+pub struct ODMatrix {
+    pub regions: Vec<Vec<LaneID>>,
+    /// Origin region, destination region and weight of the trips between them
+    pub trips: Vec<(usize, usize, f32)>,
+}
+
+impl ODMatrix {
+    pub fn is_empty(&self) -> bool {
+        self.trips.iter().all(|&(_, _, w)| w <= 0.0)
+    }
+
+    /// The origin and destination regions of the trip for a random number in [0, 1),
+    /// each trip being picked in proportion to its weight
+    pub fn pick_trip(&self, r: f32) -> Option<(usize, usize)> {
+        let trips: Vec<((usize, usize), f32)> = self
+            .trips
+            .iter()
+            .map(|&(from, to, w)| ((from, to), w))
+            .collect();
+        pick_weighted(&trips, r).copied()
+    }
+
+    /// A new vehicle driving a random trip, from a lane of its origin region to a lane of its
+    /// destination, along the route minimizing cost. None if the picked lanes aren't connected.
+    pub fn make_trip_vehicle(
+        &self,
+        map: &Map,
+        kind: VehicleKind,
+        cost: RouteCost,
+    ) -> Option<(Transform, VehicleComponent)> {
+        let (from, to) = self.pick_trip(rand_det())?;
+        let src = *self.regions.get(from)?.choose()?;
+        let dst = *self.regions.get(to)?.choose()?;
+
+        make_vehicle_on_route(map, map.driving_route(src, dst, cost)?, kind)
+    }
+}
+
+/// Introduces vehicles over time at the rate given by the SpawnConfig resource
+#[derive(Default)]
+pub struct VehicleSpawnSystem {
+    to_spawn: f32,
+}
+
+#[derive(SystemData)]
+pub struct VehicleSpawnData<'a> {
+    entities: Entities<'a>,
+    lazy: Read<'a, LazyUpdate>,
+    config: Read<'a, SpawnConfig>,
+    mix: Read<'a, VehicleMix>,
+    od: Read<'a, ODMatrix>,
+    time: Read<'a, TimeInfo>,
+    map: Read<'a, Map>,
+    congestion: Read<'a, Congestion>,
+    coworld: Write<'a, CollisionWorld, PanicHandler>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+}
+
+impl<'a> System<'a> for VehicleSpawnSystem {
+    type SystemData = VehicleSpawnData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        self.to_spawn += data.config.vehicles_per_minute * data.time.delta / 60.0;
+
+        let mut population = data.vehicles.join().count();
+
+        while self.to_spawn >= 1.0 {
+            if population >= data.config.max_population {
+                self.to_spawn = 0.0;
+                return;
+            }
+            self.to_spawn -= 1.0;
+
+            let kind = data.mix.choose();
+            let v = if data.od.is_empty() {
+                make_random_vehicle(&data.map, kind)
+            } else {
+                data.od
+                    .make_trip_vehicle(&data.map, kind, RouteCost::Congestion(&*data.congestion))
+            };
+            let (trans, vehicle) = unwrap_ret!(v);
+
+            let collider = insert_vehicle_collider(&mut data.coworld, &trans, vehicle.kind);
+            let h = collider.0;
+            let e = build_vehicle_entity(
+                data.lazy.create_entity(&data.entities),
+                trans,
+                vehicle,
+                collider,
+            );
+            data.coworld.set_owner(h, e);
+            population += 1;
+        }
+    }
+}
+
+/// Number of random positions tried for each placement before giving up on it
+const PLACEMENT_TRIES: usize = 30;
+
+/// Picks up to count positions on driving lanes, as distances along them, that are all at least
+/// min_spacing apart. Fewer are returned if the map is too crowded to fit them all.
+pub fn sample_spread_placements(map: &Map, count: usize, min_spacing: f32) -> Vec<(LaneID, f32)> {
+    let mut placements = Vec::with_capacity(count);
+    let mut positions: Vec<Vec2> = Vec::with_capacity(count);
+
+    for _ in 0..count * PLACEMENT_TRIES {
+        if placements.len() >= count {
+            break;
+        }
+
+        let lane = match map.get_random_lane(LaneKind::Driving) {
+            Some(x) => x,
+            None => continue,
+        };
+        let dist = rand_det::<f32>() * lane.points.length();
+        let (pos, _) = match lane.points.point_along(dist) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if positions
+            .iter()
+            .all(|p| p.distance2(pos) >= min_spacing * min_spacing)
+        {
+            placements.push((lane.id, dist));
+            positions.push(pos);
+        }
+    }
+
+    placements
+}
+
+/// Fills the map with up to count vehicles spread at least min_spacing apart, so that they
+/// don't all start by avoiding each other
+pub fn spawn_spread_vehicles(world: &mut World, count: usize, min_spacing: f32) -> Vec<Entity> {
+    let vehicles: Vec<_> = {
+        let map = world.read_resource::<Map>();
+        sample_spread_placements(&map, count, min_spacing)
+            .into_iter()
+            .filter_map(|(lane, dist)| make_vehicle_on_lane(&map, lane, dist))
+            .collect()
+    };
+
+    vehicles
+        .into_iter()
+        .map(|(trans, vehicle)| make_vehicle_entity(world, trans, vehicle))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::gridstore::GridStore;
+    use crate::interaction::Selectable;
+    use crate::map_model::{LanePatternBuilder, TraverseKind};
+    use crate::physics::{Collider, Kinematics, Transform};
+    use crate::rendering::assets::AssetRender;
+
+    /// A world with the vehicle components registered, the map and an empty collision world
+    fn spawn_world(map: Map) -> World {
+        let mut world = World::new();
+        world.register::<AssetRender>();
+        world.register::<Transform>();
+        world.register::<Kinematics>();
+        world.register::<VehicleComponent>();
+        world.register::<Collider>();
+        world.register::<Selectable>();
+
+        let coworld: CollisionWorld = GridStore::new(50);
+        world.insert(map);
+        world.insert(coworld);
+        world
+    }
+
+    /// A single two way road of 200m
+    fn straight_road() -> Map {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        map.connect(a, b, &LanePatternBuilder::new().build());
+        map
+    }
+
+    #[test]
+    fn test_population_grows_to_cap() {
+        let mut world = spawn_world(straight_road());
+        world.insert(SpawnConfig {
+            vehicles_per_minute: 60.0,
+            max_population: 10,
+        });
+        world.insert(ODMatrix::default());
+        world.insert(Congestion::default());
+        world.insert(VehicleMix::default());
+        world.insert(TimeInfo {
+            delta: 1.0,
+            ..Default::default()
+        });
+
+        let mut system = VehicleSpawnSystem::default();
+        let mut last_population = 0;
+        for _ in 0..20 {
+            system.run_now(&world);
+            world.maintain();
+
+            let population = world.read_component::<VehicleComponent>().join().count();
+            assert!(population >= last_population);
+            assert!(population <= 10);
+            last_population = population;
+        }
+
+        assert_eq!(last_population, 10);
+    }
+
+    #[test]
+    fn test_spawned_kinds_follow_mix() {
+        let n = 2000;
+        let mut world = spawn_world(straight_road());
+        world.insert(SpawnConfig {
+            vehicles_per_minute: n as f32 * 60.0,
+            max_population: n,
+        });
+        world.insert(ODMatrix::default());
+        world.insert(Congestion::default());
+        world.insert(VehicleMix {
+            weights: vec![
+                (VehicleKind::Car, 2.0),
+                (VehicleKind::Bus, 1.0),
+                (VehicleKind::Motorcycle, 1.0),
+            ],
+        });
+        world.insert(TimeInfo {
+            delta: 1.0,
+            ..Default::default()
+        });
+
+        VehicleSpawnSystem::default().run_now(&world);
+        world.maintain();
+
+        let vehicles = world.read_component::<VehicleComponent>();
+        assert_eq!(vehicles.join().count(), n);
+        let ratio = |kind| vehicles.join().filter(|v| v.kind == kind).count() as f32 / n as f32;
+        for &(kind, expected) in &[
+            (VehicleKind::Car, 0.5),
+            (VehicleKind::Bus, 0.25),
+            (VehicleKind::Motorcycle, 0.25),
+        ] {
+            let r = ratio(kind);
+            assert!((r - expected).abs() < 0.05, "{:?} ratio was {}", kind, r);
+        }
+
+        let only_buses = VehicleMix {
+            weights: vec![(VehicleKind::Car, 0.0), (VehicleKind::Bus, 1.0)],
+        };
+        assert_eq!(only_buses.choose(), VehicleKind::Bus);
+    }
+
+    #[test]
+    fn test_trips_follow_od_matrix() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let c = map.add_intersection(vec2!(400.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        let road1 = map.connect(a, b, &pattern);
+        let road2 = map.connect(b, c, &pattern);
+        let driving = |road, from| {
+            map.roads()[road]
+                .outgoing_lanes_from(from)
+                .iter()
+                .copied()
+                .filter(|&x| map.lanes()[x].kind == LaneKind::Driving)
+                .collect::<Vec<_>>()
+        };
+
+        let od = ODMatrix {
+            regions: vec![driving(road1, a), driving(road2, b)],
+            trips: vec![(0, 0, 1.0), (0, 1, 3.0)],
+        };
+
+        let n = 2000;
+        let mut to_second = 0;
+        for _ in 0..n {
+            let (_, vehicle) = od
+                .make_trip_vehicle(&map, VehicleKind::Car, RouteCost::Distance)
+                .unwrap();
+            let mut it = vehicle.itinerary;
+            while !it.has_ended() {
+                it.advance(&map);
+            }
+            let dst = match it.get_travers().unwrap().kind {
+                TraverseKind::Lane(x) => x,
+                TraverseKind::Turn(_) => panic!("trip ended on a turn"),
+            };
+            if od.regions[1].contains(&dst) {
+                to_second += 1;
+            } else {
+                assert!(od.regions[0].contains(&dst));
+            }
+        }
+
+        let ratio = to_second as f32 / n as f32;
+        assert!((ratio - 0.75).abs() < 0.05, "ratio was {}", ratio);
+
+        assert!(ODMatrix::default().is_empty());
+        assert_eq!(od.pick_trip(0.0), Some((0, 0)));
+        assert_eq!(od.pick_trip(0.5), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_spread_vehicles_keep_spacing() {
+        let mut map = Map::empty();
+        let center = map.add_intersection(vec2!(0.0, 0.0));
+        let pattern = LanePatternBuilder::new().build();
+        for &pos in &[vec2!(300.0, 0.0), vec2!(0.0, 300.0), vec2!(-300.0, 0.0)] {
+            let other = map.add_intersection(pos);
+            map.connect(center, other, &pattern);
+        }
+
+        let mut world = spawn_world(map);
+
+        let min_spacing = 15.0;
+        let spawned = spawn_spread_vehicles(&mut world, 30, min_spacing);
+        assert!(spawned.len() > 10);
+
+        let transforms = world.read_component::<Transform>();
+        let positions: Vec<Vec2> = spawned
+            .iter()
+            .map(|&e| transforms.get(e).unwrap().position())
+            .collect();
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!(a.distance(*b) >= min_spacing - 1e-3);
+            }
+        }
+
+        let colliders = world.read_component::<Collider>();
+        assert!(spawned.iter().all(|&e| colliders.get(e).is_some()));
+    }
+}
@@ -1,28 +1,138 @@
+use crate::analytics::{SimEvent, SimEventKind, SimEvents};
 use crate::engine_interaction::TimeInfo;
-use crate::geometry::intersections::{both_dist_to_inter, Ray};
-use crate::geometry::{Vec2, Vec2Impl};
-use crate::map_model::{Map, TrafficBehavior, Traversable, TraverseDirection, TraverseKind};
-use crate::physics::{CollisionWorld, PhysicsGroup, PhysicsObject};
+use crate::geometry::gridstore::{CellObject, GridStoreHandle};
+use crate::geometry::intersections::{both_dist_to_inter, ray_segment, time_to_collision, Ray};
+use crate::geometry::{wrap_angle, Vec2, Vec2Impl};
+use crate::map_model::{
+    Lane, LaneID, LaneKind, Map, TrafficBehavior, Traversable, TraverseDirection, TraverseKind,
+    TurnID, TURN_SIDE_THRESHOLD,
+};
+use crate::physics::{
+    Collider, CollisionWorld, Contact, Contacts, PhysicsConfig, PhysicsGroup, PhysicsObject,
+    WorldBounds,
+};
 use crate::physics::{Kinematics, Transform};
-use crate::utils::{rand_det, Choose, Restrict};
-use crate::vehicles::VehicleComponent;
-use cgmath::{Angle, InnerSpace, MetricSpace};
+use crate::utils::{Remap, Restrict};
+use crate::vehicles::{
+    BlinkerState, VehicleComponent, VehicleControl, VehicleKind, VehicleParams, VehicleParamsTable,
+};
+use cgmath::{InnerSpace, MetricSpace};
+use ordered_float::OrderedFloat;
 use specs::prelude::*;
 use specs::shred::PanicHandler;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Default)]
-pub struct VehicleDecision;
+pub struct VehicleDecision {
+    /// Vehicles that had to stop for an obstacle during the last run
+    blocked: HashSet<Entity>,
+}
 
 pub const OBJECTIVE_OK_DIST: f32 = 4.0;
 
+/// How far ahead in time crossing traffic is predicted
+pub const TTC_HORIZON: f32 = 3.0;
+
+/// Distance to the end of their lane from which vehicles signal their next turn
+pub const BLINKER_DIST: f32 = 30.0;
+
+/// Time stuck after which vehicles turn on their hazard lights
+pub const HAZARD_TIME: f32 = 10.0;
+
+/// Time buses wait at a bus stop
+pub const BUS_DWELL_TIME: f32 = 5.0;
+
+/// Distance to its entry under which a vehicle driving around a roundabout has priority
+pub const ROUNDABOUT_YIELD_DIST: f32 = 15.0;
+
+/// Time gap under which an oncoming vehicle is too close to turn in front of it
+pub const YIELD_GAP_TIME: f32 = 4.0;
+
+/// Speed vehicles must slow down to when driving over a speed bump
+pub const SPEED_BUMP_SPEED: f32 = 3.0;
+
+/// Distance before a speed bump at which vehicles must have slowed down
+pub const SPEED_BUMP_DIST: f32 = 5.0;
+
+/// Largest part of their cruising speed vehicles drive slower by because of their jitter
+pub const SPEED_JITTER: f32 = 0.1;
+
+/// Distance vehicles stop at from the obstacle in front of them, their jitter adds up to as much
+pub const FOLLOWING_DIST: f32 = 0.5;
+
+/// Distance from the center of their lane beyond which vehicles steer back towards it
+pub const LANE_KEEP_THRESHOLD: f32 = 0.3;
+
+/// Distance ahead on the lane center vehicles aim at when steering back towards it
+pub const LANE_KEEP_LOOKAHEAD: f32 = 10.0;
+
+/// Speed under which traffic can be ridden past between lanes, and the speed limit while doing it
+pub const LANE_SPLIT_SPEED: f32 = 5.0;
+
+/// Distance ahead up to which slow traffic makes motorcycles ride between lanes
+pub const LANE_SPLIT_LOOKAHEAD: f32 = 30.0;
+
+/// Lateral distance up to which slow traffic makes motorcycles ride between lanes
+pub const LANE_SPLIT_REACH: f32 = 6.0;
+
+/// Lateral room kept with the vehicles ridden past between lanes or overtaken
+pub const LANE_SPLIT_MARGIN: f32 = 0.2;
+
+/// Distance ahead up to which a slow vehicle in the lane makes others overtake it
+pub const OVERTAKE_LOOKAHEAD: f32 = 25.0;
+
+/// Part of its cruising speed under which a vehicle is worth overtaking
+pub const OVERTAKE_SPEED_RATIO: f32 = 0.5;
+
+/// Length of the oncoming lane that must be free to start overtaking
+pub const OVERTAKE_CLEAR_DIST: f32 = 80.0;
+
+/// Distance of oncoming traffic or of the end of the lane under which overtaking is given up
+pub const OVERTAKE_ABORT_DIST: f32 = 40.0;
+
+/// Room left behind the overtaken vehicle before moving back in front of it
+pub const OVERTAKE_MERGE_GAP: f32 = 8.0;
+
+/// Number of waits in a row behind an obstacle after which a vehicle honks and follows closer
+pub const FRUSTRATION_THRESHOLD: u32 = 8;
+
+/// Part of its following distance kept by a frustrated vehicle
+pub const FRUSTRATED_FOLLOWING_FACTOR: f32 = 0.5;
+
+/// Speed above which a vehicle is considered driving freely and calms down
+pub const FRUSTRATION_CALM_SPEED: f32 = 5.0;
+
+/// Distance up to which vehicles look for obstacles in front of them
+pub const SENSOR_RANGE: f32 = 50.0;
+
+/// Rate at which tires kill the sideways velocity given by collisions and contacts, per second
+pub const LATERAL_GRIP: f32 = 3.0;
+
+/// Settings of the vehicle decision pass
+#[derive(Default)]
+pub struct DecisionConfig {
+    /// Updates the vehicles one after the other in entity order instead of in parallel,
+    /// so that runs can be reproduced when debugging
+    pub sequential: bool,
+}
+
 #[derive(SystemData)]
 pub struct VehicleDecisionSystemData<'a> {
+    entities: Entities<'a>,
+    config: Read<'a, DecisionConfig>,
     map: Read<'a, Map>,
     time: Read<'a, TimeInfo>,
     coworld: Read<'a, CollisionWorld, PanicHandler>,
+    physics_config: Read<'a, PhysicsConfig>,
+    params: Read<'a, VehicleParamsTable>,
+    bounds: Read<'a, WorldBounds>,
+    events: Write<'a, SimEvents>,
+    contacts: Read<'a, Contacts>,
+    colliders: ReadStorage<'a, Collider>,
     transforms: WriteStorage<'a, Transform>,
     kinematics: WriteStorage<'a, Kinematics>,
     vehicles: WriteStorage<'a, VehicleComponent>,
+    controls: WriteStorage<'a, VehicleControl>,
 }
 
 impl<'a> System<'a> for VehicleDecision {
@@ -30,81 +140,473 @@ impl<'a> System<'a> for VehicleDecision {
 
     fn run(&mut self, mut data: Self::SystemData) {
         let cow = data.coworld;
+        let physics_config = &*data.physics_config;
+        let params = &*data.params;
+        let bounds = &*data.bounds;
         let map = &*data.map;
         let time = data.time;
+        let contacts = &*data.contacts;
 
-        (
+        let decide = |(e, trans, kin, vehicle, control, collider): (
+            Entity,
+            &mut Transform,
+            &mut Kinematics,
+            &mut VehicleComponent,
+            Option<&mut VehicleControl>,
+            Option<&Collider>,
+        )| {
+            let was_frustrated = vehicle.is_frustrated();
+            slide_along_contacts(trans, kin, contacts.get(e));
+            let obstacle = match control {
+                Some(control) => {
+                    let own = collider.map(|x| x.0);
+                    let params = params.get(vehicle.kind);
+                    controlled_physics(&cow, params, &time, trans, kin, vehicle, control, own);
+                    None
+                }
+                None => {
+                    objective_update(vehicle, &time, trans, &map);
+                    vehicle_physics(
+                        &cow,
+                        physics_config,
+                        params,
+                        bounds,
+                        &map,
+                        &time,
+                        trans,
+                        kin,
+                        vehicle,
+                    )
+                }
+            };
+
+            let honk = if !was_frustrated && vehicle.is_frustrated() {
+                Some(SimEvent {
+                    kind: SimEventKind::Honk,
+                    time: time.time,
+                    entities: (e, e),
+                    pos: trans.position(),
+                })
+            } else {
+                None
+            };
+            let stop =
+                obstacle.map(|obstacle| (e, trans.position(), kin.velocity.magnitude(), obstacle));
+            (stop, honk)
+        };
+
+        let storages = (
+            &data.entities,
             &mut data.transforms,
             &mut data.kinematics,
             &mut data.vehicles,
+            (&mut data.controls).maybe(),
+            data.colliders.maybe(),
+        );
+
+        let (stops, honks): (Vec<_>, Vec<_>) = if data.config.sequential {
+            storages.join().map(decide).unzip()
+        } else {
+            storages.par_join().map(decide).unzip()
+        };
+        let stops: Vec<(Entity, Vec2, f32, GridStoreHandle)> =
+            stops.into_iter().flatten().collect();
+        data.events.extend(honks.into_iter().flatten());
+
+        let owners: HashMap<GridStoreHandle, Entity> = if stops.is_empty() {
+            HashMap::new()
+        } else {
+            (&data.entities, &data.colliders)
+                .join()
+                .map(|(e, coll)| (coll.0, e))
+                .collect()
+        };
+
+        let mut blocked = HashSet::with_capacity(stops.len());
+        for (e, pos, speed, obstacle) in stops {
+            blocked.insert(e);
+
+            // Only record the moment a moving vehicle starts braking, not every frame of it
+            if speed < 1.0 || self.blocked.contains(&e) {
+                continue;
+            }
+            if let Some(&other) = owners.get(&obstacle) {
+                data.events.push(SimEvent {
+                    kind: SimEventKind::AvoidanceStop,
+                    time: time.time,
+                    entities: (e, other),
+                    pos,
+                });
+            }
+        }
+        self.blocked = blocked;
+    }
+}
+
+/// Time after which a vehicle that keeps waiting for an obstacle without moving is considered
+/// stuck and removed
+pub const STUCK_DESPAWN_TIME: f32 = 60.0;
+
+/// Despawns vehicles that reached a dead end or that have been stuck for too long
+#[derive(Default)]
+pub struct VehicleCleanupSystem;
+
+#[derive(SystemData)]
+pub struct VehicleCleanupSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    coworld: Write<'a, CollisionWorld, PanicHandler>,
+    colliders: ReadStorage<'a, Collider>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    vehicles: WriteStorage<'a, VehicleComponent>,
+}
+
+impl<'a> System<'a> for VehicleCleanupSystem {
+    type SystemData = VehicleCleanupSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let map = &*data.map;
+        let time = &*data.time;
+
+        for (e, collider, kin, vehicle) in (
+            &data.entities,
+            &data.colliders,
+            &data.kinematics,
+            &mut data.vehicles,
         )
-            .par_join()
-            .for_each(|(trans, kin, vehicle)| {
-                objective_update(vehicle, &time, trans, &map);
-                vehicle_physics(&cow, &map, &time, trans, kin, vehicle);
-            });
+            .join()
+        {
+            // Waiting at a red light, or behind someone who is, isn't being stuck. The timer
+            // is kept between two blocked waits as long as the vehicle doesn't get going.
+            if kin.velocity.magnitude2() >= 1.0 || is_held_by_light(vehicle, time, map) {
+                vehicle.stuck_time = 0.0;
+            } else if vehicle.wait_time > 0.0 {
+                vehicle.stuck_time += time.delta;
+            }
+
+            if (vehicle.stuck_time > STUCK_DESPAWN_TIME || is_at_dead_end(vehicle, map))
+                && data.entities.delete(e).is_ok()
+            {
+                data.coworld.remove(collider.0);
+            }
+        }
+    }
+}
+
+/// Whether the lane the vehicle is on has a red light at its end
+fn is_held_by_light(vehicle: &VehicleComponent, time: &TimeInfo, map: &Map) -> bool {
+    vehicle.itinerary.get_travers().map_or(false, |t| {
+        t.kind.is_lane() && t.exists(map) && !t.can_pass(time.time_seconds, map.lanes())
+    })
+}
+
+fn is_at_dead_end(vehicle: &VehicleComponent, map: &Map) -> bool {
+    if !vehicle.itinerary.has_ended() {
+        return false;
+    }
+
+    match vehicle.itinerary.get_travers() {
+        Some(Traversable {
+            kind: TraverseKind::Lane(id),
+            ..
+        }) => map.lanes().get(*id).map_or(false, |lane| {
+            map.intersections()[lane.dst].turns_from(*id).is_empty()
+        }),
+        _ => false,
+    }
+}
+
+/// Distance to the end of their lane from which vehicles take part in a merge
+pub const MERGE_APPROACH_DIST: f32 = 40.0;
+
+/// Zipper merge: vehicles approaching a lane fed by several turns take turns between the incoming lanes.
+/// Only the vehicle holding the merge's token may go through, the others yield at the end of their lane.
+#[derive(Default)]
+pub struct VehicleMergeSystem {
+    tokens: HashMap<LaneID, MergeToken>,
+}
+
+#[derive(Default)]
+struct MergeToken {
+    holder: Option<Entity>,
+    last_src: Option<LaneID>,
+}
+
+#[derive(SystemData)]
+pub struct VehicleMergeSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    transforms: ReadStorage<'a, Transform>,
+    vehicles: WriteStorage<'a, VehicleComponent>,
+}
+
+impl<'a> System<'a> for VehicleMergeSystem {
+    type SystemData = VehicleMergeSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let map = &*data.map;
+        let time = data.time.time_seconds;
+
+        // Vehicles approaching each merge, with the lane they come from and their distance to its end
+        let mut approaching: HashMap<LaneID, Vec<(Entity, LaneID, f32)>> = HashMap::new();
+
+        for (e, trans, vehicle) in (&data.entities, &data.transforms, &mut data.vehicles).join() {
+            vehicle.merge_yield = false;
+            if let Some((merge, src, dist)) = approaching_merge(vehicle, trans, map, time) {
+                approaching.entry(merge).or_default().push((e, src, dist));
+            }
+        }
+
+        self.tokens
+            .retain(|merge, _| approaching.contains_key(merge));
+
+        for (merge, candidates) in approaching {
+            let token = self.tokens.entry(merge).or_default();
+
+            let holder_approaching = token
+                .holder
+                .map_or(false, |h| candidates.iter().any(|(e, _, _)| *e == h));
+
+            // The holder went through, give the token to the closest vehicle from another lane if any
+            if !holder_approaching {
+                let last_src = token.last_src;
+                let (e, src, _) = *candidates
+                    .iter()
+                    .min_by_key(|(_, src, dist)| (Some(*src) == last_src, OrderedFloat(*dist)))
+                    .unwrap();
+                token.holder = Some(e);
+                token.last_src = Some(src);
+            }
+
+            for (e, _, _) in candidates {
+                if Some(e) != token.holder {
+                    if let Some(vehicle) = data.vehicles.get_mut(e) {
+                        vehicle.merge_yield = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The merged lane, the current lane and the distance to its end of a vehicle close to the end of a lane
+/// that merges with others. Vehicles stopped by a red light don't take part.
+fn approaching_merge(
+    vehicle: &VehicleComponent,
+    trans: &Transform,
+    map: &Map,
+    time: u64,
+) -> Option<(LaneID, LaneID, f32)> {
+    if vehicle.itinerary.has_ended() {
+        return None;
+    }
+
+    let travers = vehicle.itinerary.get_travers()?;
+    let id = match travers.kind {
+        TraverseKind::Lane(id) => id,
+        TraverseKind::Turn(_) => return None,
+    };
+
+    let lane = map.lanes().get(id)?;
+    if !travers.can_pass(time, map.lanes()) {
+        return None;
+    }
+
+    let dist = lane.get_inter_node_pos(lane.dst).distance(trans.position());
+    if dist > MERGE_APPROACH_DIST {
+        return None;
+    }
+
+    let merged = map.intersections().get(lane.dst)?.merged_lane(id)?;
+    Some((merged, id, dist))
+}
+
+/// Removes the part of the velocity and of the heading going into the bodies the vehicle is
+/// in contact with. vehicle_physics then drives on from this speed and heading, so that the
+/// vehicle slides along them instead of steering back into them.
+fn slide_along_contacts(trans: &mut Transform, kin: &mut Kinematics, contacts: &[Contact]) {
+    for contact in contacts {
+        let into = kin.velocity.dot(contact.normal);
+        // A fixed obstacle never gets out of the way, don't bounce off it either
+        if into < 0.0 || contact.fixed {
+            kin.velocity -= contact.normal * into;
+        }
+
+        let direction = trans.direction();
+        let into = direction.dot(contact.normal);
+        if into < 0.0 {
+            // Head-on, there is no side to slide to
+            if let Some((along, _)) = (direction - contact.normal * into).dir_dist() {
+                trans.set_direction(along);
+            }
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn vehicle_physics(
     coworld: &CollisionWorld,
+    physics_config: &PhysicsConfig,
+    params: &VehicleParamsTable,
+    bounds: &WorldBounds,
     map: &Map,
     time: &TimeInfo,
     trans: &mut Transform,
     kin: &mut Kinematics,
     vehicle: &mut VehicleComponent,
-) {
+) -> Option<GridStoreHandle> {
     let direction = trans.direction();
     //debug_assert!(direction.magnitude() > 0.5 && direction.is_finite());
 
-    let speed: f32 = kin.velocity.magnitude() * kin.velocity.dot(direction).signum();
-
-    if speed > 1.0 {
-        let dot = (kin.velocity / speed).dot(direction);
+    let magnitude = kin.velocity.magnitude();
+    if magnitude > 1.0 {
+        let dot = (kin.velocity / magnitude).dot(direction);
         if dot.abs() < 0.9 {
-            let coeff = speed.restrict(1.0, 9.0) / 9.0;
+            let coeff = magnitude.remap(1.0, 9.0, 1.0 / 9.0, 1.0);
             kin.acceleration -= kin.velocity / coeff;
-            return;
+            return None;
         }
     }
 
-    let kind = vehicle.kind;
+    // The sideways part of the velocity isn't driven, apply_controls lets it fade out
+    let speed: f32 = kin.velocity.dot(direction);
+
+    let params = params.get(vehicle.kind);
     let pos = trans.position();
 
-    let danger_length = (speed * speed / (2.0 * kind.deceleration())).min(40.0);
+    let danger_length =
+        (speed * speed / (2.0 * params.deceleration.max(MIN_PHYSICS_PARAM))).min(40.0);
 
-    let neighbors = coworld.query_around(pos, 12.0 + danger_length);
+    update_overtaking(vehicle, params, coworld, map, trans);
 
-    let objs = neighbors.map(|obj| (obj.pos, coworld.get_obj(obj.id)));
+    let neighbors = vehicle_neighbors(coworld, physics_config, pos, danger_length);
 
-    calc_decision(vehicle, map, speed, time, trans, objs);
+    let objs = neighbors
+        .iter()
+        .map(|obj| (obj.pos, coworld.get_obj(obj.id)));
 
-    let speed = speed
-        + (vehicle.desired_speed - speed).restrict(
-            -time.delta * kind.deceleration(),
-            time.delta * kind.acceleration(),
-        );
+    let obstacle = calc_decision(vehicle, params, map, speed, time, trans, bounds, objs);
+
+    apply_controls(params, time, trans, kin, vehicle, speed);
+
+    obstacle.map(|i| neighbors[i].id)
+}
+
+/// Drives the vehicle as told by its VehicleControl and records what it senses in it, instead
+/// of following its itinerary
+#[allow(clippy::too_many_arguments)]
+fn controlled_physics(
+    coworld: &CollisionWorld,
+    params: &VehicleParams,
+    time: &TimeInfo,
+    trans: &mut Transform,
+    kin: &mut Kinematics,
+    vehicle: &mut VehicleComponent,
+    control: &mut VehicleControl,
+    own: Option<GridStoreHandle>,
+) {
+    let direction = trans.direction();
+    let speed: f32 = kin.velocity.dot(direction);
+    let pos = trans.position();
+
+    let nearest = coworld
+        .query_around(pos, SENSOR_RANGE, None)
+        .filter(|obj| Some(obj.id) != own)
+        .map(|obj| obj.pos.distance(pos))
+        .filter(|&d| d <= SENSOR_RANGE)
+        .min_by_key(|&d| OrderedFloat(d));
+    control.record(speed, direction, nearest);
+
+    vehicle.desired_speed = control.desired_speed;
+    if control.desired_dir.magnitude2() > 1e-6 {
+        vehicle.desired_dir = control.desired_dir.normalize();
+    }
+
+    apply_controls(params, time, trans, kin, vehicle, speed);
+}
+
+/// The vehicles and pedestrians within the query radius of the config plus the danger length
+fn vehicle_neighbors<'c>(
+    coworld: &'c CollisionWorld,
+    physics_config: &PhysicsConfig,
+    pos: Vec2,
+    danger_length: f32,
+) -> Vec<&'c CellObject> {
+    coworld
+        .query_around(
+            pos,
+            physics_config.query_radius + danger_length,
+            Some(PhysicsGroup::Vehicles.mask() | PhysicsGroup::Pedestrians.mask()),
+        )
+        .collect()
+}
+
+/// Smallest deceleration, jerk and turning radius the physics works with, so that a
+/// misconfigured kind doesn't turn positions into NaN by dividing by zero
+const MIN_PHYSICS_PARAM: f32 = 1e-3;
+
+/// Moves the acceleration and angular velocity towards the vehicle's desired speed and
+/// direction within the limits of its parameters, then updates its heading and velocity
+fn apply_controls(
+    params: &VehicleParams,
+    time: &TimeInfo,
+    trans: &mut Transform,
+    kin: &mut Kinematics,
+    vehicle: &mut VehicleComponent,
+    speed: f32,
+) {
+    // Aim for the acceleration that reaches the desired speed exactly when ramped down to zero,
+    // taking into account the speed gained while ramping down the current acceleration
+    let jerk = params.max_jerk.max(MIN_PHYSICS_PARAM);
+    let speed_err = vehicle.desired_speed
+        - speed
+        - vehicle.acceleration * vehicle.acceleration.abs() / (2.0 * jerk);
+    let target_acc = ((2.0 * jerk * speed_err.abs())
+        .sqrt()
+        .min(speed_err.abs() / time.delta)
+        * speed_err.signum())
+    .restrict(-params.deceleration, params.acceleration);
+
+    vehicle.acceleration +=
+        (target_acc - vehicle.acceleration).restrict(-time.delta * jerk, time.delta * jerk);
 
-    let max_ang_vel = (speed.abs() / kind.min_turning_radius()).restrict(0.0, 2.0);
+    let speed = speed + vehicle.acceleration * time.delta;
 
-    let delta_ang = direction.angle(vehicle.desired_dir);
-    let mut ang = vec2!(1.0, 0.0).angle(direction);
+    let max_ang_vel =
+        (speed.abs() / params.min_turning_radius.max(MIN_PHYSICS_PARAM)).restrict(0.0, 2.0);
 
-    vehicle.ang_velocity += time.delta * kind.ang_acc();
+    let direction = trans.direction();
+    let delta_ang = direction.angle_between(vehicle.desired_dir);
+    let lateral = kin.velocity - direction * kin.velocity.dot(direction);
+
+    vehicle.ang_velocity += time.delta * params.ang_acc;
     vehicle.ang_velocity = vehicle
         .ang_velocity
-        .min(3.0 * delta_ang.0.abs())
+        .min(3.0 * delta_ang.abs())
         .min(max_ang_vel);
 
-    ang.0 += delta_ang.0.restrict(
-        -vehicle.ang_velocity * time.delta,
-        vehicle.ang_velocity * time.delta,
+    // Rebuilt from the angle rather than by rotating the direction, so that its length
+    // doesn't drift away from 1 frame after frame
+    let ang = wrap_angle(
+        trans.angle()
+            + delta_ang.restrict(
+                -vehicle.ang_velocity * time.delta,
+                vehicle.ang_velocity * time.delta,
+            ),
     );
+    trans.set_angle(ang);
+    let direction = trans.direction();
 
-    let direction = vec2!(ang.cos(), ang.sin());
-    trans.set_direction(direction);
+    // Impulses from collisions keep pushing the vehicle sideways until the tires grip again
+    kin.velocity = direction * speed + lateral * (1.0 - LATERAL_GRIP * time.delta).max(0.0);
 
-    kin.velocity = direction * speed;
+    debug_assert!(
+        direction.is_finite() && kin.velocity.is_finite(),
+        "vehicle physics produced non finite values with {:?}",
+        params
+    );
 }
 
 pub fn objective_update(
@@ -116,16 +618,24 @@ pub fn objective_update(
     if vehicle
         .itinerary
         .get_travers()
-        .map_or(false, |x| !x.is_valid(map))
+        .map_or(false, |x| !x.exists(map))
     {
         vehicle.itinerary.set_none();
     }
+    if vehicle.itinerary.is_blocked_ahead(map) {
+        vehicle.itinerary.reroute(map);
+    }
+
+    plan_next_turn(vehicle, map);
+    vehicle.blinker = blinker_state(vehicle, trans, map);
 
     if let Some(p) = vehicle.itinerary.get_point() {
         if p.distance2(trans.position()) < OBJECTIVE_OK_DIST * OBJECTIVE_OK_DIST {
             let k = vehicle.itinerary.get_travers().unwrap();
             if vehicle.itinerary.remaining_points() > 1
-                || k.can_pass(time.time_seconds, map.lanes())
+                || (k.can_pass(time.time_seconds, map.lanes())
+                    && !vehicle.merge_yield
+                    && !awaits_bus_stop(vehicle, map))
             {
                 vehicle.itinerary.advance(map);
             }
@@ -134,7 +644,7 @@ pub fn objective_update(
 
     if vehicle.itinerary.has_ended() {
         if vehicle.itinerary.get_travers().is_none() {
-            let id = unwrap_ret!(map.closest_lane(trans.position()));
+            let id = unwrap_ret!(map.closest_lane_filter(trans.position(), LaneKind::vehicles));
             vehicle.itinerary.set_simple(
                 Traversable::new(TraverseKind::Lane(id), TraverseDirection::Forward),
                 map,
@@ -149,15 +659,11 @@ pub fn objective_update(
                     map,
                 );
             }
-            TraverseKind::Lane(id) => {
-                let lane = &map.lanes()[id];
-
-                let neighs = map.intersections()[lane.dst].turns_from(id);
-
-                let turn = unwrap_ret!(neighs.choose());
+            TraverseKind::Lane(_) => {
+                let turn = unwrap_ret!(vehicle.next_turn.take());
 
                 vehicle.itinerary.set_simple(
-                    Traversable::new(TraverseKind::Turn(turn.id), TraverseDirection::Forward),
+                    Traversable::new(TraverseKind::Turn(turn), TraverseDirection::Forward),
                     map,
                 );
             }
@@ -165,19 +671,107 @@ pub fn objective_update(
     }
 }
 
+/// Chooses the turn to take at the end of the current lane, if it isn't already
+fn plan_next_turn(vehicle: &mut VehicleComponent, map: &Map) {
+    let id = match vehicle.itinerary.get_travers() {
+        Some(Traversable {
+            kind: TraverseKind::Lane(id),
+            ..
+        }) => *id,
+        _ => {
+            vehicle.next_turn = None;
+            return;
+        }
+    };
+
+    // Routes already know where they go
+    if let Some(Traversable {
+        kind: TraverseKind::Turn(turn),
+        ..
+    }) = vehicle.itinerary.peek_next(map)
+    {
+        if turn.src == id {
+            vehicle.next_turn = Some(turn);
+            return;
+        }
+    }
+
+    let planned = vehicle.next_turn.map_or(false, |turn| {
+        turn.src == id
+            && Traversable::new(TraverseKind::Turn(turn), TraverseDirection::Forward).is_valid(map)
+            && !map.lanes()[turn.dst].closed
+    });
+
+    if !planned {
+        let lane = &map.lanes()[id];
+        let turns: Vec<_> = map.intersections()[lane.dst]
+            .turns_from(id)
+            .into_iter()
+            .filter(|t| !map.lanes()[t.id.dst].closed)
+            .collect();
+        vehicle.next_turn = if turns.is_empty() {
+            None
+        } else {
+            let i = (vehicle.next_random() * turns.len() as f32) as usize;
+            Some(turns[i.min(turns.len() - 1)].id)
+        };
+    }
+}
+
+/// The turn the vehicle is on, or the one it is about to take
+fn upcoming_turn(vehicle: &VehicleComponent, trans: &Transform, map: &Map) -> Option<TurnID> {
+    match vehicle.itinerary.get_travers()?.kind {
+        TraverseKind::Turn(id) => Some(id),
+        TraverseKind::Lane(id) => {
+            let lane = map.lanes().get(id)?;
+            let end = lane.get_inter_node_pos(lane.dst);
+            if end.distance2(trans.position()) < BLINKER_DIST * BLINKER_DIST {
+                vehicle.next_turn
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn blinker_state(vehicle: &VehicleComponent, trans: &Transform, map: &Map) -> BlinkerState {
+    if vehicle.stuck_time > HAZARD_TIME {
+        return BlinkerState::Hazard;
+    }
+
+    let turn = match upcoming_turn(vehicle, trans, map) {
+        Some(x) => x,
+        None => return BlinkerState::None,
+    };
+
+    let side = turn.bend(map.lanes());
+
+    if side > TURN_SIDE_THRESHOLD {
+        BlinkerState::Left
+    } else if side < -TURN_SIDE_THRESHOLD {
+        BlinkerState::Right
+    } else {
+        BlinkerState::None
+    }
+}
+
+/// Returns the index among the neighbors of the obstacle that forced the vehicle to stop, if any
+#[allow(clippy::too_many_arguments)]
 pub fn calc_decision<'a>(
     vehicle: &mut VehicleComponent,
+    params: &VehicleParams,
     map: &Map,
     speed: f32,
     time: &TimeInfo,
     trans: &Transform,
+    bounds: &WorldBounds,
     neighs: impl Iterator<Item = (Vec2, &'a PhysicsObject)>,
-) {
+) -> Option<usize> {
     if vehicle.wait_time > 0.0 {
         vehicle.wait_time -= time.delta;
-        return;
+        return None;
     }
-    let objective: Vec2 = unwrap_ret!(vehicle.itinerary.get_point());
+    let objective: Vec2 = vehicle.itinerary.get_point()?;
 
     let is_terminal = false; // TODO: change depending on route
 
@@ -186,25 +780,61 @@ pub fn calc_decision<'a>(
     let direction_normal = trans.normal();
 
     let delta_pos: Vec2 = objective - position;
-    let (dir_to_pos, dist_to_pos) = unwrap_ret!(delta_pos.dir_dist());
-    let time_to_stop = speed / vehicle.kind.deceleration();
-    let stop_dist = time_to_stop * speed / 2.0;
+    let (dir_to_pos, dist_to_pos) = delta_pos.dir_dist()?;
+    let deceleration = params.deceleration.max(MIN_PHYSICS_PARAM);
+    let time_to_stop = speed / deceleration;
+    // Braking doesn't start at full deceleration because of the jerk limit
+    let stop_dist = time_to_stop * speed / 2.0
+        + speed * deceleration / (2.0 * params.max_jerk.max(MIN_PHYSICS_PARAM));
 
-    let mut min_front_dist: f32 = 50.0;
+    let mut min_front_dist: f32 = SENSOR_RANGE;
+    let cone_cos = vehicle.sensor_angle.cos();
+    let mut front_obj = None;
 
     let my_ray = Ray {
-        from: position - direction * vehicle.kind.width() / 2.0,
+        from: position - direction * params.width / 2.0,
         dir: direction,
     };
 
     let on_lane = vehicle.itinerary.get_travers().unwrap().kind.is_lane();
 
+    let roundabout_entry = roundabout_entry(vehicle, map);
+    let mut ring_traffic = false;
+
+    let yield_to = yield_turns(vehicle, map);
+    let priority_lanes = priority_lanes(vehicle, map);
+    let mut priority_traffic = false;
+
+    let can_split = on_lane && vehicle.kind.can_split_lanes();
+    let mut slow_traffic = false;
+
     // Collision avoidance
-    for (his_pos, nei_physics_obj) in neighs {
+    for (i, (his_pos, nei_physics_obj)) in neighs.enumerate() {
         if his_pos.distance2(position) < 1e-5 {
             continue;
         }
 
+        // Someone driving around the ring towards our entry
+        if let Some((entry, center, radius)) = roundabout_entry {
+            if nei_physics_obj.group == PhysicsGroup::Vehicles
+                && his_pos.distance2(center) < radius * radius
+                && his_pos.distance2(entry) < ROUNDABOUT_YIELD_DIST * ROUNDABOUT_YIELD_DIST
+                && nei_physics_obj.dir.dot(entry - his_pos) > 0.0
+            {
+                ring_traffic = true;
+            }
+        }
+
+        // Someone with priority over us that will soon be in the intersection
+        if yield_to
+            .iter()
+            .map(|turn| turn.src)
+            .chain(priority_lanes.iter().copied())
+            .any(|lane| within_gap(map, lane, his_pos, nei_physics_obj))
+        {
+            priority_traffic = true;
+        }
+
         let towards_vec = his_pos - position;
         let dist = towards_vec.magnitude();
         let towards_dir = towards_vec / dist;
@@ -217,15 +847,35 @@ pub fn calc_decision<'a>(
 
         let his_direction = nei_physics_obj.dir;
 
+        // Slow traffic ahead or alongside that can be ridden past
+        if can_split && is_vehicle && nei_physics_obj.speed < LANE_SPLIT_SPEED {
+            let along = towards_vec.dot(direction);
+            if his_direction.dot(direction) > 0.7
+                && along > -(params.width / 2.0 + nei_physics_obj.radius)
+                && along < LANE_SPLIT_LOOKAHEAD
+                && tow_nor_dot < LANE_SPLIT_REACH
+            {
+                slow_traffic = true;
+            }
+        }
+
+        // Only the vehicles we can't squeeze past matter when riding between lanes,
+        // pedestrians always do
+        let side_tolerance = if (vehicle.lane_splitting || vehicle.overtaking) && is_vehicle {
+            params.width / 2.0 + nei_physics_obj.radius + LANE_SPLIT_MARGIN
+        } else {
+            vehicle.sensor_width
+        };
+
         // front cone
-        if (dir_dot > 0.7 && (!is_vehicle || his_direction.dot(direction) > 0.0))
-            && (!on_lane || tow_nor_dot < 4.0)
+        if (dir_dot > cone_cos && (!is_vehicle || his_direction.dot(direction) > 0.0))
+            && (!on_lane || tow_nor_dot < side_tolerance)
         {
-            let mut dist_to_obj = dist - vehicle.kind.width() / 2.0 - nei_physics_obj.radius;
-            if !is_vehicle {
-                dist_to_obj -= 1.0;
+            let dist_to_obj = front_obj_dist(params, speed, dist, nei_physics_obj);
+            if dist_to_obj < min_front_dist {
+                min_front_dist = dist_to_obj;
+                front_obj = Some(i);
             }
-            min_front_dist = min_front_dist.min(dist_to_obj);
 
             continue;
         }
@@ -234,33 +884,90 @@ pub fn calc_decision<'a>(
             continue;
         }
 
-        // closest win
-
         let his_ray = Ray {
             from: his_pos - nei_physics_obj.radius / 2.0 * his_direction,
             dir: his_direction,
         };
 
+        // closest win: the one reaching the crossing of both paths first goes
+        let goes_first = |(my_dist, his_dist): (f32, f32)| {
+            my_dist - speed.min(2.5) < his_dist - nei_physics_obj.speed.min(2.5)
+        };
         let inter = both_dist_to_inter(my_ray, his_ray);
 
-        match inter {
-            Some((my_dist, his_dist)) => {
-                if my_dist - speed.min(2.5) < his_dist - nei_physics_obj.speed.min(2.5) {
-                    continue;
-                }
+        // Predict where both vehicles will be, the distance we can travel before touching him
+        // is usually much shorter than the straight distance when he's crossing our path
+        let ttc = time_to_collision(
+            towards_vec,
+            nei_physics_obj.velocity() - direction * speed,
+            params.width / 2.0 + nei_physics_obj.radius,
+        )
+        .filter(|&t| t < TTC_HORIZON);
+
+        if let Some(t) = ttc {
+            if inter.map_or(false, goes_first) {
+                continue;
+            }
+            if speed * t < min_front_dist {
+                min_front_dist = speed * t;
+                front_obj = Some(i);
             }
-            None => continue,
+            continue;
+        }
+
+        // No contact predicted, fall back to yielding to whoever is closer to our crossing
+        match inter {
+            Some(x) if !goes_first(x) => {}
+            _ => continue,
         }
-        min_front_dist = min_front_dist.min(dist - vehicle.kind.width() / 2.0);
+        if dist - params.width / 2.0 < min_front_dist {
+            min_front_dist = dist - params.width / 2.0;
+            front_obj = Some(i);
+        }
+    }
+
+    // The edges of the world are walls, hit by the ray from the rear of the vehicle
+    let wall_dist = bounds
+        .walls()
+        .iter()
+        .filter_map(|&(a, b)| ray_segment(my_ray, a, b))
+        .map(|t| t - params.width)
+        .fold(std::f32::INFINITY, f32::min);
+    if wall_dist < min_front_dist {
+        min_front_dist = wall_dist;
+        front_obj = None;
     }
 
+    vehicle.lane_splitting = slow_traffic;
+
     if speed.abs() < 0.2 && min_front_dist < 1.5 {
-        vehicle.wait_time = rand_det::<f32>() * 0.5;
-        return;
+        vehicle.wait_time = vehicle.next_random() * 0.5;
+        vehicle.frustration = vehicle.frustration.saturating_add(1);
+        return None;
+    }
+    if speed > FRUSTRATION_CALM_SPEED {
+        vehicle.frustration = 0;
+    }
+
+    vehicle.desired_dir = lane_keeping_dir(vehicle, map, position, objective).unwrap_or(dir_to_pos);
+    vehicle.desired_speed = params.cruising_speed;
+
+    let lane = match vehicle.itinerary.get_travers().unwrap().kind {
+        TraverseKind::Lane(id) => id,
+        TraverseKind::Turn(id) => id.dst,
+    };
+    if let Some(lane) = map.lanes().get(lane) {
+        vehicle.desired_speed = vehicle.desired_speed.min(lane.speed_limit);
+    }
+    vehicle.desired_speed *= 1.0 - SPEED_JITTER * vehicle.jitter;
+
+    if vehicle.lane_splitting {
+        vehicle.desired_speed = vehicle.desired_speed.min(LANE_SPLIT_SPEED);
     }
 
-    vehicle.desired_dir = dir_to_pos;
-    vehicle.desired_speed = vehicle.kind.cruising_speed();
+    if near_speed_bump(vehicle, params, map, trans, stop_dist) {
+        vehicle.desired_speed = vehicle.desired_speed.min(SPEED_BUMP_SPEED);
+    }
 
     if vehicle.itinerary.remaining_points() == 1 {
         if let Some(Traversable {
@@ -273,7 +980,7 @@ pub fn calc_decision<'a>(
                     if dist_to_pos
                         < OBJECTIVE_OK_DIST * 1.05
                             + stop_dist
-                            + (vehicle.kind.width() / 2.0 - OBJECTIVE_OK_DIST).max(0.0)
+                            + (params.width / 2.0 - OBJECTIVE_OK_DIST).max(0.0)
                     {
                         vehicle.desired_speed = 0.0;
                     }
@@ -283,8 +990,34 @@ pub fn calc_decision<'a>(
                         vehicle.desired_speed = 0.0;
                     }
                 }
+                TrafficBehavior::YIELD => {
+                    if ring_traffic && dist_to_pos < OBJECTIVE_OK_DIST * 1.05 + stop_dist {
+                        vehicle.desired_speed = 0.0;
+                    }
+                }
                 _ => {}
             }
+
+            if priority_traffic && dist_to_pos < OBJECTIVE_OK_DIST * 1.05 + stop_dist {
+                vehicle.desired_speed = 0.0;
+            }
+
+            if vehicle.merge_yield && dist_to_pos < OBJECTIVE_OK_DIST * 1.05 + stop_dist {
+                vehicle.desired_speed = 0.0;
+            }
+
+            if awaits_bus_stop(vehicle, map) {
+                let stop_at =
+                    OBJECTIVE_OK_DIST * 1.05 + (params.width / 2.0 - OBJECTIVE_OK_DIST).max(0.0);
+
+                if speed.abs() < 0.2 && dist_to_pos < stop_at + 1.0 {
+                    vehicle.last_stop = Some(*l_id);
+                    vehicle.wait_time = BUS_DWELL_TIME;
+                    vehicle.desired_speed = 0.0;
+                } else if dist_to_pos < stop_at + stop_dist {
+                    vehicle.desired_speed = 0.0;
+                }
+            }
         }
     }
 
@@ -293,13 +1026,2017 @@ pub fn calc_decision<'a>(
         vehicle.desired_speed = 0.0;
     }
 
-    // Stop at 50 cm of object in front
-    if min_front_dist < 0.5 + stop_dist {
-        vehicle.desired_speed = 0.0;
+    // Stop at 50 cm to 1 m of object in front, frustrated drivers get twice as close
+    let mut following_dist = FOLLOWING_DIST * (1.0 + vehicle.jitter);
+    if vehicle.is_frustrated() {
+        following_dist *= FRUSTRATED_FOLLOWING_FACTOR;
     }
+    let obstacle = if min_front_dist < following_dist + stop_dist {
+        vehicle.desired_speed = 0.0;
+        front_obj
+    } else {
+        None
+    };
 
     // Not facing the objective
     if dir_to_pos.dot(direction) < 0.8 {
         vehicle.desired_speed = vehicle.desired_speed.min(6.0);
     }
+
+    obstacle
+}
+
+/// Distance the vehicle can drive before getting too close to an object in front of it
+fn front_obj_dist(params: &VehicleParams, speed: f32, dist: f32, obj: &PhysicsObject) -> f32 {
+    let dist_to_obj = dist - params.width / 2.0 - obj.radius;
+    if obj.group == PhysicsGroup::Vehicles {
+        dist_to_obj
+    } else {
+        dist_to_obj - params.pedestrian_margin(speed)
+    }
+}
+
+/// Direction towards a point a bit ahead on the center of the lane, if the vehicle drifted
+/// away from it and its objective is far enough that aiming straight at it would take long
+/// to bring it back. Vehicles riding between lanes aim at the edge with the closest lane
+/// going the same way instead of the center.
+fn lane_keeping_dir(
+    vehicle: &VehicleComponent,
+    map: &Map,
+    position: Vec2,
+    objective: Vec2,
+) -> Option<Vec2> {
+    let lane = match vehicle.itinerary.get_travers()?.kind {
+        TraverseKind::Lane(id) => map.lanes().get(id)?,
+        TraverseKind::Turn(_) => return None,
+    };
+
+    let mut center = lane.points.project(position)?;
+    if vehicle.lane_splitting {
+        if let Some(next) = next_lane_center(map, lane, position) {
+            center = (center + next) / 2.0;
+        }
+    }
+    if vehicle.overtaking {
+        if let Some(oncoming) = oncoming_lane(map, lane).and_then(|x| x.points.project(position)) {
+            center = oncoming;
+        }
+    }
+    if center.distance2(position) < LANE_KEEP_THRESHOLD * LANE_KEEP_THRESHOLD {
+        return None;
+    }
+
+    let (dir, dist) = (objective - center).dir_dist()?;
+    if dist < LANE_KEEP_LOOKAHEAD {
+        return None;
+    }
+
+    (center + dir * LANE_KEEP_LOOKAHEAD - position)
+        .dir_dist()
+        .map(|x| x.0)
+}
+
+/// Projection of the position on the center of the closest other driving lane of the road
+/// going the same way as the given one
+fn next_lane_center(map: &Map, lane: &Lane, position: Vec2) -> Option<Vec2> {
+    map.roads()
+        .get(lane.parent)?
+        .outgoing_lanes_from(lane.src)
+        .iter()
+        .filter(|&&id| id != lane.id)
+        .filter_map(|&id| map.lanes().get(id))
+        .filter(|other| other.kind == LaneKind::Driving)
+        .filter_map(|other| other.points.project(position))
+        .min_by_key(|p| OrderedFloat(p.distance2(position)))
+}
+
+/// The closest driving lane going the other way, if the lane is the only one going its way.
+/// Roads with several lanes each way aren't overtaken on.
+fn oncoming_lane<'a>(map: &'a Map, lane: &Lane) -> Option<&'a Lane> {
+    let road = map.roads().get(lane.parent)?;
+    let driving = |from| {
+        road.outgoing_lanes_from(from)
+            .iter()
+            .filter_map(move |&id| map.lanes().get(id))
+            .filter(|x| x.kind == LaneKind::Driving)
+    };
+
+    if driving(lane.src).count() != 1 {
+        return None;
+    }
+    let start = lane.points.first()?;
+    driving(lane.dst).min_by_key(|other| {
+        OrderedFloat(
+            other
+                .points
+                .project(start)
+                .map_or(std::f32::INFINITY, |p| p.distance2(start)),
+        )
+    })
+}
+
+/// Starts passing a slow vehicle in front when the oncoming lane is free far enough, and
+/// moves back in once it is left behind or when oncoming traffic gets too close
+fn update_overtaking(
+    vehicle: &mut VehicleComponent,
+    params: &VehicleParams,
+    coworld: &CollisionWorld,
+    map: &Map,
+    trans: &Transform,
+) {
+    let lane = match vehicle.itinerary.get_travers().map(|x| x.kind) {
+        Some(TraverseKind::Lane(id)) => map.lanes().get(id),
+        _ => None,
+    };
+    let (lane, oncoming) = match lane.and_then(|l| Some((l, oncoming_lane(map, l)?))) {
+        Some(x) if !vehicle.kind.can_split_lanes() => x,
+        _ => {
+            vehicle.overtaking = false;
+            return;
+        }
+    };
+
+    let pos = trans.position();
+    let dir = trans.direction();
+    let front = params.width / 2.0 + 0.1;
+    let mask = Some(PhysicsGroup::Vehicles.mask());
+    let remaining = lane.get_inter_node_pos(lane.dst).distance(pos);
+
+    let oncoming_free = |dist: f32| {
+        remaining > dist
+            && oncoming.points.project(pos).map_or(false, |p| {
+                let ray = Ray {
+                    from: p + dir * front,
+                    dir,
+                };
+                coworld.ray_cast(ray, dist, mask).is_none()
+            })
+    };
+    let own = match lane.points.project(pos) {
+        Some(x) => x,
+        None => return,
+    };
+
+    if vehicle.overtaking {
+        let ray = Ray {
+            from: own - dir * OVERTAKE_MERGE_GAP,
+            dir,
+        };
+        let passed = coworld
+            .ray_cast(ray, OVERTAKE_MERGE_GAP + OVERTAKE_LOOKAHEAD, mask)
+            .is_none();
+        vehicle.overtaking = !passed && oncoming_free(OVERTAKE_ABORT_DIST);
+    } else {
+        let ray = Ray {
+            from: own + dir * front,
+            dir,
+        };
+        let slow_leader =
+            coworld
+                .ray_cast(ray, OVERTAKE_LOOKAHEAD, mask)
+                .map_or(false, |(h, _)| {
+                    let obj = coworld.get_obj(h);
+                    obj.dir.dot(dir) > 0.7
+                        && obj.speed < params.cruising_speed * OVERTAKE_SPEED_RATIO
+                });
+        vehicle.overtaking = slow_leader && oncoming_free(OVERTAKE_CLEAR_DIST);
+    }
+}
+
+/// Whether the vehicle has to slow down for a speed bump of its lane, either because it is
+/// driving over it or because it is within its stopping distance from it
+fn near_speed_bump(
+    vehicle: &VehicleComponent,
+    params: &VehicleParams,
+    map: &Map,
+    trans: &Transform,
+    stop_dist: f32,
+) -> bool {
+    let lane = match vehicle.itinerary.get_travers() {
+        Some(Traversable {
+            kind: TraverseKind::Lane(id),
+            ..
+        }) => match map.lanes().get(*id) {
+            Some(x) => x,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    let half_length = params.width / 2.0;
+    lane.speed_bumps
+        .iter()
+        .filter_map(|&dist| lane.points.point_along(dist))
+        .any(|(bump, _)| {
+            let ahead = (bump - trans.position()).dot(trans.direction());
+            ahead > -half_length && ahead < half_length + SPEED_BUMP_DIST + stop_dist
+        })
+}
+
+/// Whether the vehicle is a bus on a bus stop lane it didn't stop at yet
+fn awaits_bus_stop(vehicle: &VehicleComponent, map: &Map) -> bool {
+    if !matches!(vehicle.kind, VehicleKind::Bus) {
+        return false;
+    }
+
+    match vehicle.itinerary.get_travers() {
+        Some(Traversable {
+            kind: TraverseKind::Lane(id),
+            ..
+        }) => vehicle.last_stop != Some(*id) && map.lanes().get(*id).map_or(false, |x| x.bus_stop),
+        _ => false,
+    }
+}
+
+/// The movements the vehicle has to let through before taking the turn at the end of its lane
+fn yield_turns<'m>(vehicle: &VehicleComponent, map: &'m Map) -> &'m [TurnID] {
+    if vehicle.itinerary.remaining_points() != 1 {
+        return &[];
+    }
+
+    match (vehicle.itinerary.get_travers(), vehicle.next_turn) {
+        (
+            Some(Traversable {
+                kind: TraverseKind::Lane(lane),
+                ..
+            }),
+            Some(turn),
+        ) if turn.src == *lane => map
+            .intersections()
+            .get(turn.parent)
+            .and_then(|inter| inter.turns.get(&turn))
+            .map_or(&[][..], |turn| turn.yield_to.as_slice()),
+        _ => &[],
+    }
+}
+
+/// The incoming lanes that have priority over the vehicle's lane at the intersection it is about
+/// to enter, if it is controlled by a yield sign. Roundabout rings are handled separately.
+fn priority_lanes(vehicle: &VehicleComponent, map: &Map) -> Vec<LaneID> {
+    if vehicle.itinerary.remaining_points() != 1 {
+        return vec![];
+    }
+
+    let lane = match vehicle.itinerary.get_travers() {
+        Some(Traversable {
+            kind: TraverseKind::Lane(id),
+            ..
+        }) => match map.lanes().get(*id) {
+            Some(x) => x,
+            None => return vec![],
+        },
+        _ => return vec![],
+    };
+    if !lane.control.is_yield() {
+        return vec![];
+    }
+
+    let inter = match map.intersections().get(lane.dst) {
+        Some(x) if x.roundabout_ring().is_none() => x,
+        _ => return vec![],
+    };
+
+    inter
+        .roads
+        .iter()
+        .filter(|&&road| road != lane.parent)
+        .flat_map(|&road| map.roads()[road].incoming_lanes_to(inter.id))
+        .filter(|&&id| {
+            let other = &map.lanes()[id];
+            other.kind.vehicles() && !other.control.is_yield()
+        })
+        .copied()
+        .collect()
+}
+
+/// Whether the object is a vehicle coming from the lane that is either already in the
+/// intersection at its end or will reach it within YIELD_GAP_TIME
+fn within_gap(map: &Map, lane: LaneID, pos: Vec2, obj: &PhysicsObject) -> bool {
+    let lane = match map.lanes().get(lane) {
+        Some(x) => x,
+        None => return false,
+    };
+    let inter = match map.intersections().get(lane.dst) {
+        Some(x) => x,
+        None => return false,
+    };
+
+    let dir = lane.get_orientation_vec();
+    if obj.group != PhysicsGroup::Vehicles || obj.dir.dot(dir) < 0.7 {
+        return false;
+    }
+
+    let to_entry = lane.get_inter_node_pos(inter.id) - pos;
+    if to_entry.perp_dot(dir).abs() > lane.width / 2.0 {
+        return false;
+    }
+
+    let dist = to_entry.dot(dir);
+    if dist <= 0.0 {
+        // Still crossing the intersection
+        return dist > -2.0 * inter.interface_radius;
+    }
+    dist < obj.speed * YIELD_GAP_TIME
+}
+
+/// The entry point, the center and the radius of the roundabout the vehicle is about to enter
+fn roundabout_entry(vehicle: &VehicleComponent, map: &Map) -> Option<(Vec2, Vec2, f32)> {
+    if vehicle.itinerary.remaining_points() != 1 {
+        return None;
+    }
+
+    let id = match vehicle.itinerary.get_travers()?.kind {
+        TraverseKind::Lane(id) => id,
+        TraverseKind::Turn(_) => return None,
+    };
+
+    let lane = map.lanes().get(id)?;
+    if !lane.control.is_yield() {
+        return None;
+    }
+
+    let inter = map.intersections().get(lane.dst)?;
+    inter.roundabout_ring()?;
+    Some((
+        lane.get_inter_node_pos(inter.id),
+        inter.pos,
+        inter.interface_radius,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::rect::Rect;
+    use crate::map_model::test_maps::crossroads;
+    use crate::map_model::{
+        IntersectionID, Itinerary, LanePatternBuilder, LightPolicy, RoadID, TrafficControl,
+    };
+    use crate::physics::BoundsPolicy;
+    use crate::vehicles::{insert_vehicle_collider, VehicleKind};
+
+    /// A world with the vehicle components registered and every resource the decision and
+    /// physics systems read, except the map. The collision world starts empty.
+    fn vehicle_world(config: DecisionConfig) -> World {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<Collider>();
+        world.register::<Kinematics>();
+        world.register::<VehicleComponent>();
+        world.register::<VehicleControl>();
+
+        world.insert(CollisionWorld::new(50));
+        world.insert(TimeInfo::default());
+        world.insert(config);
+        world.insert(SimEvents::default());
+        world.insert(Contacts::default());
+        world.insert(PhysicsConfig::default());
+        world.insert(WorldBounds::default());
+        world.insert(VehicleParamsTable::default());
+        world
+    }
+
+    /// Itinerary following the lane to its end, from its start
+    fn lane_itinerary(map: &Map, lane: LaneID) -> Itinerary {
+        let mut it = Itinerary::default();
+        it.set_simple(
+            Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward),
+            map,
+        );
+        it.advance(map);
+        it
+    }
+
+    /// A car offset meters after the start of the lane, driving along it. Its collider is added
+    /// to the collision world of the world.
+    fn car_on_lane(world: &mut World, map: &Map, lane: LaneID, offset: f32) -> Entity {
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let mut trans = Transform::new(map.lanes()[lane].points[0] + dir * offset);
+        trans.set_direction(dir);
+
+        let collider = insert_vehicle_collider(
+            &mut world.write_resource::<CollisionWorld>(),
+            &trans,
+            VehicleKind::Car,
+        );
+        world
+            .create_entity()
+            .with(trans)
+            .with(collider)
+            .with(Kinematics::from_mass(1000.0))
+            .with(VehicleComponent::new(
+                lane_itinerary(map, lane),
+                VehicleKind::Car,
+            ))
+            .build()
+    }
+
+    /// A car driving on a straight road with a parking lane, 20m after its start
+    fn car_on_straight_road() -> (Map, Itinerary, Transform) {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().parking(true).build());
+
+        let lane = *map.roads()[road]
+            .outgoing_lanes_from(a)
+            .iter()
+            .find(|x| map.lanes()[**x].kind == LaneKind::Driving)
+            .unwrap();
+
+        let it = lane_itinerary(&map, lane);
+
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let mut trans = Transform::new(map.lanes()[lane].points[0] + dir * 20.0);
+        trans.set_direction(dir);
+
+        (map, it, trans)
+    }
+
+    #[test]
+    fn test_slows_for_parked_car() {
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+
+        let time = TimeInfo::default();
+        let speed = 10.0;
+
+        let mut vehicle = VehicleComponent::new(it.clone(), VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::empty(),
+        );
+        assert_eq!(vehicle.desired_speed, VehicleKind::Car.cruising_speed());
+
+        // Badly parked car sticking out of the parking lane, at the edge of the front cone
+        let parking = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Parking && l.get_orientation_vec().dot(dir) > 0.0)
+            .unwrap();
+        let spot = parking
+            .points
+            .project(trans.position() + dir * 8.0)
+            .unwrap();
+        assert!(parking.dist_to(spot) < 1e-3);
+        let to_road = (trans.position() + dir * 8.0 - spot).normalize();
+        let parked_pos = spot + to_road * (parking.width / 2.0 + 0.5);
+        assert!((parked_pos - trans.position()).dot(trans.normal()).abs() < 4.0);
+
+        let parked = PhysicsObject {
+            dir,
+            speed: 0.0,
+            radius: VehicleKind::Car.width() / 2.0,
+            group: PhysicsGroup::Vehicles,
+            owner: None,
+        };
+
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::once((parked_pos, &parked)),
+        );
+        assert_eq!(vehicle.desired_speed, 0.0);
+    }
+
+    #[test]
+    fn test_stops_before_world_bounds() {
+        let (map, it, trans) = car_on_straight_road();
+        let time = TimeInfo::default();
+        let speed = 10.0;
+
+        let front = trans.position() + trans.direction() * 6.0;
+        let bounds = WorldBounds {
+            rect: Rect::from_corners(vec2!(-1000.0, -1000.0), vec2!(front.x, 1000.0)),
+            policy: BoundsPolicy::Clamp,
+        };
+
+        let mut vehicle = VehicleComponent::new(it.clone(), VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &bounds,
+            std::iter::empty(),
+        );
+        assert_eq!(vehicle.desired_speed, 0.0);
+
+        // Far enough to keep going
+        let bounds = WorldBounds {
+            rect: Rect::from_corners(vec2!(-1000.0, -1000.0), vec2!(front.x + 100.0, 1000.0)),
+            policy: BoundsPolicy::Clamp,
+        };
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &bounds,
+            std::iter::empty(),
+        );
+        assert!(vehicle.desired_speed > 0.0);
+    }
+
+    #[test]
+    fn test_sensor_angle() {
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let normal = trans.normal();
+
+        let time = TimeInfo::default();
+        let speed = 10.0;
+
+        // Pedestrian 50° off our heading: close, but outside the default cone
+        let pedestrian = PhysicsObject {
+            dir: -normal,
+            speed: 0.0,
+            radius: 0.5,
+            group: PhysicsGroup::Pedestrians,
+            owner: None,
+        };
+        let angle = 50.0f32.to_radians();
+        let pos = trans.position() + (dir * angle.cos() - normal * angle.sin()) * 5.0;
+
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+        assert!(vehicle.sensor_angle < angle);
+        let front = calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::once((pos, &pedestrian)),
+        );
+        assert_eq!(front, None);
+        assert_eq!(vehicle.desired_speed, VehicleKind::Car.cruising_speed());
+
+        vehicle.sensor_angle = 60.0f32.to_radians();
+        let front = calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::once((pos, &pedestrian)),
+        );
+        assert_eq!(front, Some(0));
+        assert_eq!(vehicle.desired_speed, 0.0);
+    }
+
+    #[test]
+    fn test_brakes_early_for_crossing_car() {
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let normal = trans.normal();
+
+        let time = TimeInfo::default();
+        let speed = 10.0;
+        let kind = VehicleKind::Car;
+
+        // Car coming from the right on a perpendicular path, reaching our path just before us
+        let crossing = PhysicsObject {
+            dir: normal,
+            speed: 6.0,
+            radius: kind.width() / 2.0,
+            group: PhysicsGroup::Vehicles,
+            owner: None,
+        };
+        let crossing_pos = trans.position() + dir * 9.0 - normal * 6.0;
+
+        // The straight distance alone is too far to make us brake
+        let stop_dist = speed * speed / (2.0 * kind.deceleration());
+        let straight_dist = (crossing_pos - trans.position()).magnitude() - kind.width() / 2.0;
+        assert!(straight_dist > 0.5 + stop_dist);
+
+        let mut vehicle = VehicleComponent::new(it, kind);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            speed,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::once((crossing_pos, &crossing)),
+        );
+        assert_eq!(vehicle.desired_speed, 0.0);
+    }
+
+    #[test]
+    fn test_speed_capped_by_lane_limit() {
+        let (mut map, it, trans) = car_on_straight_road();
+        let lane = match it.get_travers().unwrap().kind {
+            TraverseKind::Lane(id) => id,
+            _ => unreachable!(),
+        };
+        map.set_lane_speed_limit(lane, 5.0);
+
+        let coworld = CollisionWorld::new(50);
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        let mut trans = trans;
+        let mut kin = Kinematics::from_mass(1000.0);
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+
+        for _ in 0..300 {
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+        }
+
+        assert!((kin.velocity.magnitude() - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_jitter_spreads_cruising_speeds() {
+        let spawn = |it: Itinerary, seed: u64| {
+            VehicleComponent::builder(VehicleKind::Car)
+                .with_itinerary(it)
+                .with_seed(seed)
+                .build()
+        };
+        let (_, it, _) = car_on_straight_road();
+        assert_eq!(spawn(it.clone(), 1).jitter, spawn(it, 1).jitter);
+
+        let cruise = |seed: u64| {
+            let (map, it, mut trans) = car_on_straight_road();
+            let coworld = CollisionWorld::new(50);
+            let time = TimeInfo {
+                delta: 1.0 / 30.0,
+                ..Default::default()
+            };
+
+            let mut kin = Kinematics::from_mass(1000.0);
+            let mut vehicle = spawn(it, seed);
+
+            for _ in 0..300 {
+                vehicle_physics(
+                    &coworld,
+                    &PhysicsConfig::default(),
+                    &VehicleParamsTable::default(),
+                    &WorldBounds::default(),
+                    &map,
+                    &time,
+                    &mut trans,
+                    &mut kin,
+                    &mut vehicle,
+                );
+            }
+            kin.velocity.magnitude()
+        };
+
+        let max_speed = VehicleKind::Car.cruising_speed();
+        let (speed_a, speed_b) = (cruise(1), cruise(2));
+        for &speed in &[speed_a, speed_b] {
+            assert!(speed <= max_speed + 1e-3);
+            assert!(speed >= max_speed * (1.0 - SPEED_JITTER) - 1e-3);
+        }
+        assert!((speed_a - speed_b).abs() > 1e-2);
+    }
+
+    #[test]
+    fn test_frustration_shrinks_following_gap() {
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let kind = VehicleKind::Car;
+
+        let time = TimeInfo {
+            delta: 0.1,
+            ..Default::default()
+        };
+        let leader = PhysicsObject {
+            dir,
+            speed: 0.0,
+            radius: kind.width() / 2.0,
+            group: PhysicsGroup::Vehicles,
+            owner: None,
+        };
+        let bumper_to_bumper = kind.width();
+
+        // Stuck right behind the leader, every wait that runs out is followed by another one
+        let mut vehicle = VehicleComponent::new(it, kind);
+        let leader_pos = trans.position() + dir * (bumper_to_bumper + 1.0);
+        let mut last = 0;
+        for _ in 0..200 {
+            calc_decision(
+                &mut vehicle,
+                &VehicleKind::Car.default_params(),
+                &map,
+                0.0,
+                &time,
+                &trans,
+                &WorldBounds::default(),
+                std::iter::once((leader_pos, &leader)),
+            );
+            assert!(vehicle.frustration >= last);
+            last = vehicle.frustration;
+        }
+        assert!(vehicle.is_frustrated());
+
+        // Creeping towards a gap that a calm driver finds too short but a frustrated one doesn't
+        let speed = 1.0;
+        let params = kind.default_params();
+        let stop_dist = speed * speed / (2.0 * params.deceleration)
+            + speed * params.deceleration / (2.0 * params.max_jerk);
+        let gap = stop_dist + FOLLOWING_DIST * (1.0 + FRUSTRATED_FOLLOWING_FACTOR) / 2.0;
+        let leader_pos = trans.position() + dir * (bumper_to_bumper + gap);
+
+        let creep = |vehicle: &mut VehicleComponent| {
+            vehicle.wait_time = 0.0;
+            calc_decision(
+                vehicle,
+                &VehicleKind::Car.default_params(),
+                &map,
+                speed,
+                &time,
+                &trans,
+                &WorldBounds::default(),
+                std::iter::once((leader_pos, &leader)),
+            );
+            vehicle.desired_speed
+        };
+
+        assert!(creep(&mut vehicle) > 0.0);
+        assert!(vehicle.is_frustrated());
+
+        vehicle.frustration = 0;
+        assert_eq!(creep(&mut vehicle), 0.0);
+
+        // Driving freely again calms it down
+        vehicle.frustration = FRUSTRATION_THRESHOLD;
+        vehicle.wait_time = 0.0;
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            FRUSTRATION_CALM_SPEED + 1.0,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::empty(),
+        );
+        assert_eq!(vehicle.frustration, 0);
+    }
+
+    #[test]
+    fn test_steers_back_to_lane_center() {
+        let (map, it, mut trans) = car_on_straight_road();
+        let lane = match it.get_travers().unwrap().kind {
+            TraverseKind::Lane(id) => id,
+            _ => unreachable!(),
+        };
+        let points = &map.lanes()[lane].points;
+        let lateral = |trans: &Transform| {
+            points
+                .project(trans.position())
+                .unwrap()
+                .distance(trans.position())
+        };
+
+        // Nudged 2m sideways
+        trans.set_position(trans.position() + trans.normal() * 2.0);
+        assert!((lateral(&trans) - 2.0).abs() < 1e-3);
+
+        let coworld = CollisionWorld::new(50);
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = trans.direction() * 10.0;
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+
+        for _ in 0..90 {
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+            kin.integrate(&mut trans, time.delta);
+        }
+
+        // Aiming only at the end of the lane, 140m away, would still leave it more than 1m off
+        assert!(lateral(&trans) < 0.5);
+    }
+
+    #[test]
+    fn test_sideways_impulse_fades_out() {
+        let (map, it, mut trans) = car_on_straight_road();
+        let coworld = CollisionWorld::new(50);
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        // Pushed sideways by a collision, not enough to make it skid
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = trans.direction() * 10.0 + trans.normal() * 3.0;
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+
+        let mut step = |trans: &mut Transform, kin: &mut Kinematics| {
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                trans,
+                kin,
+                &mut vehicle,
+            );
+            kin.integrate(trans, time.delta);
+            kin.velocity.dot(trans.normal())
+        };
+
+        // The push lasts over several ticks instead of being replaced by the driven velocity
+        let sideways = step(&mut trans, &mut kin);
+        assert!(sideways > 2.5);
+        for _ in 0..5 {
+            step(&mut trans, &mut kin);
+        }
+        assert!(kin.velocity.dot(trans.normal()) > 1.0);
+
+        for _ in 0..90 {
+            step(&mut trans, &mut kin);
+        }
+        assert!(kin.velocity.dot(trans.normal()).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_motorcycle_splits_lanes() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(300.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().n_lanes(2).build());
+
+        let lanes: Vec<LaneID> = map.roads()[road]
+            .outgoing_lanes_from(a)
+            .iter()
+            .copied()
+            .filter(|&x| map.lanes()[x].kind == LaneKind::Driving)
+            .collect();
+        assert_eq!(lanes.len(), 2);
+
+        let start = map.lanes()[lanes[0]].points[0];
+        let dir = map.lanes()[lanes[0]].get_orientation_vec();
+        let along = |trans: &Transform| (trans.position() - start).dot(dir);
+
+        // Both lanes are jammed 60m ahead
+        let mut coworld = CollisionWorld::new(50);
+        for &lane in &lanes {
+            coworld.insert_masked(
+                map.lanes()[lane].points[0] + dir * 60.0,
+                PhysicsObject {
+                    dir,
+                    speed: 0.0,
+                    radius: VehicleKind::Car.width() / 2.0,
+                    group: PhysicsGroup::Vehicles,
+                    owner: None,
+                },
+                PhysicsGroup::Vehicles.mask(),
+            );
+        }
+
+        let ride = |kind: VehicleKind| {
+            let it = lane_itinerary(&map, lanes[0]);
+
+            let mut trans = Transform::new(start);
+            trans.set_direction(dir);
+            let mut kin = Kinematics::from_mass(1000.0);
+            let mut vehicle = VehicleComponent::new(it, kind);
+            let time = TimeInfo {
+                delta: 1.0 / 30.0,
+                ..Default::default()
+            };
+
+            for _ in 0..600 {
+                vehicle_physics(
+                    &coworld,
+                    &PhysicsConfig::default(),
+                    &VehicleParamsTable::default(),
+                    &WorldBounds::default(),
+                    &map,
+                    &time,
+                    &mut trans,
+                    &mut kin,
+                    &mut vehicle,
+                );
+                kin.integrate(&mut trans, time.delta);
+            }
+            along(&trans)
+        };
+
+        // The car queues behind the one in its lane, the motorcycle rides past both
+        assert!(ride(VehicleKind::Car) < 56.0);
+        assert!(ride(VehicleKind::Motorcycle) > 80.0);
+    }
+
+    #[test]
+    fn test_overtakes_slow_vehicle() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(400.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().build());
+
+        let driving = |from| {
+            *map.roads()[road]
+                .outgoing_lanes_from(from)
+                .iter()
+                .find(|&&x| map.lanes()[x].kind == LaneKind::Driving)
+                .unwrap()
+        };
+        let lane = driving(a);
+        let oncoming = driving(b);
+        assert_eq!(
+            oncoming_lane(&map, &map.lanes()[lane]).unwrap().id,
+            oncoming
+        );
+
+        let start = map.lanes()[lane].points[0];
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let along = |p: Vec2| (p - start).dot(dir);
+
+        let drive = |oncoming_car: bool, steps: usize| {
+            let mut coworld = CollisionWorld::new(50);
+            let leader_obj = PhysicsObject {
+                dir,
+                speed: 3.0,
+                radius: VehicleKind::Car.width() / 2.0,
+                group: PhysicsGroup::Vehicles,
+                owner: None,
+            };
+            let mut leader_pos = start + dir * 40.0;
+            let leader =
+                coworld.insert_masked(leader_pos, leader_obj, PhysicsGroup::Vehicles.mask());
+            if oncoming_car {
+                coworld.insert_masked(
+                    map.lanes()[oncoming]
+                        .points
+                        .project(start + dir * 80.0)
+                        .unwrap(),
+                    PhysicsObject {
+                        dir: -dir,
+                        speed: 0.0,
+                        ..leader_obj
+                    },
+                    PhysicsGroup::Vehicles.mask(),
+                );
+            }
+
+            let it = lane_itinerary(&map, lane);
+
+            let mut trans = Transform::new(start);
+            trans.set_direction(dir);
+            let mut kin = Kinematics::from_mass(1000.0);
+            let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+            let time = TimeInfo {
+                delta: 1.0 / 30.0,
+                ..Default::default()
+            };
+
+            let mut overtook = false;
+            for _ in 0..steps {
+                leader_pos += dir * leader_obj.speed * time.delta;
+                coworld.set_position(leader, leader_pos);
+                coworld.maintain();
+
+                vehicle_physics(
+                    &coworld,
+                    &PhysicsConfig::default(),
+                    &VehicleParamsTable::default(),
+                    &WorldBounds::default(),
+                    &map,
+                    &time,
+                    &mut trans,
+                    &mut kin,
+                    &mut vehicle,
+                );
+                kin.integrate(&mut trans, time.delta);
+                overtook |= vehicle.overtaking;
+            }
+            (overtook, vehicle, trans, along(leader_pos))
+        };
+
+        // Clear oncoming lane: the car passes the leader and goes back to its lane
+        let (overtook, vehicle, trans, leader) = drive(false, 900);
+        assert!(overtook);
+        assert!(!vehicle.overtaking);
+        assert!(along(trans.position()) > leader + OVERTAKE_MERGE_GAP);
+        let own = map.lanes()[lane].points.project(trans.position()).unwrap();
+        let lateral = own.distance(trans.position());
+        assert!(lateral < 1.0);
+
+        // Car stopped in the oncoming lane: the car stays behind until it is reached
+        let (overtook, _, trans, leader) = drive(true, 300);
+        assert!(!overtook);
+        assert!(along(trans.position()) < leader);
+    }
+
+    #[test]
+    fn test_slows_down_over_speed_bump() {
+        let (mut map, it, mut trans) = car_on_straight_road();
+        let lane = match it.get_travers().unwrap().kind {
+            TraverseKind::Lane(id) => id,
+            _ => unreachable!(),
+        };
+        map.set_lane_speed_bumps(lane, vec![60.0]);
+
+        let start = map.lanes()[lane].points[0];
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let along = |trans: &Transform| (trans.position() - start).dot(dir);
+
+        let coworld = CollisionWorld::new(50);
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = dir * 10.0;
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+
+        let mut speed_at_bump = std::f32::INFINITY;
+        // 80 m at 10 m/s is some 240 ticks, the cap leaves room for slowing down at the bump
+        for _ in 0..900 {
+            if along(&trans) >= 110.0 {
+                break;
+            }
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+            trans.set_position(trans.position() + kin.velocity * time.delta);
+
+            let speed = kin.velocity.magnitude();
+            if (along(&trans) - 60.0).abs() < 1.0 {
+                speed_at_bump = speed_at_bump.min(speed);
+            }
+            if along(&trans) < 30.0 {
+                assert!(speed > 9.0);
+            }
+        }
+
+        assert!(along(&trans) >= 110.0);
+        assert!(speed_at_bump <= SPEED_BUMP_SPEED + 0.1);
+        assert!(kin.velocity.magnitude() > 7.0);
+    }
+
+    #[test]
+    fn test_records_avoidance_stop() {
+        use crate::physics::systems::KinematicsApply;
+
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let mut coworld = CollisionWorld::new(50);
+
+        // Broken down car in the middle of the lane
+        let obstacle_trans = Transform::new(trans.position() + dir * 60.0);
+        let obstacle_collider =
+            insert_vehicle_collider(&mut coworld, &obstacle_trans, VehicleKind::Car);
+        let obstacle = world
+            .create_entity()
+            .with(obstacle_trans)
+            .with(obstacle_collider)
+            .build();
+
+        let collider = insert_vehicle_collider(&mut coworld, &trans, VehicleKind::Car);
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = dir * VehicleKind::Car.cruising_speed();
+        let car = world
+            .create_entity()
+            .with(trans)
+            .with(collider)
+            .with(kin)
+            .with(VehicleComponent::new(it, VehicleKind::Car))
+            .build();
+
+        world.insert(map);
+        world.insert(coworld);
+
+        let mut decision = VehicleDecision::default();
+        for _ in 0..300 {
+            world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+            decision.run_now(&world);
+            KinematicsApply.run_now(&world);
+        }
+
+        let events = world.read_resource::<SimEvents>();
+        let stops: Vec<&SimEvent> = events
+            .iter()
+            .filter(|x| x.kind == SimEventKind::AvoidanceStop)
+            .collect();
+
+        assert!(!stops.is_empty());
+        assert!(stops.iter().all(|x| x.entities == (car, obstacle)));
+        assert!(stops[0].time > 0.0);
+    }
+
+    #[test]
+    fn test_slides_along_obstacle() {
+        use crate::physics::systems::{CollisionResponse, KinematicsApply};
+
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        let (map, it, mut trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let side = -trans.normal();
+        let start = trans.position();
+        // Veering towards the side of the road where the obstacle is
+        trans.set_direction((dir + side * 0.3).normalize());
+        let mut coworld = CollisionWorld::new(50);
+
+        let collider = insert_vehicle_collider(&mut coworld, &trans, VehicleKind::Car);
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = trans.direction() * 8.0;
+        let car = world
+            .create_entity()
+            .with(trans)
+            .with(collider)
+            .with(kin)
+            .with(VehicleComponent::new(it, VehicleKind::Car))
+            .build();
+
+        // A static body in the way, unseen by the avoidance so that only the contacts matter
+        let obstacle_pos = start + dir * 8.0 + side * 3.5;
+        let obstacle_collider = coworld.insert(obstacle_pos, PhysicsObject::default());
+        world
+            .create_entity()
+            .with(Transform::new(obstacle_pos))
+            .with(Collider(obstacle_collider))
+            .build();
+
+        world.insert(map);
+        world.insert(coworld);
+
+        let min_dist = VehicleKind::Car.width() / 2.0 + PhysicsObject::default().radius;
+        let mut touched = false;
+        for _ in 0..90 {
+            world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+            VehicleDecision::default().run_now(&world);
+            KinematicsApply.run_now(&world);
+            CollisionResponse.run_now(&world);
+            world.maintain();
+
+            touched |= !world.read_resource::<Contacts>().get(car).is_empty();
+            let pos = world
+                .read_storage::<Transform>()
+                .get(car)
+                .unwrap()
+                .position();
+            // Slides along it rather than burying itself into it
+            assert!(pos.distance(obstacle_pos) > min_dist - 1.0);
+        }
+        assert!(touched);
+
+        // Made it past the obstacle, still driving forward
+        let pos = world
+            .read_storage::<Transform>()
+            .get(car)
+            .unwrap()
+            .position();
+        assert!((pos - obstacle_pos).dot(dir) > 5.0);
+        let v = world
+            .read_storage::<Kinematics>()
+            .get(car)
+            .unwrap()
+            .velocity;
+        assert!(v.dot(dir) > 1.0);
+    }
+
+    #[test]
+    fn test_slides_along_angled_wall() {
+        use crate::physics::systems::CollisionResponse;
+
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        let (map, it, trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let pos = trans.position();
+        // Facing the car at 30 degrees from its heading
+        let wall_normal = -dir * 0.5 + trans.normal() * (3.0f32.sqrt() / 2.0);
+
+        let mut coworld = CollisionWorld::new(50);
+        let collider = insert_vehicle_collider(&mut coworld, &trans, VehicleKind::Car);
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = dir * 8.0;
+        let car = world
+            .create_entity()
+            .with(trans)
+            .with(collider)
+            .with(kin)
+            .with(VehicleComponent::new(it, VehicleKind::Car))
+            .build();
+
+        // Already slightly into the wall, so that the contact normal is the wall normal
+        let reach = VehicleKind::Car.width() / 2.0 + PhysicsObject::default().radius;
+        let wall_pos = pos - wall_normal * (reach - 0.2);
+        let wall_collider = coworld.insert(wall_pos, PhysicsObject::default());
+        world
+            .create_entity()
+            .with(Transform::new(wall_pos))
+            .with(Collider(wall_collider))
+            .build();
+
+        world.insert(map);
+        world.insert(coworld);
+
+        CollisionResponse.run_now(&world);
+        assert!(world.read_resource::<Contacts>().get(car)[0].fixed);
+
+        world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+        VehicleDecision::default().run_now(&world);
+
+        let kin = world.read_storage::<Kinematics>();
+        let v = kin.get(car).unwrap().velocity;
+        // Neither bounced off nor going through, only sliding along the wall
+        assert!(v.dot(wall_normal).abs() < 0.05);
+        assert!(v.dot(dir) > 5.0);
+    }
+
+    #[test]
+    fn test_visits_waypoints_in_order() {
+        let (map, mut it, mut trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let normal = trans.normal();
+        let start = trans.position();
+
+        let n_points = it.remaining_points();
+        let waypoints = vec![start + dir * 20.0 + normal * 2.0, start + dir * 40.0];
+        it.prepend_waypoints(waypoints.clone());
+        assert_eq!(it.remaining_points(), n_points + 2);
+        assert_eq!(it.get_point(), Some(waypoints[0]));
+
+        let coworld = CollisionWorld::new(50);
+        let mut time = TimeInfo::default();
+        let mut kin = Kinematics::from_mass(1000.0);
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+
+        let mut visited = vec![];
+        for _ in 0..300 {
+            time.advance(1.0 / 30.0);
+            let objective = vehicle.itinerary.get_point();
+            objective_update(&mut vehicle, &time, &trans, &map);
+            if vehicle.itinerary.get_point() != objective {
+                visited.push(objective.unwrap());
+            }
+
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+            trans.translate(kin.velocity * time.delta);
+        }
+
+        assert!(visited.len() >= 2);
+        assert_eq!(&visited[..2], waypoints.as_slice());
+        // Resumed the lane afterwards
+        assert!(trans.position().distance(start) > 60.0);
+    }
+
+    #[test]
+    fn test_jerk_limited_stop() {
+        let (map, it, mut trans) = car_on_straight_road();
+        let dir = trans.direction();
+        let kind = VehicleKind::Car;
+
+        // Stopped car ahead forcing a hard stop
+        let mut coworld = CollisionWorld::new(50);
+        let obstacle = trans.position() + dir * 50.0;
+        coworld.insert_masked(
+            obstacle,
+            PhysicsObject {
+                dir,
+                speed: 0.0,
+                radius: kind.width() / 2.0,
+                group: PhysicsGroup::Vehicles,
+                owner: None,
+            },
+            PhysicsGroup::Vehicles.mask(),
+        );
+
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = dir * kind.cruising_speed();
+        let mut vehicle = VehicleComponent::new(it, kind);
+
+        let max_delta = kind.max_jerk() * time.delta;
+        let mut last_speed = kin.velocity.magnitude();
+        let mut last_acc = 0.0;
+        let mut min_acc: f32 = 0.0;
+
+        for _ in 0..300 {
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+            trans.translate(kin.velocity * time.delta);
+
+            let speed = kin.velocity.magnitude();
+            let acc = (speed - last_speed) / time.delta;
+            assert!((acc - last_acc).abs() <= max_delta + 1e-2);
+
+            min_acc = min_acc.min(acc);
+            last_speed = speed;
+            last_acc = acc;
+        }
+
+        // Braked hard and stopped before the obstacle
+        assert!(min_acc < -kind.deceleration() * 0.5);
+        assert!(last_speed < 0.1);
+        assert!(trans.position().distance(obstacle) > kind.width());
+    }
+
+    #[test]
+    fn test_zero_params_stay_finite() {
+        let params = VehicleParams {
+            min_turning_radius: 0.0,
+            deceleration: 0.0,
+            max_jerk: 0.0,
+            ..VehicleKind::Car.default_params()
+        };
+        assert!(params.validate().is_err());
+
+        let time = TimeInfo {
+            delta: 1.0 / 30.0,
+            ..Default::default()
+        };
+
+        let mut trans = Transform::new(vec2!(0.0, 0.0));
+        let mut kin = Kinematics::from_mass(1000.0);
+        kin.velocity = vec2!(10.0, 0.0);
+        let mut vehicle = VehicleComponent::new(Itinerary::default(), VehicleKind::Car);
+        // Sharp turn and hard braking, both limited by the zeroed parameters
+        vehicle.desired_dir = vec2!(0.0, 1.0);
+        vehicle.desired_speed = 0.0;
+
+        for _ in 0..100 {
+            let speed = kin.velocity.magnitude();
+            apply_controls(&params, &time, &mut trans, &mut kin, &mut vehicle, speed);
+            trans.translate(kin.velocity * time.delta);
+
+            assert!(vehicle.ang_velocity.is_finite());
+            assert!(vehicle.acceleration.is_finite());
+            assert!(trans.direction().is_finite());
+            assert!(kin.velocity.is_finite());
+            assert!(trans.position().is_finite());
+        }
+        assert!(trans.direction().dot(vec2!(0.0, 1.0)) > 0.0);
+    }
+
+    #[test]
+    fn test_zipper_merge() {
+        use crate::physics::systems::KinematicsApply;
+
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        // Two one way roads merging into a third one
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(-150.0, 60.0));
+        let b = map.add_intersection(vec2!(-150.0, -60.0));
+        let m = map.add_intersection(vec2!(0.0, 0.0));
+        let c = map.add_intersection(vec2!(300.0, 0.0));
+
+        let pattern = LanePatternBuilder::new().one_way(true).build();
+        let road_a = map.connect(a, m, &pattern);
+        let road_b = map.connect(b, m, &pattern);
+        map.connect(m, c, &pattern);
+
+        let lane_a = map.roads()[road_a].outgoing_lanes_from(a)[0];
+        let lane_b = map.roads()[road_b].outgoing_lanes_from(b)[0];
+        assert!(map.intersections()[m].merged_lane(lane_a).is_some());
+
+        let mut cars = vec![];
+        for &lane in &[lane_a, lane_b] {
+            for i in 0..3 {
+                let e = car_on_lane(&mut world, &map, lane, 60.0 + i as f32 * 12.0);
+                cars.push((e, lane));
+            }
+        }
+
+        world.insert(map);
+
+        let mut merge = VehicleMergeSystem::default();
+        let mut decision = VehicleDecision::default();
+        let mut order = vec![];
+
+        for _ in 0..1800 {
+            world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+            merge.run_now(&world);
+            decision.run_now(&world);
+            KinematicsApply.run_now(&world);
+
+            let vehicles = world.read_storage::<VehicleComponent>();
+            let transforms = world.read_storage::<Transform>();
+
+            for (e, lane) in &cars {
+                let on_turn = matches!(
+                    vehicles.get(*e).unwrap().itinerary.get_travers(),
+                    Some(Traversable {
+                        kind: TraverseKind::Turn(_),
+                        ..
+                    })
+                );
+                if on_turn && !order.iter().any(|(x, _)| x == e) {
+                    order.push((*e, *lane));
+                }
+            }
+
+            for (i, (e1, _)) in cars.iter().enumerate() {
+                for (e2, _) in &cars[i + 1..] {
+                    let p1 = transforms.get(*e1).unwrap().position();
+                    let p2 = transforms.get(*e2).unwrap().position();
+                    assert!(p1.distance(p2) > VehicleKind::Car.width() / 2.0);
+                }
+            }
+        }
+
+        assert_eq!(order.len(), cars.len());
+        assert!(order.windows(2).all(|w| w[0].1 != w[1].1));
+    }
+
+    #[test]
+    fn test_yield_to_roundabout_traffic() {
+        let (map, center, roads) = crossroads(LightPolicy::Roundabout);
+
+        // Coming from the east, right before entering
+        let lane = incoming_lane(&map, roads[0], center);
+        assert!(map.lanes()[lane].control.is_yield());
+
+        let it = lane_itinerary(&map, lane);
+
+        let entry = map.lanes()[lane].get_inter_node_pos(center);
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let mut trans = Transform::new(entry - dir * 4.1);
+        trans.set_direction(dir);
+
+        let time = TimeInfo::default();
+
+        let mut vehicle = VehicleComponent::new(it.clone(), VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            0.0,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::empty(),
+        );
+        assert!(vehicle.desired_speed > 0.0);
+
+        // Going counter-clockwise around the ring, a bit before our entry
+        let (ring_center, radius) = map.intersections()[center].roundabout_ring().unwrap();
+        let ang = -20.0f32.to_radians();
+        let circulating = PhysicsObject {
+            dir: vec2!(-ang.sin(), ang.cos()),
+            speed: 8.0,
+            radius: VehicleKind::Car.width() / 2.0,
+            group: PhysicsGroup::Vehicles,
+            owner: None,
+        };
+        let circulating_pos = ring_center + vec2!(ang.cos(), ang.sin()) * radius;
+
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            &map,
+            0.0,
+            &time,
+            &trans,
+            &WorldBounds::default(),
+            std::iter::once((circulating_pos, &circulating)),
+        );
+        assert_eq!(vehicle.desired_speed, 0.0);
+    }
+
+    fn incoming_lane(map: &Map, road: RoadID, center: IntersectionID) -> LaneID {
+        *map.roads()[road]
+            .incoming_lanes_to(center)
+            .iter()
+            .find(|x| map.lanes()[**x].kind == LaneKind::Driving)
+            .unwrap()
+    }
+
+    /// A car on the lane, stopped right before entering the intersection
+    fn car_at_stop_line(map: &Map, lane: LaneID, center: IntersectionID) -> (Itinerary, Transform) {
+        let it = lane_itinerary(map, lane);
+
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let mut trans = Transform::new(map.lanes()[lane].get_inter_node_pos(center) - dir * 4.1);
+        trans.set_direction(dir);
+
+        (it, trans)
+    }
+
+    /// Desired speed of a stopped car at trans after deciding, with a car at 10 m/s driving
+    /// along dir at pos if there is one
+    fn decide_with_crossing_car(
+        map: &Map,
+        it: &Itinerary,
+        trans: &Transform,
+        next_turn: Option<TurnID>,
+        crossing: Option<(Vec2, Vec2)>,
+    ) -> f32 {
+        let mut vehicle = VehicleComponent::new(it.clone(), VehicleKind::Car);
+        vehicle.next_turn = next_turn;
+
+        let objs: Vec<(Vec2, PhysicsObject)> = crossing
+            .into_iter()
+            .map(|(pos, dir)| {
+                let obj = PhysicsObject {
+                    dir,
+                    speed: 10.0,
+                    radius: VehicleKind::Car.width() / 2.0,
+                    group: PhysicsGroup::Vehicles,
+                    owner: None,
+                };
+                (pos, obj)
+            })
+            .collect();
+
+        calc_decision(
+            &mut vehicle,
+            &VehicleKind::Car.default_params(),
+            map,
+            0.0,
+            &TimeInfo::default(),
+            trans,
+            &WorldBounds::default(),
+            objs.iter().map(|(pos, obj)| (*pos, obj)),
+        );
+        vehicle.desired_speed
+    }
+
+    #[test]
+    fn test_yield_sign_waits_for_gap() {
+        let (mut map, center, roads) = crossroads(LightPolicy::NoLights);
+        let lane = incoming_lane(&map, roads[0], center);
+        let main = incoming_lane(&map, roads[1], center);
+        map.set_lane_control(lane, TrafficControl::Yield);
+
+        let (it, trans) = car_at_stop_line(&map, lane, center);
+
+        let main_dir = map.lanes()[main].get_orientation_vec();
+        let main_end = map.lanes()[main].get_inter_node_pos(center);
+        let decide = |dist: Option<f32>| {
+            let crossing = dist.map(|dist| (main_end - main_dir * dist, main_dir));
+            decide_with_crossing_car(&map, &it, &trans, None, crossing)
+        };
+
+        // Nobody coming, no need to stop
+        assert!(decide(None) > 0.0);
+        // 2 seconds away on the main road
+        assert_eq!(decide(Some(20.0)), 0.0);
+        // 8 seconds away, enough time to go
+        assert!(decide(Some(80.0)) > 0.0);
+    }
+
+    #[test]
+    fn test_blinker_before_left_turn() {
+        let (map, center, roads) = crossroads(LightPolicy::NoLights);
+        let lane = incoming_lane(&map, roads[0], center);
+        let dir = map.lanes()[lane].get_orientation_vec();
+
+        // Heading west, turning left goes south
+        let left_turn = map.intersections()[center]
+            .turns_from(lane)
+            .into_iter()
+            .find(|turn| map.lanes()[turn.id.dst].get_orientation_vec().y < -0.9)
+            .unwrap()
+            .id;
+
+        let it = lane_itinerary(&map, lane);
+
+        let time = TimeInfo::default();
+        let end = map.lanes()[lane].get_inter_node_pos(center);
+
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Car);
+        vehicle.next_turn = Some(left_turn);
+
+        // Too far from the intersection to signal yet
+        let mut trans = Transform::new(end - dir * (BLINKER_DIST + 10.0));
+        trans.set_direction(dir);
+        objective_update(&mut vehicle, &time, &trans, &map);
+        assert_eq!(vehicle.blinker, BlinkerState::None);
+        assert_eq!(vehicle.next_turn, Some(left_turn));
+
+        let mut trans = Transform::new(end - dir * 10.0);
+        trans.set_direction(dir);
+        objective_update(&mut vehicle, &time, &trans, &map);
+        assert_eq!(vehicle.blinker, BlinkerState::Left);
+        assert!(vehicle.itinerary.get_travers().unwrap().kind.is_lane());
+    }
+
+    #[test]
+    fn test_query_radius_changes_neighbors() {
+        let mut config = PhysicsConfig::default();
+        let mut coworld = config.make_world();
+
+        let mut handles: Vec<GridStoreHandle> = [5.0, 15.0, 30.0]
+            .iter()
+            .map(|&x| {
+                coworld.insert_masked(
+                    vec2!(x, 0.0),
+                    PhysicsObject {
+                        group: PhysicsGroup::Vehicles,
+                        ..Default::default()
+                    },
+                    PhysicsGroup::Vehicles.mask(),
+                )
+            })
+            .collect();
+        handles.sort();
+
+        let neighbors = |config: &PhysicsConfig, danger_length: f32| {
+            let mut ids: Vec<GridStoreHandle> =
+                vehicle_neighbors(&coworld, config, vec2!(0.0, 0.0), danger_length)
+                    .into_iter()
+                    .map(|x| x.id)
+                    .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(neighbors(&config, 0.0), vec![handles[0]]);
+        assert_eq!(neighbors(&config, 5.0), handles[..2].to_vec());
+
+        config.query_radius = 20.0;
+        assert_eq!(neighbors(&config, 0.0), handles[..2].to_vec());
+
+        config.query_radius = 40.0;
+        assert_eq!(neighbors(&config, 0.0), handles);
+    }
+
+    #[test]
+    fn test_left_turn_waits_for_gap() {
+        let (map, center, roads) = crossroads(LightPolicy::NoLights);
+        let lane = incoming_lane(&map, roads[0], center);
+        let oncoming = incoming_lane(&map, roads[2], center);
+        let oncoming_dir = map.lanes()[oncoming].get_orientation_vec();
+
+        // Heading west, turning left goes south
+        let inter = &map.intersections()[center];
+        let left_turn = inter
+            .turns_from(lane)
+            .into_iter()
+            .find(|turn| map.lanes()[turn.id.dst].get_orientation_vec().y < -0.9)
+            .unwrap();
+        let straight = inter
+            .turns_from(oncoming)
+            .into_iter()
+            .find(|turn| map.lanes()[turn.id.dst].get_orientation_vec().x > 0.9)
+            .unwrap();
+        assert!(left_turn.yield_to.contains(&straight.id));
+        assert!(straight.yield_to.is_empty());
+        let left_turn = left_turn.id;
+
+        let (it, trans) = car_at_stop_line(&map, lane, center);
+
+        let oncoming_end = map.lanes()[oncoming].get_inter_node_pos(center);
+        let decide = |dist: f32| {
+            let crossing = Some((oncoming_end - oncoming_dir * dist, oncoming_dir));
+            decide_with_crossing_car(&map, &it, &trans, Some(left_turn), crossing)
+        };
+
+        // 2 seconds away, too close to turn in front of it
+        assert_eq!(decide(20.0), 0.0);
+        // Already in the intersection
+        assert_eq!(decide(-10.0), 0.0);
+        // 6 seconds away, enough time to go
+        assert!(decide(60.0) > 0.0);
+    }
+
+    #[test]
+    fn test_sequential_decision_is_reproducible() {
+        use crate::physics::systems::KinematicsApply;
+
+        fn run() -> Vec<(u32, u32)> {
+            let mut world = vehicle_world(DecisionConfig { sequential: true });
+
+            // Only one possible turn at each intersection so that no random choice is made
+            let mut map = Map::empty();
+            let a = map.add_intersection(vec2!(0.0, 0.0));
+            let b = map.add_intersection(vec2!(300.0, 0.0));
+            let c = map.add_intersection(vec2!(600.0, 0.0));
+            let pattern = LanePatternBuilder::new().one_way(true).build();
+            let road = map.connect(a, b, &pattern);
+            map.connect(b, c, &pattern);
+            let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+
+            let cars: Vec<Entity> = (0..5)
+                .map(|i| car_on_lane(&mut world, &map, lane, 30.0 + i as f32 * 20.0))
+                .collect();
+
+            world.insert(map);
+
+            let mut decision = VehicleDecision::default();
+            for _ in 0..600 {
+                world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+                decision.run_now(&world);
+                KinematicsApply.run_now(&world);
+            }
+
+            let transforms = world.read_storage::<Transform>();
+            cars.iter()
+                .map(|e| {
+                    let pos = transforms.get(*e).unwrap().position();
+                    (pos.x.to_bits(), pos.y.to_bits())
+                })
+                .collect()
+        }
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_external_control_overrides_decision() {
+        use crate::physics::systems::KinematicsApply;
+
+        let mut world = vehicle_world(DecisionConfig { sequential: true });
+
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(300.0, 0.0));
+        let pattern = LanePatternBuilder::new().one_way(true).build();
+        let road = map.connect(a, b, &pattern);
+        let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+        let dir = map.lanes()[lane].get_orientation_vec();
+
+        let cars = [
+            car_on_lane(&mut world, &map, lane, 20.0),
+            car_on_lane(&mut world, &map, lane, 30.0),
+        ];
+
+        // The car behind is told to turn off the road slowly
+        let side = vec2!(-dir.y, dir.x);
+        world
+            .write_storage::<VehicleControl>()
+            .insert(cars[0], VehicleControl::new(4.0, side))
+            .unwrap();
+
+        world.insert(map);
+
+        let mut decision = VehicleDecision::default();
+        world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+        decision.run_now(&world);
+        {
+            let controls = world.read_storage::<VehicleControl>();
+            let nearest = controls
+                .get(cars[0])
+                .unwrap()
+                .nearest_neighbor_dist()
+                .unwrap();
+            assert!((nearest - 10.0).abs() < 1e-3);
+        }
+
+        for _ in 0..300 {
+            world.write_resource::<TimeInfo>().advance(1.0 / 30.0);
+            decision.run_now(&world);
+            KinematicsApply.run_now(&world);
+        }
+
+        let transforms = world.read_storage::<Transform>();
+        let controls = world.read_storage::<VehicleControl>();
+        let control = controls.get(cars[0]).unwrap();
+
+        assert!((control.speed() - 4.0).abs() < 0.5);
+        assert!(control.heading().dot(side) > 0.95);
+        assert!(transforms.get(cars[0]).unwrap().direction().dot(side) > 0.95);
+
+        // The other car keeps following its lane
+        let other = transforms.get(cars[1]).unwrap();
+        assert!(other.direction().dot(dir) > 0.95);
+        assert!(other.position().dot(dir) > 40.0);
+    }
+
+    #[test]
+    fn test_bus_dwells_at_stop() {
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let c = map.add_intersection(vec2!(400.0, 0.0));
+        let pattern = LanePatternBuilder::new().one_way(true).build();
+        let road = map.connect(a, b, &pattern);
+        map.connect(b, c, &pattern);
+
+        let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+        map.set_lane_bus_stop(lane, true);
+        let it = lane_itinerary(&map, lane);
+
+        let dir = map.lanes()[lane].get_orientation_vec();
+        let end = map.lanes()[lane].get_inter_node_pos(b);
+        let mut trans = Transform::new(end - dir * 40.0);
+        trans.set_direction(dir);
+
+        let coworld = CollisionWorld::new(50);
+        let mut time = TimeInfo::default();
+        let mut kin = Kinematics::from_mass(10000.0);
+        let mut vehicle = VehicleComponent::new(it, VehicleKind::Bus);
+
+        let mut dwell_start = None;
+        let mut resumed = None;
+
+        for _ in 0..900 {
+            time.advance(1.0 / 30.0);
+            objective_update(&mut vehicle, &time, &trans, &map);
+            vehicle_physics(
+                &coworld,
+                &PhysicsConfig::default(),
+                &VehicleParamsTable::default(),
+                &WorldBounds::default(),
+                &map,
+                &time,
+                &mut trans,
+                &mut kin,
+                &mut vehicle,
+            );
+            trans.translate(kin.velocity * time.delta);
+
+            if dwell_start.is_none() && vehicle.wait_time > 0.0 {
+                assert!(trans.position().distance(end) < 7.0);
+                dwell_start = Some(time.time);
+            }
+            if dwell_start.is_some() && resumed.is_none() && kin.velocity.magnitude() > 0.5 {
+                resumed = Some(time.time);
+            }
+        }
+
+        let dwell = (resumed.unwrap() - dwell_start.unwrap()) as f32;
+        assert!(dwell >= BUS_DWELL_TIME);
+        assert!(dwell < BUS_DWELL_TIME + 1.0);
+
+        // Went on to the next road without stopping again
+        assert_eq!(vehicle.last_stop, Some(lane));
+        assert!(trans.position().x > 220.0);
+    }
+
+    #[test]
+    fn test_despawn_at_dead_end() {
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let road = map.connect(a, b, &LanePatternBuilder::new().one_way(true).build());
+        let lane = map.roads()[road].outgoing_lanes_from(a)[0];
+
+        let e = car_on_lane(&mut world, &map, lane, 190.0);
+        {
+            let mut vehicles = world.write_storage::<VehicleComponent>();
+            let it = &mut vehicles.get_mut(e).unwrap().itinerary;
+            while !it.has_ended() {
+                it.advance(&map);
+            }
+        }
+        let h = world.read_storage::<Collider>().get(e).unwrap().0;
+        world.insert(map);
+
+        VehicleCleanupSystem.run_now(&world);
+        world.maintain();
+        world.write_resource::<CollisionWorld>().maintain();
+
+        assert!(!world.is_alive(e));
+        assert!(!world.read_resource::<CollisionWorld>().contains(h));
+    }
+
+    #[test]
+    fn test_despawn_stuck_but_not_at_light() {
+        use crate::map_model::TrafficLightSchedule;
+
+        let mut world = vehicle_world(DecisionConfig::default());
+
+        let mut map = Map::empty();
+        let a = map.add_intersection(vec2!(0.0, 0.0));
+        let b = map.add_intersection(vec2!(200.0, 0.0));
+        let c = map.add_intersection(vec2!(200.0, 200.0));
+        let pattern = LanePatternBuilder::new().one_way(true).build();
+        let open = map.roads()[map.connect(a, b, &pattern)].outgoing_lanes_from(a)[0];
+        let red = map.roads()[map.connect(c, b, &pattern)].outgoing_lanes_from(c)[0];
+        map.set_lane_control(
+            red,
+            TrafficControl::Light(TrafficLightSchedule::from_basic(0, 0, 1000, 0)),
+        );
+
+        let blocked = car_on_lane(&mut world, &map, open, 0.0);
+        let queued = car_on_lane(&mut world, &map, red, 0.0);
+        world.insert(map);
+
+        // Both keep waiting behind an obstacle, as the decision system would make them do
+        for _ in 0..(STUCK_DESPAWN_TIME as usize + 10) {
+            world.write_resource::<TimeInfo>().advance(1.0);
+            for vehicle in (&mut world.write_storage::<VehicleComponent>()).join() {
+                vehicle.wait_time = 0.5;
+            }
+            VehicleCleanupSystem.run_now(&world);
+            world.maintain();
+        }
+
+        assert!(!world.is_alive(blocked));
+        assert!(world.is_alive(queued));
+        assert_eq!(
+            world
+                .read_storage::<VehicleComponent>()
+                .get(queued)
+                .unwrap()
+                .stuck_time,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_pedestrian_margin_grows_with_speed() {
+        let pedestrian = PhysicsObject {
+            radius: 0.5,
+            group: PhysicsGroup::Pedestrians,
+            ..Default::default()
+        };
+        let vehicle = PhysicsObject {
+            group: PhysicsGroup::Vehicles,
+            ..pedestrian
+        };
+        let params = VehicleKind::Car.default_params();
+        let dist = 20.0;
+
+        let slow = front_obj_dist(&params, 2.0, dist, &pedestrian);
+        let fast = front_obj_dist(&params, 15.0, dist, &pedestrian);
+        assert!(fast < slow);
+
+        // Standing still keeps the base margin
+        let base = dist - params.width / 2.0 - pedestrian.radius;
+        let stopped = front_obj_dist(&params, 0.0, dist, &pedestrian);
+        assert!((base - stopped - params.pedestrian_margin).abs() < 1e-5);
+
+        // Vehicles don't get the margin, whatever the speed
+        assert_eq!(front_obj_dist(&params, 2.0, dist, &vehicle), base);
+        assert_eq!(front_obj_dist(&params, 15.0, dist, &vehicle), base);
+    }
 }